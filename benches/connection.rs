@@ -0,0 +1,54 @@
+// Frames per second through `Connection`, over an in-memory stream so the
+// numbers reflect framing and buffering rather than the network.
+//
+//     cargo bench --bench connection
+
+use std::time::Instant;
+
+use keyz::server::connection::Connection;
+use tokio::io::{duplex, AsyncWriteExt};
+
+const FRAMES: usize = 200_000;
+
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+async fn run(payload_bytes: usize, frames: usize) {
+    let frames = frames / 64 * 64;
+    let payload = vec![b'v'; payload_bytes];
+    let batch: Vec<u8> = (0..64).flat_map(|_| frame(&payload)).collect();
+    let (mut peer, stream) = duplex(256 * 1024);
+
+    let writer = tokio::spawn(async move {
+        for _ in 0..frames / 64 {
+            peer.write_all(&batch).await.unwrap();
+        }
+    });
+
+    let start = Instant::now();
+    let mut connection = Connection::new(stream);
+    let mut bytes = 0;
+    for _ in 0..frames {
+        bytes += connection.read_message().await.unwrap().unwrap().len();
+    }
+    let elapsed = start.elapsed();
+    writer.await.unwrap();
+
+    let secs = elapsed.as_secs_f64();
+    println!(
+        "{:>7} byte payloads: {:>10.0} frames/s {:>8.1} MiB/s",
+        payload_bytes,
+        frames as f64 / secs,
+        bytes as f64 / secs / (1024.0 * 1024.0)
+    );
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    for (payload_bytes, frames) in [(16, FRAMES), (1024, FRAMES), (64 * 1024, FRAMES / 100)] {
+        runtime.block_on(run(payload_bytes, frames));
+    }
+}
@@ -0,0 +1,24 @@
+#![no_main]
+
+use std::sync::OnceLock;
+
+use keyz::config::{ProtocolConfig, StoreConfig};
+use keyz::server::dispatcher::dispatcher;
+use keyz::server::store::Store;
+use libfuzzer_sys::fuzz_target;
+use tokio::runtime::{Builder, Runtime};
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Builder::new_current_thread().build().unwrap())
+}
+
+fuzz_target!(|input: &str| {
+    let mut store = Store::new(&StoreConfig::default());
+    let protocol = ProtocolConfig::default();
+
+    // Every failure must come back as an "error:..." response, never as an Err
+    // (which would drop the client's connection).
+    let result = runtime().block_on(dispatcher(input, &mut store, &protocol));
+    assert!(result.is_ok(), "dispatcher returned Err for {:?}", input);
+});
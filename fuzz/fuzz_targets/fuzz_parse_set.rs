@@ -0,0 +1,13 @@
+#![no_main]
+
+use keyz::server::dispatcher::parse_set_command;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    if let Ok((key, value, _seconds)) = parse_set_command(input) {
+        assert!(!key.is_empty(), "empty key parsed from {:?}", input);
+        assert!(!value.is_empty(), "empty value parsed from {:?}", input);
+        assert!(input.contains(&key), "key {:?} not in {:?}", key, input);
+        assert!(input.contains(&value), "value {:?} not in {:?}", value, input);
+    }
+});
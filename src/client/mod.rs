@@ -0,0 +1,359 @@
+use std::io;
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time;
+
+use crate::server::batch;
+
+pub mod pool;
+
+const LENGTH_PREFIX_BYTES: usize = 4;
+const DEFAULT_MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+const NULL: &str = "null";
+const ERROR_PREFIX: &str = "error:";
+
+// Protocol versions this client speaks, oldest first. Every server speaks 1,
+// including ones that predate HELLO.
+const PROTOCOLS: &[u32] = &[1];
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("invalid command")]
+    InvalidCommand,
+    #[error("value too large")]
+    ValueTooLarge,
+    #[error("key too long")]
+    KeyTooLarge,
+    #[error("ttl too large")]
+    TtlTooLarge,
+    #[error("server is in read-only mode")]
+    ReadOnly,
+    #[error("value has the wrong type for this command")]
+    WrongType,
+    #[error("server is out of memory")]
+    OutOfMemory,
+    #[error("authentication failed")]
+    AuthFailed,
+    #[error("command timed out on the server")]
+    ServerTimeout,
+    #[error("server error: {0}")]
+    Server(String),
+    #[error("message is {size} bytes, limit is {limit}")]
+    MessageTooLarge { size: usize, limit: usize },
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("connection closed by server")]
+    Closed,
+    #[error("no pooled connection became free within {0:?}")]
+    PoolExhausted(Duration),
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl ClientError {
+    // Maps what follows `error:`. Servers send `<CODE>:<message>`; older ones
+    // and those running with legacy_errors send the message alone. The code
+    // decides, and the message only picks between variants sharing one.
+    fn from_server(body: &str) -> Self {
+        let (code, message) = match body.split_once(':') {
+            Some((code, message)) if is_code(code) => (Some(code), message),
+            _ => (None, body),
+        };
+
+        match (code, message) {
+            (Some("KEYTOOLONG"), _) => ClientError::KeyTooLarge,
+            (Some("TTLTOOLARGE"), _) => ClientError::TtlTooLarge,
+            (Some("READONLY"), _) => ClientError::ReadOnly,
+            (Some("PAYLOADTOOLARGE" | "MSGTOOLARGE"), _) => ClientError::ValueTooLarge,
+            // Servers before every error had its own code shared TOOLARGE
+            // and INVALID_CMD, and told these apart by message.
+            (Some("TOOLARGE") | None, "key too long") => ClientError::KeyTooLarge,
+            // Older servers said "key too large".
+            (None, "key too large") => ClientError::KeyTooLarge,
+            (Some("TOOLARGE"), _) | (None, "value too large") => ClientError::ValueTooLarge,
+            (Some("INVALID_CMD") | None, "ttl too large") => ClientError::TtlTooLarge,
+            (Some("INVALID_CMD") | None, "read only") => ClientError::ReadOnly,
+            (Some("INVALID_CMD"), _) | (None, "invalid command") => ClientError::InvalidCommand,
+            (Some("WRONGTYPE"), _) => ClientError::WrongType,
+            (Some("OOM"), _) => ClientError::OutOfMemory,
+            (Some("AUTH"), _) => ClientError::AuthFailed,
+            (Some("TIMEOUT"), _) => ClientError::ServerTimeout,
+            _ => ClientError::Server(message.to_string()),
+        }
+    }
+}
+
+// Codes are short uppercase tokens, which no legacy message starts with.
+fn is_code(word: &str) -> bool {
+    !word.is_empty() && word.bytes().all(|b| b.is_ascii_uppercase() || b == b'_')
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerInfo {
+    pub keys: usize,
+    pub slow_query_count: usize,
+    pub commands_per_second: f64,
+    pub role: String,
+    pub read_only: bool,
+    pub command_timeouts: u64,
+    pub panics: u64,
+    pub config_reloads: u64,
+    pub last_reload_epoch: u64,
+    pub listeners: Vec<String>,
+    pub metrics: ServerMetrics,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub sets: u64,
+    pub deletes: u64,
+    pub expirations: u64,
+    pub hit_ratio: f64,
+}
+
+// What the server answered to HELLO.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerHello {
+    pub version: String,
+    pub proto: u32,
+    pub protocols: Vec<u32>,
+}
+
+// A single connection to a keyz server. Requests are sent one at a time and
+// each waits for its response, so share it behind a lock or open one per
+// task.
+pub struct KeyzConnection {
+    stream: TcpStream,
+    max_message_bytes: usize,
+    timeout: Option<Duration>,
+    hello: Option<ServerHello>,
+}
+
+impl KeyzConnection {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self, ClientError> {
+        let stream = TcpStream::connect(addr).await?;
+
+        let mut connection = Self {
+            stream,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            timeout: None,
+            hello: None,
+        };
+        connection.negotiate().await?;
+
+        Ok(connection)
+    }
+
+    // Switches to the newest protocol both sides speak. A server without
+    // HELLO answers with an error, which leaves the connection on 1.
+    async fn negotiate(&mut self) -> Result<(), ClientError> {
+        let Ok(mut hello) = serde_json::from_str::<ServerHello>(&self.round_trip("HELLO").await?) else {
+            return Ok(());
+        };
+
+        let best = PROTOCOLS.iter().rev().find(|proto| hello.protocols.contains(proto));
+        if let Some(&best) = best.filter(|&&best| best != hello.proto) {
+            hello = serde_json::from_str(&self.request(&format!("HELLO {}", best)).await?)
+                .map_err(|_| ClientError::UnexpectedResponse("HELLO".into()))?;
+        }
+
+        self.hello = Some(hello);
+        Ok(())
+    }
+
+    // The HELLO reply, or None for a server that predates it.
+    pub fn server_hello(&self) -> Option<&ServerHello> {
+        self.hello.as_ref()
+    }
+
+    pub fn protocol(&self) -> u32 {
+        self.hello.as_ref().map_or(1, |hello| hello.proto)
+    }
+
+    // Applies to requests and responses alike. Anything larger fails with
+    // MessageTooLarge before it is sent, or before its payload is read, in
+    // which case the connection should be dropped.
+    pub fn set_max_message_bytes(&mut self, limit: usize) {
+        self.max_message_bytes = limit;
+    }
+
+    // Bounds every following call. A call that times out leaves its response
+    // unread, so the connection should be dropped afterwards.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub async fn set(
+        &mut self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), ClientError> {
+        let command = match ttl {
+            Some(ttl) => format!("SET {} {} EX {}", key, value, ttl.as_secs()),
+            None => format!("SET {} {}", key, value),
+        };
+
+        match self.request(&command).await?.as_str() {
+            "ok" => Ok(()),
+            other => Err(ClientError::UnexpectedResponse(other.to_string())),
+        }
+    }
+
+    // The protocol can't tell a missing key from a value of "null".
+    pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>, ClientError> {
+        let response = self.request(&format!("GET {}", key)).await?;
+
+        match response.as_str() {
+            NULL => Ok(None),
+            _ => Ok(Some(Bytes::from(response))),
+        }
+    }
+
+    pub async fn delete(&mut self, key: &str) -> Result<bool, ClientError> {
+        let response = self.request(&format!("DEL {}", key)).await?;
+        Ok(response != NULL)
+    }
+
+    pub async fn expires_in(&mut self, key: &str) -> Result<Option<Duration>, ClientError> {
+        let response = self.request(&format!("EXIN {}", key)).await?;
+
+        if response == NULL {
+            return Ok(None);
+        }
+
+        response
+            .parse()
+            .map(|secs| Some(Duration::from_secs(secs)))
+            .map_err(|_| ClientError::UnexpectedResponse(response))
+    }
+
+    pub async fn ping(&mut self) -> Result<(), ClientError> {
+        match self.request("PING").await?.as_str() {
+            "pong" => Ok(()),
+            other => Err(ClientError::UnexpectedResponse(other.to_string())),
+        }
+    }
+
+    pub async fn info(&mut self) -> Result<ServerInfo, ClientError> {
+        let response = self.request("INFO").await?;
+        serde_json::from_str(&response).map_err(|_| ClientError::UnexpectedResponse(response))
+    }
+
+    // Sends any command and returns the raw response, server errors included.
+    pub async fn command(&mut self, command: &str) -> Result<String, ClientError> {
+        self.round_trip(command).await
+    }
+
+    // Sends every command in one frame and returns their raw responses in
+    // order. Each command succeeds or fails on its own, as with command().
+    pub async fn batch(&mut self, commands: &[&str]) -> Result<Vec<String>, ClientError> {
+        let response = self.request(&batch::encode(commands)).await?;
+
+        match batch::decode(&response, commands.len()) {
+            Ok(responses) if responses.len() == commands.len() => {
+                Ok(responses.into_iter().map(str::to_string).collect())
+            }
+            _ => Err(ClientError::UnexpectedResponse(response)),
+        }
+    }
+
+    async fn request(&mut self, command: &str) -> Result<String, ClientError> {
+        let response = self.round_trip(command).await?;
+
+        match response.strip_prefix(ERROR_PREFIX) {
+            Some(message) => Err(ClientError::from_server(message)),
+            None => Ok(response),
+        }
+    }
+
+    async fn round_trip(&mut self, command: &str) -> Result<String, ClientError> {
+        match self.timeout {
+            Some(timeout) => time::timeout(timeout, self.exchange(command))
+                .await
+                .map_err(|_| ClientError::Timeout(timeout))?,
+            None => self.exchange(command).await,
+        }
+    }
+
+    async fn exchange(&mut self, command: &str) -> Result<String, ClientError> {
+        self.check_size(command.len())?;
+
+        let mut frame = Vec::with_capacity(LENGTH_PREFIX_BYTES + command.len());
+        frame.extend_from_slice(&(command.len() as u32).to_be_bytes());
+        frame.extend_from_slice(command.as_bytes());
+        self.stream.write_all(&frame).await?;
+
+        let mut prefix = [0; LENGTH_PREFIX_BYTES];
+        match self.stream.read_exact(&mut prefix).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(ClientError::Closed),
+            Err(e) => return Err(e.into()),
+        }
+
+        let len = u32::from_be_bytes(prefix) as usize;
+        self.check_size(len)?;
+
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload).await?;
+
+        Ok(String::from_utf8_lossy(&payload).into_owned())
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), ClientError> {
+        if size > self.max_message_bytes {
+            return Err(ClientError::MessageTooLarge {
+                size,
+                limit: self.max_message_bytes,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_errors_map_by_code_and_by_legacy_message() {
+        let cases = [
+            ("INVALID_CMD:set command invalid", "InvalidCommand"),
+            ("invalid command", "InvalidCommand"),
+            ("INVALID_CMD:read only", "ReadOnly"),
+            ("READONLY:read only", "ReadOnly"),
+            ("KEYTOOLONG:key too long", "KeyTooLarge"),
+            ("TTLTOOLARGE:ttl too large", "TtlTooLarge"),
+            ("PAYLOADTOOLARGE:value too large", "ValueTooLarge"),
+            ("MSGTOOLARGE:message too large", "ValueTooLarge"),
+            ("CHECKSUM:checksum mismatch", "Server(\"checksum mismatch\")"),
+            ("read only", "ReadOnly"),
+            ("TOOLARGE:key too long", "KeyTooLarge"),
+            ("key too large", "KeyTooLarge"),
+            ("TOOLARGE:message too large", "ValueTooLarge"),
+            ("WRONGTYPE:not a list", "WrongType"),
+            ("OOM:maxmemory reached", "OutOfMemory"),
+            ("AUTH:bad password", "AuthFailed"),
+            ("TIMEOUT:command timed out", "ServerTimeout"),
+            ("EXEC without MULTI", "Server(\"EXEC without MULTI\")"),
+            ("NEWCODE:something new", "Server(\"something new\")"),
+        ];
+
+        for (body, expected) in cases {
+            assert_eq!(format!("{:?}", ClientError::from_server(body)), expected, "{}", body);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time;
+
+use super::{ClientError, KeyzConnection};
+
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_size: usize,
+    pub min_idle: usize,
+    pub connect_timeout: Duration,
+    pub max_lifetime: Option<Duration>,
+    // How long `get` waits for a connection when all max_size are in use.
+    pub checkout_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            min_idle: 0,
+            connect_timeout: Duration::from_secs(5),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            checkout_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub in_use: usize,
+    pub idle: usize,
+    pub created: u64,
+    pub errors: u64,
+}
+
+struct Idle {
+    connection: KeyzConnection,
+    created: Instant,
+}
+
+struct Shared {
+    addr: String,
+    config: PoolConfig,
+    idle: Mutex<VecDeque<Idle>>,
+    // One permit per connection that may be checked out at once.
+    permits: Arc<Semaphore>,
+    in_use: AtomicUsize,
+    created: AtomicU64,
+    errors: AtomicU64,
+}
+
+// Cheap to clone; every clone hands out connections from the same pool.
+#[derive(Clone)]
+pub struct KeyzPool {
+    shared: Arc<Shared>,
+}
+
+// Derefs to the connection and gives it back to the pool when dropped.
+pub struct PooledConnection {
+    connection: Option<KeyzConnection>,
+    created: Instant,
+    shared: Arc<Shared>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl KeyzPool {
+    // Opens min_idle connections up front, then keeps that many idle from a
+    // background task that stops once the last clone of the pool is dropped.
+    pub async fn new(addr: impl Into<String>, config: PoolConfig) -> Result<Self, ClientError> {
+        let shared = Arc::new(Shared {
+            addr: addr.into(),
+            permits: Arc::new(Semaphore::new(config.max_size)),
+            config,
+            idle: Mutex::new(VecDeque::new()),
+            in_use: AtomicUsize::new(0),
+            created: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        });
+
+        shared.top_up().await?;
+        tokio::spawn(maintain(Arc::downgrade(&shared)));
+
+        Ok(Self { shared })
+    }
+
+    pub async fn get(&self) -> Result<PooledConnection, ClientError> {
+        let shared = &self.shared;
+        let limit = shared.config.checkout_timeout;
+
+        let permit = match time::timeout(limit, shared.permits.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => permit,
+            _ => return Err(ClientError::PoolExhausted(limit)),
+        };
+
+        let (connection, created) = match shared.reuse().await {
+            Some(idle) => (idle.connection, idle.created),
+            None => (shared.connect().await?, Instant::now()),
+        };
+
+        shared.in_use.fetch_add(1, Ordering::Relaxed);
+
+        Ok(PooledConnection {
+            connection: Some(connection),
+            created,
+            shared: shared.clone(),
+            _permit: permit,
+        })
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let shared = &self.shared;
+
+        PoolStats {
+            in_use: shared.in_use.load(Ordering::Relaxed),
+            idle: shared.idle.lock().unwrap().len(),
+            created: shared.created.load(Ordering::Relaxed),
+            errors: shared.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Shared {
+    async fn connect(&self) -> Result<KeyzConnection, ClientError> {
+        let limit = self.config.connect_timeout;
+
+        let result = match time::timeout(limit, KeyzConnection::connect(self.addr.as_str())).await {
+            Ok(result) => result,
+            Err(_) => Err(ClientError::Timeout(limit)),
+        };
+
+        match result {
+            Ok(connection) => {
+                self.created.fetch_add(1, Ordering::Relaxed);
+                Ok(connection)
+            }
+            Err(e) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    // Takes the most recently returned connection that is still within its
+    // lifetime and answers PING. Anything else is dropped, which is how
+    // connections the server has closed get replaced.
+    async fn reuse(&self) -> Option<Idle> {
+        loop {
+            let mut idle = self.idle.lock().unwrap().pop_back()?;
+
+            if self.expired(idle.created) {
+                continue;
+            }
+
+            let limit = self.config.connect_timeout;
+            match time::timeout(limit, idle.connection.ping()).await {
+                Ok(Ok(())) => return Some(idle),
+                _ => {
+                    self.errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    fn expired(&self, created: Instant) -> bool {
+        self.config
+            .max_lifetime
+            .is_some_and(|lifetime| created.elapsed() >= lifetime)
+    }
+
+    async fn top_up(&self) -> Result<(), ClientError> {
+        self.idle
+            .lock()
+            .unwrap()
+            .retain(|idle| !self.expired(idle.created));
+
+        loop {
+            let idle = self.idle.lock().unwrap().len();
+            let total = idle + self.in_use.load(Ordering::Relaxed);
+            if idle >= self.config.min_idle || total >= self.config.max_size {
+                return Ok(());
+            }
+
+            let connection = self.connect().await?;
+            self.idle.lock().unwrap().push_front(Idle {
+                connection,
+                created: Instant::now(),
+            });
+        }
+    }
+}
+
+async fn maintain(shared: Weak<Shared>) {
+    let mut interval = time::interval(MAINTENANCE_INTERVAL);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+
+        // Failures are already counted in errors; the next tick retries.
+        let _ = shared.top_up().await;
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = KeyzConnection;
+
+    fn deref(&self) -> &KeyzConnection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut KeyzConnection {
+        self.connection.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        self.shared.in_use.fetch_sub(1, Ordering::Relaxed);
+
+        if let Some(connection) = self.connection.take() {
+            if !self.shared.expired(self.created) {
+                self.shared.idle.lock().unwrap().push_back(Idle {
+                    connection,
+                    created: self.created,
+                });
+            }
+        }
+    }
+}
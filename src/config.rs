@@ -0,0 +1,1261 @@
+use std::{
+    env,
+    error::Error,
+    fmt, fs,
+    io::ErrorKind,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use tracing_subscriber::EnvFilter;
+
+use crate::server::response::ErrorFormat;
+
+const DEFAULT_CONFIG_PATH: &str = "keyz.toml";
+const CONFIG_PATH_ENV: &str = "KEYZ_CONFIG";
+const CONFIG_LENIENT_ENV: &str = "KEYZ_CONFIG_LENIENT";
+const CONFIG_LENIENT_FLAG: &str = "--lenient-config";
+const CONFIG_FLAG: &str = "--config";
+const INCLUDE_KEY: &str = "include";
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 7667;
+const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4317";
+const MAX_DATABASES: usize = 256;
+pub const MAX_TTL_JITTER_PERCENT: f64 = 50.0;
+// Past this, jitter starts to noticeably shorten what callers asked for.
+const TTL_JITTER_WARN_PERCENT: f64 = 20.0;
+
+// Keep these in step with the structs below; they drive the suggestions for
+// misspelled keys and which keys lenient loading drops.
+const KNOWN_KEYS: &[(&str, &[&str])] = &[
+    (
+        "server",
+        &[
+            "host",
+            "port",
+            "listeners",
+            "read_only",
+            "tcp_nodelay",
+            "tcp_keepalive_secs",
+            "tcp_recv_buffer_bytes",
+            "tcp_send_buffer_bytes",
+        ],
+    ),
+    (
+        "protocol",
+        &[
+            "structured_errors",
+            "legacy_errors",
+            "command_timeout_secs",
+            "command_timeout_response",
+            "allow_debug",
+            "allow_bulk_delete",
+            "bulk_delete_max",
+            "close_command_case_insensitive",
+            "max_message_bytes",
+            "max_inflight_bytes",
+            "frame_timeout_secs",
+            "max_batch_commands",
+            "namespace_separator",
+            "allow_flush",
+        ],
+    ),
+    (
+        "store",
+        &[
+            "slow_query_threshold_ms",
+            "slow_query_threshold",
+            "slow_log_max_len",
+            "max_key_bytes",
+            "max_key_size",
+            "max_value_bytes",
+            "max_value_size",
+            "max_ttl_secs",
+            "max_ttl",
+            "ttl_jitter_percent",
+            "databases",
+            "seed_path",
+            "seed_strict",
+        ],
+    ),
+    (
+        "logging",
+        &[
+            "level",
+            "format",
+            "access_log_path",
+            "access_log_max_bytes",
+            "access_log_keep",
+            "access_log_scope",
+        ],
+    ),
+    ("replication", &["replicate_from"]),
+    ("prometheus", &["enabled", "host", "port"]),
+    ("otel", &["enabled", "exporter", "endpoint"]),
+];
+
+// Optional fields with an example value, written commented out when unset
+// so a serialized config shows what else can be set.
+const OPTIONAL_KEYS: &[(&str, &str, &str)] = &[
+    ("server", "host", "\"127.0.0.1\""),
+    ("server", "port", "7667"),
+    ("server", "listeners", "[\"127.0.0.1:7667\"]"),
+    ("server", "tcp_keepalive_secs", "60"),
+    ("server", "tcp_recv_buffer_bytes", "262144"),
+    ("server", "tcp_send_buffer_bytes", "262144"),
+    ("protocol", "command_timeout_secs", "5"),
+    ("protocol", "max_inflight_bytes", "1073741824"),
+    ("store", "slow_query_threshold_ms", "50"),
+    ("store", "max_key_bytes", "1024"),
+    ("store", "max_value_bytes", "1048576"),
+    ("store", "max_ttl_secs", "315360000"),
+    ("store", "seed_path", "\"seed.txt\""),
+    ("logging", "access_log_path", "\"keyz-access.log\""),
+    ("logging", "access_log_max_bytes", "104857600"),
+    ("replication", "replicate_from", "\"10.0.0.4:7667\""),
+    ("otel", "endpoint", "\"http://localhost:4317\""),
+];
+
+const NANOS_PER_MILLI: u128 = 1_000_000;
+const NANOS_PER_SEC: u128 = 1_000 * NANOS_PER_MILLI;
+
+const DURATION_UNITS: &[(&str, u128)] = &[
+    ("ms", NANOS_PER_MILLI),
+    ("s", NANOS_PER_SEC),
+    ("m", 60 * NANOS_PER_SEC),
+    ("h", 3_600 * NANOS_PER_SEC),
+];
+
+// KB/MB/GB are binary multiples, the same as KiB/MiB/GiB, so "4MB" is 4194304.
+const SIZE_UNITS: &[(&str, u128)] = &[
+    ("b", 1),
+    ("kb", 1 << 10),
+    ("kib", 1 << 10),
+    ("mb", 1 << 20),
+    ("mib", 1 << 20),
+    ("gb", 1 << 30),
+    ("gib", 1 << 30),
+];
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub protocol: ProtocolConfig,
+    pub store: StoreConfig,
+    pub logging: LoggingConfig,
+    pub replication: ReplicationConfig,
+    pub prometheus: PrometheusConfig,
+    pub otel: OtelConfig,
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+// Either `host`/`port` for a single address or `listeners` for several.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub listeners: Vec<String>,
+    pub read_only: bool,
+    // Applied to every accepted connection. Unset leaves the OS default.
+    pub tcp_nodelay: bool,
+    pub tcp_keepalive_secs: Option<u64>,
+    pub tcp_recv_buffer_bytes: Option<usize>,
+    pub tcp_send_buffer_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolConfig {
+    pub structured_errors: bool,
+    // Drops the code from error replies, for clients that still match the
+    // bare `error:<message>`. Ignored when structured_errors is set.
+    pub legacy_errors: bool,
+    pub command_timeout_secs: Option<u64>,
+    // Only the message; the reply is sent as a TIMEOUT error.
+    pub command_timeout_response: String,
+    pub allow_debug: bool,
+    pub allow_bulk_delete: bool,
+    // Most keys one DELPATTERN removes; callers loop while it reports more.
+    pub bulk_delete_max: usize,
+    // Other commands match in any case; CLOSE only does when this is set.
+    pub close_command_case_insensitive: bool,
+    // Largest frame a client may send or be sent, and the most a stored value
+    // may decompress to.
+    pub max_message_bytes: usize,
+    // Most bytes all connections together may hold for requests still being
+    // read or answered; readers wait for room past it.
+    pub max_inflight_bytes: Option<usize>,
+    // How long a client may go quiet partway through sending a frame before
+    // it's dropped.
+    pub frame_timeout_secs: u64,
+    // Most sub-commands one batch frame may carry.
+    pub max_batch_commands: usize,
+    // Splits a key's namespace from the rest for the NAMESPACE commands;
+    // None, written as "", turns them off.
+    #[serde(serialize_with = "serialize_separator", deserialize_with = "deserialize_separator")]
+    pub namespace_separator: Option<char>,
+    pub allow_flush: bool,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "RawStoreConfig")]
+pub struct StoreConfig {
+    pub slow_query_threshold_ms: Option<u64>,
+    pub slow_log_max_len: usize,
+    pub max_key_bytes: Option<usize>,
+    pub max_value_bytes: Option<usize>,
+    pub max_ttl_secs: Option<u64>,
+    // Moves each SET ... EX deadline by up to this share of the TTL either
+    // way, so keys cached together don't expire together.
+    pub ttl_jitter_percent: f64,
+    pub databases: usize,
+    // Commands replayed into the store at startup, one per line.
+    pub seed_path: Option<PathBuf>,
+    // Refuse to start if any seed line fails, instead of logging it.
+    pub seed_strict: bool,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: String,
+    pub format: LogFormat,
+    pub access_log_path: Option<PathBuf>,
+    // Rotates the access log once it reaches this size; unset never rotates.
+    pub access_log_max_bytes: Option<u64>,
+    pub access_log_keep: usize,
+    pub access_log_scope: AccessLogScope,
+}
+
+// The [logging] access log fields, gathered up once the log is enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessLogConfig {
+    pub path: PathBuf,
+    pub max_bytes: Option<u64>,
+    pub keep: usize,
+    pub scope: AccessLogScope,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogScope {
+    #[default]
+    Writes,
+    All,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplicationConfig {
+    pub replicate_from: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrometheusConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtelConfig {
+    pub enabled: bool,
+    pub exporter: OtelExporter,
+    pub endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtelExporter {
+    #[default]
+    Stdout,
+    Otlp,
+}
+
+// The string-typed spellings (`max_ttl = "24h"`, `max_value_size = "4MB"`) are accepted next to
+// the numeric ones while the numeric names are phased out.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawStoreConfig {
+    slow_query_threshold_ms: Option<u64>,
+    slow_query_threshold: Option<HumanDuration>,
+    slow_log_max_len: Option<usize>,
+    max_key_bytes: Option<usize>,
+    max_key_size: Option<ByteSize>,
+    max_value_bytes: Option<usize>,
+    max_value_size: Option<ByteSize>,
+    max_ttl_secs: Option<u64>,
+    max_ttl: Option<HumanDuration>,
+    ttl_jitter_percent: Option<f64>,
+    databases: Option<usize>,
+    seed_path: Option<PathBuf>,
+    seed_strict: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HumanDuration(pub Duration);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+pub enum ConfigSource {
+    // Every file that was read, in merge order: included files come before
+    // the file that included them, and later `--config` files after earlier
+    // ones.
+    Files(Vec<PathBuf>),
+    Defaults,
+}
+
+// Where the config paths came from, named in load errors so it's clear
+// which setting pointed at a bad file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLookup {
+    Flag,
+    Env,
+    Default,
+}
+
+impl fmt::Display for ConfigLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigLookup::Flag => write!(f, "{}", CONFIG_FLAG),
+            ConfigLookup::Env => write!(f, "{}", CONFIG_PATH_ENV),
+            ConfigLookup::Default => write!(f, "the default {}", DEFAULT_CONFIG_PATH),
+        }
+    }
+}
+
+impl ConfigSource {
+    pub fn chain(&self) -> &[PathBuf] {
+        match self {
+            ConfigSource::Files(files) => files,
+            ConfigSource::Defaults => &[],
+        }
+    }
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            structured_errors: false,
+            legacy_errors: false,
+            command_timeout_secs: None,
+            command_timeout_response: "command timed out".to_string(),
+            allow_debug: false,
+            allow_bulk_delete: true,
+            bulk_delete_max: 10_000,
+            close_command_case_insensitive: false,
+            max_message_bytes: 64 * 1024 * 1024,
+            max_inflight_bytes: None,
+            frame_timeout_secs: 30,
+            max_batch_commands: 1000,
+            namespace_separator: Some(':'),
+            allow_flush: true,
+        }
+    }
+}
+
+impl ProtocolConfig {
+    pub fn error_format(&self) -> ErrorFormat {
+        match (self.structured_errors, self.legacy_errors) {
+            (true, _) => ErrorFormat::Structured,
+            (false, true) => ErrorFormat::Legacy,
+            (false, false) => ErrorFormat::Coded,
+        }
+    }
+
+    // Older configs wrote the whole reply, prefix included.
+    pub fn command_timeout_message(&self) -> &str {
+        let response = &self.command_timeout_response;
+        response.strip_prefix("error:").unwrap_or(response)
+    }
+}
+
+impl StoreConfig {
+    // Settings that are allowed but probably a mistake.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if self.ttl_jitter_percent > TTL_JITTER_WARN_PERCENT {
+            warnings.push(format!(
+                "store.ttl_jitter_percent is {}; above {} TTLs can end up much shorter than requested",
+                self.ttl_jitter_percent, TTL_JITTER_WARN_PERCENT
+            ));
+        }
+        warnings
+    }
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            slow_query_threshold_ms: None,
+            slow_log_max_len: 128,
+            max_key_bytes: Some(1024),
+            max_value_bytes: None,
+            max_ttl_secs: Some(315_360_000),
+            ttl_jitter_percent: 0.0,
+            databases: 16,
+            seed_path: None,
+            seed_strict: false,
+        }
+    }
+}
+
+impl TryFrom<RawStoreConfig> for StoreConfig {
+    type Error = String;
+
+    fn try_from(raw: RawStoreConfig) -> Result<Self, Self::Error> {
+        let defaults = StoreConfig::default();
+
+        let slow_query_threshold = raw
+            .slow_query_threshold
+            .map(|d| d.as_millis("slow_query_threshold"))
+            .transpose()?;
+        let max_key_size = raw
+            .max_key_size
+            .map(|s| s.as_usize("max_key_size"))
+            .transpose()?;
+        let max_value_size = raw
+            .max_value_size
+            .map(|s| s.as_usize("max_value_size"))
+            .transpose()?;
+        let max_ttl = raw.max_ttl.map(|d| d.as_secs("max_ttl")).transpose()?;
+
+        Ok(Self {
+            slow_query_threshold_ms: merge_alias(
+                "slow_query_threshold_ms",
+                raw.slow_query_threshold_ms,
+                "slow_query_threshold",
+                slow_query_threshold,
+            )?
+            .or(defaults.slow_query_threshold_ms),
+            slow_log_max_len: raw.slow_log_max_len.unwrap_or(defaults.slow_log_max_len),
+            max_key_bytes: merge_alias("max_key_bytes", raw.max_key_bytes, "max_key_size", max_key_size)?
+                .or(defaults.max_key_bytes),
+            max_value_bytes: merge_alias(
+                "max_value_bytes",
+                raw.max_value_bytes,
+                "max_value_size",
+                max_value_size,
+            )?
+            .or(defaults.max_value_bytes),
+            max_ttl_secs: merge_alias("max_ttl_secs", raw.max_ttl_secs, "max_ttl", max_ttl)?
+                .or(defaults.max_ttl_secs),
+            ttl_jitter_percent: raw.ttl_jitter_percent.unwrap_or(defaults.ttl_jitter_percent),
+            databases: raw.databases.unwrap_or(defaults.databases),
+            seed_path: raw.seed_path,
+            seed_strict: raw.seed_strict.unwrap_or(defaults.seed_strict),
+        })
+    }
+}
+
+fn merge_alias<T: PartialEq + fmt::Display>(
+    name: &str,
+    value: Option<T>,
+    alias: &str,
+    alias_value: Option<T>,
+) -> Result<Option<T>, String> {
+    match (value, alias_value) {
+        (Some(value), Some(alias_value)) if value != alias_value => Err(format!(
+            "`{}` ({}) and `{}` ({}) are both set with different values; remove `{}`",
+            name, value, alias, alias_value, name
+        )),
+        (value, alias_value) => Ok(value.or(alias_value)),
+    }
+}
+
+impl HumanDuration {
+    fn as_millis(&self, field: &str) -> Result<u64, String> {
+        if !self.0.subsec_nanos().is_multiple_of(NANOS_PER_MILLI as u32) {
+            return Err(format!("`{}` must be a whole number of milliseconds", field));
+        }
+
+        u64::try_from(self.0.as_millis()).map_err(|_| format!("`{}` is too large", field))
+    }
+
+    fn as_secs(&self, field: &str) -> Result<u64, String> {
+        if self.0.subsec_nanos() != 0 {
+            return Err(format!("`{}` must be a whole number of seconds", field));
+        }
+
+        Ok(self.0.as_secs())
+    }
+}
+
+impl ByteSize {
+    fn as_usize(&self, field: &str) -> Result<usize, String> {
+        usize::try_from(self.0).map_err(|_| format!("`{}` is too large", field))
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let nanos = parse_scaled(s, DURATION_UNITS, None)?;
+        let secs = u64::try_from(nanos / NANOS_PER_SEC).map_err(|_| format!("duration '{}' is too large", s))?;
+
+        Ok(HumanDuration(Duration::new(secs, (nanos % NANOS_PER_SEC) as u32)))
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = parse_scaled(s, SIZE_UNITS, Some(1))?;
+
+        u64::try_from(bytes)
+            .map(ByteSize)
+            .map_err(|_| format!("size '{}' is too large", s))
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+fn serialize_separator<S: Serializer>(separator: &Option<char>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&separator.map(String::from).unwrap_or_default())
+}
+
+fn deserialize_separator<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<char>, D::Error> {
+    let separator = String::deserialize(deserializer)?;
+    let mut chars = separator.chars();
+
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(None),
+        (Some(separator), None) => Ok(Some(separator)),
+        _ => Err(de::Error::custom(
+            "namespace_separator must be one character, or \"\" to turn namespaces off",
+        )),
+    }
+}
+
+// Parses "<number><unit>" where the number may have a fractional part, as in
+// "1.5s". The result is in the smallest unit of `units` and has to come out
+// whole, so "1.5B" is an error. `bare` is the scale used when no unit is
+// given; without one, only "0" may omit its unit.
+fn parse_scaled(input: &str, units: &[(&str, u128)], bare: Option<u128>) -> Result<u128, String> {
+    let s = input.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = (&s[..split], s[split..].trim());
+
+    let (whole, fraction) = match number.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (number, ""),
+    };
+
+    if whole.is_empty() || fraction.contains('.') || (number.contains('.') && fraction.is_empty()) {
+        return Err(format!("'{}' is not a valid number", input));
+    }
+
+    let scale = if unit.is_empty() {
+        match bare {
+            Some(scale) => scale,
+            None if whole.bytes().all(|b| b == b'0') && fraction.bytes().all(|b| b == b'0') => 1,
+            None => return Err(format!("'{}' is missing a unit", input)),
+        }
+    } else {
+        units
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+            .map(|(_, scale)| *scale)
+            .ok_or_else(|| format!("'{}' has unknown unit '{}'", input, unit))?
+    };
+
+    let too_large = || format!("'{}' is too large", input);
+
+    let whole: u128 = whole.parse().map_err(|_| too_large())?;
+    let mut total = whole.checked_mul(scale).ok_or_else(too_large)?;
+
+    if !fraction.is_empty() {
+        let digits = u32::try_from(fraction.len()).map_err(|_| too_large())?;
+        let divisor = 10u128.checked_pow(digits).ok_or_else(too_large)?;
+        let fraction: u128 = fraction.parse().map_err(|_| too_large())?;
+        let scaled = fraction.checked_mul(scale).ok_or_else(too_large)?;
+
+        if !scaled.is_multiple_of(divisor) {
+            return Err(format!("'{}' is more precise than the smallest unit", input));
+        }
+
+        total = total.checked_add(scaled / divisor).ok_or_else(too_large)?;
+    }
+
+    Ok(total)
+}
+
+impl ServerConfig {
+    pub fn listen_addresses(&self) -> Result<Vec<SocketAddr>, String> {
+        let addresses = if self.listeners.is_empty() {
+            vec![format!(
+                "{}:{}",
+                self.host.as_deref().unwrap_or(DEFAULT_HOST),
+                self.port.unwrap_or(DEFAULT_PORT)
+            )]
+        } else {
+            self.listeners.clone()
+        };
+
+        addresses
+            .iter()
+            .map(|address| {
+                address
+                    .parse()
+                    .map_err(|_| format!("server listener '{}' is not a valid address", address))
+            })
+            .collect()
+    }
+}
+
+impl ReplicationConfig {
+    pub fn primary_address(&self) -> Result<Option<SocketAddr>, String> {
+        self.replicate_from
+            .as_ref()
+            .map(|address| {
+                address
+                    .parse()
+                    .map_err(|_| format!("replication.replicate_from '{}' is not a valid address", address))
+            })
+            .transpose()
+    }
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: DEFAULT_HOST.to_string(),
+            port: 9100,
+        }
+    }
+}
+
+impl PrometheusConfig {
+    pub fn listen_address(&self) -> Result<Option<SocketAddr>, String> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        format!("{}:{}", self.host, self.port)
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("prometheus address '{}:{}' is not valid", self.host, self.port))
+    }
+}
+
+impl OtelConfig {
+    pub fn otlp_endpoint(&self) -> &str {
+        self.endpoint.as_deref().unwrap_or(DEFAULT_OTLP_ENDPOINT)
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            access_log_path: None,
+            access_log_max_bytes: None,
+            access_log_keep: 5,
+            access_log_scope: AccessLogScope::Writes,
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn access_log(&self) -> Option<AccessLogConfig> {
+        Some(AccessLogConfig {
+            path: self.access_log_path.clone()?,
+            max_bytes: self.access_log_max_bytes,
+            keep: self.access_log_keep,
+            scope: self.access_log_scope,
+        })
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Files(files) => {
+                let files: Vec<String> = files.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "{}", files.join(", "))
+            }
+            ConfigSource::Defaults => write!(f, "defaults"),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<(Config, ConfigSource), Box<dyn Error>> {
+        let (paths, lookup) = Config::lookup();
+        Config::load_via(&paths, lookup)
+    }
+
+    pub fn load_via(paths: &[PathBuf], lookup: ConfigLookup) -> Result<(Config, ConfigSource), Box<dyn Error>> {
+        Config::load_from_paths(paths).map_err(|e| format!("{} (path from {})", e, lookup).into())
+    }
+
+    // The last `--config` file, which is the one CONFIG REWRITE writes to.
+    pub fn path() -> PathBuf {
+        Config::paths().pop().unwrap_or_else(Config::default_path)
+    }
+
+    pub fn paths() -> Vec<PathBuf> {
+        Config::lookup().0
+    }
+
+    // Every `--config <path>` (or `--config=<path>`) in order, or else the
+    // file named by KEYZ_CONFIG or the default.
+    fn lookup() -> (Vec<PathBuf>, ConfigLookup) {
+        let mut paths = Vec::new();
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            if arg == CONFIG_FLAG {
+                paths.extend(args.next().map(PathBuf::from));
+            } else if let Some(path) = arg.strip_prefix("--config=") {
+                paths.push(PathBuf::from(path));
+            }
+        }
+
+        match paths.is_empty() {
+            false => (paths, ConfigLookup::Flag),
+            true if env::var_os(CONFIG_PATH_ENV).is_some() => (vec![Config::default_path()], ConfigLookup::Env),
+            true => (vec![Config::default_path()], ConfigLookup::Default),
+        }
+    }
+
+    fn default_path() -> PathBuf {
+        PathBuf::from(env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string()))
+    }
+
+    pub fn load_from(path: &Path) -> Result<(Config, ConfigSource), Box<dyn Error>> {
+        Config::load_from_paths(&[path.to_path_buf()])
+    }
+
+    // Reads each file with its includes and merges them field by field, later
+    // files overriding earlier ones, before anything is validated. A missing
+    // file is skipped, and if none exist the defaults are used; a missing
+    // include is an error.
+    pub fn load_from_paths(paths: &[PathBuf]) -> Result<(Config, ConfigSource), Box<dyn Error>> {
+        let mut merged = toml::Table::new();
+        let mut files = Vec::new();
+
+        for path in paths {
+            if let Some(table) = read_layer(path, &mut Vec::new(), &mut files)? {
+                merge(&mut merged, table);
+            }
+        }
+
+        if files.is_empty() {
+            return Ok((Config::default(), ConfigSource::Defaults));
+        }
+        let source = ConfigSource::Files(files);
+
+        let lenient = env::var(CONFIG_LENIENT_ENV).is_ok_and(|v| v == "1")
+            || env::args().any(|arg| arg == CONFIG_LENIENT_FLAG);
+
+        let config = Config::from_table(merged, lenient)
+            .map_err(|e| format!("invalid config {}: {}", source, e))?;
+
+        Ok((config, source))
+    }
+
+    // Unset optional fields are written as comments with an example value,
+    // and store sizes and durations in their numeric form. Parsing the
+    // result gives back an equal config.
+    pub fn to_toml_string(&self) -> Result<String, toml::ser::Error> {
+        let sections = [
+            toml_section("server", &self.server)?,
+            toml_section("protocol", &self.protocol)?,
+            toml_section("store", &self.store)?,
+            toml_section("logging", &self.logging)?,
+            toml_section("replication", &self.replication)?,
+            toml_section("prometheus", &self.prometheus)?,
+            toml_section("otel", &self.otel)?,
+        ];
+
+        Ok(sections.join("\n"))
+    }
+
+    // Blank input, or only comments, is the defaults, and goes through the
+    // same validation as anything else.
+    pub fn from_toml_str(contents: &str) -> Result<Config, Box<dyn Error>> {
+        let contents = strip_bom(contents);
+
+        let mut config: Config = match contents.trim().is_empty() {
+            true => Config::default(),
+            false => match toml::from_str(contents) {
+                Ok(config) => config,
+                Err(e) => {
+                    let table: toml::Table = toml::from_str(contents)?;
+                    return Err(match unknown_keys(&table).first() {
+                        Some(unknown) => unknown.to_string().into(),
+                        None => e.into(),
+                    });
+                }
+            },
+        };
+        config.validate()?;
+        config.warnings = config.store.warnings();
+
+        Ok(config)
+    }
+
+    // Drops unknown keys instead of rejecting them, leaving a warning for each
+    // in `warnings` for the caller to log once logging is up.
+    pub fn from_toml_str_lenient(contents: &str) -> Result<Config, Box<dyn Error>> {
+        Config::from_table(toml::from_str(strip_bom(contents))?, true)
+    }
+
+    fn from_table(mut table: toml::Table, lenient: bool) -> Result<Config, Box<dyn Error>> {
+        let unknown = unknown_keys(&table);
+
+        if !lenient {
+            if let Some(unknown) = unknown.first() {
+                return Err(unknown.to_string().into());
+            }
+        }
+
+        for key in &unknown {
+            match key.section {
+                Some(section) => {
+                    if let Some(toml::Value::Table(section)) = table.get_mut(section) {
+                        section.remove(&key.key);
+                    }
+                }
+                None => {
+                    table.remove(&key.key);
+                }
+            }
+        }
+
+        let mut config: Config = toml::Value::Table(table).try_into()?;
+        config.validate()?;
+        config.warnings = unknown
+            .iter()
+            .map(|key| format!("ignoring {}", key))
+            .chain(config.store.warnings())
+            .collect();
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !self.server.listeners.is_empty()
+            && (self.server.host.is_some() || self.server.port.is_some())
+        {
+            return Err("server.listeners cannot be combined with server.host or server.port".into());
+        }
+
+        self.server.listen_addresses()?;
+
+        if self.server.tcp_keepalive_secs == Some(0) {
+            return Err("server.tcp_keepalive_secs must be greater than 0".into());
+        }
+
+        if self.server.tcp_recv_buffer_bytes == Some(0) || self.server.tcp_send_buffer_bytes == Some(0) {
+            return Err("server.tcp_recv_buffer_bytes and tcp_send_buffer_bytes must be greater than 0".into());
+        }
+        self.replication.primary_address()?;
+        self.prometheus.listen_address()?;
+
+        if self.protocol.command_timeout_secs == Some(0) {
+            return Err("protocol.command_timeout_secs must be greater than 0".into());
+        }
+
+        if self.protocol.bulk_delete_max == 0 {
+            return Err("protocol.bulk_delete_max must be greater than 0".into());
+        }
+
+        if self.protocol.max_message_bytes == 0 {
+            return Err("protocol.max_message_bytes must be greater than 0".into());
+        }
+
+        if self.protocol.max_inflight_bytes.is_some_and(|limit| limit < self.protocol.max_message_bytes) {
+            return Err("protocol.max_inflight_bytes cannot be smaller than protocol.max_message_bytes".into());
+        }
+
+        if self.protocol.frame_timeout_secs == 0 {
+            return Err("protocol.frame_timeout_secs must be greater than 0".into());
+        }
+
+        if self.protocol.max_batch_commands == 0 {
+            return Err("protocol.max_batch_commands must be greater than 0".into());
+        }
+
+        if self.store.max_value_bytes.is_some_and(|limit| limit > self.protocol.max_message_bytes) {
+            return Err("store.max_value_bytes cannot be larger than protocol.max_message_bytes".into());
+        }
+
+        if self.store.slow_log_max_len == 0 {
+            return Err("store.slow_log_max_len must be greater than 0".into());
+        }
+
+        if self.store.max_key_bytes == Some(0) || self.store.max_value_bytes == Some(0) {
+            return Err("store.max_key_bytes and store.max_value_bytes must be greater than 0".into());
+        }
+
+        if !(0.0..=MAX_TTL_JITTER_PERCENT).contains(&self.store.ttl_jitter_percent) {
+            return Err(format!("store.ttl_jitter_percent must be between 0 and {}", MAX_TTL_JITTER_PERCENT).into());
+        }
+
+        if !(1..=MAX_DATABASES).contains(&self.store.databases) {
+            return Err(format!("store.databases must be between 1 and {}", MAX_DATABASES).into());
+        }
+
+        if self.logging.access_log_max_bytes == Some(0) {
+            return Err("logging.access_log_max_bytes must be greater than 0".into());
+        }
+
+        if EnvFilter::try_new(&self.logging.level).is_err() {
+            return Err(format!("logging.level '{}' is not a valid filter", self.logging.level).into());
+        }
+
+        Ok(())
+    }
+}
+
+fn toml_section(name: &str, fields: &impl Serialize) -> Result<String, toml::ser::Error> {
+    let set = toml::Table::try_from(fields)?;
+    let mut section = format!("[{}]\n{}", name, toml::to_string(fields)?);
+
+    for (_, key, example) in OPTIONAL_KEYS
+        .iter()
+        .filter(|(section, key, _)| *section == name && !set.contains_key(*key))
+    {
+        section.push_str(&format!("# {} = {}\n", key, example));
+    }
+
+    Ok(section)
+}
+
+// Reads one file and, before it, the files it includes. `stack` holds the
+// files being read, to catch a file that ends up including itself; `files`
+// collects every file read, in merge order.
+fn read_layer(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<Option<toml::Table>, Box<dyn Error>> {
+    if path.is_dir() {
+        return Err(format!("failed to read {}: it is a directory, not a file", path.display()).into());
+    }
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound && stack.is_empty() => return Ok(None),
+        Err(e) if e.kind() == ErrorKind::InvalidData => {
+            return Err(format!("failed to read {}: it is not UTF-8 text", path.display()).into())
+        }
+        Err(e) => return Err(format!("failed to read {}: {}", path.display(), e).into()),
+    };
+    let contents = strip_bom(&contents);
+
+    let canonical = fs::canonicalize(path)?;
+    if stack.contains(&canonical) {
+        let chain: Vec<String> = stack
+            .iter()
+            .chain([&canonical])
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(format!("config include cycle: {}", chain.join(" -> ")).into());
+    }
+
+    let mut table: toml::Table =
+        toml::from_str(contents).map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+    let includes = includes(&mut table).map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+
+    stack.push(canonical);
+    let mut merged = toml::Table::new();
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    for include in includes {
+        if let Some(base) = read_layer(&dir.join(include), stack, files)? {
+            merge(&mut merged, base);
+        }
+    }
+    stack.pop();
+
+    merge(&mut merged, table);
+    files.push(path.to_path_buf());
+
+    Ok(Some(merged))
+}
+
+// Editors on Windows may save a byte order mark, which TOML doesn't allow.
+fn strip_bom(contents: &str) -> &str {
+    contents.strip_prefix('\u{feff}').unwrap_or(contents)
+}
+
+// Removes the top-level `include` key, which is a path or a list of them.
+fn includes(table: &mut toml::Table) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    match table.remove(INCLUDE_KEY) {
+        None => Ok(Vec::new()),
+        Some(toml::Value::String(path)) => Ok(vec![PathBuf::from(path)]),
+        Some(toml::Value::Array(paths)) => paths
+            .into_iter()
+            .map(|path| match path {
+                toml::Value::String(path) => Ok(PathBuf::from(path)),
+                _ => Err("include must be a list of paths".into()),
+            })
+            .collect(),
+        Some(_) => Err("include must be a list of paths".into()),
+    }
+}
+
+// Tables merge key by key; anything else, arrays included, is replaced.
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+struct UnknownKey {
+    section: Option<&'static str>,
+    key: String,
+    suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.section, self.suggestion) {
+            (Some(section), Some(suggestion)) => write!(
+                f,
+                "unknown key `{}` in [{}]; did you mean `{}`?",
+                self.key, section, suggestion
+            ),
+            (Some(section), None) => write!(f, "unknown key `{}` in [{}]", self.key, section),
+            (None, Some(suggestion)) => write!(
+                f,
+                "unknown section [{}]; did you mean [{}]?",
+                self.key, suggestion
+            ),
+            (None, None) => write!(f, "unknown section [{}]", self.key),
+        }
+    }
+}
+
+fn unknown_keys(table: &toml::Table) -> Vec<UnknownKey> {
+    let mut unknown = Vec::new();
+
+    for (name, value) in table {
+        let known = KNOWN_KEYS.iter().find(|(section, _)| section == name);
+
+        let (section, fields) = match known {
+            Some((section, fields)) => (*section, *fields),
+            None => {
+                unknown.push(UnknownKey {
+                    section: None,
+                    key: name.clone(),
+                    suggestion: closest(name, KNOWN_KEYS.iter().map(|(section, _)| *section)),
+                });
+                continue;
+            }
+        };
+
+        if let toml::Value::Table(entries) = value {
+            for key in entries.keys() {
+                if !fields.contains(&key.as_str()) {
+                    unknown.push(UnknownKey {
+                        section: Some(section),
+                        key: key.clone(),
+                        suggestion: closest(key, fields.iter().copied()),
+                    });
+                }
+            }
+        }
+    }
+
+    unknown
+}
+
+// Only suggests names within a third of the typo's length (and at least two
+// edits), so unrelated keys don't get a misleading "did you mean".
+fn closest(name: &str, candidates: impl Iterator<Item = &'static str>) -> Option<&'static str> {
+    let max_distance = (name.chars().count() / 3).max(2);
+
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A letter dropped from the middle of any known key still finds it.
+    #[test]
+    fn every_known_key_is_suggested_for_a_dropped_letter() {
+        for (section, fields) in KNOWN_KEYS {
+            for field in *fields {
+                let mut typo = field.to_string();
+                typo.remove(typo.len() / 2);
+                let table: toml::Table = toml::from_str(&format!("[{}]\n{} = 1\n", section, typo)).unwrap();
+
+                let unknown = unknown_keys(&table);
+                assert_eq!(unknown.len(), 1, "{}.{}", section, typo);
+                assert_eq!(unknown[0].suggestion, Some(*field), "{}.{}", section, typo);
+            }
+        }
+    }
+
+    #[test]
+    fn durations_parse_with_every_unit_in_any_case() {
+        let cases = [
+            ("0", Duration::ZERO),
+            ("0.0", Duration::ZERO),
+            ("0s", Duration::ZERO),
+            ("1.5s", Duration::from_millis(1500)),
+            ("1.5S", Duration::from_millis(1500)),
+            ("250ms", Duration::from_millis(250)),
+            ("250MS", Duration::from_millis(250)),
+            ("1.5ms", Duration::from_micros(1500)),
+            ("2m", Duration::from_secs(120)),
+            ("2M", Duration::from_secs(120)),
+            ("0.5m", Duration::from_secs(30)),
+            ("24h", Duration::from_secs(86_400)),
+            ("24H", Duration::from_secs(86_400)),
+            (" 10 s ", Duration::from_secs(10)),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<HumanDuration>(), Ok(HumanDuration(expected)), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn bad_durations_are_refused() {
+        let cases = [
+            ("", "not a valid number"),
+            ("s", "not a valid number"),
+            ("-1s", "not a valid number"),
+            ("1.s", "not a valid number"),
+            ("1.2.3s", "not a valid number"),
+            ("10", "missing a unit"),
+            ("1x", "unknown unit"),
+            ("1.0000000001s", "more precise than the smallest unit"),
+            ("18446744073709551616s", "too large"),
+            ("999999999999999999999999999999999999999h", "too large"),
+        ];
+
+        for (input, error) in cases {
+            let result = input.parse::<HumanDuration>();
+            assert!(matches!(&result, Err(e) if e.contains(error)), "{:?}: {:?}", input, result);
+        }
+    }
+
+    #[test]
+    fn sizes_parse_with_every_unit_in_any_case() {
+        let cases = [
+            ("0", 0),
+            ("512", 512),
+            ("10b", 10),
+            ("10B", 10),
+            ("1kb", 1024),
+            ("1KB", 1024),
+            ("1KiB", 1024),
+            ("1.5kb", 1536),
+            ("4MB", 4_194_304),
+            ("4mib", 4_194_304),
+            ("0.5MB", 524_288),
+            ("2GB", 2 << 30),
+            ("1GiB", 1 << 30),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<ByteSize>(), Ok(ByteSize(expected)), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn bad_sizes_are_refused() {
+        let cases = [
+            ("", "not a valid number"),
+            ("MB", "not a valid number"),
+            ("-1MB", "not a valid number"),
+            ("1.5B", "more precise than the smallest unit"),
+            ("1TB", "unknown unit"),
+            ("17179869184GB", "too large"),
+            ("99999999999999999999999999999999999999999", "too large"),
+        ];
+
+        for (input, error) in cases {
+            let result = input.parse::<ByteSize>();
+            assert!(matches!(&result, Err(e) if e.contains(error)), "{:?}: {:?}", input, result);
+        }
+    }
+
+    #[test]
+    fn a_field_and_its_alias_must_agree() {
+        let config = Config::from_toml_str("[store]\nmax_ttl_secs = 120\nmax_ttl = \"2m\"\n").unwrap();
+        assert_eq!(config.store.max_ttl_secs, Some(120));
+
+        let error = Config::from_toml_str("[store]\nmax_ttl_secs = 60\nmax_ttl = \"2m\"\n").unwrap_err().to_string();
+        assert!(
+            error.contains("`max_ttl_secs` (60) and `max_ttl` (120) are both set with different values"),
+            "{}",
+            error
+        );
+
+        let error = Config::from_toml_str("[store]\nmax_value_bytes = 1024\nmax_value_size = \"4MB\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("`max_value_bytes` (1024) and `max_value_size` (4194304)"), "{}", error);
+
+        let error = Config::from_toml_str("[store]\nmax_ttl = \"1.5s\"\n").unwrap_err().to_string();
+        assert!(error.contains("`max_ttl` must be a whole number of seconds"), "{}", error);
+    }
+}
@@ -0,0 +1,11 @@
+#[cfg(feature = "client")]
+pub mod client;
+pub mod config;
+pub mod logging;
+pub mod reload;
+pub mod server;
+
+pub use config::{Config, ProtocolConfig, StoreConfig};
+pub use server::error::{ErrorCode, KeyzError};
+pub use server::instance::Server;
+pub use server::store::Store;
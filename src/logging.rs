@@ -0,0 +1,95 @@
+use std::error::Error;
+
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+
+use crate::config::{LogFormat, LoggingConfig, OtelConfig, OtelExporter};
+
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    tracer_provider: Option<TracerProvider>,
+}
+
+pub fn init(config: &LoggingConfig, otel: &OtelConfig) -> Result<LogHandle, Box<dyn Error>> {
+    let filter = EnvFilter::try_new(&config.level)?;
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let (text, json) = match config.format {
+        LogFormat::Text => (Some(fmt::layer()), None),
+        LogFormat::Json => (None, Some(fmt::layer().json())),
+    };
+
+    let tracer_provider = match otel.enabled {
+        true => Some(tracer_provider(otel)?),
+        false => None,
+    };
+    let otel = tracer_provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("keyz")));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .with(otel)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+
+    Ok(LogHandle {
+        filter: handle,
+        tracer_provider,
+    })
+}
+
+fn tracer_provider(config: &OtelConfig) -> Result<TracerProvider, Box<dyn Error>> {
+    let builder = TracerProvider::builder();
+
+    let builder = match config.exporter {
+        OtelExporter::Stdout => {
+            builder.with_batch_exporter(opentelemetry_stdout::SpanExporter::default(), runtime::Tokio)
+        }
+        OtelExporter::Otlp => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(config.otlp_endpoint())
+                .build()?;
+            builder.with_batch_exporter(exporter, runtime::Tokio)
+        }
+    };
+
+    Ok(builder.build())
+}
+
+// None unless the current span is being exported through OpenTelemetry.
+pub fn current_trace_id() -> Option<String> {
+    let context = Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+impl LogHandle {
+    pub fn set_level(&self, level: &str) -> Result<(), Box<dyn Error>> {
+        let filter = EnvFilter::try_new(level)?;
+        self.filter.reload(filter)?;
+
+        Ok(())
+    }
+
+    // Flushes spans still queued in the batch exporter.
+    pub fn shutdown(&self) {
+        if let Some(provider) = &self.tracer_provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("failed to flush traces: {}", e);
+            }
+        }
+    }
+}
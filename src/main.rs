@@ -1,9 +1,124 @@
-mod server;
+use std::sync::Arc;
+use std::{env, process};
+
+use tracing::{error, info, warn};
+
+use keyz::config::{Config, LoggingConfig, OtelConfig};
+use keyz::reload::Reloader;
+use keyz::{logging, Server};
+
+// Prints the effective config as TOML and exits, without starting the server.
+const CHECK_CONFIG_FLAG: &str = "--check-config";
 
 #[tokio::main]
 async fn main() {
-    let socket_addr = server::helpers::socket_address_from_string_ip("127.0.0.1:7667".to_string()).expect("Invalid IP address");
-    let listener = server::helpers::create_listener(socket_addr).await.unwrap();
+    if env::args().any(|arg| arg == CHECK_CONFIG_FLAG) {
+        process::exit(check_config());
+    }
+
+    let (config, source) = match Config::load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            let _ = logging::init(&LoggingConfig::default(), &OtelConfig::default());
+            error!(error = %e, "failed to load config");
+            process::exit(1);
+        }
+    };
+
+    let log_handle = match logging::init(&config.logging, &config.otel) {
+        Ok(handle) => handle,
+        Err(e) => {
+            eprintln!("failed to initialise logging: {}", e);
+            process::exit(1);
+        }
+    };
+
+    info!(source = %source, "config loaded");
+
+    for warning in &config.warnings {
+        warn!(source = %source, "{}", warning);
+    }
+
+    let server = match Server::bind(&config).await {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            error!(error = %e, "failed to start server");
+            process::exit(1);
+        }
+    };
+
+    server.store().set_config_path(Config::path());
+
+    for addr in server.local_addrs() {
+        info!(addr = %addr, "listening");
+    }
 
-    server::init::start(&listener).await;
+    let reloader = Reloader::new(
+        Config::paths(),
+        &config,
+        server.store().clone(),
+        server.protocol(),
+        Some(log_handle.clone()),
+    );
+    reload_on_hangup(reloader);
+
+    let handle = server.clone();
+    tokio::spawn(async move {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => handle.shutdown(),
+            Err(e) => error!(error = %e, "failed to listen for shutdown signal"),
+        }
+    });
+
+    server.run().await;
+    log_handle.shutdown();
+}
+
+fn check_config() -> i32 {
+    let (config, source) = match Config::load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    match config.to_toml_string() {
+        Ok(toml) => {
+            println!("# effective config from {}", source);
+            for warning in &config.warnings {
+                println!("# {}", warning);
+            }
+            print!("{}", toml);
+            0
+        }
+        Err(e) => {
+            eprintln!("failed to serialize config: {}", e);
+            1
+        }
+    }
 }
+
+#[cfg(unix)]
+fn reload_on_hangup(reloader: Reloader) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!(error = %e, "failed to install SIGHUP handler, config reload disabled");
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if let Err(e) = reloader.reload() {
+                error!(error = %e, "config reload failed, keeping running configuration");
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn reload_on_hangup(_reloader: Reloader) {}
@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use tracing::{info, warn};
+
+use crate::config::{AccessLogConfig, Config, LogFormat, OtelConfig, ProtocolConfig};
+use crate::logging::LogHandle;
+use crate::server::store::Store;
+
+pub struct Reloader {
+    paths: Vec<PathBuf>,
+    store: Store,
+    protocol: Arc<ArcSwap<ProtocolConfig>>,
+    logging: Option<LogHandle>,
+    // Only read at startup, so a reload that changes them keeps these values.
+    listen_addresses: Vec<SocketAddr>,
+    replicate_from: Option<SocketAddr>,
+    prometheus: Option<SocketAddr>,
+    otel: OtelConfig,
+    slow_log_max_len: usize,
+    databases: usize,
+    max_inflight_bytes: Option<usize>,
+    log_format: LogFormat,
+    access_log: Option<AccessLogConfig>,
+}
+
+impl Reloader {
+    pub fn new(
+        paths: Vec<PathBuf>,
+        config: &Config,
+        store: Store,
+        protocol: Arc<ArcSwap<ProtocolConfig>>,
+        logging: Option<LogHandle>,
+    ) -> Self {
+        Self {
+            paths,
+            store,
+            protocol,
+            logging,
+            listen_addresses: config.server.listen_addresses().unwrap_or_default(),
+            replicate_from: config.replication.primary_address().unwrap_or_default(),
+            prometheus: config.prometheus.listen_address().unwrap_or_default(),
+            otel: config.otel.clone(),
+            slow_log_max_len: config.store.slow_log_max_len,
+            databases: config.store.databases,
+            max_inflight_bytes: config.protocol.max_inflight_bytes,
+            log_format: config.logging.format,
+            access_log: config.logging.access_log(),
+        }
+    }
+
+    // Re-reads and validates every file before touching anything, so a
+    // broken config leaves the running one in place.
+    pub fn reload(&self) -> Result<(), Box<dyn Error>> {
+        let (config, source) = Config::load_from_paths(&self.paths)?;
+
+        for warning in &config.warnings {
+            warn!(source = %source, "{}", warning);
+        }
+
+        let listen_addresses = config.server.listen_addresses()?;
+        if listen_addresses != self.listen_addresses {
+            warn!(
+                current = ?self.listen_addresses,
+                requested = ?listen_addresses,
+                "server listeners cannot change without a restart, keeping current addresses"
+            );
+        }
+
+        let replicate_from = config.replication.primary_address()?;
+        if replicate_from != self.replicate_from {
+            warn!(
+                current = ?self.replicate_from,
+                requested = ?replicate_from,
+                "replication.replicate_from cannot change without a restart, keeping current primary"
+            );
+        }
+
+        let prometheus = config.prometheus.listen_address()?;
+        if prometheus != self.prometheus {
+            warn!(
+                current = ?self.prometheus,
+                requested = ?prometheus,
+                "prometheus endpoint cannot change without a restart, keeping current address"
+            );
+        }
+
+        if config.otel != self.otel {
+            warn!(
+                current = ?self.otel,
+                requested = ?config.otel,
+                "otel settings cannot change without a restart, keeping current exporter"
+            );
+        }
+
+        if config.store.slow_log_max_len != self.slow_log_max_len {
+            warn!(
+                current = self.slow_log_max_len,
+                requested = config.store.slow_log_max_len,
+                "store.slow_log_max_len cannot change without a restart, keeping current value"
+            );
+        }
+
+        if config.store.databases != self.databases {
+            warn!(
+                current = self.databases,
+                requested = config.store.databases,
+                "store.databases cannot change without a restart, keeping current value"
+            );
+        }
+
+        if config.protocol.max_inflight_bytes != self.max_inflight_bytes {
+            warn!(
+                current = ?self.max_inflight_bytes,
+                requested = ?config.protocol.max_inflight_bytes,
+                "protocol.max_inflight_bytes cannot change without a restart, keeping current value"
+            );
+        }
+
+        if config.logging.format != self.log_format {
+            warn!(
+                current = ?self.log_format,
+                requested = ?config.logging.format,
+                "logging.format cannot change without a restart, keeping current value"
+            );
+        }
+
+        let access_log = config.logging.access_log();
+        if access_log != self.access_log {
+            warn!(
+                current = ?self.access_log,
+                requested = ?access_log,
+                "access log settings cannot change without a restart, keeping current log"
+            );
+        }
+
+        if let Some(logging) = &self.logging {
+            logging.set_level(&config.logging.level)?;
+        }
+
+        self.store.apply_config(&config.store);
+        self.store.set_read_only(config.server.read_only);
+        self.store.set_max_payload_bytes(config.protocol.max_message_bytes);
+        self.protocol.store(Arc::new(config.protocol));
+
+        info!(source = %source, "config reloaded");
+
+        Ok(())
+    }
+}
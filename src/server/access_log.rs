@@ -0,0 +1,163 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::config::{AccessLogConfig, AccessLogScope};
+
+const BUFFER_ENTRIES: usize = 4096;
+
+// One line of the access log. Values are never logged, only the key.
+#[derive(Debug, Serialize)]
+pub struct AccessEntry {
+    pub ts_ms: u64,
+    pub peer: String,
+    pub db: usize,
+    pub command: String,
+    pub key: String,
+    pub duration_us: u64,
+    pub status: &'static str,
+}
+
+// Entries go to a writer thread through a bounded channel so a slow disk
+// never holds up a connection. When the channel is full the entry is dropped
+// and counted instead.
+pub struct AccessLog {
+    sender: SyncSender<AccessEntry>,
+    scope: AccessLogScope,
+    dropped: AtomicU64,
+}
+
+impl AccessLog {
+    // Opens (or creates) the file straight away so a bad path fails at
+    // startup rather than in the writer.
+    pub fn open(config: &AccessLogConfig) -> io::Result<Self> {
+        let writer = Writer::open(config)?;
+        let (sender, receiver) = mpsc::sync_channel(BUFFER_ENTRIES);
+
+        thread::Builder::new()
+            .name("keyz-access-log".to_string())
+            .spawn(move || writer.run(receiver))?;
+
+        Ok(Self {
+            sender,
+            scope: config.scope,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn scope(&self) -> AccessLogScope {
+        self.scope
+    }
+
+    pub fn record(&self, entry: AccessEntry) {
+        if self.sender.try_send(entry).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+struct Writer {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    keep: usize,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+impl Writer {
+    fn open(config: &AccessLogConfig) -> io::Result<Self> {
+        let file = append(&config.path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: config.path.clone(),
+            max_bytes: config.max_bytes,
+            keep: config.keep,
+            file: BufWriter::new(file),
+            size,
+        })
+    }
+
+    // Flushes whenever the channel runs dry, so lines reach the file promptly
+    // without a write call per entry under load. Returns once every sender is
+    // gone.
+    fn run(mut self, entries: Receiver<AccessEntry>) {
+        while let Ok(entry) = entries.recv() {
+            self.write(&entry);
+            while let Ok(entry) = entries.try_recv() {
+                self.write(&entry);
+            }
+
+            if let Err(e) = self.file.flush() {
+                warn!(error = %e, "failed to flush access log");
+            }
+        }
+    }
+
+    fn write(&mut self, entry: &AccessEntry) {
+        if let Err(e) = self.try_write(entry) {
+            warn!(error = %e, "failed to write access log");
+        }
+    }
+
+    fn try_write(&mut self, entry: &AccessEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        if let Some(max_bytes) = self.max_bytes {
+            if self.size > 0 && self.size + line.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    // Shifts `path` to `path.1`, `path.1` to `path.2` and so on, dropping
+    // whatever was in `path.<keep>`. With keep = 0 the log just starts over.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let file = match self.keep {
+            0 => File::create(&self.path)?,
+            keep => {
+                let _ = fs::remove_file(rotated(&self.path, keep));
+                for n in (1..keep).rev() {
+                    let from = rotated(&self.path, n);
+                    if from.exists() {
+                        fs::rename(from, rotated(&self.path, n + 1))?;
+                    }
+                }
+                fs::rename(&self.path, rotated(&self.path, 1))?;
+
+                append(&self.path)?
+            }
+        };
+
+        self.file = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
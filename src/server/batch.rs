@@ -0,0 +1,90 @@
+use crate::server::error::KeyzError;
+
+// A batch frame carries several commands and is answered by one frame
+// carrying as many responses, in the same order:
+//
+//     *<count>\n<len>\n<command><len>\n<command>...
+//
+// Lengths are decimal byte counts. Frames are read as text, so a binary
+// length wouldn't survive the trip. No command starts with '*', so a batch
+// can't be mistaken for one.
+pub const MARKER: char = '*';
+
+pub fn is_batch(frame: &str) -> bool {
+    frame.starts_with(MARKER)
+}
+
+pub fn encode<S: AsRef<str>>(parts: &[S]) -> String {
+    let mut frame = format!("{}{}\n", MARKER, parts.len());
+    for part in parts {
+        let part = part.as_ref();
+        frame.push_str(&part.len().to_string());
+        frame.push('\n');
+        frame.push_str(part);
+    }
+    frame
+}
+
+// The parts of a batch frame. A count over `max_parts` is refused before any
+// part is read.
+pub fn decode(frame: &str, max_parts: usize) -> Result<Vec<&str>, KeyzError> {
+    let rest = frame.strip_prefix(MARKER).ok_or_else(invalid)?;
+    let (count, mut rest) = split_length(rest)?;
+    if count > max_parts {
+        return Err(KeyzError::InvalidCommand(format!("batch has {} commands, limit is {}", count, max_parts)));
+    }
+
+    let mut parts = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (len, after) = split_length(rest)?;
+        // Also refuses a length that ends inside a character.
+        let part = after.get(..len).ok_or_else(invalid)?;
+        parts.push(part);
+        rest = &after[len..];
+    }
+
+    match rest.is_empty() {
+        true => Ok(parts),
+        false => Err(invalid()),
+    }
+}
+
+fn split_length(text: &str) -> Result<(usize, &str), KeyzError> {
+    let (digits, rest) = text.split_once('\n').ok_or_else(invalid)?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    Ok((digits.parse().map_err(|_| invalid())?, rest))
+}
+
+fn invalid() -> KeyzError {
+    KeyzError::InvalidCommand("batch frame invalid".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let parts = ["SET a 1", "", "SET b two words\nand a newline", "GET ü"];
+        let frame = encode(&parts);
+
+        assert!(frame.starts_with("*4\n7\nSET a 1"));
+        assert_eq!(decode(&frame, 4).unwrap(), parts);
+        assert_eq!(decode(&encode::<&str>(&[]), 1).unwrap(), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        for frame in ["*", "*x\n", "*1\n", "*1\n5\nabc", "*1\n3\nabcd", "*1\n-1\nabc", "*2\n1\na", "*1\n1\nü", "GET a"] {
+            assert!(decode(frame, 10).is_err(), "{:?}", frame);
+        }
+
+        assert_eq!(
+            decode("*3\n1\na1\nb1\nc", 2).unwrap_err().to_string(),
+            KeyzError::InvalidCommand("batch has 3 commands, limit is 2".into()).to_string()
+        );
+    }
+}
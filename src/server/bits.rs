@@ -0,0 +1,129 @@
+// Bit arrays for SETBIT and the commands around it. Bits are numbered from
+// the most significant bit of the first byte, as Redis does, so bit 0 is
+// 0x80 of byte 0 and the array reads the same as the bytes it's made of.
+
+// The highest offset SETBIT takes, so one command can't ask for more than
+// 512 MiB.
+pub const MAX_OFFSET: u64 = (1 << 32) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl BitOp {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "AND" => Some(BitOp::And),
+            "OR" => Some(BitOp::Or),
+            "XOR" => Some(BitOp::Xor),
+            "NOT" => Some(BitOp::Not),
+            _ => None,
+        }
+    }
+}
+
+fn mask(offset: u64) -> u8 {
+    0x80 >> (offset % 8)
+}
+
+// Bits past the end are 0.
+pub fn get(bits: &[u8], offset: u64) -> bool {
+    match bits.get((offset / 8) as usize) {
+        Some(byte) => byte & mask(offset) != 0,
+        None => false,
+    }
+}
+
+// Sets one bit, growing `bits` with zero bytes to reach it, and returns what
+// the bit was.
+pub fn set(bits: &mut Vec<u8>, offset: u64, bit: bool) -> bool {
+    let index = (offset / 8) as usize;
+    if bits.len() <= index {
+        bits.resize(index + 1, 0);
+    }
+
+    let was = bits[index] & mask(offset) != 0;
+    match bit {
+        true => bits[index] |= mask(offset),
+        false => bits[index] &= !mask(offset),
+    }
+    was
+}
+
+pub fn count(bytes: &[u8]) -> u64 {
+    bytes.iter().map(|byte| byte.count_ones() as u64).sum()
+}
+
+// The offset of the first bit equal to `bit`, counted from bytes[0].
+pub fn position(bytes: &[u8], bit: bool) -> Option<u64> {
+    bytes.iter().enumerate().find_map(|(index, &byte)| {
+        let byte = if bit { byte } else { !byte };
+        (byte != 0).then(|| index as u64 * 8 + byte.leading_zeros() as u64)
+    })
+}
+
+// BITOP over `sources`, the shorter ones read as padded with zero bytes, so
+// the result is as long as the longest. NOT takes only the first source.
+pub fn op(op: BitOp, sources: &[&[u8]]) -> Vec<u8> {
+    let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+
+    (0..len)
+        .map(|index| {
+            let mut bytes = sources.iter().map(|source| source.get(index).copied().unwrap_or(0));
+            let first = bytes.next().unwrap_or(0);
+            match op {
+                BitOp::And => bytes.fold(first, |result, byte| result & byte),
+                BitOp::Or => bytes.fold(first, |result, byte| result | byte),
+                BitOp::Xor => bytes.fold(first, |result, byte| result ^ byte),
+                BitOp::Not => !first,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_are_numbered_from_the_top_of_each_byte() {
+        let mut bits = Vec::new();
+        assert!(!set(&mut bits, 0, true));
+        assert!(!set(&mut bits, 9, true));
+        assert_eq!(bits, [0x80, 0x40]);
+
+        assert!(set(&mut bits, 9, false));
+        assert_eq!(bits, [0x80, 0x00]);
+        assert!(get(&bits, 0));
+        assert!(!get(&bits, 1));
+        assert!(!get(&bits, 1000));
+    }
+
+    #[test]
+    fn counts_and_positions() {
+        let bits = [0x00, 0x0f, 0xff];
+        assert_eq!(count(&bits), 12);
+        assert_eq!(position(&bits, true), Some(12));
+        assert_eq!(position(&bits, false), Some(0));
+        assert_eq!(position(&[0xff, 0xfe], false), Some(15));
+        assert_eq!(position(&[0xff], false), None);
+        assert_eq!(position(&[], true), None);
+    }
+
+    #[test]
+    fn operations_pad_shorter_sources_with_zeros() {
+        let (a, b): (&[u8], &[u8]) = (&[0b1100_1010, 0xff], &[0b1010_0110]);
+
+        assert_eq!(op(BitOp::And, &[a, b]), [0b1000_0010, 0x00]);
+        assert_eq!(op(BitOp::Or, &[a, b]), [0b1110_1110, 0xff]);
+        assert_eq!(op(BitOp::Xor, &[a, b]), [0b0110_1100, 0xff]);
+        assert_eq!(op(BitOp::Not, &[a]), [0b0011_0101, 0x00]);
+        assert_eq!(op(BitOp::Or, &[]), Vec::<u8>::new());
+        assert_eq!(BitOp::parse("xor"), Some(BitOp::Xor));
+        assert_eq!(BitOp::parse("NAND"), None);
+    }
+}
@@ -1,34 +1,709 @@
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fs;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use super::store::Store;
+use crate::config::{Config, ProtocolConfig, StoreConfig, MAX_TTL_JITTER_PERCENT};
+use crate::logging;
+
+use super::bits::BitOp;
+use super::error::KeyzError;
+use super::jsonpath::{self, PathError};
+use super::registry::{self, COMMANDS};
+use super::response::Response;
+use super::store::{GetExModifier, ListEnd, ScoreBound, Store};
+
+const MEMORY_DOCTOR_BIGGEST_KEYS: usize = 5;
 
 pub fn set(
     key: &str,
     value: String,
     store: &mut Store,
     seconds: u64,
-) -> Result<String, Box<dyn Error>> {
-    store.insert(key.to_string(), value.into_bytes().to_vec(), seconds);
-    Ok("ok".to_string())
+) -> Result<Response, Box<dyn Error>> {
+    store.insert(key.to_string(), value.into_bytes().to_vec(), seconds)?;
+    Ok(Response::Ok)
+}
+
+pub fn psetex(key: &str, value: String, ttl_ms: u64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.insert_ms(key.to_string(), value.into_bytes(), ttl_ms)?;
+    Ok(Response::Ok)
+}
+
+pub fn msetnx(pairs: &[&str], store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let entries = pairs
+        .chunks(2)
+        .map(|pair| (pair[0].to_string(), pair[1].as_bytes().to_vec()))
+        .collect();
+
+    Ok(Response::Integer(store.insert_all_if_absent(entries)? as i64))
+}
+
+pub fn get(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.get(key)? {
+        Some(value) => Ok(Response::value(value)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn incr(key: &str, by: i64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.increment(key, by)?))
+}
+
+pub fn push(key: &str, value: String, end: ListEnd, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.push(key, value.into_bytes(), end)? as i64))
+}
+
+pub fn pop(key: &str, end: ListEnd, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.pop(key, end)? {
+        Some(value) => Ok(Response::value(value)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn lrange(key: &str, start: i64, stop: i64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let items: Vec<String> = store
+        .list_range(key, start, stop)?
+        .iter()
+        .map(|item| String::from_utf8_lossy(item).into_owned())
+        .collect();
+    Ok(Response::json(&items)?)
+}
+
+pub fn llen(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.list_len(key)? as i64))
+}
+
+pub fn hset(key: &str, field: &str, value: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let added = store.hash_set(key, field.to_string(), value.as_bytes().to_vec())?;
+    Ok(Response::Integer(added as i64))
 }
 
-pub fn get(key: &str, store: &mut Store) -> Result<String, Box<dyn Error>> {
-    match store.get(key) {
-        Some(value) => Ok(String::from_utf8(value).unwrap()),
-        None => Ok("null".to_string()),
+pub fn hget(key: &str, field: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.hash_get(key, field)? {
+        Some(value) => Ok(Response::value(value)),
+        None => Ok(Response::Null),
     }
 }
 
-pub fn delete(key: &str, store: &mut Store) -> Result<String, Box<dyn Error>> {
+pub fn hdel(key: &str, field: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.hash_delete(key, field)? as i64))
+}
+
+pub fn hexists(key: &str, field: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.hash_get(key, field)?.is_some() as i64))
+}
+
+// HGETALL as a JSON object, HKEYS and HVALS as arrays, all in field order.
+pub fn hgetall(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let fields: BTreeMap<String, String> = store
+        .hash_get_all(key)?
+        .into_iter()
+        .map(|(field, value)| (field, String::from_utf8_lossy(&value).into_owned()))
+        .collect();
+    Ok(Response::json(&fields)?)
+}
+
+pub fn hkeys(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let fields: Vec<String> = store.hash_get_all(key)?.into_keys().collect();
+    Ok(Response::json(&fields)?)
+}
+
+pub fn hvals(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let values: Vec<String> = store
+        .hash_get_all(key)?
+        .values()
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+        .collect();
+    Ok(Response::json(&values)?)
+}
+
+pub fn sadd(key: &str, member: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.set_add(key, member.to_string())? as i64))
+}
+
+pub fn srem(key: &str, member: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.set_remove(key, member)? as i64))
+}
+
+pub fn sismember(key: &str, member: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.set_contains(key, member)? as i64))
+}
+
+pub fn scard(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.set_len(key)? as i64))
+}
+
+pub fn smembers(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::json(&store.set_members(key)?)?)
+}
+
+pub fn sunion(keys: &[&str], store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::json(&store.set_union(keys)?)?)
+}
+
+pub fn sinterstore(destination: &str, keys: &[&str], store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.set_intersect_store(destination, keys)? as i64))
+}
+
+pub fn zadd(key: &str, score: f64, member: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.zset_add(key, score, member.to_string())? as i64))
+}
+
+pub fn zrem(key: &str, member: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.zset_remove(key, member)? as i64))
+}
+
+pub fn zscore(key: &str, member: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.zset_score(key, member)? {
+        Some(score) => Ok(Response::value(score.to_string())),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn zrank(key: &str, member: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.zset_rank(key, member)? {
+        Some(rank) => Ok(Response::Integer(rank as i64)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn zrange(
+    key: &str,
+    start: i64,
+    stop: i64,
+    with_scores: bool,
+    store: &mut Store,
+) -> Result<Response, Box<dyn Error>> {
+    scored_members(store.zset_range(key, start, stop)?, with_scores)
+}
+
+pub fn zrange_by_score(
+    key: &str,
+    min: ScoreBound,
+    max: ScoreBound,
+    with_scores: bool,
+    store: &mut Store,
+) -> Result<Response, Box<dyn Error>> {
+    scored_members(store.zset_range_by_score(key, min, max)?, with_scores)
+}
+
+// A JSON array of members, or with WITHSCORES of each member followed by
+// its score, as ZSCORE writes it. Strings rather than numbers, since JSON
+// has no inf.
+fn scored_members(members: Vec<(String, f64)>, with_scores: bool) -> Result<Response, Box<dyn Error>> {
+    let items: Vec<String> = match with_scores {
+        true => members.into_iter().flat_map(|(member, score)| [member, score.to_string()]).collect(),
+        false => members.into_iter().map(|(member, _)| member).collect(),
+    };
+    Ok(Response::json(&items)?)
+}
+
+pub fn setbit(key: &str, offset: u64, bit: bool, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.set_bit(key, offset, bit)? as i64))
+}
+
+pub fn getbit(key: &str, offset: u64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.get_bit(key, offset)? as i64))
+}
+
+pub fn bitcount(key: &str, range: Option<(i64, i64)>, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.bit_count(key, range)? as i64))
+}
+
+pub fn bitpos(
+    key: &str,
+    bit: bool,
+    start: i64,
+    end: Option<i64>,
+    store: &mut Store,
+) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.bit_position(key, bit, start, end)?))
+}
+
+pub fn bitop(op: BitOp, destination: &str, keys: &[&str], store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.bit_op(op, destination, keys)? as i64))
+}
+
+pub fn type_of(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.value_type(key) {
+        Some(name) => Ok(Response::value(name)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn getex(key: &str, modifier: GetExModifier, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.get_ex(key, modifier)? {
+        Some(value) => Ok(Response::value(value)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn delete(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
     match store.delete(key) {
-        Some(value) => Ok(value),
-        None => Ok("null".to_string()),
+        Some(value) => Ok(Response::value(value)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn expiring(count: usize, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::json(&store.expiring(count))?)
+}
+
+pub fn touch(keys: &[&str], store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.touch(keys) as i64))
+}
+
+pub fn unlink(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.unlink(key) {
+        true => Ok(Response::Ok),
+        false => Ok(Response::Null),
     }
 }
 
-pub fn expires_in(key: &str, store: &mut Store) -> Result<String, Box<dyn Error>> {
+pub fn delete_pattern(pattern: &str, limit: usize, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::json(&store.delete_matching(pattern, limit))?)
+}
+
+pub fn expires_in(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
     match store.expires_in(key) {
-        Some(value) => Ok(value.to_string()),
-        None => Ok("null".to_string()),
+        Some(value) => Ok(Response::Integer(value as i64)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn expire(key: &str, seconds: u64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.update_ttl(key, seconds)? as i64))
+}
+
+pub fn expire_at(key: &str, timestamp: u64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.expire_at(key, timestamp)? as i64))
+}
+
+pub fn pexpire(key: &str, ttl_ms: u64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.update_ttl_ms(key, ttl_ms)? as i64))
+}
+
+pub fn pexpire_at(key: &str, timestamp_ms: u64, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.expire_at_ms(key, timestamp_ms)? as i64))
+}
+
+pub fn pttl(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.expires_in_ms(key) {
+        Some(ttl_ms) => Ok(Response::Integer(ttl_ms as i64)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn jget(key: &str, path: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let path = jsonpath::parse(path).map_err(path_error)?;
+
+    let document = match store.get(key)? {
+        Some(value) => parse_document(&value)?,
+        None => return Ok(Response::Null),
+    };
+
+    match jsonpath::get(&document, &path) {
+        Some(value) => Ok(Response::json(value)?),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn jset(key: &str, path: &str, json: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let path = jsonpath::parse(path).map_err(path_error)?;
+    let value: serde_json::Value =
+        serde_json::from_str(json).map_err(|_| KeyzError::InvalidCommand("invalid json".into()))?;
+
+    store.update(key, |current| {
+        let mut document = match current {
+            Some(current) => parse_document(&current)?,
+            None => serde_json::Value::Null,
+        };
+
+        jsonpath::set(&mut document, &path, value).map_err(path_error)?;
+        Ok(serde_json::to_vec(&document).expect("json values always serialize"))
+    })?;
+
+    Ok(Response::Ok)
+}
+
+fn parse_document(value: &[u8]) -> Result<serde_json::Value, KeyzError> {
+    serde_json::from_slice(value).map_err(|_| KeyzError::InvalidCommand("not json".into()))
+}
+
+fn path_error(error: PathError) -> KeyzError {
+    KeyzError::InvalidCommand(error.to_string())
+}
+
+pub fn select(db: usize, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.select(db)?;
+    Ok(Response::Ok)
+}
+
+pub fn flush(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.flush();
+    Ok(Response::Ok)
+}
+
+pub fn swap_databases(first: usize, second: usize, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.swap_databases(first, second)?;
+    Ok(Response::Ok)
+}
+
+pub fn flush_all(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.flush_all();
+    Ok(Response::Ok)
+}
+
+pub fn keys(pattern: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let keys = store.keys_matching(pattern);
+    Ok(Response::Array(keys.into_iter().map(Response::value).collect()))
+}
+
+// Resumes after the cursor, the hex of the last key returned, so a scan
+// isn't thrown off by writes in between. The page is halved until it fits
+// in a frame, which is what makes SCAN safe where KEYS is too big.
+pub fn scan(
+    after: Option<&str>,
+    pattern: &str,
+    count: usize,
+    store: &mut Store,
+    protocol: &ProtocolConfig,
+) -> Result<Response, Box<dyn Error>> {
+    let (mut keys, mut more) = store.scan(after, pattern, count);
+
+    loop {
+        let cursor = match (more, keys.last()) {
+            (true, Some(last)) => encode_cursor(last),
+            _ => "0".to_string(),
+        };
+        let page = Response::Array(vec![
+            Response::value(cursor),
+            Response::Array(keys.iter().cloned().map(Response::value).collect()),
+        ]);
+
+        if keys.len() <= 1 || page.render(protocol.error_format()).len() <= protocol.max_message_bytes {
+            return Ok(page);
+        }
+        keys.truncate(keys.len() / 2);
+        more = true;
+    }
+}
+
+fn encode_cursor(key: &str) -> String {
+    key.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Keys are never empty, so no cursor collides with the starting 0.
+pub fn decode_cursor(cursor: &str) -> Option<String> {
+    if !cursor.is_ascii() || !cursor.len().is_multiple_of(2) {
+        return None;
     }
+
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+pub fn info(store: &mut Store, protocol: &ProtocolConfig) -> Result<Response, Box<dyn Error>> {
+    let mut stats = store.stats();
+    if let Some(separator) = protocol.namespace_separator {
+        stats.top_namespaces = store.top_namespaces(separator);
+    }
+    stats.trace_id = logging::current_trace_id();
+    Ok(Response::json(&stats)?)
+}
+
+// The same text the /metrics endpoint serves, for scraping over the protocol.
+pub fn metrics_text(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::value(store.render_prometheus()))
+}
+
+pub fn namespace_list(separator: char, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::json(&store.namespaces(separator))?)
+}
+
+pub fn namespace_stats(namespace: &str, separator: char, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::json(&store.namespace_stats(namespace, separator))?)
+}
+
+pub fn namespace_flush(namespace: &str, separator: char, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.flush_namespace(namespace, separator) as i64))
+}
+
+pub fn reset_stats(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.histograms().reset();
+    Ok(Response::Ok)
+}
+
+pub fn slowlog_get(count: usize, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let entries = store.slow_log().latest(count);
+    Ok(Response::json(&entries)?)
+}
+
+pub fn slowlog_reset(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.slow_log().reset();
+    Ok(Response::Ok)
+}
+
+pub fn latency_latest(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::json(&store.latency().latest())?)
+}
+
+pub fn latency_history(event: &str, count: usize, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let entries = store.slow_log().latest_for(event, count);
+    Ok(Response::json(&entries)?)
+}
+
+pub fn latency_reset(event: Option<&str>, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.latency().reset(event) as i64))
+}
+
+pub fn latency_graph(event: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.latency().graph(event) {
+        Some(graph) => Ok(Response::value(graph)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn ping(message: Option<&str>) -> Result<Response, Box<dyn Error>> {
+    match message {
+        Some(message) => Ok(Response::value(message.to_string())),
+        None => Ok(Response::value("pong")),
+    }
+}
+
+pub fn echo(message: &str) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::value(message.to_string()))
+}
+
+pub fn command_count() -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(COMMANDS.len() as i64))
+}
+
+// Unknown names come back as null in their position.
+pub fn command_info(names: Vec<&str>) -> Result<Response, Box<dyn Error>> {
+    if names.is_empty() {
+        return Ok(Response::json(&COMMANDS)?);
+    }
+
+    let docs: Vec<_> = names.into_iter().map(registry::find).collect();
+    Ok(Response::json(&docs)?)
+}
+
+pub fn config_get(parameter: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let limits = store.store_config();
+    let first_listener = || store.listeners().first().and_then(|addr| addr.parse::<SocketAddr>().ok());
+
+    let value = match parameter {
+        "server.read_only" => store.read_only_setting().to_string(),
+        "server.host" => return Ok(optional(first_listener().map(|addr| addr.ip()))),
+        "server.port" => return Ok(optional(first_listener().map(|addr| addr.port()))),
+        "server.listeners" => return Ok(Response::Array(store.listeners().into_iter().map(Response::value).collect())),
+        "store.slow_query_threshold_ms" => return Ok(optional(limits.slow_query_threshold_ms)),
+        "store.slow_log_max_len" => limits.slow_log_max_len.to_string(),
+        "store.max_key_bytes" => return Ok(optional(limits.max_key_bytes)),
+        "store.max_value_bytes" => return Ok(optional(limits.max_value_bytes)),
+        "store.max_ttl_secs" => return Ok(optional(limits.max_ttl_secs)),
+        "store.ttl_jitter_percent" => limits.ttl_jitter_percent.to_string(),
+        "store.databases" => limits.databases.to_string(),
+        _ => return Err(KeyzError::InvalidCommand("unknown config parameter".into()).into()),
+    };
+
+    Ok(Response::value(value))
+}
+
+// Optional limits are cleared with `null`.
+pub fn config_set(parameter: &str, value: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let mut limits = store.store_config();
+
+    match parameter {
+        "server.read_only" => match value {
+            "true" => store.set_read_only(true),
+            "false" => store.set_read_only(false),
+            _ => return Err(config_value_invalid()),
+        },
+        "store.slow_query_threshold_ms" => limits.slow_query_threshold_ms = parse_optional(value)?,
+        "store.max_key_bytes" => limits.max_key_bytes = parse_optional(value)?,
+        "store.max_value_bytes" => limits.max_value_bytes = parse_optional(value)?,
+        "store.max_ttl_secs" => limits.max_ttl_secs = parse_optional(value)?,
+        "store.ttl_jitter_percent" => {
+            limits.ttl_jitter_percent = value
+                .parse()
+                .ok()
+                .filter(|percent| (0.0..=MAX_TTL_JITTER_PERCENT).contains(percent))
+                .ok_or_else(config_value_invalid)?
+        }
+        "server.host" | "server.port" | "server.listeners" | "store.slow_log_max_len" | "store.databases" => {
+            return Err(KeyzError::InvalidCommand("field is not writable".into()).into())
+        }
+        _ => return Err(KeyzError::InvalidCommand("unknown config parameter".into()).into()),
+    }
+
+    store.set_store_config(&limits);
+    Ok(Response::Ok)
+}
+
+// Re-reads the config file and writes it back with the runtime values of the
+// CONFIG SET fields and the current protocol settings. Comments and the
+// human-readable spellings of store limits are not preserved.
+pub fn config_rewrite(store: &mut Store, protocol: &ProtocolConfig) -> Result<Response, Box<dyn Error>> {
+    let path = match store.config_path() {
+        Some(path) => path,
+        None => return Err(KeyzError::InvalidCommand("no config file".into()).into()),
+    };
+
+    let (mut config, _) = Config::load_from(&path)
+        .map_err(|_| KeyzError::InvalidCommand("config file invalid".into()))?;
+
+    config.server.read_only = store.read_only_setting();
+    // The seed settings are only read at startup, so the file's stay.
+    config.store = StoreConfig {
+        seed_path: config.store.seed_path.take(),
+        seed_strict: config.store.seed_strict,
+        ..store.store_config()
+    };
+    config.protocol = protocol.clone();
+
+    let contents = config
+        .to_toml_string()
+        .map_err(|e| KeyzError::InvalidCommand(e.to_string()))?;
+    fs::write(&path, contents).map_err(KeyzError::Io)?;
+
+    Ok(Response::Ok)
+}
+
+fn optional(value: Option<impl ToString>) -> Response {
+    match value {
+        Some(value) => Response::value(value.to_string()),
+        None => Response::Null,
+    }
+}
+
+fn parse_optional<T: FromStr>(value: &str) -> Result<Option<T>, Box<dyn Error>> {
+    match value {
+        "null" => Ok(None),
+        value => value.parse().map(Some).map_err(|_| config_value_invalid()),
+    }
+}
+
+fn config_value_invalid() -> Box<dyn Error> {
+    KeyzError::InvalidCommand("config value invalid".into()).into()
+}
+
+pub fn debug_object(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.debug_entry(key) {
+        Some(entry) => Ok(Response::json(&entry)?),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn dump(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.dump(key)? {
+        Some(blob) => Ok(Response::value(blob)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn restore(key: &str, ttl_ms: u64, blob: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    store.restore(key.to_string(), blob, ttl_ms)?;
+    Ok(Response::Ok)
+}
+
+// Strings are gzip-compressed, so they answer compressed:gzip with the raw
+// to stored size ratio; other types just name their encoding.
+pub fn object_encoding(key: &str, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    match store.debug_entry(key) {
+        Some(entry) if entry.compressed => Ok(Response::value(format!(
+            "compressed:{}:ratio={:.2}",
+            entry.encoding,
+            entry.original_bytes as f64 / entry.stored_bytes.max(1) as f64
+        ))),
+        Some(entry) => Ok(Response::value(entry.encoding)),
+        None => Ok(Response::Null),
+    }
+}
+
+pub fn memory_usage(key: &str, decompressed: bool, store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let usage = match store.memory_usage(key, decompressed) {
+        Some(usage) => usage,
+        None => return Ok(Response::Integer(0)),
+    };
+
+    match usage.raw_bytes {
+        Some(raw) => Ok(Response::value(format!(
+            "raw:{} stored:{} saved:{:.1}%",
+            raw,
+            usage.stored_bytes,
+            saved_percent(raw, usage.stored_bytes)
+        ))),
+        None => Ok(Response::Integer(usage.usage_bytes as i64)),
+    }
+}
+
+pub fn memory_doctor(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    let report = store.memory_report(MEMORY_DOCTOR_BIGGEST_KEYS);
+
+    if report.keys == 0 {
+        return Ok(Response::value("the store is empty"));
+    }
+
+    let biggest: Vec<String> = report
+        .biggest
+        .iter()
+        .map(|(key, bytes)| format!("{} ({} bytes)", key, bytes))
+        .collect();
+
+    let saved = saved_percent(report.raw_bytes, report.stored_bytes);
+    let compression = if saved > 0.0 {
+        format!(
+            "compression is paying off: values take {} bytes instead of {}, saving {:.1}%",
+            report.stored_bytes, report.raw_bytes, saved
+        )
+    } else {
+        format!(
+            "compression is not paying off: values take {} bytes compressed but only {} raw, mostly because they are small or already compressed",
+            report.stored_bytes, report.raw_bytes
+        )
+    };
+
+    Ok(Response::value(format!(
+        "{} keys use about {} bytes\nbiggest keys: {}\n{}",
+        report.keys,
+        report.usage_bytes,
+        biggest.join(", "),
+        compression
+    )))
+}
+
+fn saved_percent(raw: usize, stored: usize) -> f64 {
+    match raw {
+        0 => 0.0,
+        raw => (1.0 - stored as f64 / raw as f64) * 100.0,
+    }
+}
+
+pub fn debug_purge(store: &mut Store) -> Result<Response, Box<dyn Error>> {
+    Ok(Response::Integer(store.purge_expired() as i64))
+}
+
+// Blocks the calling thread on purpose so it behaves like a genuinely slow
+// command, which is what command_timeout_secs has to cope with.
+pub fn debug_sleep(duration: Duration) -> Result<Response, Box<dyn Error>> {
+    thread::sleep(duration);
+    Ok(Response::Ok)
+}
+
+// Exercises the connection's panic handling.
+pub fn debug_panic() -> Result<Response, Box<dyn Error>> {
+    panic!("DEBUG PANIC");
+}
+
+pub fn debug_panic_locked(store: &Store) -> Result<Response, Box<dyn Error>> {
+    store.panic_while_locked()
 }
@@ -0,0 +1,327 @@
+use std::error::Error;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time;
+
+use crate::server::error::KeyzError;
+
+const LENGTH_PREFIX_BYTES: usize = 4;
+const INITIAL_BUFFER_BYTES: usize = 4 * 1024;
+// The buffer grows by at most this much per read, so a length prefix alone
+// can't make the server allocate the whole frame.
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+// Bytes all connections together may hold for frames being read and not yet
+// answered. A connection takes permits a chunk at a time as payload arrives
+// and gives them back once its response is written, or while it waits for
+// more.
+pub struct InflightBudget {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl InflightBudget {
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.min(Semaphore::MAX_PERMITS);
+        Self { semaphore: Arc::new(Semaphore::new(limit)), limit }
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.limit - self.semaphore.available_permits()
+    }
+
+    fn try_acquire(&self, bytes: usize) -> Option<OwnedSemaphorePermit> {
+        let bytes = bytes.min(self.limit) as u32;
+        self.semaphore.clone().try_acquire_many_owned(bytes).ok()
+    }
+
+    async fn acquire(&self, bytes: usize) -> OwnedSemaphorePermit {
+        let bytes = bytes.min(self.limit) as u32;
+        // The semaphore is never closed.
+        self.semaphore.clone().acquire_many_owned(bytes).await.unwrap()
+    }
+}
+
+// Owns the stream together with read and write buffers that are reused for
+// every frame on the connection. A read can end anywhere, including inside
+// the length prefix, or carry several frames at once, so frames are parsed
+// out of the buffer and whatever follows stays there for the next call.
+pub struct Connection<S = TcpStream> {
+    stream: S,
+    read_buffer: BytesMut,
+    write_buffer: BytesMut,
+    max_message_bytes: usize,
+    frame_timeout: Option<Duration>,
+    budget: Option<Arc<InflightBudget>>,
+    // What this connection holds of `budget` for the current request.
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            read_buffer: BytesMut::with_capacity(INITIAL_BUFFER_BYTES),
+            write_buffer: BytesMut::with_capacity(INITIAL_BUFFER_BYTES),
+            max_message_bytes: usize::MAX,
+            frame_timeout: None,
+            budget: None,
+            permit: None,
+        }
+    }
+
+    pub fn set_inflight_budget(&mut self, budget: Option<Arc<InflightBudget>>) {
+        self.budget = budget;
+    }
+
+    // How long a peer may go quiet once it has started sending a frame.
+    pub fn set_frame_timeout(&mut self, limit: Duration) {
+        self.frame_timeout = Some(limit);
+    }
+
+    // Frames announcing a longer payload fail with MessageTooLarge before any
+    // of it is buffered.
+    pub fn set_max_message_bytes(&mut self, limit: usize) {
+        self.max_message_bytes = limit;
+    }
+
+    // Returns None when the peer closes the connection between frames.
+    pub async fn read_message(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        loop {
+            if let Some(message) = self.parse_frame()? {
+                return Ok(Some(message));
+            }
+
+            let mid_frame = !self.read_buffer.is_empty();
+            let read = match self.frame_timeout {
+                Some(limit) if mid_frame => time::timeout(limit, self.read_chunk())
+                    .await
+                    .map_err(|_| "client stalled in the middle of a frame")??,
+                _ => self.read_chunk().await?,
+            };
+
+            if read == 0 {
+                if self.read_buffer.is_empty() {
+                    return Ok(None);
+                }
+
+                return Err("connection closed in the middle of a frame".into());
+            }
+        }
+    }
+
+    // Makes room for at most a chunk of the frame being read, never more than
+    // it still needs, taking budget for it first, then reads into it.
+    async fn read_chunk(&mut self) -> io::Result<usize> {
+        if self.read_buffer.capacity() == self.read_buffer.len() {
+            match self.frame_len() {
+                Some(frame_len) => {
+                    let wanted = frame_len.saturating_sub(self.read_buffer.len()).clamp(1, READ_CHUNK_BYTES);
+                    if let Some(budget) = &self.budget {
+                        match (budget.try_acquire(wanted), &mut self.permit) {
+                            (Some(permit), Some(held)) => held.merge(permit),
+                            (Some(permit), None) => self.permit = Some(permit),
+                            // Waiting on others while holding part of the frame
+                            // could deadlock with one doing the same, so what's
+                            // held goes back and is waited for along with the rest.
+                            (None, held) => {
+                                let held = held.take().map_or(0, |permit| permit.num_permits());
+                                self.permit = Some(budget.acquire(held + wanted).await);
+                            }
+                        }
+                    }
+                    self.read_buffer.reserve(wanted);
+                }
+                // Still waiting on a prefix, which costs next to nothing.
+                None => self.read_buffer.reserve(INITIAL_BUFFER_BYTES),
+            }
+        }
+
+        self.stream.read_buf(&mut self.read_buffer).await
+    }
+
+    // The length of the frame at the front of the buffer, once its prefix is
+    // in.
+    fn frame_len(&self) -> Option<usize> {
+        let prefix = self.read_buffer.get(..LENGTH_PREFIX_BYTES)?;
+        Some(LENGTH_PREFIX_BYTES + u32::from_be_bytes(prefix.try_into().unwrap()) as usize)
+    }
+
+    fn parse_frame(&mut self) -> Result<Option<String>, KeyzError> {
+        let Some(frame_len) = self.frame_len() else {
+            return Ok(None);
+        };
+
+        let payload_len = frame_len - LENGTH_PREFIX_BYTES;
+        if payload_len > self.max_message_bytes {
+            return Err(KeyzError::MessageTooLarge { size: payload_len, limit: self.max_message_bytes });
+        }
+
+        if self.read_buffer.len() < frame_len {
+            return Ok(None);
+        }
+
+        self.read_buffer.advance(LENGTH_PREFIX_BYTES);
+        let payload = self.read_buffer.split_to(frame_len - LENGTH_PREFIX_BYTES);
+
+        Ok(Some(String::from_utf8_lossy(&payload).into_owned()))
+    }
+
+    pub async fn write_message(&mut self, message: &str) -> Result<(), Box<dyn Error>> {
+        self.write_buffer.clear();
+        self.write_buffer.reserve(LENGTH_PREFIX_BYTES + message.len());
+        self.write_buffer.put_u32(message.len() as u32);
+        self.write_buffer.put_slice(message.as_bytes());
+
+        self.stream.write_all(&self.write_buffer).await?;
+        self.release();
+
+        Ok(())
+    }
+
+    // Hands the request's budget back, along with read buffer space past the
+    // initial size, so an idle connection keeps neither.
+    fn release(&mut self) {
+        if self.permit.take().is_some() && self.read_buffer.capacity() > INITIAL_BUFFER_BYTES {
+            let mut buffer = BytesMut::with_capacity(INITIAL_BUFFER_BYTES.max(self.read_buffer.len()));
+            buffer.extend_from_slice(&self.read_buffer);
+            self.read_buffer = buffer;
+        }
+    }
+
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, DuplexStream};
+    use tokio::task;
+
+    use super::*;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    // Writes each piece separately and lets the reader drain it before the
+    // next, so every piece arrives as its own read.
+    async fn read_pieces(pieces: Vec<Vec<u8>>, messages: usize) -> Vec<String> {
+        let (mut peer, stream) = duplex(4 * READ_CHUNK_BYTES);
+        let reader = task::spawn(async move {
+            let mut connection: Connection<DuplexStream> = Connection::new(stream);
+            let mut read = Vec::new();
+            for _ in 0..messages {
+                read.push(connection.read_message().await.unwrap().unwrap());
+            }
+            read
+        });
+
+        for piece in pieces {
+            peer.write_all(&piece).await.unwrap();
+            for _ in 0..4 {
+                task::yield_now().await;
+            }
+        }
+
+        reader.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn length_prefix_split_across_reads() {
+        let bytes = frame(b"GET key");
+        let pieces = vec![bytes[..1].to_vec(), bytes[1..3].to_vec(), bytes[3..5].to_vec(), bytes[5..].to_vec()];
+        assert_eq!(read_pieces(pieces, 1).await, vec!["GET key"]);
+
+        let byte_at_a_time = frame(b"SET k v").into_iter().map(|b| vec![b]).collect();
+        assert_eq!(read_pieces(byte_at_a_time, 1).await, vec!["SET k v"]);
+    }
+
+    #[tokio::test]
+    async fn several_frames_in_one_read() {
+        let mut bytes = frame(b"SET a 1");
+        bytes.extend(frame(b""));
+        bytes.extend(frame(b"GET a"));
+        // The last frame is cut short, and finished by the next read.
+        let tail = frame(b"DEL a");
+        bytes.extend_from_slice(&tail[..6]);
+
+        let read = read_pieces(vec![bytes, tail[6..].to_vec()], 4).await;
+        assert_eq!(read, vec!["SET a 1", "", "GET a", "DEL a"]);
+    }
+
+    #[tokio::test]
+    async fn frame_ending_on_the_chunk_edge() {
+        let payload = vec![b'x'; READ_CHUNK_BYTES - LENGTH_PREFIX_BYTES];
+        let mut bytes = frame(&payload);
+        assert_eq!(bytes.len(), READ_CHUNK_BYTES);
+        bytes.extend(frame(b"PING"));
+        let read = read_pieces(vec![bytes], 2).await;
+        assert_eq!(read[0].len(), payload.len());
+        assert_eq!(read[1], "PING");
+
+        // The same frame, this time with each read ending on the edge.
+        let chunked = frame(&vec![b'y'; 2 * READ_CHUNK_BYTES]);
+        let pieces = chunked.chunks(READ_CHUNK_BYTES).map(<[u8]>::to_vec).collect();
+        let read = read_pieces(pieces, 1).await;
+        assert_eq!(read[0].len(), 2 * READ_CHUNK_BYTES);
+    }
+
+    #[tokio::test]
+    async fn closing_inside_the_prefix_is_an_error() {
+        let (mut peer, stream) = duplex(64);
+        let mut connection = Connection::new(stream);
+        peer.write_all(&[0, 0]).await.unwrap();
+        drop(peer);
+
+        let err = connection.read_message().await.unwrap_err();
+        assert_eq!(err.to_string(), "connection closed in the middle of a frame");
+    }
+
+    #[tokio::test]
+    async fn frames_over_half_the_budget_do_not_starve_each_other() {
+        let budget = Arc::new(InflightBudget::new(200 * 1024));
+        let payload = vec![b'z'; 150 * 1024];
+        let bytes = frame(&payload);
+
+        let mut peers = Vec::new();
+        let mut readers = Vec::new();
+        for _ in 0..2 {
+            let (peer, stream) = duplex(4 * READ_CHUNK_BYTES);
+            let budget = budget.clone();
+            readers.push(task::spawn(async move {
+                let mut connection = Connection::new(stream);
+                connection.set_inflight_budget(Some(budget));
+                let message = connection.read_message().await.unwrap().unwrap();
+                connection.write_message("ok").await.unwrap();
+                message.len()
+            }));
+            peers.push(peer);
+        }
+
+        // Both frames arrive a chunk at a time, in step.
+        for chunk in bytes.chunks(READ_CHUNK_BYTES) {
+            for peer in &mut peers {
+                peer.write_all(chunk).await.unwrap();
+                for _ in 0..4 {
+                    task::yield_now().await;
+                }
+            }
+        }
+
+        for reader in readers {
+            let read = time::timeout(Duration::from_secs(5), reader).await.expect("connections deadlocked");
+            assert_eq!(read.unwrap(), payload.len());
+        }
+        assert_eq!(budget.in_use(), 0);
+    }
+}
@@ -1,44 +1,532 @@
 use std::error::Error;
+use std::time::Duration;
 
 use regex::{Captures, Regex};
 
+use crate::config::ProtocolConfig;
+
 use super::{
-    commands::{delete, expires_in, get, set},
-    store::Store,
+    bits::{self, BitOp},
+    commands::{
+        bitcount, bitop, bitpos, command_count, command_info, config_get, config_rewrite, config_set, debug_object, debug_panic, debug_panic_locked, debug_purge, debug_sleep, decode_cursor, delete, delete_pattern, dump, echo, expire, expire_at, expires_in, expiring, flush, flush_all, get, getbit, getex, hdel, hexists, hget, hgetall, hkeys, hset, hvals, incr, info, jget, jset, keys, latency_graph,
+        latency_history, latency_latest, latency_reset, llen, lrange, memory_doctor, memory_usage, msetnx, metrics_text, pexpire, pexpire_at, psetex, namespace_flush, namespace_list, namespace_stats, object_encoding, ping, pop, pttl, push, reset_stats, restore, sadd, scan, scard, select, set, setbit, slowlog_get, slowlog_reset, sinterstore, sismember, smembers, srem, sunion, swap_databases, touch, type_of, unlink, zadd, zrange, zrange_by_score, zrank, zrem, zscore,
+    },
+    error::KeyzError,
+    registry::COMMANDS,
+    response::Response,
+    store::{GetExModifier, ListEnd, ScoreBound, Store},
 };
 
-const SET: &str = "SET";
-const GET: &str = "GET";
-const DELETE: &str = "DEL";
-const EXPIRES_IN: &str = "EXIN";
+pub(super) const SET: &str = "SET";
+pub(super) const GET: &str = "GET";
+pub(super) const GETEX: &str = "GETEX";
+pub(super) const SETEX: &str = "SETEX";
+pub(super) const PSETEX: &str = "PSETEX";
+pub(super) const MSETNX: &str = "MSETNX";
+pub(super) const INCR: &str = "INCR";
+pub(super) const TYPE: &str = "TYPE";
+pub(super) const LPUSH: &str = "LPUSH";
+pub(super) const RPUSH: &str = "RPUSH";
+pub(super) const LPOP: &str = "LPOP";
+pub(super) const RPOP: &str = "RPOP";
+pub(super) const LRANGE: &str = "LRANGE";
+pub(super) const LLEN: &str = "LLEN";
+pub(super) const HSET: &str = "HSET";
+pub(super) const HGET: &str = "HGET";
+pub(super) const HDEL: &str = "HDEL";
+pub(super) const HEXISTS: &str = "HEXISTS";
+pub(super) const HGETALL: &str = "HGETALL";
+pub(super) const HKEYS: &str = "HKEYS";
+pub(super) const HVALS: &str = "HVALS";
+pub(super) const SADD: &str = "SADD";
+pub(super) const SREM: &str = "SREM";
+pub(super) const SISMEMBER: &str = "SISMEMBER";
+pub(super) const SCARD: &str = "SCARD";
+pub(super) const SMEMBERS: &str = "SMEMBERS";
+pub(super) const SUNION: &str = "SUNION";
+pub(super) const SINTERSTORE: &str = "SINTERSTORE";
+pub(super) const ZADD: &str = "ZADD";
+pub(super) const ZREM: &str = "ZREM";
+pub(super) const ZSCORE: &str = "ZSCORE";
+pub(super) const ZRANK: &str = "ZRANK";
+pub(super) const ZRANGE: &str = "ZRANGE";
+pub(super) const ZRANGEBYSCORE: &str = "ZRANGEBYSCORE";
+pub(super) const SETBIT: &str = "SETBIT";
+pub(super) const GETBIT: &str = "GETBIT";
+pub(super) const BITCOUNT: &str = "BITCOUNT";
+pub(super) const BITPOS: &str = "BITPOS";
+pub(super) const BITOP: &str = "BITOP";
+pub(super) const DELETE: &str = "DEL";
+pub(super) const EXPIRES_IN: &str = "EXIN";
+pub(super) const EXPIRE: &str = "EXPIRE";
+pub(super) const EXPIREAT: &str = "EXPIREAT";
+pub(super) const PEXPIRE: &str = "PEXPIRE";
+pub(super) const PEXPIREAT: &str = "PEXPIREAT";
+pub(super) const TTL: &str = "TTL";
+pub(super) const PTTL: &str = "PTTL";
+pub(super) const INFO: &str = "INFO";
+pub(super) const SLOWLOG: &str = "SLOWLOG";
+pub(super) const PING: &str = "PING";
+pub(super) const HELLO: &str = "HELLO";
+pub(super) const ECHO: &str = "ECHO";
+pub(super) const KEYS: &str = "KEYS";
+pub(super) const SCAN: &str = "SCAN";
+pub(super) const DEBUG: &str = "DEBUG";
+pub(super) const CONFIG: &str = "CONFIG";
+pub(super) const LATENCY: &str = "LATENCY";
+pub(super) const MEMORY: &str = "MEMORY";
+pub(super) const JSET: &str = "JSET";
+pub(super) const JGET: &str = "JGET";
+pub(super) const COMMAND: &str = "COMMAND";
+pub(super) const CLOSE: &str = "CLOSE";
+pub(super) const SELECT: &str = "SELECT";
+pub(super) const FLUSH: &str = "FLUSH";
+pub(super) const FLUSHALL: &str = "FLUSHALL";
+pub(super) const FLUSHDB: &str = "FLUSHDB";
+pub(super) const SWAPDB: &str = "SWAPDB";
+pub(super) const NAMESPACE: &str = "NAMESPACE";
+pub(super) const METRICS: &str = "METRICS";
+pub(super) const DELPATTERN: &str = "DELPATTERN";
+pub(super) const UNLINK: &str = "UNLINK";
+pub(super) const TOUCH: &str = "TOUCH";
+pub(super) const EXPIRING: &str = "EXPIRING";
+pub(super) const OBJECT: &str = "OBJECT";
+pub(super) const DUMP: &str = "DUMP";
+pub(super) const RESTORE: &str = "RESTORE";
+pub(super) const RESETSTATS: &str = "RESETSTATS";
+pub(super) const SUBSCRIBE: &str = "SUBSCRIBE";
+pub(super) const UNSUBSCRIBE: &str = "UNSUBSCRIBE";
+pub(super) const PUBLISH: &str = "PUBLISH";
+pub(super) const MULTI: &str = "MULTI";
+pub(super) const EXEC: &str = "EXEC";
+pub(super) const DISCARD: &str = "DISCARD";
+pub(super) const RESET: &str = "RESET";
+pub(super) const WATCH: &str = "WATCH";
+pub(super) const UNWATCH: &str = "UNWATCH";
+
+const SLOWLOG_DEFAULT_COUNT: usize = 10;
+const SCAN_DEFAULT_COUNT: usize = 10;
+
+pub async fn dispatcher(
+    command: &str,
+    store: &mut Store,
+    protocol: &ProtocolConfig,
+) -> Result<String, Box<dyn Error>> {
+    dispatch(command, store, protocol)
+}
+
+// Synchronous core of `dispatcher`, for callers that run commands off the
+// async workers.
+pub fn dispatch(
+    command: &str,
+    store: &mut Store,
+    protocol: &ProtocolConfig,
+) -> Result<String, Box<dyn Error>> {
+    let response = dispatch_response(command, store, protocol)?;
+    Ok(response.render_for(protocol).0)
+}
+
+// Like dispatch, but hands back the typed response before it is rendered.
+pub fn dispatch_response(
+    command: &str,
+    store: &mut Store,
+    protocol: &ProtocolConfig,
+) -> Result<Response, Box<dyn Error>> {
+    let lock = store.transaction_lock();
+    let _shared = lock.read().unwrap();
+
+    record_route(command, store, protocol)
+}
+
+// Runs a MULTI block's commands back to back while every other command waits.
+// A failing command answers with its error in the array and the rest still
+// run.
+// `aborted` is asked with the lock held, once no other command can write, and
+// a true answer runs nothing and returns null.
+pub fn exec(
+    commands: &[String],
+    store: &mut Store,
+    protocol: &ProtocolConfig,
+    aborted: impl FnOnce() -> bool,
+) -> Result<Response, Box<dyn Error>> {
+    let lock = store.transaction_lock();
+    let _exclusive = lock.write().unwrap();
+
+    if aborted() {
+        return Ok(Response::Null);
+    }
+
+    let responses = commands
+        .iter()
+        .map(|command| record_route(command, store, protocol))
+        .collect::<Result<_, _>>()?;
+    Ok(Response::Array(responses))
+}
+
+fn record_route(
+    command: &str,
+    store: &mut Store,
+    protocol: &ProtocolConfig,
+) -> Result<Response, Box<dyn Error>> {
+    let result = route(command, store, protocol);
+
+    let error = match &result {
+        Ok(_) => None,
+        Err(e) => e.downcast_ref::<KeyzError>().map(KeyzError::error_code),
+    };
+    store.record_dispatch(command_label(command), error);
+
+    match result {
+        Ok(response) => Ok(response),
+        Err(e) => match e.downcast::<KeyzError>() {
+            Ok(e) => Ok(Response::from(&*e)),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+// Unknown names are folded into one label so clients can't grow the metric's
+// or the latency monitor's label set without bound.
+pub fn command_label(command: &str) -> &'static str {
+    let name = command.split(' ').next().unwrap_or_default();
+
+    COMMANDS
+        .iter()
+        .find(|doc| doc.name.eq_ignore_ascii_case(name))
+        .map_or("unknown", |doc| doc.name)
+}
 
-pub async fn dispatcher(command: String, store: &mut Store) -> Result<String, Box<dyn Error>> {
+fn route(
+    command: &str,
+    store: &mut Store,
+    protocol: &ProtocolConfig,
+) -> Result<Response, Box<dyn Error>> {
     let splited: Vec<&str> = command.splitn(3, ' ').collect();
 
-    if splited.len() < 2 {
-        return Ok("error:invalid command".into());
+    // Only the keywords are uppercased, keys and values keep their case.
+    let command_name = splited[0].to_ascii_uppercase();
+    let command_name = command_name.as_str();
+    let arguments = command.split_once(' ').map(|(_, rest)| rest);
+
+    if store.is_read_only() && is_write(command_name, splited.get(1).copied()) {
+        return Err(KeyzError::ReadOnly.into());
     }
 
-    let command_name = splited[0];
+    match command_name {
+        INFO => return info(store, protocol),
+        METRICS if arguments.is_none() => return metrics_text(store),
+        PING => return ping(arguments),
+        RESETSTATS if arguments.is_none() => return reset_stats(store),
+        FLUSH | FLUSHDB if arguments.is_none() => return flush(store),
+        FLUSHALL if arguments.is_none() => return flush_all(store),
+        _ => (),
+    }
 
-    let key = splited[1].to_string();
+    if splited.len() < 2 {
+        return Err(KeyzError::invalid_command().into());
+    }
+
+    let key = splited[1];
 
     match command_name {
         SET => {
-            match parse_set_command(&command) {
+            match parse_set_command(command) {
                 Ok((key, value, seconds)) => set(&key, value, store, seconds),
-                Err(_) => Ok("error:set command invalid".into()),
+                Err(_) => Err(KeyzError::InvalidCommand("set command invalid".into()).into()),
             }
         }
-        GET => get(&key, store),
-        DELETE => delete(&key, store),
-        EXPIRES_IN => expires_in(&key, store),
-        _ => Ok("error:invalid command".into()),
+        SETEX | PSETEX => match parse_setex_command(command) {
+            Ok((key, seconds, value)) if command_name == SETEX => set(&key, value, store, seconds),
+            Ok((key, ttl_ms, value)) => psetex(&key, value, ttl_ms, store),
+            Err(e) => Err(e.into()),
+        },
+        MSETNX => match arguments.unwrap_or_default().split_whitespace().collect::<Vec<_>>() {
+            pairs if !pairs.is_empty() && pairs.len() % 2 == 0 => msetnx(&pairs, store),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        GET => get(key, store),
+        INCR if splited.len() == 2 => incr(key, 1, store),
+        TYPE if splited.len() == 2 => type_of(key, store),
+        LPUSH | RPUSH => match splited.get(2) {
+            Some(value) if command_name == LPUSH => push(key, value.to_string(), ListEnd::Front, store),
+            Some(value) => push(key, value.to_string(), ListEnd::Back, store),
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        LPOP if splited.len() == 2 => pop(key, ListEnd::Front, store),
+        RPOP if splited.len() == 2 => pop(key, ListEnd::Back, store),
+        LLEN if splited.len() == 2 => llen(key, store),
+        LRANGE => match splited.get(2).map(|rest| rest.split_whitespace().collect::<Vec<_>>()).as_deref() {
+            Some([start, stop]) => match (start.parse::<i64>(), stop.parse::<i64>()) {
+                (Ok(start), Ok(stop)) => lrange(key, start, stop, store),
+                _ => Err(KeyzError::InvalidCommand("lrange index invalid".into()).into()),
+            },
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        HSET => match splited.get(2).and_then(|rest| rest.split_once(' ')) {
+            Some((field, value)) if !field.is_empty() => hset(key, field, value, store),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        HGET if splited.len() == 3 => hget(key, splited[2], store),
+        HDEL if splited.len() == 3 => hdel(key, splited[2], store),
+        HEXISTS if splited.len() == 3 => hexists(key, splited[2], store),
+        HGETALL if splited.len() == 2 => hgetall(key, store),
+        HKEYS if splited.len() == 2 => hkeys(key, store),
+        HVALS if splited.len() == 2 => hvals(key, store),
+        SADD | SREM | SISMEMBER => match splited.get(2) {
+            Some(member) if command_name == SADD => sadd(key, member, store),
+            Some(member) if command_name == SREM => srem(key, member, store),
+            Some(member) => sismember(key, member, store),
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        SCARD if splited.len() == 2 => scard(key, store),
+        SMEMBERS if splited.len() == 2 => smembers(key, store),
+        SUNION => match splited.get(2).map(|rest| rest.split_whitespace().collect::<Vec<_>>()).as_deref() {
+            Some([other]) => sunion(&[key, other], store),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        SINTERSTORE => match splited.get(2).map(|rest| rest.split_whitespace().collect::<Vec<_>>()).as_deref() {
+            Some([first, second]) => sinterstore(key, &[first, second], store),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        ZADD => match splited.get(2).and_then(|rest| rest.split_once(' ')) {
+            Some((score, member)) if !member.is_empty() => match parse_score(score) {
+                Ok(score) => zadd(key, score, member, store),
+                Err(e) => Err(e.into()),
+            },
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        ZREM | ZSCORE | ZRANK => match splited.get(2) {
+            Some(member) if command_name == ZREM => zrem(key, member, store),
+            Some(member) if command_name == ZSCORE => zscore(key, member, store),
+            Some(member) => zrank(key, member, store),
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        ZRANGE => match parse_range_arguments(splited.get(2).copied()) {
+            Some((start, stop, with_scores)) => match (start.parse::<i64>(), stop.parse::<i64>()) {
+                (Ok(start), Ok(stop)) => zrange(key, start, stop, with_scores, store),
+                _ => Err(KeyzError::InvalidCommand("zrange index invalid".into()).into()),
+            },
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        ZRANGEBYSCORE => match parse_range_arguments(splited.get(2).copied()) {
+            Some((min, max, with_scores)) => match (parse_score_bound(min), parse_score_bound(max)) {
+                (Ok(min), Ok(max)) => zrange_by_score(key, min, max, with_scores, store),
+                (Err(e), _) | (_, Err(e)) => Err(e.into()),
+            },
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        SETBIT => match splited.get(2).map(|rest| rest.split_whitespace().collect::<Vec<_>>()).as_deref() {
+            Some([offset, bit]) => match (parse_bit_offset(offset), parse_bit(bit)) {
+                (Ok(offset), Ok(bit)) => setbit(key, offset, bit, store),
+                (Err(e), _) | (_, Err(e)) => Err(e.into()),
+            },
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        GETBIT => match splited.get(2).map(|offset| parse_bit_offset(offset)) {
+            Some(Ok(offset)) => getbit(key, offset, store),
+            Some(Err(e)) => Err(e.into()),
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        BITCOUNT => match splited.get(2).map(|rest| rest.split_whitespace().collect::<Vec<_>>()).as_deref() {
+            None => bitcount(key, None, store),
+            Some([start, end]) => match (start.parse::<i64>(), end.parse::<i64>()) {
+                (Ok(start), Ok(end)) => bitcount(key, Some((start, end)), store),
+                _ => Err(KeyzError::InvalidCommand("bit index invalid".into()).into()),
+            },
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        BITPOS => match splited.get(2).map(|rest| rest.split_whitespace().collect::<Vec<_>>()).as_deref() {
+            Some([bit, range @ ..]) if range.len() <= 2 => {
+                let range: Result<Vec<i64>, _> = range.iter().map(|index| index.parse::<i64>()).collect();
+                match (parse_bit(bit), range.as_deref()) {
+                    (Ok(bit), Ok(range)) => {
+                        bitpos(key, bit, range.first().copied().unwrap_or(0), range.get(1).copied(), store)
+                    }
+                    (Err(e), _) => Err(e.into()),
+                    (_, Err(_)) => Err(KeyzError::InvalidCommand("bit index invalid".into()).into()),
+                }
+            }
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        BITOP => match (BitOp::parse(key), splited.get(2).map(|rest| rest.split_whitespace().collect::<Vec<_>>())) {
+            (Some(BitOp::Not), Some(keys)) if keys.len() != 2 => {
+                Err(KeyzError::InvalidCommand("BITOP NOT takes one source key".into()).into())
+            }
+            (Some(op), Some(keys)) if keys.len() >= 2 => bitop(op, keys[0], &keys[1..], store),
+            (None, Some(_)) => Err(KeyzError::InvalidCommand("bitop operation invalid".into()).into()),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        GETEX => match parse_getex_command(command) {
+            Ok((key, modifier)) => getex(&key, modifier, store),
+            Err(_) => Err(KeyzError::InvalidCommand("getex command invalid".into()).into()),
+        },
+        DELETE => delete(key, store),
+        UNLINK => unlink(key, store),
+        TOUCH => touch(&arguments.unwrap_or_default().split_whitespace().collect::<Vec<_>>(), store),
+        EXPIRES_IN | TTL => expires_in(key, store),
+        PTTL => pttl(key, store),
+        EXPIRE | EXPIREAT | PEXPIRE | PEXPIREAT => match splited.get(2).map(|ttl| ttl.trim().parse::<u64>()) {
+            Some(Ok(seconds)) if command_name == EXPIRE => expire(key, seconds, store),
+            Some(Ok(timestamp)) if command_name == EXPIREAT => expire_at(key, timestamp, store),
+            Some(Ok(ttl_ms)) if command_name == PEXPIRE => pexpire(key, ttl_ms, store),
+            Some(Ok(timestamp_ms)) => pexpire_at(key, timestamp_ms, store),
+            _ => Err(KeyzError::InvalidCommand("expire seconds invalid".into()).into()),
+        },
+        ECHO => echo(arguments.unwrap_or_default()),
+        JGET => match splited.get(2) {
+            Some(path) => jget(key, path.trim(), store),
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        JSET => match splited.get(2).and_then(|rest| rest.split_once(' ')) {
+            Some((path, json)) => jset(key, path, json, store),
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        KEYS => keys(key, store),
+        SCAN => match parse_scan_command(arguments.unwrap_or_default()) {
+            Ok((after, pattern, count)) => scan(after.as_deref(), &pattern, count, store, protocol),
+            Err(e) => Err(e.into()),
+        },
+        DUMP if splited.len() == 2 => dump(key, store),
+        RESTORE => match splited.get(2).and_then(|rest| rest.split_once(' ')) {
+            Some((ttl_ms, blob)) => match ttl_ms.parse::<u64>() {
+                Ok(ttl_ms) => restore(key, ttl_ms, blob.trim(), store),
+                Err(_) => Err(KeyzError::InvalidCommand("restore ttl invalid".into()).into()),
+            },
+            None => Err(KeyzError::invalid_command().into()),
+        },
+        EXPIRING if splited.len() == 2 => match key.parse::<usize>() {
+            Ok(count) => expiring(count, store),
+            Err(_) => Err(KeyzError::InvalidCommand("expiring count invalid".into()).into()),
+        },
+        DELPATTERN if !protocol.allow_bulk_delete => {
+            Err(KeyzError::InvalidCommand("bulk delete disabled".into()).into())
+        }
+        DELPATTERN => delete_pattern(key, protocol.bulk_delete_max, store),
+        SELECT if splited.len() == 2 => match key.parse::<usize>() {
+            Ok(db) => select(db, store),
+            Err(_) => Err(KeyzError::InvalidCommand("db index invalid".into()).into()),
+        },
+        SWAPDB => match (key.parse::<usize>(), splited.get(2).map(|db| db.parse::<usize>())) {
+            (Ok(first), Some(Ok(second))) => swap_databases(first, second, store),
+            _ => Err(KeyzError::InvalidCommand("db index invalid".into()).into()),
+        },
+        SLOWLOG => match (subcommand_of(key, &["GET", "RESET"]), splited.get(2)) {
+            ("GET", None) => slowlog_get(SLOWLOG_DEFAULT_COUNT, store),
+            ("GET", Some(count)) => match count.trim().parse::<usize>() {
+                Ok(count) => slowlog_get(count, store),
+                Err(_) => Err(KeyzError::InvalidCommand("slowlog count invalid".into()).into()),
+            },
+            ("RESET", None) => slowlog_reset(store),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        LATENCY => {
+            let arguments: Vec<&str> = splited.get(2).map_or(Vec::new(), |rest| rest.split_whitespace().collect());
+
+            match (subcommand_of(key, &["LATEST", "HISTORY", "RESET", "GRAPH"]), arguments.as_slice()) {
+                ("LATEST", []) => latency_latest(store),
+                ("HISTORY", [event]) => latency_history(event, SLOWLOG_DEFAULT_COUNT, store),
+                ("HISTORY", [event, count]) => match count.parse::<usize>() {
+                    Ok(count) => latency_history(event, count, store),
+                    Err(_) => Err(KeyzError::InvalidCommand("latency count invalid".into()).into()),
+                },
+                ("RESET", []) => latency_reset(None, store),
+                ("RESET", [event]) => latency_reset(Some(event), store),
+                ("GRAPH", [event]) => latency_graph(event, store),
+                _ => Err(KeyzError::invalid_command().into()),
+            }
+        }
+        MEMORY => {
+            let arguments: Vec<&str> = splited.get(2).map_or(Vec::new(), |rest| rest.split_whitespace().collect());
+
+            match (subcommand_of(key, &["USAGE", "DOCTOR"]), arguments.as_slice()) {
+                ("USAGE", [key]) => memory_usage(key, false, store),
+                ("USAGE", [key, decomp]) if decomp.eq_ignore_ascii_case("DECOMP") => memory_usage(key, true, store),
+                ("DOCTOR", []) => memory_doctor(store),
+                _ => Err(KeyzError::invalid_command().into()),
+            }
+        }
+        NAMESPACE => {
+            let Some(separator) = protocol.namespace_separator else {
+                return Err(KeyzError::InvalidCommand("namespaces disabled".into()).into());
+            };
+
+            match (subcommand_of(key, &["LIST", "STATS", "FLUSH"]), splited.get(2).map(|rest| rest.trim())) {
+                ("LIST", None) => namespace_list(separator, store),
+                ("STATS", Some(namespace)) if !namespace.contains(' ') => {
+                    namespace_stats(namespace, separator, store)
+                }
+                ("FLUSH", Some(_)) if !protocol.allow_flush => {
+                    Err(KeyzError::InvalidCommand("flush disabled".into()).into())
+                }
+                ("FLUSH", Some(namespace)) if !namespace.contains(' ') => {
+                    namespace_flush(namespace, separator, store)
+                }
+                _ => Err(KeyzError::invalid_command().into()),
+            }
+        }
+        OBJECT => match (subcommand_of(key, &["ENCODING"]), splited.get(2).map(|rest| rest.trim())) {
+            ("ENCODING", Some(key)) if !key.contains(' ') => object_encoding(key, store),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        COMMAND => match (subcommand_of(key, &["COUNT", "INFO"]), splited.get(2)) {
+            ("COUNT", None) => command_count(),
+            ("INFO", names) => command_info(names.map_or(Vec::new(), |names| names.split_whitespace().collect())),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        CONFIG => match (subcommand_of(key, &["GET", "SET", "REWRITE"]), splited.get(2).map(|rest| rest.trim())) {
+            ("GET", Some(parameter)) if !parameter.contains(' ') => config_get(parameter, store),
+            ("SET", Some(rest)) => match rest.split_once(' ') {
+                Some((parameter, value)) => config_set(parameter, value.trim(), store),
+                None => Err(KeyzError::invalid_command().into()),
+            },
+            ("REWRITE", None) => config_rewrite(store, protocol),
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        DEBUG if !protocol.allow_debug => {
+            Err(KeyzError::InvalidCommand("debug disabled".into()).into())
+        }
+        DEBUG => match (subcommand_of(key, &["OBJECT", "PURGE", "PANIC", "SLEEP"]), splited.get(2)) {
+            ("OBJECT", Some(key)) => debug_object(key, store),
+            ("PURGE", None) => debug_purge(store),
+            ("PANIC", None) => debug_panic(),
+            ("PANIC", Some(locked)) if locked.trim().eq_ignore_ascii_case("LOCKED") => debug_panic_locked(store),
+            ("SLEEP", Some(secs)) => match secs.trim().parse::<f64>().map(Duration::try_from_secs_f64) {
+                Ok(Ok(duration)) => debug_sleep(duration),
+                _ => Err(KeyzError::InvalidCommand("debug sleep seconds invalid".into()).into()),
+            },
+            _ => Err(KeyzError::invalid_command().into()),
+        },
+        _ => Err(KeyzError::invalid_command().into()),
     }
 }
 
-fn parse_set_command(input: &str) -> Result<(String, String, u64), Box<dyn Error>> {
-    let re = Regex::new(r"SET\s+(\S+)\s+(.+?)(?:\s+EX)(\s+\d+)$").unwrap();
+pub fn is_write_command(command: &str) -> bool {
+    let mut parts = command.splitn(3, ' ');
+    let name = parts.next().unwrap_or_default().to_ascii_uppercase();
+    is_write(&name, parts.next())
+}
+
+// Read-only mode is enforced here, once, before any handler runs, so a new
+// command that mutates the store only has to be listed here to be covered.
+// Which of `known` a subcommand is, in any case, so arms can match it against
+// their literals without uppercasing a copy of it. Anything else is "".
+fn subcommand_of(word: &str, known: &[&'static str]) -> &'static str {
+    known.iter().copied().find(|name| name.eq_ignore_ascii_case(word)).unwrap_or("")
+}
+
+fn is_write(command_name: &str, subcommand: Option<&str>) -> bool {
+    match command_name {
+        SET | SETEX | PSETEX | MSETNX | INCR | LPUSH | RPUSH | LPOP | RPOP | HSET | HDEL | SADD | SREM | SINTERSTORE
+        | ZADD | ZREM | SETBIT | BITOP | GETEX | DELETE | UNLINK | EXPIRE | EXPIREAT | PEXPIRE | PEXPIREAT | DELPATTERN | JSET | RESTORE
+        | FLUSH | FLUSHDB | FLUSHALL | SWAPDB => true,
+        DEBUG => subcommand.is_some_and(|subcommand| subcommand.eq_ignore_ascii_case("PURGE")),
+        NAMESPACE => subcommand.is_some_and(|subcommand| subcommand.eq_ignore_ascii_case("FLUSH")),
+        _ => false,
+    }
+}
+
+pub fn parse_set_command(input: &str) -> Result<(String, String, u64), Box<dyn Error>> {
+    let re = Regex::new(r"(?i:SET)\s+(\S+)\s+(.+?)(?:\s+(?i:EX))(\s+\d+)$").unwrap();
 
     match re.captures(input) {
         Some(captures) => {
@@ -56,6 +544,132 @@ fn parse_set_command(input: &str) -> Result<(String, String, u64), Box<dyn Error
     }
 }
 
+// SETEX key seconds value, and PSETEX with milliseconds, as in Redis. The
+// value runs to the end of the line, as with SET.
+pub fn parse_setex_command(input: &str) -> Result<(String, u64, String), KeyzError> {
+    let mut parts = input.splitn(4, ' ');
+    let name = parts.next().unwrap_or_default();
+
+    let (Some(key), Some(ttl), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(KeyzError::invalid_command());
+    };
+    if key.is_empty() || value.is_empty() {
+        return Err(KeyzError::invalid_command());
+    }
+
+    match ttl.parse::<u64>() {
+        Ok(ttl) if ttl > 0 => Ok((key.to_string(), ttl, value.to_string())),
+        _ => Err(KeyzError::InvalidCommand(format!(
+            "invalid expire time in {}",
+            name.to_ascii_lowercase()
+        ))),
+    }
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count], with cursor 0 to start. COUNT
+// is how many matching keys to return, not how many to look at.
+pub fn parse_scan_command(input: &str) -> Result<(Option<String>, String, usize), KeyzError> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let [cursor, options @ ..] = words.as_slice() else {
+        return Err(KeyzError::invalid_command());
+    };
+    let mut options = options;
+
+    let after = match *cursor {
+        "0" => None,
+        cursor => match decode_cursor(cursor) {
+            Some(key) => Some(key),
+            None => return Err(KeyzError::InvalidCommand("scan cursor invalid".into())),
+        },
+    };
+
+    let mut pattern = "*".to_string();
+    let mut count = SCAN_DEFAULT_COUNT;
+    while let [option, value, rest @ ..] = options {
+        if option.eq_ignore_ascii_case("MATCH") {
+            pattern = value.to_string();
+        } else if option.eq_ignore_ascii_case("COUNT") {
+            count = match value.parse::<usize>() {
+                Ok(count) if count > 0 => count,
+                _ => return Err(KeyzError::InvalidCommand("scan count invalid".into())),
+            };
+        } else {
+            return Err(KeyzError::invalid_command());
+        }
+        options = rest;
+    }
+    if !options.is_empty() {
+        return Err(KeyzError::invalid_command());
+    }
+
+    Ok((after, pattern, count))
+}
+
+// A sorted set score: any float but NaN, so inf and -inf are fine.
+pub fn parse_score(text: &str) -> Result<f64, KeyzError> {
+    match text.parse::<f64>() {
+        Ok(score) if !score.is_nan() => Ok(score),
+        _ => Err(KeyzError::InvalidCommand("score is not a number".into())),
+    }
+}
+
+// A ZRANGEBYSCORE bound. A leading ( leaves the score itself out.
+pub fn parse_score_bound(text: &str) -> Result<ScoreBound, KeyzError> {
+    match text.strip_prefix('(') {
+        Some(score) => Ok(ScoreBound::Exclusive(parse_score(score)?)),
+        None => Ok(ScoreBound::Inclusive(parse_score(text)?)),
+    }
+}
+
+// What follows the key in ZRANGE and ZRANGEBYSCORE: both ends of the range
+// and whether WITHSCORES is given.
+fn parse_range_arguments(arguments: Option<&str>) -> Option<(&str, &str, bool)> {
+    let words: Vec<&str> = arguments?.split_whitespace().collect();
+    match *words.as_slice() {
+        [start, stop] => Some((start, stop, false)),
+        [start, stop, option] if option.eq_ignore_ascii_case("WITHSCORES") => Some((start, stop, true)),
+        _ => None,
+    }
+}
+
+pub fn parse_bit_offset(text: &str) -> Result<u64, KeyzError> {
+    match text.parse::<u64>() {
+        Ok(offset) if offset <= bits::MAX_OFFSET => Ok(offset),
+        _ => Err(KeyzError::InvalidCommand("bit offset is not an integer or out of range".into())),
+    }
+}
+
+pub fn parse_bit(text: &str) -> Result<bool, KeyzError> {
+    match text {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(KeyzError::InvalidCommand("bit is not 0 or 1".into())),
+    }
+}
+
+// GETEX key [EX seconds | PX milliseconds | PERSIST]. A TTL of 0 is refused,
+// as the key would be gone before the reply.
+pub fn parse_getex_command(input: &str) -> Result<(String, GetExModifier), Box<dyn Error>> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let [_, key, options @ ..] = words.as_slice() else {
+        return Err("error:invalid command".into());
+    };
+
+    let modifier = match *options {
+        [] => GetExModifier::None,
+        [option] if option.eq_ignore_ascii_case("PERSIST") => GetExModifier::Persist,
+        [option, ttl] if option.eq_ignore_ascii_case("EX") => GetExModifier::Ex(ttl.parse()?),
+        [option, ttl] if option.eq_ignore_ascii_case("PX") => GetExModifier::Px(ttl.parse()?),
+        _ => return Err("error:invalid command".into()),
+    };
+
+    if matches!(modifier, GetExModifier::Ex(0) | GetExModifier::Px(0)) {
+        return Err("error:invalid command".into());
+    }
+
+    Ok((key.to_string(), modifier))
+}
+
 fn command_match_with_expire(captures: Captures) -> Result<(String, String, u64), Box<dyn Error>> {
     let key = captures[1].to_string();
     let value = captures[2].to_string();
@@ -63,14 +677,14 @@ fn command_match_with_expire(captures: Captures) -> Result<(String, String, u64)
     let expire = captures.get(3).is_some();
 
     if expire {
-        Ok((key, value, captures[3].trim().parse::<u64>().unwrap()))
+        Ok((key, value, captures[3].trim().parse::<u64>()?))
     } else {
         Ok((key, value, 0))
     }
 }
 
 fn command_not_match_with_expire(input: &str) -> Result<(String, String, u64), Box<dyn Error>> {
-    let re = Regex::new(r"SET\s+(\S+)\s+(.+)(?:\s+EX\s+(\d+))?").unwrap();
+    let re = Regex::new(r"(?i:SET)\s+(\S+)\s+(.+)(?:\s+(?i:EX)\s+(\d+))?").unwrap();
     let captures = re.captures(input);
 
     match captures {
@@ -0,0 +1,106 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::Crc;
+
+use crate::server::error::KeyzError;
+
+pub const VERSION: u8 = 1;
+pub const GZIP: u8 = 1;
+
+// version, crc32, expires_at_ms and compression kind, then the payload.
+const HEADER_BYTES: usize = 1 + 4 + 8 + 1;
+
+// One key as DUMP hands it out. The checksum covers everything after itself,
+// so a blob damaged anywhere past the version byte is caught.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Dump {
+    pub expires_at_ms: u64,
+    pub compression: u8,
+    pub payload: Vec<u8>,
+}
+
+pub fn encode(dump: &Dump) -> String {
+    let mut body = Vec::with_capacity(HEADER_BYTES - 5 + dump.payload.len());
+    body.extend_from_slice(&dump.expires_at_ms.to_be_bytes());
+    body.push(dump.compression);
+    body.extend_from_slice(&dump.payload);
+
+    let mut blob = Vec::with_capacity(HEADER_BYTES + dump.payload.len());
+    blob.push(VERSION);
+    blob.extend_from_slice(&checksum(&body).to_be_bytes());
+    blob.extend_from_slice(&body);
+
+    STANDARD.encode(blob)
+}
+
+pub fn decode(blob: &str) -> Result<Dump, KeyzError> {
+    let blob = STANDARD.decode(blob).map_err(|_| invalid())?;
+    if blob.len() < HEADER_BYTES || blob[0] != VERSION {
+        return Err(invalid());
+    }
+
+    let expected = u32::from_be_bytes(blob[1..5].try_into().unwrap());
+    let body = &blob[5..];
+    let actual = checksum(body);
+    if expected != actual {
+        return Err(KeyzError::ChecksumMismatch { expected, actual });
+    }
+
+    let compression = body[8];
+    if compression != GZIP {
+        return Err(invalid());
+    }
+
+    Ok(Dump {
+        expires_at_ms: u64::from_be_bytes(body[..8].try_into().unwrap()),
+        compression,
+        payload: body[9..].to_vec(),
+    })
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+fn invalid() -> KeyzError {
+    KeyzError::InvalidCommand("dump payload invalid".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Dump {
+        Dump { expires_at_ms: 1_700_000_000_000, compression: GZIP, payload: b"payload".to_vec() }
+    }
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(decode(&encode(&sample())).unwrap(), sample());
+    }
+
+    #[test]
+    fn rejects_corrupt_blobs() {
+        let mut blob = STANDARD.decode(encode(&sample())).unwrap();
+        *blob.last_mut().unwrap() ^= 0xff;
+
+        assert!(matches!(
+            decode(&STANDARD.encode(&blob)),
+            Err(KeyzError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_blobs() {
+        let blob = STANDARD.decode(encode(&sample())).unwrap();
+
+        for bad in [
+            "not base64!".to_string(),
+            STANDARD.encode(&blob[..HEADER_BYTES - 1]),
+            STANDARD.encode([&[VERSION + 1], &blob[1..]].concat()),
+        ] {
+            assert!(matches!(decode(&bad), Err(KeyzError::InvalidCommand(_))), "{}", bad);
+        }
+    }
+}
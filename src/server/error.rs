@@ -0,0 +1,197 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::server::response::{ErrorFormat, Response};
+
+// The full code table is part of the wire contract, so codes for features
+// that do not exist yet are reserved here rather than added ad hoc later.
+// Every KeyzError variant has a code of its own; the README lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum ErrorCode {
+    InvalidCommand = 4001,
+    TtlTooLarge = 4002,
+    ChecksumMismatch = 4003,
+    KeyNotFound = 4004,
+    ValueTooLarge = 4131,
+    PayloadTooLarge = 4132,
+    MessageTooLarge = 4133,
+    KeyTooLarge = 4141,
+    AuthFailed = 4010,
+    ReadOnly = 4030,
+    WrongType = 4090,
+    RateLimited = 4029,
+    MemoryExceeded = 5030,
+    Timeout = 5040,
+    Io = 5000,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidCommand => "invalid_command",
+            ErrorCode::TtlTooLarge => "ttl_too_large",
+            ErrorCode::ChecksumMismatch => "checksum_mismatch",
+            ErrorCode::KeyNotFound => "key_not_found",
+            ErrorCode::ValueTooLarge => "value_too_large",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::MessageTooLarge => "message_too_large",
+            ErrorCode::KeyTooLarge => "key_too_large",
+            ErrorCode::AuthFailed => "auth_failed",
+            ErrorCode::ReadOnly => "read_only",
+            ErrorCode::WrongType => "wrong_type",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::MemoryExceeded => "memory_exceeded",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::Io => "io",
+        }
+    }
+
+    // The short token in `error:<TOKEN>:<message>`. Clients match on it, so
+    // an existing token never changes.
+    pub fn token(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidCommand => "INVALID_CMD",
+            ErrorCode::TtlTooLarge => "TTLTOOLARGE",
+            ErrorCode::ChecksumMismatch => "CHECKSUM",
+            ErrorCode::KeyNotFound => "NOTFOUND",
+            ErrorCode::ValueTooLarge => "TOOLARGE",
+            ErrorCode::PayloadTooLarge => "PAYLOADTOOLARGE",
+            ErrorCode::MessageTooLarge => "MSGTOOLARGE",
+            ErrorCode::KeyTooLarge => "KEYTOOLONG",
+            ErrorCode::AuthFailed => "AUTH",
+            ErrorCode::ReadOnly => "READONLY",
+            ErrorCode::WrongType => "WRONGTYPE",
+            ErrorCode::RateLimited => "RATELIMIT",
+            ErrorCode::MemoryExceeded => "OOM",
+            ErrorCode::Timeout => "TIMEOUT",
+            ErrorCode::Io => "IO",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeyzError {
+    #[error("{0}")]
+    InvalidCommand(String),
+    #[error("value for key '{key}' is {size} bytes, limit is {limit}")]
+    ValueTooLarge { key: String, size: usize, limit: usize },
+    #[error("key '{key}' is {size} bytes, limit is {limit}")]
+    KeyTooLarge { key: String, size: usize, limit: usize },
+    #[error("value decompresses to more than {limit} bytes")]
+    PayloadTooLarge { limit: usize },
+    #[error("message is {size} bytes, limit is {limit}")]
+    MessageTooLarge { size: usize, limit: usize },
+    #[error("TTL of {ttl}s exceeds maximum of {limit}s")]
+    TtlTooLarge { ttl: u64, limit: u64 },
+    #[error("checksum mismatch: expected {expected:08x}, got {actual:08x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[error("operation against a key holding a {actual}")]
+    WrongType { actual: &'static str },
+    #[error("server is in read-only mode")]
+    ReadOnly,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl KeyzError {
+    pub fn invalid_command() -> Self {
+        KeyzError::InvalidCommand("invalid command".to_string())
+    }
+
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            KeyzError::InvalidCommand(_) => ErrorCode::InvalidCommand,
+            KeyzError::ValueTooLarge { .. } => ErrorCode::ValueTooLarge,
+            KeyzError::KeyTooLarge { .. } => ErrorCode::KeyTooLarge,
+            KeyzError::PayloadTooLarge { .. } => ErrorCode::PayloadTooLarge,
+            KeyzError::MessageTooLarge { .. } => ErrorCode::MessageTooLarge,
+            KeyzError::TtlTooLarge { .. } => ErrorCode::TtlTooLarge,
+            KeyzError::ChecksumMismatch { .. } => ErrorCode::ChecksumMismatch,
+            KeyzError::WrongType { .. } => ErrorCode::WrongType,
+            KeyzError::ReadOnly => ErrorCode::ReadOnly,
+            KeyzError::Io(_) => ErrorCode::Io,
+        }
+    }
+
+    fn legacy_message(&self) -> String {
+        match self {
+            KeyzError::ValueTooLarge { .. } => "value too large".to_string(),
+            KeyzError::KeyTooLarge { .. } => "key too long".to_string(),
+            KeyzError::PayloadTooLarge { .. } => "value too large".to_string(),
+            KeyzError::MessageTooLarge { .. } => "message too large".to_string(),
+            KeyzError::TtlTooLarge { .. } => "ttl too large".to_string(),
+            KeyzError::ChecksumMismatch { .. } => "checksum mismatch".to_string(),
+            KeyzError::WrongType { .. } => "wrong type".to_string(),
+            KeyzError::ReadOnly => "read only".to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn to_response(&self, format: ErrorFormat) -> String {
+        Response::from(self).render(format)
+    }
+}
+
+impl From<&KeyzError> for Response {
+    fn from(error: &KeyzError) -> Self {
+        Response::Error {
+            code: error.error_code(),
+            message: error.legacy_message(),
+            detail: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn every_variant() -> Vec<KeyzError> {
+        vec![
+            KeyzError::invalid_command(),
+            KeyzError::ValueTooLarge { key: "k".into(), size: 2, limit: 1 },
+            KeyzError::KeyTooLarge { key: "k".into(), size: 2, limit: 1 },
+            KeyzError::PayloadTooLarge { limit: 1 },
+            KeyzError::MessageTooLarge { size: 2, limit: 1 },
+            KeyzError::TtlTooLarge { ttl: 2, limit: 1 },
+            KeyzError::ChecksumMismatch { expected: 1, actual: 2 },
+            KeyzError::WrongType { actual: "list" },
+            KeyzError::ReadOnly,
+            KeyzError::Io(io::Error::other("disk")),
+        ]
+    }
+
+    // Doesn't compile once a variant is added without a case above.
+    fn listed(error: &KeyzError) -> bool {
+        match error {
+            KeyzError::InvalidCommand(_)
+            | KeyzError::ValueTooLarge { .. }
+            | KeyzError::KeyTooLarge { .. }
+            | KeyzError::PayloadTooLarge { .. }
+            | KeyzError::MessageTooLarge { .. }
+            | KeyzError::TtlTooLarge { .. }
+            | KeyzError::ChecksumMismatch { .. }
+            | KeyzError::WrongType { .. }
+            | KeyzError::ReadOnly
+            | KeyzError::Io(_) => true,
+        }
+    }
+
+    #[test]
+    fn every_variant_has_its_own_code() {
+        let errors = every_variant();
+        assert!(errors.iter().all(listed));
+        let codes: HashSet<u16> = errors.iter().map(|error| error.error_code() as u16).collect();
+        let kinds: HashSet<&str> = errors.iter().map(|error| error.error_code().as_str()).collect();
+        let tokens: HashSet<&str> = errors.iter().map(|error| error.error_code().token()).collect();
+
+        assert_eq!(codes.len(), errors.len());
+        assert_eq!(kinds.len(), errors.len());
+        assert_eq!(tokens.len(), errors.len());
+        assert!(!codes.contains(&0));
+    }
+}
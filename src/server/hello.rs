@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::config::ProtocolConfig;
+use crate::server::dispatcher::HELLO;
+use crate::server::error::KeyzError;
+use crate::server::response::Response;
+
+// 1 is the text protocol every client starts in. 2 sends every error as
+// structured JSON, whatever protocol.structured_errors says.
+pub const LEGACY: u32 = 1;
+pub const STRUCTURED: u32 = 2;
+pub const VERSIONS: &[u32] = &[LEGACY, STRUCTURED];
+
+#[derive(Serialize)]
+struct Hello {
+    server: &'static str,
+    version: &'static str,
+    proto: u32,
+    protocols: &'static [u32],
+    features: Features,
+}
+
+#[derive(Serialize)]
+struct Features {
+    auth: bool,
+    pubsub: bool,
+    typed_responses: bool,
+}
+
+// Answers HELLO, or returns None for any other command. With a version it
+// switches the connection to it first; an unsupported one leaves the mode
+// as it was.
+pub fn hello(command: &str, proto: &mut u32) -> Option<Result<Response, KeyzError>> {
+    let mut words = command.split(' ');
+    if !words.next()?.eq_ignore_ascii_case(HELLO) {
+        return None;
+    }
+
+    match (words.next(), words.next()) {
+        (None, _) => (),
+        (Some(version), None) => match version.parse() {
+            Ok(version) if VERSIONS.contains(&version) => *proto = version,
+            _ => return Some(Err(KeyzError::InvalidCommand("protocol version unsupported".into()))),
+        },
+        _ => return Some(Err(KeyzError::invalid_command())),
+    }
+
+    let hello = Hello {
+        server: "keyz",
+        version: env!("CARGO_PKG_VERSION"),
+        proto: *proto,
+        protocols: VERSIONS,
+        features: Features { auth: false, pubsub: true, typed_responses: true },
+    };
+    Some(Response::json(&hello).map_err(|e| KeyzError::InvalidCommand(e.to_string())))
+}
+
+// The settings one command on the connection runs with.
+pub fn session_protocol(protocol: Arc<ProtocolConfig>, proto: u32) -> Arc<ProtocolConfig> {
+    if proto == STRUCTURED && !protocol.structured_errors {
+        return Arc::new(ProtocolConfig { structured_errors: true, ..(*protocol).clone() });
+    }
+    protocol
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_versions_keep_the_current_mode() {
+        let mut proto = LEGACY;
+
+        assert!(hello("GET key", &mut proto).is_none());
+        assert!(hello("hello 2", &mut proto).unwrap().is_ok());
+        assert_eq!(proto, STRUCTURED);
+
+        for command in ["HELLO 3", "HELLO two", "HELLO 1 2"] {
+            assert!(hello(command, &mut proto).unwrap().is_err(), "{}", command);
+            assert_eq!(proto, STRUCTURED);
+        }
+    }
+}
@@ -43,9 +43,10 @@ pub async fn read_message(stream: &mut TcpStream) -> Result<String, Box<dyn Erro
 
 pub async fn write_message(stream: &mut TcpStream, message: &str) -> Result<(), Box<dyn Error>> {
     let len = message.len() as u32;
-    let len_bytes = len.to_be_bytes();
-    stream.write_all(&len_bytes).await?;
-    stream.write_all(message.as_bytes()).await?;
+    let mut frame = Vec::with_capacity(4 + message.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(message.as_bytes());
+    stream.write_all(&frame).await?;
     Ok(())
 }
 
@@ -79,5 +80,5 @@ pub fn socket_address_from_string_ip(ip: String) -> Result<SocketAddr, Box<dyn E
 
     let socket_addr = SocketAddr::new(ip_addr, port);
 
-    return Ok(socket_addr);
+    Ok(socket_addr)
 }
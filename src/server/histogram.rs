@@ -0,0 +1,226 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::registry::COMMANDS;
+
+// Values below 2^SUB_BITS get a bucket each; every power of two above is split
+// into 2^SUB_BITS buckets, so a reported value is at most 1/8 above the truth.
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = SUB_BUCKETS + (64 - SUB_BITS as usize) * SUB_BUCKETS;
+
+const UNKNOWN: &str = "unknown";
+
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BITS;
+    let mantissa = (value >> shift) as usize & (SUB_BUCKETS - 1);
+
+    SUB_BUCKETS + shift as usize * SUB_BUCKETS + mantissa
+}
+
+// The largest value that lands in `index`.
+fn bucket_ceiling(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+
+    let shift = (index - SUB_BUCKETS) / SUB_BUCKETS;
+    let mantissa = ((index - SUB_BUCKETS) % SUB_BUCKETS + SUB_BUCKETS) as u64;
+
+    // Wraps to u64::MAX for the top bucket.
+    ((mantissa + 1) << shift).wrapping_sub(1)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+// Log-bucketed counts of latencies in microseconds. Recording is a couple of
+// relaxed atomic adds, so connections never wait on each other.
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, micros: u64) {
+        self.buckets[bucket(micros)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    // Percentiles read each bucket once without stopping writers, so under
+    // load they may be a few samples out of step with `count`.
+    pub fn stats(&self) -> LatencyStats {
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        let max = self.max.load(Ordering::Relaxed);
+
+        let percentile = |percentile: u64| {
+            let rank = (total * percentile).div_ceil(100).max(1);
+            let mut seen = 0;
+
+            for (index, count) in counts.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    return bucket_ceiling(index).min(max);
+                }
+            }
+            max
+        };
+
+        LatencyStats {
+            count: total,
+            p50_us: if total == 0 { 0 } else { percentile(50) },
+            p95_us: if total == 0 { 0 } else { percentile(95) },
+            p99_us: if total == 0 { 0 } else { percentile(99) },
+            max_us: max,
+        }
+    }
+
+    pub fn reset(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+// One histogram per command label, allocated up front from the registry so
+// recording never has to insert into a shared map.
+pub struct CommandHistograms {
+    commands: Vec<(&'static str, LatencyHistogram)>,
+}
+
+impl Default for CommandHistograms {
+    fn default() -> Self {
+        Self {
+            commands: COMMANDS
+                .iter()
+                .map(|doc| doc.name)
+                .chain([UNKNOWN])
+                .map(|name| (name, LatencyHistogram::default()))
+                .collect(),
+        }
+    }
+}
+
+impl CommandHistograms {
+    pub fn record(&self, command: &str, elapsed: Duration) {
+        let histogram = self
+            .commands
+            .iter()
+            .find(|(name, _)| *name == command)
+            .or_else(|| self.commands.last())
+            .map(|(_, histogram)| histogram);
+
+        if let Some(histogram) = histogram {
+            histogram.record(elapsed.as_micros() as u64);
+        }
+    }
+
+    // Commands that have not run since the last reset are left out.
+    pub fn stats(&self) -> Vec<(&'static str, LatencyStats)> {
+        self.commands
+            .iter()
+            .filter(|(_, histogram)| histogram.count() > 0)
+            .map(|(name, histogram)| (*name, histogram.stats()))
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        for (_, histogram) in &self.commands {
+            histogram.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_every_value_in_order() {
+        let mut last = 0;
+
+        for value in (0..10_000).chain([u32::MAX as u64, u64::MAX / 2, u64::MAX]) {
+            let index = bucket(value);
+            assert!(index < BUCKETS, "{}", value);
+            assert!(index >= last, "{}", value);
+            assert!(bucket_ceiling(index) >= value, "{}", value);
+            last = index;
+        }
+        assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+        assert_eq!(bucket_ceiling(BUCKETS - 1), u64::MAX);
+    }
+
+    #[test]
+    fn bucket_ceilings_stay_within_an_eighth() {
+        for value in [7, 8, 9, 15, 16, 17, 100, 1_000, 123_456, 10_000_000] {
+            let ceiling = bucket_ceiling(bucket(value));
+            assert!(ceiling >= value);
+            assert!(ceiling - value <= value / 8, "{} -> {}", value, ceiling);
+        }
+        assert_eq!(bucket_ceiling(bucket(16)), 17);
+        assert_eq!(bucket_ceiling(bucket(1024)), 1151);
+    }
+
+    #[test]
+    fn percentiles_follow_the_recorded_values() {
+        let histogram = LatencyHistogram::default();
+        for micros in 1..=100 {
+            histogram.record(micros);
+        }
+
+        let stats = histogram.stats();
+        assert_eq!(stats.count, 100);
+        assert!((50..=56).contains(&stats.p50_us), "{:?}", stats);
+        assert!((95..=100).contains(&stats.p95_us), "{:?}", stats);
+        assert!((99..=100).contains(&stats.p99_us), "{:?}", stats);
+        assert_eq!(stats.max_us, 100);
+
+        histogram.reset();
+        assert_eq!(histogram.stats(), LatencyStats { count: 0, p50_us: 0, p95_us: 0, p99_us: 0, max_us: 0 });
+    }
+
+    #[test]
+    fn unknown_commands_share_one_histogram() {
+        let histograms = CommandHistograms::default();
+        histograms.record("GET", Duration::from_micros(10));
+        histograms.record("NOPE", Duration::from_micros(20));
+        histograms.record("ALSONOPE", Duration::from_micros(30));
+
+        let stats = histograms.stats();
+        let names: Vec<&str> = stats.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["GET", "unknown"]);
+        assert_eq!(stats[1].1.count, 2);
+    }
+}
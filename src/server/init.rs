@@ -1,47 +1,149 @@
-use tokio::io::AsyncWriteExt;
+use std::backtrace::Backtrace;
+use std::error::Error;
+use std::future::Future;
+use std::net::{Shutdown, SocketAddr};
+use std::panic;
+use std::sync::{Arc, Once};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use arc_swap::ArcSwap;
+use socket2::SockRef;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{self, JoinSet};
+use tokio::time;
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
 
-use crate::server::dispatcher::dispatcher;
+use crate::config::{AccessLogScope, ProtocolConfig};
+use crate::server::access_log::AccessEntry;
+use crate::server::batch::{self, is_batch};
+use crate::server::connection::Connection;
+use crate::server::dispatcher::{command_label, dispatch_response, is_write_command};
+use crate::server::error::{ErrorCode, KeyzError};
+use crate::server::hello::{self, session_protocol};
 use crate::server::helpers;
+use crate::server::pubsub::{self, is_close, is_subscribe, serve_subscriber, PubSubHub, Subscription};
+use crate::server::replication::{serve_replica, SYNC};
+use crate::server::response::Response;
+use crate::server::socket::SocketOptions;
 use crate::server::store::Store;
+use crate::server::transaction::Transaction;
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+const SPAN_KEY_CHARS: usize = 64;
+
+// Runs one accept loop per listener, all sharing the same store, until
+// `shutdown` completes.
+pub async fn serve(
+    listeners: Vec<Arc<TcpListener>>,
+    store: Store,
+    protocol: Arc<ArcSwap<ProtocolConfig>>,
+    socket: SocketOptions,
+    shutdown: impl Future<Output = ()>,
+) {
+    store.set_listeners(listeners.iter().filter_map(|listener| listener.local_addr().ok()));
+    let pubsub = Arc::new(PubSubHub::default());
+
+    let mut accept_loops = JoinSet::new();
+    for listener in listeners {
+        let store = store.clone();
+        let protocol = protocol.clone();
+        let pubsub = pubsub.clone();
+        let socket = socket.clone();
+
+        accept_loops.spawn(async move { start(&listener, store, protocol, pubsub, &socket).await });
+    }
+
+    shutdown.await;
+    info!("shutting down");
+
+    accept_loops.shutdown().await;
+}
+
+pub async fn start(
+    listener: &TcpListener,
+    store: Store,
+    protocol: Arc<ArcSwap<ProtocolConfig>>,
+    pubsub: Arc<PubSubHub>,
+    socket: &SocketOptions,
+) {
+    install_panic_hook();
 
-pub async fn start(listener: &TcpListener) {
     loop {
-        let conn = helpers::listener_accept_conn(&listener).await.unwrap();
+        let (stream, peer) = match helpers::listener_accept_conn(listener).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!(error = %e, "failed to accept connection");
+                continue;
+            }
+        };
+
+        // Before anything is read, so the first request already goes out
+        // with them. A socket that refuses one is still served.
+        if let Err(e) = socket.apply(&stream) {
+            warn!(error = %e, %peer, "failed to set socket options");
+        }
 
-        let stream = conn.0;
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
 
-        handle_connection(stream).await;
+        handle_connection(stream, peer, id, store.clone(), protocol.clone(), pubsub.clone()).await;
     }
 }
 
-async fn handle_connection(mut stream: TcpStream) {
-    let mut store = Store::new();
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    id: u64,
+    mut store: Store,
+    protocol: Arc<ArcSwap<ProtocolConfig>>,
+    pubsub: Arc<PubSubHub>,
+) {
+    let span = info_span!("connection", id, %peer);
+    // A second handle on the socket, so it can still be shut down when a
+    // panic takes the task, and the stream, with it.
+    let socket = SockRef::from(&stream).try_clone();
+    let supervisor_store = store.clone();
+
+    let task = tokio::spawn(async move {
+        info!("connection accepted");
+
+        let mut connection = Connection::new(stream);
+        connection.set_inflight_budget(store.inflight_budget());
+        let mut transaction = Transaction::new(id);
+        let mut proto = hello::LEGACY;
 
-    tokio::spawn(async move {
         loop {
-            let command = match helpers::read_message(&mut stream).await {
-                Ok(command) => command,
-                Err(e) => {
-                    println!("[-] Failed to read command: {}", e);
+            connection.set_max_message_bytes(protocol.load().max_message_bytes);
+            connection.set_frame_timeout(Duration::from_secs(protocol.load().frame_timeout_secs));
+
+            let read = connection.read_message().await.map_err(|e| read_error_reply(&*e, &protocol.load()));
+            let command = match read {
+                Ok(Some(command)) => command,
+                Ok(None) => break,
+                // The rest of an oversized frame is still unread, so the
+                // connection can't go on after the reply.
+                Err(Some(reply)) => {
+                    let _ = connection.write_message(&reply).await;
+                    let _ = connection.shutdown().await;
                     break;
                 }
+                Err(None) => break,
             };
 
-            if command == "CLOSE" {
+            if is_close(&command, &protocol.load()) {
                 let response = "Closing connection";
-                println!("[.] Closing connection");
-                match helpers::write_message(&mut stream, &response).await {
+                match connection.write_message(response).await {
                     Ok(_) => (),
                     Err(e) => {
-                        println!("[-] Failed to write response: {}", e);
+                        warn!(error = %e, "failed to write response");
                         break;
                     }
                 }
-                match stream.shutdown().await {
+                match connection.shutdown().await {
                     Ok(_) => (),
                     Err(e) => {
-                        println!("[-] Failed to close connection closing by force: {}", e);
+                        warn!(error = %e, "failed to close connection, closing by force");
                         break;
                     }
                 }
@@ -49,23 +151,327 @@ async fn handle_connection(mut stream: TcpStream) {
                 break;
             }
 
-            let response = match dispatcher(command, &mut store).await {
+            if command.eq_ignore_ascii_case(SYNC) {
+                serve_replica(&mut connection, &store).await;
+                break;
+            }
+
+            if is_batch(&command) {
+                let reply = run_batch(&command, peer, &mut store, &protocol, &pubsub, &mut transaction, &mut proto);
+                let response = match reply.await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        error!(error = %e, "failed to dispatch command");
+                        break;
+                    }
+                };
+                match connection.write_message(&response).await {
+                    Ok(_) => continue,
+                    Err(e) => {
+                        warn!(error = %e, "failed to write response");
+                        break;
+                    }
+                }
+            }
+
+            if let Some(reply) = transaction.queue(&command, &protocol.load()) {
+                match connection.write_message(&reply).await {
+                    Ok(_) => continue,
+                    Err(e) => {
+                        warn!(error = %e, "failed to write response");
+                        break;
+                    }
+                }
+            }
+
+            if is_subscribe(&command) {
+                match serve_subscriber(&mut connection, command, &pubsub, &protocol.load_full()).await {
+                    Subscription::Ended => continue,
+                    Subscription::Closed => break,
+                }
+            }
+
+            let reply = execute(&command, peer, &mut store, &protocol, &pubsub, &mut transaction, &mut proto);
+            let response = match reply.await {
                 Ok(response) => response,
                 Err(e) => {
-                    println!("[-] Failed to dispatch command: {}", e);
+                    error!(error = %e, "failed to dispatch command");
                     break;
                 }
             };
 
-            match helpers::write_message(&mut stream, &response)
-                .await
-            {
+            match connection.write_message(&response).await {
                 Ok(_) => (),
                 Err(e) => {
-                    println!("[-] Failed to write response: {}", e);
+                    warn!(error = %e, "failed to write response");
                     break;
                 }
             }
         }
+
+        transaction.unwatch(&store);
+        info!("connection closed");
+    }.instrument(span.clone()));
+
+    tokio::spawn(async move {
+        match task.await {
+            Err(e) if e.is_panic() => {
+                supervisor_store.record_panic();
+                error!(parent: &span, "command handler panicked, closing the connection");
+
+                if let Ok(socket) = socket {
+                    let _ = socket.shutdown(Shutdown::Both);
+                }
+            }
+            _ => (),
+        }
+    });
+}
+
+// Logs every panic with a backtrace, inside whatever span was current, so a
+// connection's panic is logged with its peer. Any hook set before still runs.
+fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            error!(panic = %info, backtrace = %Backtrace::force_capture(), "panicked");
+            previous(info);
+        }));
     });
 }
+
+// Runs one command the way every command is run, timed and logged.
+async fn execute(
+    command: &str,
+    peer: SocketAddr,
+    store: &mut Store,
+    protocol: &ArcSwap<ProtocolConfig>,
+    pubsub: &PubSubHub,
+    transaction: &mut Transaction,
+    proto: &mut u32,
+) -> Result<String, Box<dyn Error>> {
+    let started = Instant::now();
+
+    let protocol = session_protocol(protocol.load_full(), *proto);
+
+    let (name, key) = describe(command);
+    let command_span = info_span!(
+        "command",
+        command.name = name,
+        command.key = truncate(key, SPAN_KEY_CHARS),
+        response.size_bytes = field::Empty,
+    );
+
+    // PUBLISH needs the hub rather than the store, so it never reaches
+    // the dispatcher.
+    let reply = run_command(command, store, &protocol, pubsub, transaction, proto);
+    let (response, failed) = reply.instrument(command_span.clone()).await?;
+    // i64 because tracing-opentelemetry exports u64 fields as strings.
+    command_span.record("response.size_bytes", response.len() as i64);
+    drop(command_span);
+
+    let elapsed = started.elapsed();
+    store.slow_log().record(command, elapsed);
+    store.latency().record(command_label(command), elapsed);
+    store.histograms().record(command_label(command), elapsed);
+    store.record_command();
+    log_access(store, peer, command, elapsed, failed);
+
+    debug!(
+        command = name,
+        key,
+        latency_us = elapsed.as_micros() as u64,
+        "command dispatched"
+    );
+
+    Ok(response)
+}
+
+// Runs every command of a batch frame in order and answers with one batch
+// of responses. A command that fails gets its error in its slot and the rest
+// still run; unlike EXEC nothing is rolled back or held back. Commands that
+// take over the connection can't be batched.
+async fn run_batch(
+    frame: &str,
+    peer: SocketAddr,
+    store: &mut Store,
+    protocol: &ArcSwap<ProtocolConfig>,
+    pubsub: &PubSubHub,
+    transaction: &mut Transaction,
+    proto: &mut u32,
+) -> Result<String, Box<dyn Error>> {
+    let config = protocol.load_full();
+    let commands = match batch::decode(frame, config.max_batch_commands) {
+        Ok(commands) => commands,
+        Err(e) => return Ok(e.to_response(config.error_format())),
+    };
+
+    let mut responses = Vec::with_capacity(commands.len());
+    for command in commands {
+        let refused = match command {
+            command if is_batch(command) => Some("batches can't be nested".to_string()),
+            command if is_close(command, &config) || command.eq_ignore_ascii_case(SYNC) || is_subscribe(command) => {
+                Some(format!("{} is not allowed in a batch", describe(command).0))
+            }
+            _ => None,
+        };
+
+        let response = if let Some(message) = refused {
+            KeyzError::InvalidCommand(message).to_response(config.error_format())
+        } else if let Some(reply) = transaction.queue(command, &config) {
+            reply
+        } else {
+            execute(command, peer, store, protocol, pubsub, transaction, proto).await?
+        };
+        responses.push(response);
+    }
+
+    let response = batch::encode(&responses);
+    if response.len() > config.max_message_bytes {
+        let message = format!("batch response exceeds {} bytes; send fewer commands", config.max_message_bytes);
+        let error = Response::Error { code: ErrorCode::ValueTooLarge, detail: message.clone(), message };
+        return Ok(error.render(config.error_format()));
+    }
+    Ok(response)
+}
+
+// The rendered response, and whether it reports a failure. Transaction
+// control, HELLO and PUBLISH need connection or server state the dispatcher
+// doesn't have, so they are answered here.
+async fn run_command(
+    command: &str,
+    store: &mut Store,
+    protocol: &Arc<ProtocolConfig>,
+    pubsub: &PubSubHub,
+    transaction: &mut Transaction,
+    proto: &mut u32,
+) -> Result<(String, bool), Box<dyn Error>> {
+    if let Some(reply) = transaction.control(command, store, protocol) {
+        return render(reply, protocol);
+    }
+
+    if let Some(reply) = hello::hello(command, proto) {
+        let reply = reply.unwrap_or_else(|e| Response::from(&e));
+        return render(Ok(reply), &session_protocol(protocol.clone(), *proto));
+    }
+
+    match pubsub::publish(command, pubsub) {
+        Some(Ok(delivered)) => return Ok((delivered, false)),
+        Some(Err(e)) => return Ok((e.to_response(protocol.error_format()), true)),
+        None => (),
+    }
+
+    match protocol.command_timeout_secs {
+        Some(secs) => dispatch_with_timeout(command, store, protocol, Duration::from_secs(secs)).await,
+        None => render(dispatch_response(command, store, protocol), protocol),
+    }
+}
+
+// The reply owed for a failed read, if any: a client that sent an oversized
+// frame is told why before it is disconnected.
+fn read_error_reply(e: &(dyn Error + 'static), protocol: &ProtocolConfig) -> Option<String> {
+    debug!(error = %e, "failed to read command");
+
+    match e.downcast_ref::<KeyzError>() {
+        Some(e @ KeyzError::MessageTooLarge { .. }) => Some(e.to_response(protocol.error_format())),
+        _ => None,
+    }
+}
+
+fn render(
+    response: Result<Response, Box<dyn Error>>,
+    protocol: &ProtocolConfig,
+) -> Result<(String, bool), Box<dyn Error>> {
+    Ok(response?.render_for(protocol))
+}
+
+// Runs the command on the blocking pool so a slow command can't hold the
+// connection past the deadline. The command itself isn't cancelled: it runs to
+// completion in the background and any store mutation it makes still lands,
+// only its response is replaced with the timeout response.
+async fn dispatch_with_timeout(
+    command: &str,
+    store: &mut Store,
+    protocol: &Arc<ProtocolConfig>,
+    limit: Duration,
+) -> Result<(String, bool), Box<dyn Error>> {
+    let task = {
+        let command = command.to_owned();
+        let mut store = store.clone();
+        let protocol = protocol.clone();
+        let span = Span::current();
+
+        task::spawn_blocking(move || {
+            let response = span.in_scope(|| {
+                render(dispatch_response(&command, &mut store, &protocol), &protocol).map_err(|e| e.to_string())
+            });
+            (response, store.selected_db())
+        })
+    };
+
+    // The command ran on a clone of the store, so a SELECT has to be carried
+    // back to this connection's one.
+    match time::timeout(limit, task).await {
+        Ok(Ok((response, db))) => {
+            store.select(db)?;
+            response.map_err(Into::into)
+        }
+        // Carried over so the connection's supervisor sees it.
+        Ok(Err(e)) => match e.try_into_panic() {
+            Ok(panic) => panic::resume_unwind(panic),
+            Err(e) => Err(e.into()),
+        },
+        Err(_) => {
+            store.record_command_timeout();
+            warn!(command = describe(command).0, "command timed out");
+            let message = protocol.command_timeout_message().to_string();
+            let response = Response::Error {
+                code: ErrorCode::Timeout,
+                message: message.clone(),
+                detail: message,
+            };
+            Ok((response.render(protocol.error_format()), true))
+        }
+    }
+}
+
+fn log_access(store: &Store, peer: SocketAddr, command: &str, elapsed: Duration, failed: bool) {
+    let Some(access_log) = store.access_log() else {
+        return;
+    };
+    if access_log.scope() == AccessLogScope::Writes && !is_write_command(command) {
+        return;
+    }
+
+    let (name, key) = describe(command);
+    access_log.record(AccessEntry {
+        ts_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+        peer: peer.to_string(),
+        db: store.selected_db(),
+        command: name.to_string(),
+        key: key.to_string(),
+        duration_us: elapsed.as_micros() as u64,
+        status: if failed { "error" } else { "ok" },
+    });
+}
+
+// Only the command name and key are logged so values never end up in logs.
+fn describe(command: &str) -> (&str, &str) {
+    let mut parts = command.splitn(3, ' ');
+    let name = parts.next().unwrap_or_default();
+    let key = parts.next().unwrap_or_default();
+
+    (name, key)
+}
+
+fn truncate(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
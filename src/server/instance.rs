@@ -0,0 +1,158 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::config::{Config, ProtocolConfig};
+use crate::server::access_log::AccessLog;
+use crate::server::socket::SocketOptions;
+use crate::server::{helpers, init, prometheus, replication, seed, store::Store};
+
+/// A keyz server that can be embedded in another binary.
+///
+/// `bind` opens every configured listener, `run` serves them until
+/// `shutdown` is called. Share the server through an `Arc` to call
+/// `shutdown` from another task while `run` is in progress.
+pub struct Server {
+    listeners: Vec<Arc<TcpListener>>,
+    store: Store,
+    protocol: Arc<ArcSwap<ProtocolConfig>>,
+    replicate_from: Option<SocketAddr>,
+    socket: SocketOptions,
+    // Taken by the first `run`, since axum needs to own the listener.
+    prometheus: Mutex<Option<TcpListener>>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Server {
+    /// Binds all listen addresses from `config.server`, plus the metrics
+    /// endpoint if `[prometheus]` is enabled, and opens the access log if
+    /// one is configured. Fails with an error naming the address or file if
+    /// any of them cannot be opened.
+    pub async fn bind(config: &Config) -> Result<Server, Box<dyn Error>> {
+        let mut listeners = Vec::new();
+
+        for addr in config.server.listen_addresses()? {
+            let listener = helpers::create_listener(addr)
+                .await
+                .map_err(|e| format!("failed to bind {}: {}", addr, e))?;
+            listeners.push(Arc::new(listener));
+        }
+
+        let store = Store::new(&config.store);
+        store.set_max_payload_bytes(config.protocol.max_message_bytes);
+        if let Some(limit) = config.protocol.max_inflight_bytes {
+            store.set_max_inflight_bytes(limit);
+        }
+        // Before read_only is applied, so a read-only server can still start
+        // with data.
+        if let Some(path) = &config.store.seed_path {
+            seed::seed(&store, path, config.store.seed_strict, &config.protocol)?;
+        }
+        store.set_read_only(config.server.read_only);
+
+        let replicate_from = config.replication.primary_address()?;
+        store.set_replica(replicate_from.is_some());
+
+        if let Some(access_log) = config.logging.access_log() {
+            let opened = AccessLog::open(&access_log)
+                .map_err(|e| format!("failed to open access log {}: {}", access_log.path.display(), e))?;
+            store.set_access_log(opened);
+        }
+
+        let prometheus = match config.prometheus.listen_address()? {
+            Some(addr) => Some(
+                helpers::create_listener(addr)
+                    .await
+                    .map_err(|e| format!("failed to bind {}: {}", addr, e))?,
+            ),
+            None => None,
+        };
+
+        Ok(Server {
+            listeners,
+            store,
+            protocol: Arc::new(ArcSwap::from_pointee(config.protocol.clone())),
+            replicate_from,
+            socket: SocketOptions::from_config(&config.server),
+            prometheus: Mutex::new(prometheus),
+            shutdown: watch::channel(false).0,
+        })
+    }
+
+    /// The address of the first listener, useful after binding port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        match self.listeners.first() {
+            Some(listener) => Ok(listener.local_addr()?),
+            None => Err("server has no listeners".into()),
+        }
+    }
+
+    /// The addresses of all listeners.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect()
+    }
+
+    /// The store shared by every connection.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// The live protocol settings, which can be swapped while running.
+    pub fn protocol(&self) -> Arc<ArcSwap<ProtocolConfig>> {
+        self.protocol.clone()
+    }
+
+    /// Accepts connections on every listener, and replicates from the
+    /// configured primary, until `shutdown` is called. Connections that are
+    /// already open are left to finish on their own.
+    pub async fn run(&self) {
+        let mut shutdown = self.shutdown.subscribe();
+
+        let replication = self.replicate_from.map(|primary| {
+            info!(primary = %primary, "replicating");
+            tokio::spawn(replication::replicate_from(primary, self.store.clone()))
+        });
+
+        let metrics = self
+            .prometheus
+            .lock()
+            .unwrap()
+            .take()
+            .map(|listener| tokio::spawn(prometheus::serve(listener, self.store.clone())));
+
+        init::serve(
+            self.listeners.clone(),
+            self.store.clone(),
+            self.protocol.clone(),
+            self.socket.clone(),
+            async move {
+                while !*shutdown.borrow() {
+                    if shutdown.changed().await.is_err() {
+                        break;
+                    }
+                }
+            },
+        )
+        .await;
+
+        if let Some(replication) = replication {
+            replication.abort();
+        }
+        if let Some(metrics) = metrics {
+            metrics.abort();
+        }
+    }
+
+    /// Stops `run` from accepting new connections.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+}
@@ -0,0 +1,208 @@
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+// Dotted/bracket selectors such as `user.tags[0]`. A leading `$` names the
+// document root and may be followed by a selector (`$`, `$.user`, `$[0]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum PathError {
+    #[error("invalid path")]
+    Invalid,
+    #[error("path type mismatch")]
+    TypeMismatch,
+    #[error("index out of range")]
+    IndexOutOfRange,
+}
+
+pub fn parse(path: &str) -> Result<Vec<Segment>, PathError> {
+    let (rooted, rest) = match path.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, path),
+    };
+
+    if !rooted && rest.is_empty() {
+        return Err(PathError::Invalid);
+    }
+
+    let mut segments = Vec::new();
+    let mut chars = rest.char_indices().peekable();
+
+    // Without `$` the path may start straight with a key.
+    if !rooted && !rest.starts_with(['.', '[']) {
+        segments.push(Segment::Key(read_key(rest, &mut chars)?));
+    }
+
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '.' => segments.push(Segment::Key(read_key(rest, &mut chars)?)),
+            '[' => {
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, ']')) => break,
+                        Some((_, c)) if c.is_ascii_digit() => digits.push(c),
+                        _ => return Err(PathError::Invalid),
+                    }
+                }
+                let index = digits.parse().map_err(|_| PathError::Invalid)?;
+                segments.push(Segment::Index(index));
+            }
+            _ => return Err(PathError::Invalid),
+        }
+    }
+
+    Ok(segments)
+}
+
+fn read_key(
+    path: &str,
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+) -> Result<String, PathError> {
+    let start = match chars.peek() {
+        Some((start, _)) => *start,
+        None => return Err(PathError::Invalid),
+    };
+    let mut end = start;
+
+    while let Some((i, c)) = chars.peek() {
+        if matches!(c, '.' | '[' | ']') {
+            break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+
+    match &path[start..end] {
+        "" => Err(PathError::Invalid),
+        key => Ok(key.to_string()),
+    }
+}
+
+pub fn get<'a>(document: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(document, |current, segment| match segment {
+        Segment::Key(key) => current.get(key),
+        Segment::Index(index) => current.get(index),
+    })
+}
+
+// Null stand-ins along the way become objects or arrays, and an index one past
+// the end of an array appends, so a path into an empty document can always be
+// set.
+pub fn set(document: &mut Value, path: &[Segment], value: Value) -> Result<(), PathError> {
+    let mut current = document;
+
+    for segment in path {
+        current = match segment {
+            Segment::Key(key) => {
+                if current.is_null() {
+                    *current = Value::Object(Map::new());
+                }
+                match current {
+                    Value::Object(map) => map.entry(key.clone()).or_insert(Value::Null),
+                    _ => return Err(PathError::TypeMismatch),
+                }
+            }
+            Segment::Index(index) => {
+                if current.is_null() {
+                    *current = Value::Array(Vec::new());
+                }
+                match current {
+                    Value::Array(items) => {
+                        if *index == items.len() {
+                            items.push(Value::Null);
+                        }
+                        items.get_mut(*index).ok_or(PathError::IndexOutOfRange)?
+                    }
+                    _ => return Err(PathError::TypeMismatch),
+                }
+            }
+        };
+    }
+
+    *current = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn key(key: &str) -> Segment {
+        Segment::Key(key.to_string())
+    }
+
+    #[test]
+    fn parses_dotted_and_bracket_selectors() {
+        assert_eq!(parse("user").unwrap(), vec![key("user")]);
+        assert_eq!(
+            parse("user.tags[0]").unwrap(),
+            vec![key("user"), key("tags"), Segment::Index(0)]
+        );
+        assert_eq!(
+            parse("matrix[1][12].cell").unwrap(),
+            vec![key("matrix"), Segment::Index(1), Segment::Index(12), key("cell")]
+        );
+        assert_eq!(parse("$").unwrap(), vec![]);
+        assert_eq!(parse("$.user").unwrap(), vec![key("user")]);
+        assert_eq!(parse("$[2]").unwrap(), vec![Segment::Index(2)]);
+        assert_eq!(parse("[2]").unwrap(), vec![Segment::Index(2)]);
+        assert_eq!(parse("naïve.ключ").unwrap(), vec![key("naïve"), key("ключ")]);
+    }
+
+    #[test]
+    fn rejects_malformed_selectors() {
+        for path in ["", "a.", "a..b", ".", "a[", "a[]", "a[x]", "a[-1]", "a]", "$a", "a[0]b"] {
+            assert_eq!(parse(path), Err(PathError::Invalid), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn gets_nested_values() {
+        let doc = json!({"user": {"tags": ["a", "b"], "age": 3}});
+
+        assert_eq!(get(&doc, &parse("user.tags[1]").unwrap()), Some(&json!("b")));
+        assert_eq!(get(&doc, &parse("user.age").unwrap()), Some(&json!(3)));
+        assert_eq!(get(&doc, &parse("$").unwrap()), Some(&doc));
+        assert_eq!(get(&doc, &parse("user.tags[2]").unwrap()), None);
+        assert_eq!(get(&doc, &parse("user.missing.deeper").unwrap()), None);
+        assert_eq!(get(&doc, &parse("user[0]").unwrap()), None);
+    }
+
+    #[test]
+    fn sets_create_intermediate_objects() {
+        let mut doc = Value::Null;
+        set(&mut doc, &parse("a.b.c").unwrap(), json!(1)).unwrap();
+        assert_eq!(doc, json!({"a": {"b": {"c": 1}}}));
+
+        set(&mut doc, &parse("a.list").unwrap(), json!([])).unwrap();
+        set(&mut doc, &parse("a.list[0]").unwrap(), json!("x")).unwrap();
+        set(&mut doc, &parse("a.list[1].name").unwrap(), json!("y")).unwrap();
+        assert_eq!(doc["a"]["list"], json!(["x", {"name": "y"}]));
+
+        set(&mut doc, &parse("a.grid[0][0]").unwrap(), json!(0)).unwrap();
+        assert_eq!(doc["a"]["grid"], json!([[0]]));
+
+        set(&mut doc, &parse("$").unwrap(), json!(true)).unwrap();
+        assert_eq!(doc, json!(true));
+    }
+
+    #[test]
+    fn sets_reject_conflicting_shapes() {
+        let mut doc = json!({"n": 1, "list": [0]});
+
+        assert_eq!(set(&mut doc, &parse("n.x").unwrap(), json!(1)), Err(PathError::TypeMismatch));
+        assert_eq!(set(&mut doc, &parse("n[0]").unwrap(), json!(1)), Err(PathError::TypeMismatch));
+        assert_eq!(
+            set(&mut doc, &parse("list[5]").unwrap(), json!(1)),
+            Err(PathError::IndexOutOfRange)
+        );
+        assert_eq!(doc, json!({"n": 1, "list": [0]}));
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+const SAMPLES_PER_EVENT: usize = 128;
+const GRAPH_WIDTH: usize = 20;
+const GRAPH_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Serialize)]
+pub struct LatencySummary {
+    pub event: String,
+    pub p50_us: u64,
+    pub p99_us: u64,
+    pub last_us: u64,
+    pub age_secs: u64,
+}
+
+struct Samples {
+    latencies_us: VecDeque<u64>,
+    last_at: Instant,
+}
+
+// Keeps the most recent latencies of every command name, keyed by the bounded
+// set of labels the dispatcher hands out.
+#[derive(Default)]
+pub struct LatencyMonitor {
+    events: Mutex<BTreeMap<&'static str, Samples>>,
+}
+
+impl LatencyMonitor {
+    pub fn record(&self, event: &'static str, elapsed: Duration) {
+        let mut events = self.events.lock().unwrap();
+        let samples = events.entry(event).or_insert_with(|| Samples {
+            latencies_us: VecDeque::with_capacity(SAMPLES_PER_EVENT),
+            last_at: Instant::now(),
+        });
+
+        if samples.latencies_us.len() == SAMPLES_PER_EVENT {
+            samples.latencies_us.pop_front();
+        }
+        samples.latencies_us.push_back(elapsed.as_micros() as u64);
+        samples.last_at = Instant::now();
+    }
+
+    pub fn latest(&self) -> Vec<LatencySummary> {
+        let events = self.events.lock().unwrap();
+
+        events
+            .iter()
+            .map(|(event, samples)| {
+                let mut sorted: Vec<u64> = samples.latencies_us.iter().copied().collect();
+                sorted.sort_unstable();
+
+                LatencySummary {
+                    event: event.to_string(),
+                    p50_us: percentile(&sorted, 50),
+                    p99_us: percentile(&sorted, 99),
+                    last_us: samples.latencies_us.back().copied().unwrap_or_default(),
+                    age_secs: samples.last_at.elapsed().as_secs(),
+                }
+            })
+            .collect()
+    }
+
+    // Returns how many events were cleared.
+    pub fn reset(&self, event: Option<&str>) -> usize {
+        let mut events = self.events.lock().unwrap();
+
+        match event {
+            Some(event) => events.remove(event).map_or(0, |_| 1),
+            None => {
+                let cleared = events.len();
+                events.clear();
+                cleared
+            }
+        }
+    }
+
+    // One character per sample, scaled between the fastest and slowest of the
+    // last GRAPH_WIDTH samples. None if the event has no samples.
+    pub fn graph(&self, event: &str) -> Option<String> {
+        let events = self.events.lock().unwrap();
+        let samples = &events.get(event)?.latencies_us;
+
+        let recent: Vec<u64> = samples
+            .iter()
+            .skip(samples.len().saturating_sub(GRAPH_WIDTH))
+            .copied()
+            .collect();
+        let min = recent.iter().copied().min().unwrap_or_default();
+        let max = recent.iter().copied().max().unwrap_or_default();
+        let top = GRAPH_LEVELS.len() as u64 - 1;
+
+        Some(
+            recent
+                .iter()
+                .map(|latency| match max - min {
+                    0 => GRAPH_LEVELS[0],
+                    range => GRAPH_LEVELS[((latency - min) * top / range) as usize],
+                })
+                .collect(),
+        )
+    }
+}
+
+fn percentile(sorted: &[u64], percentile: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = (sorted.len() * percentile).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
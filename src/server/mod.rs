@@ -1,5 +1,28 @@
+pub mod access_log;
+pub mod batch;
+pub mod bits;
 pub mod commands;
+pub mod connection;
 pub mod dispatcher;
+pub mod dump;
+pub mod error;
+pub mod glob;
+pub mod hello;
 pub mod helpers;
+pub mod histogram;
 pub mod init;
-pub mod store;
\ No newline at end of file
+pub mod instance;
+pub mod jsonpath;
+pub mod latency;
+pub mod prometheus;
+pub mod pubsub;
+pub mod registry;
+pub mod replication;
+pub mod response;
+pub mod seed;
+pub mod slowlog;
+pub mod socket;
+pub mod store;
+pub mod transaction;
+
+pub mod value_type;
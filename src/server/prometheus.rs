@@ -0,0 +1,106 @@
+use std::time::Instant;
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use prometheus::{Encoder, Gauge, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+use crate::server::error::ErrorCode;
+use crate::server::store::{Store, StoreStats};
+
+// Counters are bumped as commands are dispatched; the gauges are refreshed
+// from a stats snapshot each time /metrics is scraped.
+pub struct PrometheusMetrics {
+    registry: Registry,
+    commands_total: IntCounterVec,
+    errors_total: IntCounterVec,
+    keys_count: IntGauge,
+    compressed_keys: IntGauge,
+    hit_ratio: Gauge,
+    uptime_seconds: Gauge,
+    started: Instant,
+}
+
+impl Default for PrometheusMetrics {
+    fn default() -> Self {
+        let commands_total = IntCounterVec::new(
+            Opts::new("keyz_commands_total", "Commands dispatched, by command"),
+            &["command"],
+        )
+        .unwrap();
+        let errors_total = IntCounterVec::new(
+            Opts::new("keyz_errors_total", "Error responses, by kind"),
+            &["kind"],
+        )
+        .unwrap();
+        let keys_count = IntGauge::new("keyz_keys_count", "Keys in the store").unwrap();
+        let compressed_keys =
+            IntGauge::new("keyz_compressed_keys", "Keys stored compressed").unwrap();
+        let hit_ratio = Gauge::new("keyz_hit_ratio", "GET hits over hits plus misses").unwrap();
+        let uptime_seconds =
+            Gauge::new("keyz_uptime_seconds", "Seconds since the store was created").unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(commands_total.clone())).unwrap();
+        registry.register(Box::new(errors_total.clone())).unwrap();
+        registry.register(Box::new(keys_count.clone())).unwrap();
+        registry.register(Box::new(compressed_keys.clone())).unwrap();
+        registry.register(Box::new(hit_ratio.clone())).unwrap();
+        registry.register(Box::new(uptime_seconds.clone())).unwrap();
+
+        Self {
+            registry,
+            commands_total,
+            errors_total,
+            keys_count,
+            compressed_keys,
+            hit_ratio,
+            uptime_seconds,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl PrometheusMetrics {
+    pub fn record_command(&self, command: &str, error: Option<ErrorCode>) {
+        self.commands_total.with_label_values(&[command]).inc();
+
+        if let Some(code) = error {
+            self.errors_total.with_label_values(&[code.as_str()]).inc();
+        }
+    }
+
+    pub fn render(&self, stats: &StoreStats) -> String {
+        // Every value is gzip-compressed on insert.
+        self.keys_count.set(stats.keys as i64);
+        self.compressed_keys.set(stats.keys as i64);
+        self.hit_ratio.set(stats.metrics.hit_ratio);
+        self.uptime_seconds.set(self.started.elapsed().as_secs_f64());
+
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!(error = %e, "failed to encode prometheus metrics");
+        }
+
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+pub async fn serve(listener: TcpListener, store: Store) {
+    let addr = listener.local_addr().ok();
+    let app = Router::new().route("/metrics", get(metrics)).with_state(store);
+
+    info!(addr = ?addr, "serving prometheus metrics");
+
+    if let Err(e) = axum::serve(listener, app).await {
+        error!(error = %e, "prometheus endpoint failed");
+    }
+}
+
+async fn metrics(State(store): State<Store>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, TextEncoder::new().format_type().to_string())],
+        store.render_prometheus(),
+    )
+}
+
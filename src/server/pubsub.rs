@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use tokio::sync::mpsc::{self, Sender};
+use tracing::{debug, warn};
+
+use crate::config::ProtocolConfig;
+use crate::server::connection::Connection;
+use crate::server::dispatcher::{PING, PUBLISH, SUBSCRIBE, UNSUBSCRIBE};
+use crate::server::error::KeyzError;
+
+// Messages a subscriber may fall behind by before PUBLISH starts skipping it.
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+// Every subscribed connection's sender, by channel. Shared by all listeners.
+#[derive(Default)]
+pub struct PubSubHub {
+    channels: Mutex<HashMap<String, Vec<Sender<String>>>>,
+}
+
+impl PubSubHub {
+    pub fn subscribe(&self, channel: &str, sender: Sender<String>) {
+        let mut channels = self.channels.lock().unwrap();
+        channels.entry(channel.to_string()).or_default().push(sender);
+    }
+
+    pub fn unsubscribe(&self, channel: &str, sender: &Sender<String>) {
+        let mut channels = self.channels.lock().unwrap();
+
+        if let Some(senders) = channels.get_mut(channel) {
+            senders.retain(|s| !s.same_channel(sender));
+            if senders.is_empty() {
+                channels.remove(channel);
+            }
+        }
+    }
+
+    // Returns how many subscribers got the message. One whose buffer is full
+    // misses it rather than holding up the publisher.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(senders) = channels.get_mut(channel) else {
+            return 0;
+        };
+
+        let frame = format!("message {} {}", channel, message);
+        let mut delivered = 0;
+        senders.retain(|sender| match sender.try_send(frame.clone()) {
+            Ok(()) => {
+                delivered += 1;
+                true
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                warn!(channel, "subscriber is falling behind, dropping message");
+                true
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+
+        if senders.is_empty() {
+            channels.remove(channel);
+        }
+        delivered
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.lock().unwrap().len()
+    }
+}
+
+pub enum Subscription {
+    // The connection unsubscribed from everything and takes normal commands
+    // again.
+    Ended,
+    Closed,
+}
+
+enum Event {
+    Command(Result<Option<String>, String>),
+    Message(String),
+}
+
+// Runs a connection in subscribe mode, starting with the SUBSCRIBE that put
+// it there. Only SUBSCRIBE, UNSUBSCRIBE, PING and CLOSE are accepted until the
+// last channel is dropped; published messages are written as they arrive.
+pub async fn serve_subscriber(
+    connection: &mut Connection,
+    first: String,
+    hub: &PubSubHub,
+    protocol: &ProtocolConfig,
+) -> Subscription {
+    let (sender, mut receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+    let mut channels = HashSet::new();
+    let mut command = Some(first);
+
+    let outcome = loop {
+        if let Some(command) = command.take() {
+            let (name, channel) = split(&command);
+
+            let reply = match (name.as_str(), channel) {
+                (SUBSCRIBE, Some(channel)) => {
+                    if channels.insert(channel.to_string()) {
+                        hub.subscribe(channel, sender.clone());
+                    }
+                    format!("subscribe {} {}", channel, channels.len())
+                }
+                (UNSUBSCRIBE, Some(channel)) => {
+                    if channels.remove(channel) {
+                        hub.unsubscribe(channel, &sender);
+                    }
+                    format!("unsubscribe {} {}", channel, channels.len())
+                }
+                (PING, None) => "pong".to_string(),
+                _ if is_close(&command, protocol) => {
+                    let _ = connection.write_message("Closing connection").await;
+                    let _ = connection.shutdown().await;
+                    break Subscription::Closed;
+                }
+                _ => KeyzError::InvalidCommand(
+                    "only SUBSCRIBE, UNSUBSCRIBE, PING and CLOSE are allowed while subscribed".into(),
+                )
+                .to_response(protocol.error_format()),
+            };
+
+            if let Err(e) = connection.write_message(&reply).await {
+                warn!(error = %e, "failed to write response");
+                break Subscription::Closed;
+            }
+            if channels.is_empty() {
+                break Subscription::Ended;
+            }
+        }
+
+        // Read errors become strings here: a boxed error isn't Send, so it
+        // can't be held across the write below.
+        let event = tokio::select! {
+            message = connection.read_message() => Event::Command(message.map_err(|e| e.to_string())),
+            // Never None: `sender` is held for the whole loop.
+            Some(message) = receiver.recv() => Event::Message(message),
+        };
+
+        match event {
+            Event::Command(Ok(Some(message))) => command = Some(message),
+            Event::Command(Ok(None)) => break Subscription::Closed,
+            Event::Command(Err(e)) => {
+                debug!(error = %e, "failed to read command");
+                break Subscription::Closed;
+            }
+            Event::Message(message) => {
+                if let Err(e) = connection.write_message(&message).await {
+                    warn!(error = %e, "failed to write message");
+                    break Subscription::Closed;
+                }
+            }
+        }
+    };
+
+    for channel in &channels {
+        hub.unsubscribe(channel, &sender);
+    }
+    outcome
+}
+
+// Answers PUBLISH, or returns None for any other command.
+pub fn publish(command: &str, hub: &PubSubHub) -> Option<Result<String, KeyzError>> {
+    let (name, rest) = command.split_once(' ')?;
+    if !name.eq_ignore_ascii_case(PUBLISH) {
+        return None;
+    }
+
+    Some(match rest.split_once(' ') {
+        Some((channel, message)) => Ok(hub.publish(channel, message).to_string()),
+        None => Err(KeyzError::invalid_command()),
+    })
+}
+
+pub fn is_subscribe(command: &str) -> bool {
+    command.split(' ').next().is_some_and(|name| name.eq_ignore_ascii_case(SUBSCRIBE))
+}
+
+pub fn is_close(command: &str, protocol: &ProtocolConfig) -> bool {
+    match protocol.close_command_case_insensitive {
+        true => command.eq_ignore_ascii_case("CLOSE"),
+        false => command == "CLOSE",
+    }
+}
+
+// The uppercased name and, for a command of exactly two words, its argument.
+fn split(command: &str) -> (String, Option<&str>) {
+    let mut parts = command.split(' ');
+    let name = parts.next().unwrap_or_default().to_ascii_uppercase();
+
+    match (parts.next(), parts.next()) {
+        (Some(channel), None) if !channel.is_empty() => (name, Some(channel)),
+        _ => (name, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_reaches_every_subscriber_of_the_channel() {
+        let hub = PubSubHub::default();
+        let (first, mut first_rx) = mpsc::channel(4);
+        let (second, mut second_rx) = mpsc::channel(4);
+        hub.subscribe("news", first.clone());
+        hub.subscribe("news", second);
+        hub.subscribe("other", first.clone());
+
+        assert_eq!(hub.publish("news", "hello"), 2);
+        assert_eq!(first_rx.recv().await.unwrap(), "message news hello");
+        assert_eq!(second_rx.recv().await.unwrap(), "message news hello");
+        assert_eq!(hub.publish("nobody", "hello"), 0);
+
+        hub.unsubscribe("news", &first);
+        assert_eq!(hub.publish("news", "again"), 1);
+
+        drop(second_rx);
+        assert_eq!(hub.publish("news", "gone"), 0);
+        assert_eq!(hub.channel_count(), 1);
+    }
+}
@@ -0,0 +1,743 @@
+use serde::Serialize;
+
+use super::dispatcher::{
+    BITCOUNT, BITOP, BITPOS, CLOSE, COMMAND, CONFIG, DEBUG, DELETE, DELPATTERN, DISCARD, DUMP, ECHO, EXEC,
+    EXPIRE, EXPIREAT, EXPIRES_IN, EXPIRING, FLUSH, FLUSHALL, FLUSHDB, GET, GETBIT, GETEX, HDEL, HELLO,
+    HEXISTS, HGET, HGETALL, HKEYS, HSET, HVALS, INCR, INFO, JGET, JSET, KEYS, LATENCY, LLEN, LPOP, LPUSH,
+    LRANGE, MEMORY, METRICS, MSETNX, MULTI, NAMESPACE, OBJECT, PEXPIRE, PEXPIREAT, PING, PSETEX, PTTL,
+    PUBLISH, RESET, RESETSTATS, RESTORE, RPOP, RPUSH, SADD, SCAN, SCARD, SELECT, SET, SETBIT, SETEX,
+    SINTERSTORE, SISMEMBER, SLOWLOG, SMEMBERS, SREM, SUBSCRIBE, SUNION, SWAPDB, TOUCH, TTL, TYPE, UNLINK,
+    UNSUBSCRIBE, UNWATCH, WATCH, ZADD, ZRANGE, ZRANGEBYSCORE, ZRANK, ZREM, ZSCORE,
+};
+
+// Documentation for every command a client can send, served by COMMAND INFO.
+// Positive arity is the exact number of words including the name, negative
+// the minimum.
+#[derive(Debug, Serialize)]
+pub struct CommandDoc {
+    pub name: &'static str,
+    pub arity: i32,
+    pub flags: &'static [&'static str],
+    pub syntax: &'static str,
+    pub description: &'static str,
+    pub notes: &'static str,
+}
+
+pub const COMMANDS: &[CommandDoc] = &[
+    CommandDoc {
+        name: SET,
+        arity: -3,
+        flags: &["write"],
+        syntax: "SET key value [EX secs]",
+        description: "Insert or update a key, optionally expiring after secs seconds",
+        notes: "The value runs to the end of the line, so it may contain spaces",
+    },
+    CommandDoc {
+        name: SETEX,
+        arity: -4,
+        flags: &["write"],
+        syntax: "SETEX key seconds value",
+        description: "Insert or update a key that expires after seconds seconds",
+        notes: "The same as SET key value EX seconds; seconds must be positive",
+    },
+    CommandDoc {
+        name: PSETEX,
+        arity: -4,
+        flags: &["write"],
+        syntax: "PSETEX key milliseconds value",
+        description: "Insert or update a key that expires after milliseconds milliseconds",
+        notes: "Like SETEX with the TTL in milliseconds, which is never jittered",
+    },
+    CommandDoc {
+        name: MSETNX,
+        arity: -3,
+        flags: &["write"],
+        syntax: "MSETNX key value [key value ...]",
+        description: "Set every pair only if none of the keys exists",
+        notes: "Returns 1 if all were set and 0 if none were; values can't contain spaces",
+    },
+    CommandDoc {
+        name: GET,
+        arity: 2,
+        flags: &["readonly", "fast"],
+        syntax: "GET key",
+        description: "Return the value of a key",
+        notes: "Returns null for missing and expired keys",
+    },
+    CommandDoc {
+        name: INCR,
+        arity: 2,
+        flags: &["write", "fast"],
+        syntax: "INCR key",
+        description: "Add one to the integer stored at a key and return the result",
+        notes: "A missing key counts as 0 and keeps no TTL; a value that isn't an integer is an error",
+    },
+    CommandDoc {
+        name: TYPE,
+        arity: 2,
+        flags: &["readonly", "fast"],
+        syntax: "TYPE key",
+        description: "Return the type of the value stored at a key",
+        notes: "One of string, list, hash or set; null for missing keys",
+    },
+    CommandDoc {
+        name: LPUSH,
+        arity: -3,
+        flags: &["write", "fast"],
+        syntax: "LPUSH key value",
+        description: "Add a value to the front of a list and return its length",
+        notes: "Creates the list if the key is missing; the value runs to the end of the line",
+    },
+    CommandDoc {
+        name: RPUSH,
+        arity: -3,
+        flags: &["write", "fast"],
+        syntax: "RPUSH key value",
+        description: "Add a value to the back of a list and return its length",
+        notes: "Creates the list if the key is missing; the value runs to the end of the line",
+    },
+    CommandDoc {
+        name: LPOP,
+        arity: 2,
+        flags: &["write", "fast"],
+        syntax: "LPOP key",
+        description: "Remove and return the first element of a list",
+        notes: "Returns null for a missing key; the key is deleted with its last element",
+    },
+    CommandDoc {
+        name: RPOP,
+        arity: 2,
+        flags: &["write", "fast"],
+        syntax: "RPOP key",
+        description: "Remove and return the last element of a list",
+        notes: "Returns null for a missing key; the key is deleted with its last element",
+    },
+    CommandDoc {
+        name: LRANGE,
+        arity: 4,
+        flags: &["readonly"],
+        syntax: "LRANGE key start stop",
+        description: "Return the elements from start to stop, inclusive, as a JSON array",
+        notes: "Negative indices count from the end, so 0 -1 is the whole list",
+    },
+    CommandDoc {
+        name: LLEN,
+        arity: 2,
+        flags: &["readonly", "fast"],
+        syntax: "LLEN key",
+        description: "Return the length of a list",
+        notes: "Returns 0 for a missing key",
+    },
+    CommandDoc {
+        name: HSET,
+        arity: -4,
+        flags: &["write", "fast"],
+        syntax: "HSET key field value",
+        description: "Set a field of a hash and return 1 if the field is new, 0 if it was overwritten",
+        notes: "Creates the hash if the key is missing; the value runs to the end of the line",
+    },
+    CommandDoc {
+        name: HGET,
+        arity: 3,
+        flags: &["readonly", "fast"],
+        syntax: "HGET key field",
+        description: "Return the value of a field of a hash",
+        notes: "Returns null for a missing key or field",
+    },
+    CommandDoc {
+        name: HDEL,
+        arity: 3,
+        flags: &["write", "fast"],
+        syntax: "HDEL key field",
+        description: "Remove a field from a hash and return 1 if it was there",
+        notes: "The key is deleted with its last field",
+    },
+    CommandDoc {
+        name: HEXISTS,
+        arity: 3,
+        flags: &["readonly", "fast"],
+        syntax: "HEXISTS key field",
+        description: "Return 1 if the hash has the field, 0 if not",
+        notes: "Returns 0 for a missing key",
+    },
+    CommandDoc {
+        name: HGETALL,
+        arity: 2,
+        flags: &["readonly"],
+        syntax: "HGETALL key",
+        description: "Return every field and value of a hash as a JSON object",
+        notes: "Fields are sorted; a missing key is an empty object",
+    },
+    CommandDoc {
+        name: HKEYS,
+        arity: 2,
+        flags: &["readonly"],
+        syntax: "HKEYS key",
+        description: "Return the fields of a hash as a JSON array",
+        notes: "Fields are sorted; a missing key is an empty array",
+    },
+    CommandDoc {
+        name: HVALS,
+        arity: 2,
+        flags: &["readonly"],
+        syntax: "HVALS key",
+        description: "Return the values of a hash as a JSON array",
+        notes: "Values are in field order; a missing key is an empty array",
+    },
+    CommandDoc {
+        name: SADD,
+        arity: -3,
+        flags: &["write", "fast"],
+        syntax: "SADD key member",
+        description: "Add a member to a set and return 1 if it is new, 0 if it was already there",
+        notes: "Creates the set if the key is missing; the member runs to the end of the line",
+    },
+    CommandDoc {
+        name: SREM,
+        arity: -3,
+        flags: &["write", "fast"],
+        syntax: "SREM key member",
+        description: "Remove a member from a set and return 1 if it was there",
+        notes: "The key is deleted with its last member",
+    },
+    CommandDoc {
+        name: SISMEMBER,
+        arity: -3,
+        flags: &["readonly", "fast"],
+        syntax: "SISMEMBER key member",
+        description: "Return 1 if the set has the member, 0 if not",
+        notes: "Returns 0 for a missing key",
+    },
+    CommandDoc {
+        name: SCARD,
+        arity: 2,
+        flags: &["readonly", "fast"],
+        syntax: "SCARD key",
+        description: "Return the number of members of a set",
+        notes: "Returns 0 for a missing key",
+    },
+    CommandDoc {
+        name: SMEMBERS,
+        arity: 2,
+        flags: &["readonly"],
+        syntax: "SMEMBERS key",
+        description: "Return the members of a set as a JSON array",
+        notes: "Members are sorted; a missing key is an empty array",
+    },
+    CommandDoc {
+        name: SUNION,
+        arity: 3,
+        flags: &["readonly"],
+        syntax: "SUNION key other",
+        description: "Return the members of either set as a JSON array",
+        notes: "Members are sorted; a missing key counts as an empty set",
+    },
+    CommandDoc {
+        name: SINTERSTORE,
+        arity: 4,
+        flags: &["write"],
+        syntax: "SINTERSTORE destination key other",
+        description: "Replace destination with the members both sets have and return how many there are",
+        notes: "An empty result deletes destination; the stored set has no TTL",
+    },
+    CommandDoc {
+        name: ZADD,
+        arity: -4,
+        flags: &["write", "fast"],
+        syntax: "ZADD key score member",
+        description: "Add a member to a sorted set, or give an existing one a new score, and return 1 if it is new",
+        notes: "Scores are floats and may be inf or -inf but not NaN; the member runs to the end of the line",
+    },
+    CommandDoc {
+        name: ZREM,
+        arity: -3,
+        flags: &["write", "fast"],
+        syntax: "ZREM key member",
+        description: "Remove a member from a sorted set and return 1 if it was there",
+        notes: "The key is deleted with its last member",
+    },
+    CommandDoc {
+        name: ZSCORE,
+        arity: -3,
+        flags: &["readonly", "fast"],
+        syntax: "ZSCORE key member",
+        description: "Return the score of a member as a string",
+        notes: "Returns null for a missing key or member",
+    },
+    CommandDoc {
+        name: ZRANK,
+        arity: -3,
+        flags: &["readonly", "fast"],
+        syntax: "ZRANK key member",
+        description: "Return the 0-based rank of a member, lowest score first",
+        notes: "Equal scores rank by member; returns null for a missing key or member",
+    },
+    CommandDoc {
+        name: ZRANGE,
+        arity: -4,
+        flags: &["readonly"],
+        syntax: "ZRANGE key start stop [WITHSCORES]",
+        description: "Return the members from rank start to stop, inclusive, as a JSON array",
+        notes: "Indices work as in LRANGE; WITHSCORES puts each score after its member",
+    },
+    CommandDoc {
+        name: ZRANGEBYSCORE,
+        arity: -4,
+        flags: &["readonly"],
+        syntax: "ZRANGEBYSCORE key min max [WITHSCORES]",
+        description: "Return the members scored from min to max, inclusive, as a JSON array",
+        notes: "A bound written as (score leaves that score out; -inf and +inf are open ends",
+    },
+    CommandDoc {
+        name: SETBIT,
+        arity: 4,
+        flags: &["write", "fast"],
+        syntax: "SETBIT key offset 0|1",
+        description: "Set one bit of a bit array and return what it was",
+        notes: "Creates the key, and grows it with zeros, as needed; offsets go up to 4294967295",
+    },
+    CommandDoc {
+        name: GETBIT,
+        arity: 3,
+        flags: &["readonly", "fast"],
+        syntax: "GETBIT key offset",
+        description: "Return the bit at an offset, 0 or 1",
+        notes: "Bits past the end and missing keys are 0",
+    },
+    CommandDoc {
+        name: BITCOUNT,
+        arity: -2,
+        flags: &["readonly"],
+        syntax: "BITCOUNT key [start end]",
+        description: "Return the number of bits set, optionally only in the bytes from start to end",
+        notes: "The range is in bytes, inclusive, and negative indices count from the end",
+    },
+    CommandDoc {
+        name: BITPOS,
+        arity: -3,
+        flags: &["readonly"],
+        syntax: "BITPOS key 0|1 [start [end]]",
+        description: "Return the offset of the first bit that is 0 or 1, or -1 if there is none",
+        notes: "start and end are bytes; looking for 0 without an end counts the bytes past the end as zeros",
+    },
+    CommandDoc {
+        name: BITOP,
+        arity: -4,
+        flags: &["write"],
+        syntax: "BITOP AND|OR|XOR|NOT destination key [key ...]",
+        description: "Store the bitwise result of the keys in destination and return its length in bytes",
+        notes: "Shorter keys are padded with zeros; NOT takes one key; an empty result deletes destination",
+    },
+    CommandDoc {
+        name: GETEX,
+        arity: -2,
+        flags: &["write", "fast"],
+        syntax: "GETEX key [EX seconds | PX milliseconds | PERSIST]",
+        description: "Return the value of a key and optionally change its TTL",
+        notes: "Returns null for missing and expired keys; the read and the TTL change are one step",
+    },
+    CommandDoc {
+        name: DELETE,
+        arity: 2,
+        flags: &["write", "fast"],
+        syntax: "DEL key",
+        description: "Delete a key",
+        notes: "Returns the deleted value, or null if the key did not exist",
+    },
+    CommandDoc {
+        name: UNLINK,
+        arity: 2,
+        flags: &["write", "fast"],
+        syntax: "UNLINK key",
+        description: "Delete a key, freeing its memory in the background",
+        notes: "Returns ok, or null if the key did not exist. The key is gone for readers straight away",
+    },
+    CommandDoc {
+        name: DELPATTERN,
+        arity: 2,
+        flags: &["write"],
+        syntax: "DELPATTERN pattern",
+        description: "Delete the live keys matching a glob pattern",
+        notes: "Deletes at most protocol.bulk_delete_max keys per call and returns {\"deleted\":n,\"more\":bool}; call again while more is true",
+    },
+    CommandDoc {
+        name: EXPIRES_IN,
+        arity: 2,
+        flags: &["readonly", "fast"],
+        syntax: "EXIN key",
+        description: "Return the seconds left before a key expires",
+        notes: "Returns null for missing keys and keys without a TTL",
+    },
+    CommandDoc {
+        name: EXPIRE,
+        arity: 3,
+        flags: &["write", "fast"],
+        syntax: "EXPIRE key seconds",
+        description: "Set a new TTL on a key without changing its value",
+        notes: "Returns 1, or 0 if the key is missing or expired; 0 seconds deletes the key",
+    },
+    CommandDoc {
+        name: EXPIREAT,
+        arity: 3,
+        flags: &["write", "fast"],
+        syntax: "EXPIREAT key unix_timestamp_secs",
+        description: "Set a key to expire at a unix time without changing its value",
+        notes: "Returns 1, or 0 if the key is missing or expired; a time in the past deletes the key",
+    },
+    CommandDoc {
+        name: TTL,
+        arity: 2,
+        flags: &["readonly", "fast"],
+        syntax: "TTL key",
+        description: "Return the seconds left before a key expires",
+        notes: "Same as EXIN",
+    },
+    CommandDoc {
+        name: PTTL,
+        arity: 2,
+        flags: &["readonly", "fast"],
+        syntax: "PTTL key",
+        description: "Return the milliseconds left before a key expires",
+        notes: "Returns null for missing keys and keys without a TTL",
+    },
+    CommandDoc {
+        name: PEXPIRE,
+        arity: 3,
+        flags: &["write", "fast"],
+        syntax: "PEXPIRE key milliseconds",
+        description: "Set a new TTL in milliseconds on a key without changing its value",
+        notes: "Returns 1, or 0 if the key is missing or expired; 0 milliseconds deletes the key",
+    },
+    CommandDoc {
+        name: PEXPIREAT,
+        arity: 3,
+        flags: &["write", "fast"],
+        syntax: "PEXPIREAT key unix_timestamp_ms",
+        description: "Set a key to expire at a unix time in milliseconds without changing its value",
+        notes: "Returns 1, or 0 if the key is missing or expired; a time in the past deletes the key",
+    },
+    CommandDoc {
+        name: TOUCH,
+        arity: -2,
+        flags: &["readonly", "fast"],
+        syntax: "TOUCH key [key ...]",
+        description: "Count how many of the keys exist, without reading their values",
+        notes: "A key named twice counts twice",
+    },
+    CommandDoc {
+        name: EXPIRING,
+        arity: 2,
+        flags: &["readonly"],
+        syntax: "EXPIRING count",
+        description: "Return the keys with the nearest deadlines as [{key, ttl_secs}]",
+        notes: "Keys without a TTL are never listed. Walks the whole keyspace",
+    },
+    CommandDoc {
+        name: KEYS,
+        arity: 2,
+        flags: &["readonly"],
+        syntax: "KEYS pattern",
+        description: "Return the sorted live keys matching a glob pattern",
+        notes: "Supports *, ?, [a-z] and \\ escapes. Walks the whole keyspace",
+    },
+    CommandDoc {
+        name: SCAN,
+        arity: -2,
+        flags: &["readonly"],
+        syntax: "SCAN cursor [MATCH pattern] [COUNT count]",
+        description: "Page through the keys of the selected database, starting from cursor 0",
+        notes: "Returns [next cursor, keys]; next cursor 0 means done. Pages shrink to fit max_message_bytes",
+    },
+    CommandDoc {
+        name: DUMP,
+        arity: 2,
+        flags: &["readonly"],
+        syntax: "DUMP key",
+        description: "Serialize a key into a base64 blob for RESTORE",
+        notes: "The blob carries a version, a CRC32, the expiry and the compressed payload. Returns null for missing keys",
+    },
+    CommandDoc {
+        name: RESTORE,
+        arity: 4,
+        flags: &["write"],
+        syntax: "RESTORE key ttl_ms blob",
+        description: "Create a key from a DUMP blob",
+        notes: "Replaces any existing value. ttl_ms of 0 means no expiry; corrupt blobs fail with checksum mismatch",
+    },
+    CommandDoc {
+        name: JGET,
+        arity: 3,
+        flags: &["readonly"],
+        syntax: "JGET key path",
+        description: "Return the JSON at a path inside a stored document",
+        notes: "Paths look like user.tags[0]; $ is the whole document",
+    },
+    CommandDoc {
+        name: JSET,
+        arity: -4,
+        flags: &["write"],
+        syntax: "JSET key path json",
+        description: "Set the JSON at a path inside a stored document",
+        notes: "Creates missing objects and arrays and keeps the key's TTL",
+    },
+    CommandDoc {
+        name: SELECT,
+        arity: 2,
+        flags: &["fast"],
+        syntax: "SELECT db",
+        description: "Switch this connection to another logical database",
+        notes: "Databases are numbered from 0 up to store.databases - 1; connections start on 0",
+    },
+    CommandDoc {
+        name: FLUSH,
+        arity: 1,
+        flags: &["write"],
+        syntax: "FLUSH",
+        description: "Delete every key in the selected database",
+        notes: "",
+    },
+    CommandDoc {
+        name: FLUSHDB,
+        arity: 1,
+        flags: &["write"],
+        syntax: "FLUSHDB",
+        description: "Delete every key in the selected database",
+        notes: "The same as FLUSH",
+    },
+    CommandDoc {
+        name: SWAPDB,
+        arity: 3,
+        flags: &["write"],
+        syntax: "SWAPDB db1 db2",
+        description: "Exchange the keys of two databases",
+        notes: "Atomic; connections on either database see the other's keys straight away",
+    },
+    CommandDoc {
+        name: FLUSHALL,
+        arity: 1,
+        flags: &["write"],
+        syntax: "FLUSHALL",
+        description: "Delete every key in every database",
+        notes: "",
+    },
+    CommandDoc {
+        name: INFO,
+        arity: 1,
+        flags: &["readonly"],
+        syntax: "INFO",
+        description: "Return store statistics as JSON",
+        notes: "",
+    },
+    CommandDoc {
+        name: METRICS,
+        arity: 1,
+        flags: &["readonly"],
+        syntax: "METRICS",
+        description: "Return the Prometheus metrics in text format",
+        notes: "The same text as the [prometheus] endpoint's /metrics, whether or not that is enabled",
+    },
+    CommandDoc {
+        name: RESETSTATS,
+        arity: 1,
+        flags: &["admin"],
+        syntax: "RESETSTATS",
+        description: "Clear the per-command latency histograms shown by INFO",
+        notes: "",
+    },
+    CommandDoc {
+        name: SLOWLOG,
+        arity: -2,
+        flags: &["admin"],
+        syntax: "SLOWLOG GET [count] | SLOWLOG RESET",
+        description: "Inspect or clear the slow log",
+        notes: "count defaults to 10",
+    },
+    CommandDoc {
+        name: LATENCY,
+        arity: -2,
+        flags: &["admin"],
+        syntax: "LATENCY LATEST | LATENCY HISTORY command [count] | LATENCY RESET [command] | LATENCY GRAPH command",
+        description: "Inspect the recent latencies of each command",
+        notes: "Keeps the last 128 samples per command",
+    },
+    CommandDoc {
+        name: MEMORY,
+        arity: -2,
+        flags: &["readonly"],
+        syntax: "MEMORY USAGE key [DECOMP] | MEMORY DOCTOR",
+        description: "Report how much memory keys take",
+        notes: "Sizes are estimates that include per-entry overhead",
+    },
+    CommandDoc {
+        name: NAMESPACE,
+        arity: -2,
+        flags: &["write"],
+        syntax: "NAMESPACE LIST | NAMESPACE STATS ns | NAMESPACE FLUSH ns",
+        description: "Group keys by the part before protocol.namespace_separator",
+        notes: "LIST and STATS walk the selected database. FLUSH deletes the namespace's keys and needs allow_flush",
+    },
+    CommandDoc {
+        name: OBJECT,
+        arity: 3,
+        flags: &["readonly"],
+        syntax: "OBJECT ENCODING key",
+        description: "Describe how a key's value is stored",
+        notes: "Returns e.g. compressed:gzip:ratio=3.20, the raw size over the stored size, or int for integers",
+    },
+    CommandDoc {
+        name: CONFIG,
+        arity: -2,
+        flags: &["admin"],
+        syntax: "CONFIG GET field | CONFIG SET field value | CONFIG REWRITE",
+        description: "Read, change or persist runtime configuration",
+        notes: "A SIGHUP reload resets CONFIG SET changes to the file's values",
+    },
+    CommandDoc {
+        name: COMMAND,
+        arity: -2,
+        flags: &["readonly"],
+        syntax: "COMMAND COUNT | COMMAND INFO [name ...]",
+        description: "Describe the commands this server understands",
+        notes: "COMMAND INFO without names describes every command",
+    },
+    CommandDoc {
+        name: SUBSCRIBE,
+        arity: 2,
+        flags: &["pubsub"],
+        syntax: "SUBSCRIBE channel",
+        description: "Receive the messages published to a channel",
+        notes: "Replies subscribe <channel> <count>, then writes message <channel> <message> for each message. While subscribed only SUBSCRIBE, UNSUBSCRIBE, PING and CLOSE are accepted",
+    },
+    CommandDoc {
+        name: UNSUBSCRIBE,
+        arity: 2,
+        flags: &["pubsub"],
+        syntax: "UNSUBSCRIBE channel",
+        description: "Stop receiving a channel's messages",
+        notes: "Replies unsubscribe <channel> <count>; at a count of 0 the connection takes normal commands again",
+    },
+    CommandDoc {
+        name: HELLO,
+        arity: -1,
+        flags: &["fast"],
+        syntax: "HELLO [version]",
+        description: "Describe the server and its protocols, or switch this connection to a protocol version",
+        notes: "Returns {server, version, proto, protocols, features}. An unsupported version leaves the mode unchanged",
+    },
+    CommandDoc {
+        name: PUBLISH,
+        arity: -3,
+        flags: &["pubsub", "fast"],
+        syntax: "PUBLISH channel message",
+        description: "Send a message to every subscriber of a channel",
+        notes: "Returns how many subscribers got it. A subscriber more than 1024 messages behind misses it",
+    },
+    CommandDoc {
+        name: PING,
+        arity: -1,
+        flags: &["fast"],
+        syntax: "PING [message]",
+        description: "Return pong, or the message if one is given",
+        notes: "Does not touch the store",
+    },
+    CommandDoc {
+        name: ECHO,
+        arity: -2,
+        flags: &["fast"],
+        syntax: "ECHO message",
+        description: "Return the message",
+        notes: "",
+    },
+    CommandDoc {
+        name: DEBUG,
+        arity: -2,
+        flags: &["admin"],
+        syntax: "DEBUG OBJECT key | DEBUG PURGE | DEBUG SLEEP secs | DEBUG PANIC",
+        description: "Commands for inspecting and exercising the server",
+        notes: "Needs allow_debug in [protocol]",
+    },
+    CommandDoc {
+        name: MULTI,
+        arity: 1,
+        flags: &["transaction"],
+        syntax: "MULTI",
+        description: "Start a transaction; later commands answer QUEUED until EXEC",
+        notes: "A queued command with the wrong name or argument count makes the EXEC fail",
+    },
+    CommandDoc {
+        name: EXEC,
+        arity: 1,
+        flags: &["transaction"],
+        syntax: "EXEC",
+        description: "Run the queued commands with no other command in between",
+        notes: "Returns a JSON array with each command's response, or null if a WATCHed key was written",
+    },
+    CommandDoc {
+        name: WATCH,
+        arity: -2,
+        flags: &["transaction"],
+        syntax: "WATCH key [key ...]",
+        description: "Make the next EXEC fail if any of the keys is written first",
+        notes: "Keys are watched in the selected database. EXEC, DISCARD, RESET and UNWATCH stop watching",
+    },
+    CommandDoc {
+        name: UNWATCH,
+        arity: 1,
+        flags: &["transaction"],
+        syntax: "UNWATCH",
+        description: "Stop watching every key this connection watches",
+        notes: "",
+    },
+    CommandDoc {
+        name: DISCARD,
+        arity: 1,
+        flags: &["transaction"],
+        syntax: "DISCARD",
+        description: "Drop the queued commands and leave the transaction",
+        notes: "",
+    },
+    CommandDoc {
+        name: RESET,
+        arity: 1,
+        flags: &["transaction"],
+        syntax: "RESET",
+        description: "Drop any transaction and switch back to database 0",
+        notes: "",
+    },
+    CommandDoc {
+        name: CLOSE,
+        arity: 1,
+        flags: &["fast"],
+        syntax: "CLOSE",
+        description: "Close the connection",
+        notes: "Handled by the connection, so it never reaches the store",
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static CommandDoc> {
+    COMMANDS.iter().find(|doc| doc.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn names_are_unique_and_match_their_syntax() {
+        let mut seen = HashSet::new();
+
+        for doc in COMMANDS {
+            assert!(seen.insert(doc.name), "{} is listed twice", doc.name);
+            assert!(doc.syntax.starts_with(doc.name), "{} syntax", doc.name);
+            assert_ne!(doc.arity, 0, "{} arity", doc.name);
+        }
+    }
+
+    #[test]
+    fn finds_commands_regardless_of_case() {
+        assert_eq!(find("jset").map(|doc| doc.name), Some("JSET"));
+        assert!(find("NOPE").is_none());
+    }
+}
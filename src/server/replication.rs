@@ -0,0 +1,262 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::server::connection::Connection;
+use crate::server::dispatcher::parse_set_command;
+use crate::server::store::{
+    ClockSource, ListEnd, Store, BITS_FRAME, DB_FRAME, FLUSHALL_FRAME, FLUSH_FRAME, HDEL_FRAME, HSET_FRAME,
+    LPOP_FRAME, LPUSH_FRAME, PERSIST_FRAME, PEXPIRE_FRAME, RESTORE_FRAME, RPOP_FRAME, RPUSH_FRAME, SADD_FRAME,
+    SETINT_FRAME, SREM_FRAME, SWAPDB_FRAME, ZADD_FRAME, ZREM_FRAME,
+};
+
+pub const SYNC: &str = "SYNC";
+
+const RESYNC_DELAY: Duration = Duration::from_secs(1);
+
+// Replication is a full resync on every connect followed by a live stream,
+// not an exactly-once log. The replica sends SYNC, then receives every live
+// key as frames and after that every write applied on the primary, in the
+// primary's order. Strings travel as DUMP blobs and list items and hash
+// values in base64, so binary values arrive intact, and integers keep their
+// type. When the stream breaks for any reason (the primary restarts, or this
+// replica falls further behind than the primary buffers) the replica clears
+// its data and syncs again from scratch, so it converges on the primary's
+// state, last writer wins, but serves a partial store while the snapshot is
+// loading. TTLs are replicated as the milliseconds left when the primary
+// sent them, counted again from when a frame arrives, and each side expires
+// keys on its own, so a replica's deadlines trail the primary's by the lag.
+// Frames for databases other than 0 carry a `DB <n>` prefix; a replica with
+// fewer databases than its primary drops the frames it has no database for.
+pub async fn replicate_from(primary: SocketAddr, store: Store) {
+    loop {
+        match sync(primary, &store).await {
+            Ok(()) => warn!(%primary, "primary closed the replication stream"),
+            Err(e) => warn!(%primary, error = %e, "replication stream failed"),
+        }
+
+        time::sleep(RESYNC_DELAY).await;
+    }
+}
+
+async fn sync(primary: SocketAddr, store: &Store) -> Result<(), Box<dyn Error>> {
+    let mut connection = Connection::new(TcpStream::connect(primary).await?);
+    connection.write_message(SYNC).await?;
+
+    store.clear();
+    info!(%primary, "syncing from primary");
+
+    while let Some(frame) = connection.read_message().await? {
+        apply(&frame, store);
+    }
+
+    Ok(())
+}
+
+// Writes go straight to the store, past the read-only check that keeps
+// clients from writing to a replica.
+pub(crate) fn apply<C: ClockSource>(frame: &str, store: &Store<C>) {
+    if let Some((DB_FRAME, rest)) = frame.split_once(' ') {
+        let mut store = store.clone();
+
+        match rest.split_once(' ').map(|(db, frame)| (db.parse(), frame)) {
+            Some((Ok(db), frame)) => match store.select(db) {
+                Ok(()) => apply(frame, &store),
+                Err(e) => warn!(db, error = %e, "dropping replicated frame"),
+            },
+            _ => warn!("invalid replicated DB frame"),
+        }
+        return;
+    }
+
+    match frame {
+        FLUSH_FRAME => {
+            store.flush();
+            return;
+        }
+        FLUSHALL_FRAME => {
+            store.flush_all();
+            return;
+        }
+        _ => (),
+    }
+
+    match frame.split_once(' ') {
+        Some((RESTORE_FRAME, rest)) => match rest.split_once(' ').and_then(|(key, rest)| Some((key, rest.split_once(' ')?))) {
+            Some((key, (ttl_ms, blob))) => match ttl_ms.parse() {
+                Ok(ttl_ms) => {
+                    if let Err(e) = store.restore(key.to_string(), blob, ttl_ms) {
+                        warn!(error = %e, "failed to apply replicated RESTORE");
+                    }
+                }
+                Err(_) => warn!("invalid replicated RESTORE"),
+            },
+            None => warn!("invalid replicated RESTORE"),
+        },
+        Some((SETINT_FRAME, rest)) => match rest.split_once(' ').and_then(|(key, rest)| Some((key, rest.split_once(' ')?))) {
+            Some((key, (ttl_ms, value))) => match (ttl_ms.parse(), value.parse()) {
+                (Ok(ttl_ms), Ok(value)) => {
+                    if let Err(e) = store.insert_integer(key.to_string(), value, ttl_ms) {
+                        warn!(error = %e, "failed to apply replicated SETINT");
+                    }
+                }
+                _ => warn!("invalid replicated SETINT"),
+            },
+            None => warn!("invalid replicated SETINT"),
+        },
+        // Written by primaries and exports from before strings were sent as
+        // RESTORE, so older export files still import.
+        Some(("SET", _)) => match parse_set_command(frame) {
+            Ok((key, value, seconds)) => {
+                if let Err(e) = store.insert(key, value.into_bytes(), seconds) {
+                    warn!(error = %e, "failed to apply replicated SET");
+                }
+            }
+            Err(e) => warn!(error = %e, "invalid replicated SET"),
+        },
+        Some(("DEL", key)) => {
+            store.delete(key);
+        }
+        Some((frame @ (LPUSH_FRAME | RPUSH_FRAME), rest)) => match rest.split_once(' ') {
+            Some((key, encoded)) => match STANDARD.decode(encoded) {
+                Ok(value) => {
+                    let end = if frame == LPUSH_FRAME { ListEnd::Front } else { ListEnd::Back };
+                    if let Err(e) = store.push(key, value, end) {
+                        warn!(error = %e, "failed to apply replicated {}", frame);
+                    }
+                }
+                Err(_) => warn!("invalid replicated {}", frame),
+            },
+            None => warn!("invalid replicated {}", frame),
+        },
+        Some((frame @ (LPOP_FRAME | RPOP_FRAME), key)) => {
+            let end = if frame == LPOP_FRAME { ListEnd::Front } else { ListEnd::Back };
+            if let Err(e) = store.pop(key, end) {
+                warn!(error = %e, "failed to apply replicated {}", frame);
+            }
+        }
+        Some((HSET_FRAME, rest)) => match rest.split_once(' ').and_then(|(key, rest)| Some((key, rest.split_once(' ')?))) {
+            Some((key, (field, encoded))) => match STANDARD.decode(encoded) {
+                Ok(value) => {
+                    if let Err(e) = store.hash_set(key, field.to_string(), value) {
+                        warn!(error = %e, "failed to apply replicated HSET");
+                    }
+                }
+                Err(_) => warn!("invalid replicated HSET"),
+            },
+            None => warn!("invalid replicated HSET"),
+        },
+        Some((HDEL_FRAME, rest)) => match rest.split_once(' ') {
+            Some((key, field)) => {
+                if let Err(e) = store.hash_delete(key, field) {
+                    warn!(error = %e, "failed to apply replicated HDEL");
+                }
+            }
+            None => warn!("invalid replicated HDEL"),
+        },
+        Some((frame @ (SADD_FRAME | SREM_FRAME), rest)) => match rest.split_once(' ') {
+            Some((key, member)) => {
+                let applied = match frame {
+                    SADD_FRAME => store.set_add(key, member.to_string()),
+                    _ => store.set_remove(key, member),
+                };
+                if let Err(e) = applied {
+                    warn!(error = %e, "failed to apply replicated {}", frame);
+                }
+            }
+            None => warn!("invalid replicated {}", frame),
+        },
+        Some((ZADD_FRAME, rest)) => match rest.split_once(' ').and_then(|(key, rest)| Some((key, rest.split_once(' ')?))) {
+            Some((key, (score, member))) => match score.parse() {
+                Ok(score) => {
+                    if let Err(e) = store.zset_add(key, score, member.to_string()) {
+                        warn!(error = %e, "failed to apply replicated ZADD");
+                    }
+                }
+                Err(_) => warn!("invalid replicated ZADD"),
+            },
+            None => warn!("invalid replicated ZADD"),
+        },
+        Some((ZREM_FRAME, rest)) => match rest.split_once(' ') {
+            Some((key, member)) => {
+                if let Err(e) = store.zset_remove(key, member) {
+                    warn!(error = %e, "failed to apply replicated ZREM");
+                }
+            }
+            None => warn!("invalid replicated ZREM"),
+        },
+        Some(("SETBIT", rest)) => match rest.split_once(' ').and_then(|(key, rest)| Some((key, rest.split_once(' ')?))) {
+            Some((key, (offset, bit))) => match (offset.parse(), bit) {
+                (Ok(offset), "0" | "1") => {
+                    if let Err(e) = store.set_bit(key, offset, bit == "1") {
+                        warn!(error = %e, "failed to apply replicated SETBIT");
+                    }
+                }
+                _ => warn!("invalid replicated SETBIT"),
+            },
+            None => warn!("invalid replicated SETBIT"),
+        },
+        Some((BITS_FRAME, rest)) => match rest.split_once(' ').map(|(key, encoded)| (key, STANDARD.decode(encoded))) {
+            Some((key, Ok(array))) => {
+                if let Err(e) = store.replace_bits(key, array) {
+                    warn!(error = %e, "failed to apply replicated BITS");
+                }
+            }
+            _ => warn!("invalid replicated BITS"),
+        },
+        Some((PERSIST_FRAME, key)) => {
+            store.persist(key);
+        }
+        Some((PEXPIRE_FRAME, rest)) => match rest.split_once(' ').map(|(key, ttl_ms)| (key, ttl_ms.parse())) {
+            Some((key, Ok(ttl_ms))) => {
+                if let Err(e) = store.update_ttl_ms(key, ttl_ms) {
+                    warn!(error = %e, "failed to apply replicated PEXPIRE");
+                }
+            }
+            _ => warn!("invalid replicated PEXPIRE"),
+        },
+        Some((SWAPDB_FRAME, dbs)) => match dbs.split_once(' ').map(|(a, b)| (a.parse(), b.parse())) {
+            Some((Ok(first), Ok(second))) => {
+                if let Err(e) = store.swap_databases(first, second) {
+                    warn!(error = %e, "failed to apply replicated SWAPDB");
+                }
+            }
+            _ => warn!("invalid replicated SWAPDB"),
+        },
+        _ => warn!("unexpected replication frame"),
+    }
+}
+
+pub async fn serve_replica(connection: &mut Connection, store: &Store) {
+    let (mut updates, snapshot) = store.subscribe_with_snapshot();
+    info!(keys = snapshot.len(), "replica syncing");
+
+    for frame in snapshot {
+        if let Err(e) = connection.write_message(&frame).await {
+            warn!(error = %e, "failed to send snapshot to replica");
+            return;
+        }
+    }
+
+    loop {
+        let frame = match updates.recv().await {
+            Ok(frame) => frame,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "replica fell behind, disconnecting it so it resyncs");
+                return;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        if let Err(e) = connection.write_message(&frame).await {
+            warn!(error = %e, "replica disconnected");
+            return;
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use bytes::Bytes;
+use serde::Serialize;
+use serde_json::Value as Json;
+
+use crate::config::ProtocolConfig;
+use crate::server::error::ErrorCode;
+
+#[derive(Serialize)]
+struct StructuredError<'a> {
+    error: &'static str,
+    message: &'a str,
+    code: u16,
+}
+
+// How error responses are written. `Legacy` is the bare `error:<message>`
+// from before codes existed, kept for clients that match on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Legacy,
+    Coded,
+    Structured,
+}
+
+// What a command answered, kept typed until `render` turns it into the text
+// sent on the wire. Nothing else should format responses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Ok,
+    Value(Bytes),
+    Integer(i64),
+    Null,
+    // `message` is the short text sent after the code, `detail` the full
+    // description sent with structured errors.
+    Error {
+        code: ErrorCode,
+        message: String,
+        detail: String,
+    },
+    Array(Vec<Response>),
+}
+
+impl Response {
+    pub fn value(value: impl Into<Bytes>) -> Self {
+        Response::Value(value.into())
+    }
+
+    pub fn json(value: &impl Serialize) -> serde_json::Result<Self> {
+        Ok(Response::value(serde_json::to_string(value)?))
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Response::Error { .. })
+    }
+
+    // What goes on the wire, and whether it's an error. A response longer
+    // than max_message_bytes would be refused by the client, so a TOOLARGE
+    // error is sent in its place.
+    pub fn render_for(&self, protocol: &ProtocolConfig) -> (String, bool) {
+        let format = protocol.error_format();
+        let rendered = self.render(format);
+        let limit = protocol.max_message_bytes;
+        if rendered.len() <= limit {
+            return (rendered, self.is_error());
+        }
+
+        let message = format!("response exceeds {} bytes; use SCAN/COUNT", limit);
+        let error = Response::Error { code: ErrorCode::ValueTooLarge, detail: message.clone(), message };
+        (error.render(format), true)
+    }
+
+    pub fn render(&self, format: ErrorFormat) -> String {
+        match self {
+            Response::Ok => "ok".to_string(),
+            Response::Value(value) => String::from_utf8_lossy(value).into_owned(),
+            Response::Integer(value) => value.to_string(),
+            Response::Null => "null".to_string(),
+            Response::Error { code, message, detail } => match format {
+                ErrorFormat::Legacy => format!("error:{}", message),
+                ErrorFormat::Coded => format!("error:{}:{}", code.token(), message),
+                ErrorFormat::Structured => {
+                    let body = StructuredError {
+                        error: code.as_str(),
+                        message: detail,
+                        code: *code as u16,
+                    };
+
+                    serde_json::to_string(&body)
+                        .unwrap_or_else(|_| format!("error:{}:{}", code.token(), message))
+                }
+            },
+            Response::Array(_) => self.to_json(format).to_string(),
+        }
+    }
+
+    // Arrays go out as JSON, with each element in the JSON type closest to
+    // its legacy text.
+    fn to_json(&self, format: ErrorFormat) -> Json {
+        match self {
+            Response::Integer(value) => Json::from(*value),
+            Response::Null => Json::Null,
+            Response::Array(items) => {
+                Json::Array(items.iter().map(|item| item.to_json(format)).collect())
+            }
+            other => Json::String(other.render(format)),
+        }
+    }
+}
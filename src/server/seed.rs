@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use tracing::{info, warn};
+
+use crate::config::ProtocolConfig;
+use crate::server::dispatcher::dispatch_response;
+use crate::server::store::Store;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SeedSummary {
+    pub applied: usize,
+    // Blank lines and comments.
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+// Replays a file of commands, one per line as a client would send them. Lines
+// that are blank or start with # are skipped, as in the CLI's batch files, so
+// one file serves both. A failing line is logged and passed over, unless
+// `strict`, when it fails the whole seed.
+pub fn seed(
+    store: &Store,
+    path: &Path,
+    strict: bool,
+    protocol: &ProtocolConfig,
+) -> Result<SeedSummary, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read seed file {}: {}", path.display(), e))?;
+
+    // Its own clone, so a SELECT in the file doesn't outlive the seed.
+    let mut store = store.clone();
+    let mut summary = SeedSummary::default();
+
+    for (index, line) in contents.lines().enumerate() {
+        let command = line.trim_end_matches('\r');
+        if command.trim().is_empty() || command.trim_start().starts_with('#') {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let failure = match dispatch_response(command, &mut store, protocol) {
+            Ok(response) if response.is_error() => Some(response.render(protocol.error_format())),
+            Ok(_) => None,
+            Err(e) => Some(e.to_string()),
+        };
+
+        match failure {
+            None => summary.applied += 1,
+            Some(reason) if strict => {
+                return Err(format!("seed file {} line {}: {}", path.display(), index + 1, reason).into());
+            }
+            Some(reason) => {
+                warn!(line = index + 1, reason, "seed command failed");
+                summary.failed += 1;
+            }
+        }
+    }
+
+    info!(
+        path = %path.display(),
+        applied = summary.applied,
+        skipped = summary.skipped,
+        failed = summary.failed,
+        "store seeded"
+    );
+    Ok(summary)
+}
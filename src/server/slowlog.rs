@@ -0,0 +1,110 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+const MAX_COMMAND_CHARS: usize = 128;
+const DISABLED: u64 = u64::MAX;
+
+#[derive(Clone, Serialize)]
+pub struct SlowEntry {
+    pub command_truncated: String,
+    pub duration_ms: u64,
+    pub timestamp_secs: u64,
+}
+
+pub struct SlowQueryLog {
+    entries: Mutex<VecDeque<SlowEntry>>,
+    threshold_ms: AtomicU64,
+    max_len: usize,
+}
+
+impl SlowQueryLog {
+    pub fn new(threshold_ms: Option<u64>, max_len: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(max_len)),
+            threshold_ms: AtomicU64::new(threshold_ms.unwrap_or(DISABLED)),
+            max_len,
+        }
+    }
+
+    pub fn set_threshold(&self, threshold_ms: Option<u64>) {
+        self.threshold_ms
+            .store(threshold_ms.unwrap_or(DISABLED), Ordering::Relaxed);
+    }
+
+    pub fn threshold(&self) -> Option<u64> {
+        match self.threshold_ms.load(Ordering::Relaxed) {
+            DISABLED => None,
+            threshold_ms => Some(threshold_ms),
+        }
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn record(&self, command: &str, elapsed: Duration) {
+        let threshold_ms = match self.threshold() {
+            Some(threshold_ms) => threshold_ms,
+            None => return,
+        };
+
+        let duration_ms = elapsed.as_millis() as u64;
+        if duration_ms <= threshold_ms {
+            return;
+        }
+
+        let timestamp_secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let entry = SlowEntry {
+            command_truncated: command.chars().take(MAX_COMMAND_CHARS).collect(),
+            duration_ms,
+            timestamp_secs,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.max_len {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    pub fn latest(&self, count: usize) -> Vec<SlowEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().rev().take(count).cloned().collect()
+    }
+
+    // Entries whose command name is `name`, newest first.
+    pub fn latest_for(&self, name: &str, count: usize) -> Vec<SlowEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.command_truncated.split(' ').next() == Some(name))
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+}
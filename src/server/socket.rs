@@ -0,0 +1,108 @@
+use std::io;
+use std::time::Duration;
+
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::TcpStream;
+
+use crate::config::ServerConfig;
+
+// The [server] options set on each accepted socket. The default changes
+// nothing, so sockets keep whatever the OS gives them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    pub nodelay: bool,
+    pub keepalive: Option<Duration>,
+    pub recv_buffer_bytes: Option<usize>,
+    pub send_buffer_bytes: Option<usize>,
+}
+
+impl SocketOptions {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            nodelay: config.tcp_nodelay,
+            keepalive: config.tcp_keepalive_secs.map(Duration::from_secs),
+            recv_buffer_bytes: config.tcp_recv_buffer_bytes,
+            send_buffer_bytes: config.tcp_send_buffer_bytes,
+        }
+    }
+
+    // Probes start after `keepalive` of silence and repeat at the same
+    // interval, so a dead peer is noticed within a few multiples of it.
+    fn keepalive(&self) -> Option<TcpKeepalive> {
+        let idle = self.keepalive?;
+        let keepalive = TcpKeepalive::new().with_time(idle);
+
+        #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+        let keepalive = keepalive.with_interval(idle);
+
+        Some(keepalive)
+    }
+
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        let socket = SockRef::from(stream);
+
+        if self.nodelay {
+            socket.set_nodelay(true)?;
+        }
+        if let Some(keepalive) = self.keepalive() {
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        if let Some(bytes) = self.recv_buffer_bytes {
+            socket.set_recv_buffer_size(bytes)?;
+        }
+        if let Some(bytes) = self.send_buffer_bytes {
+            socket.set_send_buffer_size(bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn options_follow_the_server_config() {
+        assert_eq!(SocketOptions::from_config(&ServerConfig::default()), SocketOptions::default());
+        assert!(SocketOptions::default().keepalive().is_none());
+
+        let config = ServerConfig {
+            tcp_nodelay: true,
+            tcp_keepalive_secs: Some(30),
+            tcp_recv_buffer_bytes: Some(65536),
+            ..ServerConfig::default()
+        };
+        let options = SocketOptions::from_config(&config);
+
+        assert!(options.nodelay);
+        assert_eq!(options.keepalive, Some(Duration::from_secs(30)));
+        assert_eq!(options.recv_buffer_bytes, Some(65536));
+        assert_eq!(options.send_buffer_bytes, None);
+        assert!(options.keepalive().is_some());
+    }
+
+    #[tokio::test]
+    async fn options_are_set_on_the_accepted_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        let options = SocketOptions {
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(45)),
+            recv_buffer_bytes: Some(128 * 1024),
+            send_buffer_bytes: Some(96 * 1024),
+        };
+        options.apply(&stream).unwrap();
+
+        let socket = SockRef::from(&stream);
+        assert!(socket.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(45));
+        // Linux reports double what was asked for, to cover its bookkeeping.
+        assert!(socket.recv_buffer_size().unwrap() >= 128 * 1024);
+        assert!(socket.send_buffer_size().unwrap() >= 96 * 1024);
+    }
+}
@@ -1,129 +1,2839 @@
-use std::io::{Write, Read};
-use std::num::Wrapping;
+use std::io::{self, Write, Read};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{OnceLock, PoisonError, RwLock};
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    ops::RangeInclusive,
+    time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH},
 };
+
+// Built with `--cfg keyz_loom`, the locks and atomics are loom's so the model
+// checks in tests/loom_store.rs see every ordering between them.
+#[cfg(keyz_loom)]
+use loom::sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex, MutexGuard};
+#[cfg(not(keyz_loom))]
+use std::sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex, MutexGuard};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use flate2::read::GzDecoder;
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, warn};
+
+use crate::config::StoreConfig;
+use crate::server::access_log::AccessLog;
+use crate::server::bits::{self, BitOp};
+use crate::server::connection::InflightBudget;
+use crate::server::dump::{self, Dump};
+use crate::server::error::{ErrorCode, KeyzError};
+use crate::server::glob::glob_match;
+use crate::server::histogram::{CommandHistograms, LatencyStats};
+use crate::server::latency::LatencyMonitor;
+use crate::server::prometheus::PrometheusMetrics;
+use crate::server::replication;
+use crate::server::slowlog::SlowQueryLog;
+use crate::server::value_type::ValueData;
+
+type Data = HashMap<String, (ValueData, u64)>;
+type Watches = HashMap<String, Vec<(u64, mpsc::Sender<()>)>>;
+
+const RATE_WINDOW_MS: u64 = 1000;
+const RATE_BUCKET_MS: u64 = 100;
+// One more than the window holds, for the bucket being filled.
+const RATE_BUCKETS: usize = (RATE_WINDOW_MS / RATE_BUCKET_MS) as usize + 1;
+// Up to 2^28 commands per bucket, leaving 36 bits of slot: enough until 2187.
+const RATE_COUNT_BITS: u32 = 28;
+const RATE_COUNT_MASK: u64 = (1 << RATE_COUNT_BITS) - 1;
+const REPLICATION_BUFFER: usize = 4096;
+
+// Prefixes replication frames for every database but 0, e.g.
+// `DB 3 DEL key`.
+pub const DB_FRAME: &str = "DB";
+pub const FLUSH_FRAME: &str = "FLUSH";
+pub const FLUSHALL_FRAME: &str = "FLUSHALL";
+pub const SWAPDB_FRAME: &str = "SWAPDB";
+pub const PEXPIRE_FRAME: &str = "PEXPIRE";
+pub const PERSIST_FRAME: &str = "PERSIST";
+pub const LPUSH_FRAME: &str = "LPUSH";
+pub const RPUSH_FRAME: &str = "RPUSH";
+pub const LPOP_FRAME: &str = "LPOP";
+pub const RPOP_FRAME: &str = "RPOP";
+pub const HSET_FRAME: &str = "HSET";
+pub const HDEL_FRAME: &str = "HDEL";
+pub const SADD_FRAME: &str = "SADD";
+pub const SREM_FRAME: &str = "SREM";
+pub const ZADD_FRAME: &str = "ZADD";
+pub const ZREM_FRAME: &str = "ZREM";
+// A whole bit array, base64 encoded, replacing the key.
+pub const BITS_FRAME: &str = "BITS";
+// A string as a DUMP blob, `RESTORE key ttl_ms blob` with 0 for no expiry,
+// so any bytes make it through a text frame. List items and hash values in
+// LPUSH, RPUSH and HSET frames are base64 encoded for the same reason.
+pub const RESTORE_FRAME: &str = "RESTORE";
+// An integer as `SETINT key ttl_ms n`, so a replica holds it as one too.
+pub const SETINT_FRAME: &str = "SETINT";
+
+// An expiry long past, marking a key UNLINK has hidden until its entry is
+// removed in the background. Real expiries are never this small.
+const UNLINKED_AT: u64 = 1;
+
+// Keys looked at, across all databases, when INFO builds the TTL histogram.
+const TTL_HISTOGRAM_SAMPLE: usize = 1024;
+
+// Namespaces INFO lists, largest first.
+const TOP_NAMESPACES: usize = 10;
+
+// What GETEX does to the TTL of the key it reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GetExModifier {
+    None,
+    Ex(u64),
+    Px(u64),
+    Persist,
+}
+
+// Which end of a list LPUSH/RPUSH and LPOP/RPOP work on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Front,
+    Back,
+}
+
+// One end of a ZRANGEBYSCORE range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+impl ScoreBound {
+    fn at_or_below(self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(min) => min <= score,
+            ScoreBound::Exclusive(min) => min < score,
+        }
+    }
+
+    fn at_or_above(self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(max) => score <= max,
+            ScoreBound::Exclusive(max) => score < max,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct StoreMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    sets: AtomicU64,
+    deletes: AtomicU64,
+    expirations: AtomicU64,
+    config_reloads: AtomicU64,
+    last_reload_epoch: AtomicU64,
+    command_timeouts: AtomicU64,
+    panics: AtomicU64,
+    pending_unlinks: AtomicUsize,
+    commands: CommandRateCounter,
+    prometheus: PrometheusMetrics,
+    latency: LatencyMonitor,
+    histograms: CommandHistograms,
+}
+
+// Kept as atomics shared by every clone of the store so a config reload
+// takes effect on all connections at once. MAX stands in for no limit.
+struct StoreLimits {
+    max_key_bytes: AtomicUsize,
+    max_value_bytes: AtomicUsize,
+    max_ttl_secs: AtomicU64,
+    // An f64 held as its bits.
+    ttl_jitter_percent: AtomicU64,
+    // From protocol.max_message_bytes rather than [store], so it is set
+    // separately; decompressing a value past it is refused.
+    max_payload_bytes: AtomicUsize,
+}
+
+impl StoreLimits {
+    fn new(config: &StoreConfig) -> Self {
+        let limits = Self {
+            max_key_bytes: AtomicUsize::new(usize::MAX),
+            max_value_bytes: AtomicUsize::new(usize::MAX),
+            max_ttl_secs: AtomicU64::new(u64::MAX),
+            ttl_jitter_percent: AtomicU64::new(0),
+            max_payload_bytes: AtomicUsize::new(usize::MAX),
+        };
+        limits.apply(config);
+        limits
+    }
+
+    fn apply(&self, config: &StoreConfig) {
+        self.max_key_bytes
+            .store(config.max_key_bytes.unwrap_or(usize::MAX), Ordering::Relaxed);
+        self.max_value_bytes
+            .store(config.max_value_bytes.unwrap_or(usize::MAX), Ordering::Relaxed);
+        self.max_ttl_secs
+            .store(config.max_ttl_secs.unwrap_or(u64::MAX), Ordering::Relaxed);
+        self.ttl_jitter_percent
+            .store(config.ttl_jitter_percent.to_bits(), Ordering::Relaxed);
+    }
+
+    fn max_key_bytes(&self) -> Option<usize> {
+        Some(self.max_key_bytes.load(Ordering::Relaxed)).filter(|limit| *limit != usize::MAX)
+    }
+
+    fn max_value_bytes(&self) -> Option<usize> {
+        Some(self.max_value_bytes.load(Ordering::Relaxed)).filter(|limit| *limit != usize::MAX)
+    }
+
+    fn max_ttl_secs(&self) -> Option<u64> {
+        Some(self.max_ttl_secs.load(Ordering::Relaxed)).filter(|limit| *limit != u64::MAX)
+    }
+
+    fn ttl_jitter_percent(&self) -> f64 {
+        f64::from_bits(self.ttl_jitter_percent.load(Ordering::Relaxed))
+    }
+
+    // A random offset of up to ttl_jitter_percent of the TTL either way.
+    fn jitter_ms(&self, ttl_ms: u64) -> i64 {
+        let percent = self.ttl_jitter_percent();
+        if percent <= 0.0 {
+            return 0;
+        }
+
+        let spread = ttl_ms as f64 * percent / 100.0;
+        ((fastrand::f64() * 2.0 - 1.0) * spread).round() as i64
+    }
+}
+
+// Commands counted in fixed buckets of RATE_BUCKET_MS, so memory doesn't
+// grow with load and recording takes no lock. Each bucket packs the slot it
+// counts for (time / RATE_BUCKET_MS) above the count, so a bucket left over
+// from an earlier lap of the ring is recognised and restarted in one step.
+pub struct CommandRateCounter {
+    buckets: [AtomicU64; RATE_BUCKETS],
+}
+
+impl Default for CommandRateCounter {
+    fn default() -> Self {
+        Self { buckets: std::array::from_fn(|_| AtomicU64::new(0)) }
+    }
+}
+
+impl CommandRateCounter {
+    pub fn record(&self, now_ms: u64) {
+        let slot = now_ms / RATE_BUCKET_MS;
+        let bucket = &self.buckets[slot as usize % RATE_BUCKETS];
+
+        let mut packed = bucket.load(Ordering::Relaxed);
+        loop {
+            let next = match packed >> RATE_COUNT_BITS == slot {
+                true if packed & RATE_COUNT_MASK == RATE_COUNT_MASK => return,
+                true => packed + 1,
+                false => slot << RATE_COUNT_BITS | 1,
+            };
+            match bucket.compare_exchange_weak(packed, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(current) => packed = current,
+            }
+        }
+    }
+
+    // Commands in the last RATE_WINDOW_MS of whole buckets. The bucket being
+    // filled is left out, so the rate trails by less than a bucket.
+    pub fn rate_per_second(&self, now_ms: u64) -> f64 {
+        let current = now_ms / RATE_BUCKET_MS;
+        let window = RATE_WINDOW_MS / RATE_BUCKET_MS;
+
+        let count: u64 = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .filter(|packed| {
+                let slot = packed >> RATE_COUNT_BITS;
+                slot < current && current - slot <= window
+            })
+            .map(|packed| packed & RATE_COUNT_MASK)
+            .sum();
+
+        count as f64 * 1000.0 / RATE_WINDOW_MS as f64
+    }
+}
+
+fn compress(value: &[u8]) -> Vec<u8> {
+    let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    e.write_all(value).unwrap();
+    e.finish().unwrap()
+}
+
+// Approximates what one entry costs on top of its key and payload bytes: the
+// map slot holding the key, value and expiry.
+const ENTRY_OVERHEAD_BYTES: usize = std::mem::size_of::<(String, (ValueData, u64))>();
+
+fn entry_usage(key: &str, value: &ValueData) -> usize {
+    key.len() + value.stored_bytes() + ENTRY_OVERHEAD_BYTES
+}
+
+// Stops reading one byte past `limit`, so a small payload that inflates to
+// gigabytes never gets the chance to allocate them.
+fn decompress(payload: &[u8], limit: usize) -> Result<Vec<u8>, KeyzError> {
+    let mut d = GzDecoder::new(payload).take(limit.saturating_add(1) as u64);
+    let mut decompressed_data = Vec::new();
+    d.read_to_end(&mut decompressed_data)
+        .map_err(|_| KeyzError::InvalidCommand("dump payload invalid".into()))?;
+
+    if decompressed_data.len() > limit {
+        return Err(KeyzError::PayloadTooLarge { limit });
+    }
+    Ok(decompressed_data)
+}
+
+// The decompressed size without keeping the bytes. Values already in the
+// store were checked on the way in, so this doesn't need a limit.
+fn decompressed_len(payload: &[u8]) -> usize {
+    std::io::copy(&mut GzDecoder::new(payload), &mut std::io::sink()).unwrap() as usize
+}
+
+// Same as `stored_bytes`, except that strings count as decompressed.
+fn raw_len(value: &ValueData) -> usize {
+    match value {
+        ValueData::String(compressed) => decompressed_len(compressed),
+        value => value.stored_bytes(),
+    }
+}
+
+// A string value as GET returns it, with integers in decimal. Any other type
+// is a WRONGTYPE error.
+fn string_value(value: &ValueData, limit: usize) -> Result<Vec<u8>, KeyzError> {
+    match value {
+        ValueData::String(compressed) => decompress(compressed, limit),
+        ValueData::Integer(n) => Ok(n.to_string().into_bytes()),
+        value => Err(KeyzError::WrongType { actual: value.type_name() }),
+    }
+}
+
+// At most one warning per this long about the clock stepping back.
+const CLOCK_WARNING_INTERVAL_MS: u64 = 60_000;
+
+// Where a store reads the time from. Each store holds its own, so a test can
+// hand one store a clock it moves by hand.
+pub trait ClockSource: Clone + Default + Send + Sync + 'static {
+    fn now_millis(&self) -> Result<u64, SystemTimeError>;
+}
+
+#[derive(Clone, Default)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now_millis(&self) -> Result<u64, SystemTimeError> {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+    }
+}
+
+// Never goes backwards: if the clock steps back (or reads before the epoch)
+// the latest reading is used instead, so TTLs don't suddenly grow. The floor
+// belongs to one store and its clones, not to the process.
+struct MonotonicClock<C> {
+    source: C,
+    floor: AtomicU64,
+    // Set while readings are behind the floor, so one step back is one
+    // warning however many reads it lasts.
+    behind: AtomicBool,
+    last_warning: AtomicU64,
+}
+
+impl<C: ClockSource> MonotonicClock<C> {
+    fn new(source: C) -> Self {
+        Self { source, floor: AtomicU64::new(0), behind: AtomicBool::new(false), last_warning: AtomicU64::new(0) }
+    }
+
+    fn now_millis(&self) -> u64 {
+        let reading = self.source.now_millis();
+        let floor = match &reading {
+            Ok(now) => self.floor.fetch_max(*now, Ordering::Relaxed).max(*now),
+            Err(_) => self.floor.load(Ordering::Relaxed),
+        };
+
+        let step_ms = match reading {
+            Ok(now) if now == floor => {
+                self.behind.store(false, Ordering::Relaxed);
+                return now;
+            }
+            Ok(now) => floor - now,
+            Err(e) => e.duration().as_millis() as u64,
+        };
+        if !self.behind.swap(true, Ordering::Relaxed) {
+            self.warn_backward(floor, step_ms);
+        }
+        floor
+    }
+
+    fn warn_backward(&self, floor: u64, step_ms: u64) {
+        let last = self.last_warning.load(Ordering::Relaxed);
+        if last != 0 && floor.saturating_sub(last) < CLOCK_WARNING_INTERVAL_MS {
+            return;
+        }
+        if self.last_warning.compare_exchange(last, floor.max(1), Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            warn!("[store] clock moved backward by {}ms, using cached value", step_ms);
+        }
+    }
+}
+
+// `payload` is the value compressed as the store holds it, which goes into
+// the blob as is, as with DUMP.
+fn restore_frame(key: &str, payload: &[u8], ttl_ms: u64) -> String {
+    let blob = dump::encode(&Dump { expires_at_ms: 0, compression: dump::GZIP, payload: payload.to_vec() });
+    format!("{} {} {} {}", RESTORE_FRAME, key, ttl_ms, blob)
+}
+
+fn setint_frame(key: &str, value: i64, ttl_ms: u64) -> String {
+    format!("{} {} {} {}", SETINT_FRAME, key, ttl_ms, value)
+}
+
+fn push_frame(frame: &str, key: &str, item: &[u8]) -> String {
+    format!("{} {} {}", frame, key, STANDARD.encode(item))
+}
+
+fn hset_frame(key: &str, field: &str, value: &[u8]) -> String {
+    format!("{} {} {} {}", HSET_FRAME, key, field, STANDARD.encode(value))
+}
+
+fn bits_frame(key: &str, bits: &[u8]) -> String {
+    format!("{} {} {}", BITS_FRAME, key, STANDARD.encode(bits))
+}
+
+// A command that panics while holding one of the store's locks poisons it.
+// What it left behind is kept rather than having every later command on the
+// lock panic in turn and take the whole database down with it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn db_frame(db: usize, frame: String) -> String {
+    match db {
+        0 => frame,
+        db => format!("{} {} {}", DB_FRAME, db, frame),
+    }
+}
+
+// The frames that rebuild one live key on a replica. A string or integer is
+// one frame carrying its TTL; a collection is written element by element and
+// then given its TTL.
+fn snapshot_frames(key: &str, value: &ValueData, expires_at: u64, now: u64) -> Vec<String> {
+    let ttl_ms = replicated_ttl_ms(expires_at, now);
+    let mut frames = match value {
+        ValueData::String(compressed) => return vec![restore_frame(key, compressed, ttl_ms)],
+        ValueData::Integer(n) => return vec![setint_frame(key, *n, ttl_ms)],
+        ValueData::List(items) => items.iter().map(|item| push_frame(RPUSH_FRAME, key, item)).collect::<Vec<_>>(),
+        ValueData::Hash(fields) => fields.iter().map(|(field, value)| hset_frame(key, field, value)).collect(),
+        ValueData::Set(members) => members.iter().map(|member| format!("{} {} {}", SADD_FRAME, key, member)).collect(),
+        ValueData::SortedSet(members) => members
+            .iter()
+            .map(|(member, score)| format!("{} {} {} {}", ZADD_FRAME, key, score, member))
+            .collect(),
+        ValueData::Bits(bits) => vec![bits_frame(key, bits)],
+    };
+
+    if ttl_ms != 0 {
+        frames.push(format!("{} {} {}", PEXPIRE_FRAME, key, ttl_ms));
+    }
+    frames
+}
+
+// LRANGE/ZRANGE indices over `len` elements: negative ones count from the
+// end and both are clamped, so only a range that ends up empty is None.
+fn rank_range(len: usize, start: i64, stop: i64) -> Option<RangeInclusive<usize>> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+    if start > stop || start >= len {
+        return None;
+    }
+
+    Some(start as usize..=stop as usize)
+}
+
+// Members by rank: by score, then by member for equal scores.
+fn ranked(members: &BTreeMap<String, f64>) -> Vec<(&String, f64)> {
+    let mut ranked: Vec<_> = members.iter().map(|(member, score)| (member, *score)).collect();
+    ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+    ranked
+}
+
+// Deadlines and `now` are unix times in milliseconds, 0 meaning no expiry.
+fn is_expired(expires_at: u64, now: u64) -> bool {
+    expires_at != 0 && now > expires_at
+}
+
+// Whole seconds left, rounded down as EXIN and TTL report them.
+fn secs_left(expires_at: u64, now: u64) -> u64 {
+    (expires_at - now) / 1000
+}
+
+// Milliseconds left for a replicated TTL, 0 for none, and at least 1 so a key
+// expiring this millisecond isn't replicated as never expiring.
+fn replicated_ttl_ms(expires_at: u64, now: u64) -> u64 {
+    match expires_at {
+        0 => 0,
+        expires_at => expires_at.saturating_sub(now).max(1),
+    }
+}
+
+#[derive(Serialize)]
+pub struct MetricsSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub sets: u64,
+    pub deletes: u64,
+    pub expirations: u64,
+    pub hit_ratio: f64,
+}
+
+#[derive(Serialize)]
+pub struct DebugEntry {
+    pub stored_bytes: usize,
+    pub original_bytes: usize,
+    pub compressed: bool,
+    pub encoding: &'static str,
+    pub ttl_remaining_secs: Option<u64>,
+}
+
+// One key as Store::iter yields it: a copy of the value taken when the
+// iterator reached the key. A string stays compressed until bytes() asks for
+// it.
+pub struct ValueView {
+    value: ValueData,
+    expires_at: u64,
+    now: u64,
+    limit: usize,
+}
+
+impl ValueView {
+    pub fn value(&self) -> &ValueData {
+        &self.value
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.value.type_name()
+    }
+
+    // The decompressed bytes of a string; collections are WRONGTYPE, read
+    // them through value().
+    pub fn bytes(&self) -> Result<Vec<u8>, KeyzError> {
+        string_value(&self.value, self.limit)
+    }
+
+    // Time left when the key was read, None if it has no TTL.
+    pub fn ttl(&self) -> Option<Duration> {
+        match self.expires_at {
+            0 => None,
+            expires_at => Some(Duration::from_millis(expires_at.saturating_sub(self.now))),
+        }
+    }
+
+    pub fn stored_bytes(&self) -> usize {
+        self.value.stored_bytes()
+    }
+}
+
+// Walks the selected database live rather than from a snapshot. The key
+// names are copied when the iterator is made; each value is then read under
+// the lock on its own, when next() reaches it, and the lock is let go before
+// the caller sees it. So a key written meanwhile may show its old or new
+// value, a key deleted meanwhile is skipped, and one created after iter()
+// isn't seen. The caller can write to the store while iterating.
+pub struct Iter<C: ClockSource = SystemClock> {
+    store: Store<C>,
+    keys: std::vec::IntoIter<String>,
+}
+
+impl<C: ClockSource> Iterator for Iter<C> {
+    type Item = (String, ValueView);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let limit = self.store.max_payload_bytes();
+
+        self.keys.by_ref().find_map(|key| {
+            let data = self.store.lock_data();
+            let now = self.store.now_ms();
+
+            match data.get(&key) {
+                Some((value, expires_at)) if !is_expired(*expires_at, now) => {
+                    let view = ValueView { value: value.clone(), expires_at: *expires_at, now, limit };
+                    Some((key, view))
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+pub struct MemoryUsage {
+    pub usage_bytes: usize,
+    pub stored_bytes: usize,
+    pub raw_bytes: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct BulkDelete {
+    pub deleted: usize,
+    pub more: bool,
+}
+
+#[derive(Default)]
+pub struct MemoryReport {
+    pub keys: usize,
+    pub usage_bytes: usize,
+    pub stored_bytes: usize,
+    pub raw_bytes: usize,
+    pub biggest: Vec<(String, usize)>,
+}
+
+// Remaining TTLs of up to TTL_HISTOGRAM_SAMPLE live keys; `sampled` says
+// how many were looked at, which is every key in a small store.
+#[derive(Default, Serialize)]
+pub struct TtlHistogram {
+    pub under_10s: usize,
+    pub under_60s: usize,
+    pub under_10m: usize,
+    pub under_1h: usize,
+    pub under_1d: usize,
+    pub over_1d: usize,
+    pub none: usize,
+    pub sampled: usize,
+}
+
+impl TtlHistogram {
+    fn add(&mut self, ttl_secs: Option<u64>) {
+        let bucket = match ttl_secs {
+            None => &mut self.none,
+            Some(ttl) if ttl < 10 => &mut self.under_10s,
+            Some(ttl) if ttl < 60 => &mut self.under_60s,
+            Some(ttl) if ttl < 10 * 60 => &mut self.under_10m,
+            Some(ttl) if ttl < 60 * 60 => &mut self.under_1h,
+            Some(ttl) if ttl < 24 * 60 * 60 => &mut self.under_1d,
+            Some(_) => &mut self.over_1d,
+        };
+
+        *bucket += 1;
+        self.sampled += 1;
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExpiringKey {
+    pub key: String,
+    pub ttl_secs: u64,
+}
+
+// Unlike the INFO histogram, `ttl_histogram` covers every key in the
+// namespace.
+#[derive(Serialize)]
+pub struct NamespaceStats {
+    pub keys: usize,
+    pub bytes: usize,
+    pub ttl_histogram: TtlHistogram,
+}
+
+#[derive(Serialize)]
+pub struct DatabaseStats {
+    pub db: usize,
+    pub keys: usize,
+    // Keys with a TTL.
+    pub expires: usize,
+}
+
+#[derive(Serialize)]
+pub struct StoreStats {
+    pub keys: usize,
+    // Only databases that hold keys are listed.
+    pub databases: Vec<DatabaseStats>,
+    pub ttl_histogram: TtlHistogram,
+    pub slow_query_count: usize,
+    pub commands_per_second: f64,
+    // Per command since startup or the last RESETSTATS, skipping commands
+    // that haven't run.
+    pub latency: BTreeMap<&'static str, LatencyStats>,
+    pub role: &'static str,
+    pub read_only: bool,
+    pub command_timeouts: u64,
+    // Connections closed because handling one of their commands panicked.
+    pub panics: u64,
+    pub pending_unlinks: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_log_dropped: Option<u64>,
+    // Bytes held for requests being read or answered, when that's budgeted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inflight_bytes: Option<usize>,
+    pub config_reloads: u64,
+    pub last_reload_epoch: u64,
+    pub listeners: Vec<String>,
+    pub metrics: MetricsSnapshot,
+    // Filled in by INFO, which knows the namespace separator.
+    pub top_namespaces: Vec<(String, usize)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+impl StoreMetrics {
+    fn snapshot(&self) -> MetricsSnapshot {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+
+        MetricsSnapshot {
+            hits,
+            misses,
+            sets: self.sets.load(Ordering::Relaxed),
+            deletes: self.deletes.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            hit_ratio: hits as f64 / (hits + misses).max(1) as f64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Store<C: ClockSource = SystemClock> {
+    // One map per logical database. `db` is the one this clone works on, so
+    // each connection's clone carries its own SELECT.
+    databases: Arc<Vec<Mutex<Data>>>,
+    db: usize,
+    metrics: Arc<StoreMetrics>,
+    slow_log: Arc<SlowQueryLog>,
+    limits: Arc<StoreLimits>,
+    listeners: Arc<Mutex<Vec<String>>>,
+    access_log: Arc<OnceLock<AccessLog>>,
+    inflight: Arc<OnceLock<std::sync::Arc<InflightBudget>>>,
+    // Held shared by every dispatched command and exclusively by EXEC, so a
+    // transaction's commands run with nothing in between.
+    transactions: Arc<RwLock<()>>,
+    // WATCHed keys per database, with the connection watching each and the
+    // sender that tells it the key was written.
+    watches: Arc<Vec<Mutex<Watches>>>,
+    // Where CONFIG REWRITE writes to; unset for embedded servers.
+    config_path: Arc<Mutex<Option<PathBuf>>>,
+    read_only: Arc<AtomicBool>,
+    replica: Arc<AtomicBool>,
+    replication: broadcast::Sender<String>,
+    clock: Arc<MonotonicClock<C>>,
+}
+
+impl<C: ClockSource> Store<C> {
+    pub fn new(config: &StoreConfig) -> Self {
+        Self::with_clock(config, C::default())
+    }
+
+    // A store reading the time from `clock` rather than a default one.
+    pub fn with_clock(config: &StoreConfig, clock: C) -> Self {
+        Self {
+            databases: Arc::new((0..config.databases.max(1)).map(|_| Mutex::new(HashMap::new())).collect()),
+            db: 0,
+            metrics: Arc::new(StoreMetrics::default()),
+            slow_log: Arc::new(SlowQueryLog::new(
+                config.slow_query_threshold_ms,
+                config.slow_log_max_len,
+            )),
+            limits: Arc::new(StoreLimits::new(config)),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+            access_log: Arc::new(OnceLock::new()),
+            inflight: Arc::new(OnceLock::new()),
+            transactions: Arc::new(RwLock::new(())),
+            watches: Arc::new((0..config.databases.max(1)).map(|_| Mutex::new(HashMap::new())).collect()),
+            config_path: Arc::new(Mutex::new(None)),
+            read_only: Arc::new(AtomicBool::new(false)),
+            replica: Arc::new(AtomicBool::new(false)),
+            replication: broadcast::channel(REPLICATION_BUFFER).0,
+            clock: Arc::new(MonotonicClock::new(clock)),
+        }
+    }
+
+    // Unix time in milliseconds, never less than an earlier reading.
+    fn now_ms(&self) -> u64 {
+        self.clock.now_millis()
+    }
+
+    // Applies the settings that can change while running. The slow log length
+    // is fixed at startup.
+    pub fn apply_config(&self, config: &StoreConfig) {
+        self.set_store_config(config);
+
+        self.metrics.config_reloads.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .last_reload_epoch
+            .store(self.now_ms() / 1000, Ordering::Relaxed);
+    }
+
+    // The store settings in effect right now, including CONFIG SET changes.
+    pub fn store_config(&self) -> StoreConfig {
+        StoreConfig {
+            slow_query_threshold_ms: self.slow_log.threshold(),
+            slow_log_max_len: self.slow_log.max_len(),
+            max_key_bytes: self.limits.max_key_bytes(),
+            max_value_bytes: self.limits.max_value_bytes(),
+            max_ttl_secs: self.limits.max_ttl_secs(),
+            ttl_jitter_percent: self.limits.ttl_jitter_percent(),
+            databases: self.databases.len(),
+            seed_path: None,
+            seed_strict: false,
+        }
+    }
+
+    // Like apply_config, but not counted as a reload. slow_log_max_len only
+    // takes effect at startup.
+    pub fn set_store_config(&self, config: &StoreConfig) {
+        self.limits.apply(config);
+        self.slow_log.set_threshold(config.slow_query_threshold_ms);
+    }
+
+    pub fn select(&mut self, db: usize) -> Result<(), KeyzError> {
+        if db >= self.databases.len() {
+            return Err(KeyzError::InvalidCommand("db index out of range".into()));
+        }
+
+        self.db = db;
+        Ok(())
+    }
+
+    pub fn selected_db(&self) -> usize {
+        self.db
+    }
+
+    fn data(&self) -> &Mutex<Data> {
+        &self.databases[self.db]
+    }
+
+    fn lock_data(&self) -> MutexGuard<'_, Data> {
+        lock(self.data())
+    }
+
+    // For DEBUG PANIC LOCKED, which checks that a lock poisoned this way
+    // doesn't take the database down.
+    pub fn panic_while_locked(&self) -> ! {
+        let _data = self.lock_data();
+        panic!("DEBUG PANIC LOCKED");
+    }
+
+    // Always in index order, so two callers locking everything can't
+    // deadlock, and single-database writers only ever hold one of them.
+    fn lock_all(&self) -> Vec<MutexGuard<'_, Data>> {
+        self.databases.iter().map(lock).collect()
+    }
+
+    pub fn slow_log(&self) -> &SlowQueryLog {
+        &self.slow_log
+    }
+
+    pub fn latency(&self) -> &LatencyMonitor {
+        &self.metrics.latency
+    }
+
+    pub fn histograms(&self) -> &CommandHistograms {
+        &self.metrics.histograms
+    }
+
+    pub fn set_listeners(&self, addresses: impl IntoIterator<Item = SocketAddr>) {
+        *lock(&self.listeners) = addresses.into_iter().map(|a| a.to_string()).collect();
+    }
+
+    pub fn listeners(&self) -> Vec<String> {
+        lock(&self.listeners).clone()
+    }
+
+    // Set once at startup; later calls are ignored.
+    pub fn set_access_log(&self, access_log: AccessLog) {
+        let _ = self.access_log.set(access_log);
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.access_log.get()
+    }
+
+    // Set once at startup; later calls are ignored, as connections already
+    // hold the first budget.
+    pub fn set_max_inflight_bytes(&self, limit: usize) {
+        let _ = self.inflight.set(std::sync::Arc::new(InflightBudget::new(limit)));
+    }
+
+    pub fn inflight_budget(&self) -> Option<std::sync::Arc<InflightBudget>> {
+        self.inflight.get().cloned()
+    }
+
+    pub fn transaction_lock(&self) -> Arc<RwLock<()>> {
+        self.transactions.clone()
+    }
+
+    pub fn watch(&self, id: u64, key: &str, signal: mpsc::Sender<()>) {
+        let mut watches = lock(&self.watches[self.db]);
+        watches.entry(key.to_string()).or_default().push((id, signal));
+    }
+
+    // `keys` are (database, key) pairs, as a connection may have watched
+    // keys in several databases.
+    pub fn unwatch<'a>(&self, id: u64, keys: impl IntoIterator<Item = &'a (usize, String)>) {
+        for (db, key) in keys {
+            let mut watches = lock(&self.watches[*db]);
+
+            if let Some(watchers) = watches.get_mut(key) {
+                watchers.retain(|(watcher, _)| *watcher != id);
+                if watchers.is_empty() {
+                    watches.remove(key);
+                }
+            }
+        }
+    }
+
+    // Called with the data lock held, so a watcher is told before anyone can
+    // read the new value. A full channel already holds a signal.
+    fn signal_watchers(&self, db: usize, key: Option<&str>) {
+        let watches = lock(&self.watches[db]);
+        if watches.is_empty() {
+            return;
+        }
+
+        let signal = |watchers: &Vec<(u64, mpsc::Sender<()>)>| {
+            for (_, signal) in watchers {
+                let _ = signal.try_send(());
+            }
+        };
+        match key {
+            Some(key) => watches.get(key).into_iter().for_each(signal),
+            None => watches.values().for_each(signal),
+        }
+    }
+
+    pub fn set_config_path(&self, path: PathBuf) {
+        *lock(&self.config_path) = Some(path);
+    }
+
+    pub fn config_path(&self) -> Option<PathBuf> {
+        lock(&self.config_path).clone()
+    }
+
+    // The read_only setting itself; is_read_only also counts replicas.
+    pub fn read_only_setting(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    pub fn set_max_payload_bytes(&self, limit: usize) {
+        self.limits.max_payload_bytes.store(limit, Ordering::Relaxed);
+    }
+
+    fn max_payload_bytes(&self) -> usize {
+        self.limits.max_payload_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    // A replica is always read-only for clients, whatever server.read_only says.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed) || self.is_replica()
+    }
+
+    pub fn set_replica(&self, replica: bool) {
+        self.replica.store(replica, Ordering::Relaxed);
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.replica.load(Ordering::Relaxed)
+    }
+
+    // Mutations are published while the data lock is held, so replicas see
+    // them in the order they were applied here. Nothing is formatted unless a
+    // replica is listening.
+    fn replicate(&self, frame: impl FnOnce() -> String) {
+        self.publish(|| db_frame(self.db, frame()));
+    }
+
+    // Like replicate, for frames that aren't about the selected database.
+    fn publish(&self, frame: impl FnOnce() -> String) {
+        if self.replication.receiver_count() > 0 {
+            let _ = self.replication.send(frame());
+        }
+    }
+
+    // Subscribes to the mutation stream and captures every live key as
+    // frames under one lock, so nothing falls between the snapshot and the
+    // stream. Writes wait while the snapshot is taken.
+    pub fn subscribe_with_snapshot(&self) -> (broadcast::Receiver<String>, Vec<String>) {
+        let databases = self.lock_all();
+        let updates = self.replication.subscribe();
+        (updates, self.snapshot(&databases))
+    }
+
+    // Every live key in every database as replication frames.
+    fn snapshot(&self, databases: &[MutexGuard<'_, Data>]) -> Vec<String> {
+        let now = self.now_ms();
+
+        databases
+            .iter()
+            .enumerate()
+            .flat_map(|(db, data)| {
+                data.iter()
+                    .filter(|(_, (_, expires_at))| !is_expired(*expires_at, now))
+                    .flat_map(move |(key, (value, expires_at))| snapshot_frames(key, value, *expires_at, now))
+                    .map(move |frame| db_frame(db, frame))
+            })
+            .collect()
+    }
+
+    pub fn iter(&self) -> Iter<C> {
+        let keys: Vec<String> = self.lock_data().keys().cloned().collect();
+        Iter { store: self.clone(), keys: keys.into_iter() }
+    }
+
+    // Writes every database to `out` as the frames a replica syncs from,
+    // each behind a 4-byte big-endian length as on the wire. Unlike iter()
+    // this is a point-in-time copy: the frames are built with every database
+    // locked, then written with the locks released. TTLs are saved as the
+    // time left, so they resume counting down on import. Returns the number
+    // of frames written.
+    pub fn export(&self, out: &mut impl Write) -> io::Result<usize> {
+        let frames = self.snapshot(&self.lock_all());
+
+        for frame in &frames {
+            out.write_all(&(frame.len() as u32).to_be_bytes())?;
+            out.write_all(frame.as_bytes())?;
+        }
+        out.flush()?;
+
+        Ok(frames.len())
+    }
+
+    // Applies frames written by export() on top of what the store holds,
+    // the way a replica applies its primary's. A frame that doesn't apply,
+    // say a key over max_key_bytes, is logged and skipped; a file cut short
+    // or not made of frames is an error, with the frames before it applied.
+    pub fn import(&self, input: &mut impl Read) -> io::Result<usize> {
+        let mut applied = 0;
+
+        loop {
+            let mut prefix = [0; 4];
+            match input.read_exact(&mut prefix) {
+                Ok(()) => (),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(applied),
+                Err(e) => return Err(e),
+            }
+
+            // Read through take() so a bogus length doesn't allocate up front.
+            let len = u32::from_be_bytes(prefix) as u64;
+            let mut frame = Vec::new();
+            input.by_ref().take(len).read_to_end(&mut frame)?;
+            if frame.len() as u64 != len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "export ends in the middle of a frame"));
+            }
+
+            let frame = String::from_utf8(frame)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "export frame is not UTF-8"))?;
+            replication::apply(&frame, self);
+            applied += 1;
+        }
+    }
+
+    // Empties every database without replicating it; a replica uses this
+    // before it resyncs.
+    pub fn clear(&self) {
+        for mut data in self.lock_all() {
+            data.clear();
+        }
+    }
+
+    // Empties the selected database and returns how many keys it held.
+    pub fn flush(&self) -> usize {
+        let mut data = self.lock_data();
+        let flushed = data.len();
+
+        self.replicate(|| FLUSH_FRAME.to_string());
+        self.signal_watchers(self.db, None);
+        data.clear();
+
+        flushed
+    }
+
+    // Empties every database under all their locks at once and returns how
+    // many keys they held.
+    pub fn flush_all(&self) -> usize {
+        let mut databases = self.lock_all();
+        let flushed = databases.iter().map(|data| data.len()).sum();
+
+        self.publish(|| FLUSHALL_FRAME.to_string());
+        for (db, data) in databases.iter_mut().enumerate() {
+            self.signal_watchers(db, None);
+            data.clear();
+        }
+
+        flushed
+    }
+
+    // Exchanges the contents of two databases under both their locks, so no
+    // reader sees one swapped without the other. Connections stay on their
+    // index and see the other database's keys from then on.
+    pub fn swap_databases(&self, first: usize, second: usize) -> Result<(), KeyzError> {
+        if first >= self.databases.len() || second >= self.databases.len() {
+            return Err(KeyzError::InvalidCommand("db index out of range".into()));
+        }
+        if first == second {
+            return Ok(());
+        }
+
+        let (low, high) = (first.min(second), first.max(second));
+        let mut low_data = lock(&self.databases[low]);
+        let mut high_data = lock(&self.databases[high]);
+
+        self.publish(|| format!("{} {} {}", SWAPDB_FRAME, first, second));
+        self.signal_watchers(low, None);
+        self.signal_watchers(high, None);
+        std::mem::swap(&mut *low_data, &mut *high_data);
+
+        Ok(())
+    }
+
+    pub fn record_command(&self) {
+        self.metrics.commands.record(self.now_ms());
+    }
+
+    pub fn record_dispatch(&self, command: &str, error: Option<ErrorCode>) {
+        self.metrics.prometheus.record_command(command, error);
+    }
+
+    pub fn render_prometheus(&self) -> String {
+        self.metrics.prometheus.render(&self.stats())
+    }
+
+    pub fn record_command_timeout(&self) {
+        self.metrics.command_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_panic(&self) {
+        self.metrics.panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn check_limits(&self, key: &str, value_bytes: usize) -> Result<(), KeyzError> {
+        let limit = self.limits.max_key_bytes.load(Ordering::Relaxed);
+        if key.len() > limit {
+            return Err(KeyzError::KeyTooLarge { size: key.len(), key: key.to_string(), limit });
+        }
+
+        let limit = self.limits.max_value_bytes.load(Ordering::Relaxed);
+        if value_bytes > limit {
+            return Err(KeyzError::ValueTooLarge { key: key.to_string(), size: value_bytes, limit });
+        }
+
+        Ok(())
+    }
+
+    pub fn insert(&self, key: String, value: Vec<u8>, seconds: u64) -> Result<(), KeyzError> {
+        self.check_limits(&key, value.len())?;
+
+        let expire_in = match seconds {
+            0 => 0,
+            seconds => self.ttl_deadline(seconds)?,
+        };
+        self.insert_at(key, value, expire_in);
+
+        Ok(())
+    }
+
+    // Like insert with the TTL in milliseconds, which is never jittered.
+    pub fn insert_ms(&self, key: String, value: Vec<u8>, ttl_ms: u64) -> Result<(), KeyzError> {
+        self.check_limits(&key, value.len())?;
+
+        let expire_in = match ttl_ms {
+            0 => 0,
+            ttl_ms => self.ttl_deadline_ms(ttl_ms)?,
+        };
+        self.insert_at(key, value, expire_in);
+
+        Ok(())
+    }
+
+    // Replicated with the milliseconds left rather than the TTL asked for,
+    // so a jittered deadline reaches replicas as is instead of being
+    // jittered again.
+    fn insert_at(&self, key: String, value: Vec<u8>, expire_in: u64) {
+        let mut data = self.lock_data();
+        debug!(key = %key, expire_in, "inserting key");
+
+        let compressed_data = compress(&value);
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+
+        let ttl_ms = replicated_ttl_ms(expire_in, self.now_ms());
+        self.replicate(|| restore_frame(&key, &compressed_data, ttl_ms));
+        self.signal_watchers(self.db, Some(&key));
+        data.insert(key, (ValueData::String(compressed_data), expire_in));
+    }
+
+    // MSETNX: sets every pair, without a TTL, only if none of the keys is
+    // live, all under one lock so no other write can land in between.
+    // Returns whether they were set.
+    pub fn insert_all_if_absent(&self, entries: Vec<(String, Vec<u8>)>) -> Result<bool, KeyzError> {
+        for (key, value) in &entries {
+            self.check_limits(key, value.len())?;
+        }
+
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let taken = entries
+            .iter()
+            .any(|(key, _)| data.get(key).is_some_and(|(_, expires_at)| !is_expired(*expires_at, now)));
+        if taken {
+            return Ok(false);
+        }
+
+        debug!(keys = entries.len(), "inserting keys if absent");
+        for (key, value) in entries {
+            let compressed = compress(&value);
+            self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+            self.replicate(|| restore_frame(&key, &compressed, 0));
+            self.signal_watchers(self.db, Some(&key));
+            data.insert(key, (ValueData::String(compressed), 0));
+        }
+
+        Ok(true)
+    }
+
+    // Read-modify-write of one key under the data lock, so concurrent updates
+    // of the same key can't lose each other's changes. `modify` gets the
+    // current value, or None if the key is missing or expired, and the key
+    // keeps its expiry.
+    pub fn update(
+        &self,
+        key: &str,
+        modify: impl FnOnce(Option<Vec<u8>>) -> Result<Vec<u8>, KeyzError>,
+    ) -> Result<(), KeyzError> {
+        let limit = self.limits.max_key_bytes.load(Ordering::Relaxed);
+        if key.len() > limit {
+            return Err(KeyzError::KeyTooLarge { size: key.len(), key: key.to_string(), limit });
+        }
+
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let (current, expires_at) = match data.get(key) {
+            Some((value, expires_at)) if !is_expired(*expires_at, now) => {
+                (Some(string_value(value, self.max_payload_bytes())?), *expires_at)
+            }
+            _ => (None, 0),
+        };
+
+        let value = modify(current)?;
+
+        let limit = self.limits.max_value_bytes.load(Ordering::Relaxed);
+        if value.len() > limit {
+            return Err(KeyzError::ValueTooLarge { key: key.to_string(), size: value.len(), limit });
+        }
+
+        let compressed = compress(&value);
+        let ttl_ms = replicated_ttl_ms(expires_at, now);
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| restore_frame(key, &compressed, ttl_ms));
+        self.signal_watchers(self.db, Some(key));
+        data.insert(key.to_string(), (ValueData::String(compressed), expires_at));
+
+        Ok(())
+    }
+
+    // INCR: adds `by` to the key's integer, treating a missing key as 0. A
+    // string holding a number becomes an integer, and the key keeps its
+    // expiry.
+    pub fn increment(&self, key: &str, by: i64) -> Result<i64, KeyzError> {
+        let limit = self.limits.max_key_bytes.load(Ordering::Relaxed);
+        if key.len() > limit {
+            return Err(KeyzError::KeyTooLarge { size: key.len(), key: key.to_string(), limit });
+        }
+
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let (current, expires_at) = match data.get(key) {
+            Some((ValueData::Integer(n), expires_at)) if !is_expired(*expires_at, now) => (*n, *expires_at),
+            Some((value, expires_at)) if !is_expired(*expires_at, now) => {
+                let value = string_value(value, self.max_payload_bytes())?;
+                let n = std::str::from_utf8(&value)
+                    .ok()
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .ok_or_else(|| KeyzError::InvalidCommand("value is not an integer".into()))?;
+                (n, *expires_at)
+            }
+            _ => (0, 0),
+        };
+        let value = current
+            .checked_add(by)
+            .ok_or_else(|| KeyzError::InvalidCommand("increment would overflow".into()))?;
+
+        let ttl_ms = replicated_ttl_ms(expires_at, now);
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| setint_frame(key, value, ttl_ms));
+        self.signal_watchers(self.db, Some(key));
+        data.insert(key.to_string(), (ValueData::Integer(value), expires_at));
+
+        Ok(value)
+    }
+
+    // The value at `key` with the data lock held, made `empty()` if the key
+    // is missing or expired. Writes to collections use it; reads of a
+    // missing key shouldn't create one.
+    fn get_or_create<'a>(&self, data: &'a mut Data, key: &str, empty: fn() -> ValueData) -> &'a mut ValueData {
+        let now = self.now_ms();
+        // Looked up first so a hit doesn't allocate the key.
+        if !data.contains_key(key) {
+            data.insert(key.to_string(), (empty(), 0));
+        }
+        let entry = data.get_mut(key).unwrap();
+
+        if is_expired(entry.1, now) {
+            self.metrics.expirations.fetch_add(1, Ordering::Relaxed);
+            *entry = (empty(), 0);
+        }
+        &mut entry.0
+    }
+
+    fn get_or_create_list<'a>(&self, data: &'a mut Data, key: &str) -> Result<&'a mut VecDeque<Vec<u8>>, KeyzError> {
+        match self.get_or_create(data, key, || ValueData::List(VecDeque::new())) {
+            ValueData::List(items) => Ok(items),
+            value => Err(KeyzError::WrongType { actual: value.type_name() }),
+        }
+    }
+
+    fn get_or_create_hash<'a>(
+        &self,
+        data: &'a mut Data,
+        key: &str,
+    ) -> Result<&'a mut HashMap<String, Vec<u8>>, KeyzError> {
+        match self.get_or_create(data, key, || ValueData::Hash(HashMap::new())) {
+            ValueData::Hash(fields) => Ok(fields),
+            value => Err(KeyzError::WrongType { actual: value.type_name() }),
+        }
+    }
+
+    fn get_or_create_set<'a>(&self, data: &'a mut Data, key: &str) -> Result<&'a mut HashSet<String>, KeyzError> {
+        match self.get_or_create(data, key, || ValueData::Set(HashSet::new())) {
+            ValueData::Set(members) => Ok(members),
+            value => Err(KeyzError::WrongType { actual: value.type_name() }),
+        }
+    }
+
+    fn get_or_create_sorted_set<'a>(
+        &self,
+        data: &'a mut Data,
+        key: &str,
+    ) -> Result<&'a mut BTreeMap<String, f64>, KeyzError> {
+        match self.get_or_create(data, key, || ValueData::SortedSet(BTreeMap::new())) {
+            ValueData::SortedSet(members) => Ok(members),
+            value => Err(KeyzError::WrongType { actual: value.type_name() }),
+        }
+    }
+
+    fn get_or_create_bits<'a>(&self, data: &'a mut Data, key: &str) -> Result<&'a mut Vec<u8>, KeyzError> {
+        match self.get_or_create(data, key, || ValueData::Bits(Vec::new())) {
+            ValueData::Bits(bits) => Ok(bits),
+            value => Err(KeyzError::WrongType { actual: value.type_name() }),
+        }
+    }
+
+    // The live entry at `key` for GET and GETEX. An expired one is removed
+    // and counted, and either way a key that isn't live is a miss.
+    fn read_entry<'a>(&self, data: &'a mut Data, key: &str, now: u64) -> Option<&'a mut (ValueData, u64)> {
+        if data.get(key).is_some_and(|(_, expires_at)| is_expired(*expires_at, now)) {
+            data.remove(key);
+            self.metrics.expirations.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let entry = data.get_mut(key);
+        if entry.is_none() {
+            self.metrics.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        entry
+    }
+
+    fn live_value<'a>(data: &'a mut Data, key: &str, now: u64) -> Option<&'a mut ValueData> {
+        match data.get_mut(key) {
+            Some((value, expires_at)) if !is_expired(*expires_at, now) => Some(value),
+            _ => None,
+        }
+    }
+
+    // The live list at `key`, or None if there isn't one.
+    fn live_list<'a>(data: &'a mut Data, key: &str, now: u64) -> Result<Option<&'a mut VecDeque<Vec<u8>>>, KeyzError> {
+        match Self::live_value(data, key, now) {
+            Some(ValueData::List(items)) => Ok(Some(items)),
+            Some(value) => Err(KeyzError::WrongType { actual: value.type_name() }),
+            None => Ok(None),
+        }
+    }
+
+    fn live_hash<'a>(
+        data: &'a mut Data,
+        key: &str,
+        now: u64,
+    ) -> Result<Option<&'a mut HashMap<String, Vec<u8>>>, KeyzError> {
+        match Self::live_value(data, key, now) {
+            Some(ValueData::Hash(fields)) => Ok(Some(fields)),
+            Some(value) => Err(KeyzError::WrongType { actual: value.type_name() }),
+            None => Ok(None),
+        }
+    }
+
+    // LPUSH/RPUSH: returns the list's new length.
+    pub fn push(&self, key: &str, value: Vec<u8>, end: ListEnd) -> Result<usize, KeyzError> {
+        self.check_limits(key, value.len())?;
+
+        let mut data = self.lock_data();
+        let items = self.get_or_create_list(&mut data, key)?;
+
+        let frame = match end {
+            ListEnd::Front => LPUSH_FRAME,
+            ListEnd::Back => RPUSH_FRAME,
+        };
+        self.replicate(|| push_frame(frame, key, &value));
+
+        match end {
+            ListEnd::Front => items.push_front(value),
+            ListEnd::Back => items.push_back(value),
+        }
+        let len = items.len();
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.signal_watchers(self.db, Some(key));
+        Ok(len)
+    }
+
+    // LPOP/RPOP. A list is deleted along with its last element, so no empty
+    // lists are left behind.
+    pub fn pop(&self, key: &str, end: ListEnd) -> Result<Option<Vec<u8>>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(items) = Self::live_list(&mut data, key, now)? else {
+            self.metrics.misses.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        };
+        let (value, frame) = match end {
+            ListEnd::Front => (items.pop_front(), LPOP_FRAME),
+            ListEnd::Back => (items.pop_back(), RPOP_FRAME),
+        };
+        if items.is_empty() {
+            data.remove(key);
+        }
+
+        self.metrics.hits.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| format!("{} {}", frame, key));
+        self.signal_watchers(self.db, Some(key));
+        Ok(value)
+    }
+
+    // LRANGE, with Redis's indices: negative ones count from the end, and
+    // both are clamped to the list. A missing key is an empty list.
+    pub fn list_range(&self, key: &str, start: i64, stop: i64) -> Result<Vec<Vec<u8>>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(items) = Self::live_list(&mut data, key, now)? else {
+            return Ok(Vec::new());
+        };
+
+        match rank_range(items.len(), start, stop) {
+            Some(range) => Ok(items.range(range).cloned().collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn list_len(&self, key: &str) -> Result<usize, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        Ok(Self::live_list(&mut data, key, now)?.map_or(0, |items| items.len()))
+    }
+
+    // HSET: returns whether the field is new.
+    pub fn hash_set(&self, key: &str, field: String, value: Vec<u8>) -> Result<bool, KeyzError> {
+        self.check_limits(key, field.len() + value.len())?;
+
+        let mut data = self.lock_data();
+        let fields = self.get_or_create_hash(&mut data, key)?;
+
+        self.replicate(|| hset_frame(key, &field, &value));
+        let added = fields.insert(field, value).is_none();
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.signal_watchers(self.db, Some(key));
+        Ok(added)
+    }
+
+    pub fn hash_get(&self, key: &str, field: &str) -> Result<Option<Vec<u8>>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let value = Self::live_hash(&mut data, key, now)?.and_then(|fields| fields.get(field).cloned());
+        match value {
+            Some(_) => self.metrics.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.metrics.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        Ok(value)
+    }
+
+    // HDEL: returns whether the field was there. The hash goes with its last
+    // field.
+    pub fn hash_delete(&self, key: &str, field: &str) -> Result<bool, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(fields) = Self::live_hash(&mut data, key, now)? else {
+            return Ok(false);
+        };
+        if fields.remove(field).is_none() {
+            return Ok(false);
+        }
+        if fields.is_empty() {
+            data.remove(key);
+        }
+
+        self.metrics.deletes.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| format!("{} {} {}", HDEL_FRAME, key, field));
+        self.signal_watchers(self.db, Some(key));
+        Ok(true)
+    }
+
+    // Every field and value, sorted by field. A missing key is an empty hash.
+    pub fn hash_get_all(&self, key: &str) -> Result<BTreeMap<String, Vec<u8>>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        Ok(Self::live_hash(&mut data, key, now)?
+            .map(|fields| fields.iter().map(|(field, value)| (field.clone(), value.clone())).collect())
+            .unwrap_or_default())
+    }
+
+    fn live_set<'a>(data: &'a mut Data, key: &str, now: u64) -> Result<Option<&'a mut HashSet<String>>, KeyzError> {
+        match Self::live_value(data, key, now) {
+            Some(ValueData::Set(members)) => Ok(Some(members)),
+            Some(value) => Err(KeyzError::WrongType { actual: value.type_name() }),
+            None => Ok(None),
+        }
+    }
+
+    // SADD: returns whether the member is new.
+    pub fn set_add(&self, key: &str, member: String) -> Result<bool, KeyzError> {
+        self.check_limits(key, member.len())?;
+
+        let mut data = self.lock_data();
+        let members = self.get_or_create_set(&mut data, key)?;
+        if members.contains(&member) {
+            return Ok(false);
+        }
+
+        self.replicate(|| format!("{} {} {}", SADD_FRAME, key, member));
+        members.insert(member);
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.signal_watchers(self.db, Some(key));
+        Ok(true)
+    }
+
+    // SREM: returns whether the member was there. The set goes with its last
+    // member.
+    pub fn set_remove(&self, key: &str, member: &str) -> Result<bool, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(members) = Self::live_set(&mut data, key, now)? else {
+            return Ok(false);
+        };
+        if !members.remove(member) {
+            return Ok(false);
+        }
+        if members.is_empty() {
+            data.remove(key);
+        }
+
+        self.metrics.deletes.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| format!("{} {} {}", SREM_FRAME, key, member));
+        self.signal_watchers(self.db, Some(key));
+        Ok(true)
+    }
+
+    pub fn set_contains(&self, key: &str, member: &str) -> Result<bool, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        Ok(Self::live_set(&mut data, key, now)?.is_some_and(|members| members.contains(member)))
+    }
+
+    pub fn set_len(&self, key: &str) -> Result<usize, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        Ok(Self::live_set(&mut data, key, now)?.map_or(0, |members| members.len()))
+    }
+
+    // Sorted, so SMEMBERS and SUNION answer the same way every time. A
+    // missing key is an empty set.
+    pub fn set_members(&self, key: &str) -> Result<BTreeSet<String>, KeyzError> {
+        self.set_union(&[key])
+    }
+
+    pub fn set_union(&self, keys: &[&str]) -> Result<BTreeSet<String>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let mut union = BTreeSet::new();
+        for key in keys {
+            if let Some(members) = Self::live_set(&mut data, key, now)? {
+                union.extend(members.iter().cloned());
+            }
+        }
+        Ok(union)
+    }
+
+    // SINTERSTORE: replaces `destination` with the members every key has in
+    // common, read and written under one lock. An empty result deletes
+    // `destination`, as an empty set can't exist. Replicas get the result
+    // rather than the command, like INCR.
+    pub fn set_intersect_store(&self, destination: &str, keys: &[&str]) -> Result<usize, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let mut intersection: Option<HashSet<String>> = None;
+        for key in keys {
+            let members = Self::live_set(&mut data, key, now)?;
+            intersection = Some(match (intersection, members) {
+                (None, Some(members)) => members.clone(),
+                (Some(mut common), Some(members)) => {
+                    common.retain(|member| members.contains(member));
+                    common
+                }
+                (_, None) => HashSet::new(),
+            });
+        }
+        let intersection = intersection.unwrap_or_default();
+
+        self.check_limits(destination, intersection.iter().map(String::len).sum())?;
+
+        self.replicate(|| format!("DEL {}", destination));
+        for member in &intersection {
+            self.replicate(|| format!("{} {} {}", SADD_FRAME, destination, member));
+        }
+
+        let len = intersection.len();
+        match len {
+            0 => data.remove(destination),
+            _ => data.insert(destination.to_string(), (ValueData::Set(intersection), 0)),
+        };
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.signal_watchers(self.db, Some(destination));
+        Ok(len)
+    }
+
+    fn live_sorted_set<'a>(
+        data: &'a mut Data,
+        key: &str,
+        now: u64,
+    ) -> Result<Option<&'a mut BTreeMap<String, f64>>, KeyzError> {
+        match Self::live_value(data, key, now) {
+            Some(ValueData::SortedSet(members)) => Ok(Some(members)),
+            Some(value) => Err(KeyzError::WrongType { actual: value.type_name() }),
+            None => Ok(None),
+        }
+    }
+
+    // ZADD: returns whether the member is new; an existing one gets the new
+    // score.
+    pub fn zset_add(&self, key: &str, score: f64, member: String) -> Result<bool, KeyzError> {
+        if score.is_nan() {
+            return Err(KeyzError::InvalidCommand("score is not a number".into()));
+        }
+        self.check_limits(key, member.len())?;
+
+        let mut data = self.lock_data();
+        let members = self.get_or_create_sorted_set(&mut data, key)?;
+
+        self.replicate(|| format!("{} {} {} {}", ZADD_FRAME, key, score, member));
+        let added = members.insert(member, score).is_none();
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.signal_watchers(self.db, Some(key));
+        Ok(added)
+    }
+
+    // ZREM: returns whether the member was there. The sorted set goes with
+    // its last member.
+    pub fn zset_remove(&self, key: &str, member: &str) -> Result<bool, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(members) = Self::live_sorted_set(&mut data, key, now)? else {
+            return Ok(false);
+        };
+        if members.remove(member).is_none() {
+            return Ok(false);
+        }
+        if members.is_empty() {
+            data.remove(key);
+        }
+
+        self.metrics.deletes.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| format!("{} {} {}", ZREM_FRAME, key, member));
+        self.signal_watchers(self.db, Some(key));
+        Ok(true)
+    }
+
+    pub fn zset_score(&self, key: &str, member: &str) -> Result<Option<f64>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        Ok(Self::live_sorted_set(&mut data, key, now)?.and_then(|members| members.get(member).copied()))
+    }
+
+    // 0 for the lowest score.
+    pub fn zset_rank(&self, key: &str, member: &str) -> Result<Option<usize>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(members) = Self::live_sorted_set(&mut data, key, now)? else {
+            return Ok(None);
+        };
+        Ok(ranked(members).iter().position(|(candidate, _)| *candidate == member))
+    }
+
+    // ZRANGE: members from rank `start` to `stop`, inclusive, with their
+    // scores. Indices work as in LRANGE.
+    pub fn zset_range(&self, key: &str, start: i64, stop: i64) -> Result<Vec<(String, f64)>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(members) = Self::live_sorted_set(&mut data, key, now)? else {
+            return Ok(Vec::new());
+        };
+        let ranked = ranked(members);
+
+        match rank_range(ranked.len(), start, stop) {
+            Some(range) => Ok(ranked[range].iter().map(|(member, score)| ((*member).clone(), *score)).collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // ZRANGEBYSCORE: members scored between `min` and `max`, by rank.
+    pub fn zset_range_by_score(
+        &self,
+        key: &str,
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> Result<Vec<(String, f64)>, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(members) = Self::live_sorted_set(&mut data, key, now)? else {
+            return Ok(Vec::new());
+        };
+
+        Ok(ranked(members)
+            .into_iter()
+            .filter(|(_, score)| min.at_or_below(*score) && max.at_or_above(*score))
+            .map(|(member, score)| (member.clone(), score))
+            .collect())
+    }
+
+    fn live_bits<'a>(data: &'a mut Data, key: &str, now: u64) -> Result<Option<&'a mut Vec<u8>>, KeyzError> {
+        match Self::live_value(data, key, now) {
+            Some(ValueData::Bits(bits)) => Ok(Some(bits)),
+            Some(value) => Err(KeyzError::WrongType { actual: value.type_name() }),
+            None => Ok(None),
+        }
+    }
+
+    // SETBIT: returns what the bit was. A bit far past the end grows the
+    // array with zeros up to it.
+    pub fn set_bit(&self, key: &str, offset: u64, bit: bool) -> Result<bool, KeyzError> {
+        if offset > bits::MAX_OFFSET {
+            return Err(KeyzError::InvalidCommand("bit offset out of range".into()));
+        }
+        self.check_limits(key, (offset / 8 + 1) as usize)?;
+
+        let mut data = self.lock_data();
+        let array = self.get_or_create_bits(&mut data, key)?;
+
+        self.replicate(|| format!("SETBIT {} {} {}", key, offset, bit as u8));
+        let was = bits::set(array, offset, bit);
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.signal_watchers(self.db, Some(key));
+        Ok(was)
+    }
+
+    pub fn get_bit(&self, key: &str, offset: u64) -> Result<bool, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        Ok(Self::live_bits(&mut data, key, now)?.is_some_and(|array| bits::get(array, offset)))
+    }
+
+    // BITCOUNT, over the bytes from `start` to `end` if given. Indices work
+    // as in LRANGE, on bytes.
+    pub fn bit_count(&self, key: &str, range: Option<(i64, i64)>) -> Result<u64, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some(array) = Self::live_bits(&mut data, key, now)? else {
+            return Ok(0);
+        };
+        let (start, end) = range.unwrap_or((0, -1));
+
+        match rank_range(array.len(), start, end) {
+            Some(range) => Ok(bits::count(&array[range])),
+            None => Ok(0),
+        }
+    }
+
+    // BITPOS: the offset of the first bit equal to `bit` within the bytes
+    // from `start` to `end`, or -1. As in Redis, looking for a 0 without an
+    // end treats the array as padded with zeros, so a full array answers
+    // the first offset past it.
+    pub fn bit_position(&self, key: &str, bit: bool, start: i64, end: Option<i64>) -> Result<i64, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let array = match Self::live_bits(&mut data, key, now)? {
+            Some(array) => array,
+            None => return Ok(if bit { -1 } else { 0 }),
+        };
+        let Some(range) = rank_range(array.len(), start, end.unwrap_or(-1)) else {
+            return Ok(-1);
+        };
+
+        let first = *range.start() as i64;
+        let past = (*range.end() + 1) as i64;
+        match bits::position(&array[range], bit) {
+            Some(offset) => Ok(first * 8 + offset as i64),
+            None if !bit && end.is_none() => Ok(past * 8),
+            None => Ok(-1),
+        }
+    }
+
+    // BITOP: replaces `destination` with the result, with no TTL, and
+    // returns its length in bytes. A missing source counts as empty, and an
+    // empty result deletes `destination`.
+    pub fn bit_op(&self, op: BitOp, destination: &str, keys: &[&str]) -> Result<usize, KeyzError> {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let mut sources = Vec::with_capacity(keys.len());
+        for key in keys {
+            sources.push(Self::live_bits(&mut data, key, now)?.cloned().unwrap_or_default());
+        }
+        let sources: Vec<&[u8]> = sources.iter().map(Vec::as_slice).collect();
+        let result = bits::op(op, &sources);
+        self.check_limits(destination, result.len())?;
+
+        let len = result.len();
+        self.put_bits(&mut data, destination, result);
+        Ok(len)
+    }
 
-pub struct Store {
-    data: Arc<Mutex<HashMap<String, (Vec<u8>, u64)>>>,
-}
+    // Replaces `key` with a whole bit array, as replicas receive BITOP's
+    // result.
+    pub fn replace_bits(&self, key: &str, array: Vec<u8>) -> Result<(), KeyzError> {
+        self.check_limits(key, array.len())?;
 
-impl Store {
-    pub fn new() -> Self {
-        Self {
-            data: Arc::new(Mutex::new(HashMap::new())),
+        let mut data = self.lock_data();
+        self.put_bits(&mut data, key, array);
+        Ok(())
+    }
+
+    fn put_bits(&self, data: &mut Data, key: &str, array: Vec<u8>) {
+        match array.is_empty() {
+            true => {
+                self.replicate(|| format!("DEL {}", key));
+                data.remove(key);
+            }
+            false => {
+                self.replicate(|| bits_frame(key, &array));
+                data.insert(key.to_string(), (ValueData::Bits(array), 0));
+            }
         }
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.signal_watchers(self.db, Some(key));
     }
 
-    pub fn insert(&self, key: String, value: Vec<u8>, seconds: u64) {
-        let mut data = self.data.lock().unwrap();
-        println!("[STORE] Inserting key:{} expire secs: {}", key, seconds);
+    pub fn value_type(&self, key: &str) -> Option<&'static str> {
+        let data = self.lock_data();
+
+        match data.get(key) {
+            Some((value, expires_at)) if !is_expired(*expires_at, self.now_ms()) => {
+                Some(value.type_name())
+            }
+            _ => None,
+        }
+    }
 
-        let mut e = GzEncoder::new(Vec::new(), Compression::default());
-        e.write_all(&value).unwrap();
-        let compressed_data = e.finish().unwrap();
+    // Second TTLs from SET and GETEX are jittered, so keys written together
+    // don't all expire together. Millisecond ones are kept exact.
+    fn ttl_deadline(&self, ttl: u64) -> Result<u64, KeyzError> {
+        self.check_ttl(ttl)?;
+        let ttl_ms = ttl.saturating_mul(1000);
+        let deadline = self.ttl_deadline_ms(ttl_ms)?;
 
-        if seconds == 0 {
-            data.insert(key, (compressed_data, 0));
-            return;
+        Ok(deadline.saturating_add_signed(self.limits.jitter_ms(ttl_ms)))
+    }
+
+    fn ttl_deadline_ms(&self, ttl_ms: u64) -> Result<u64, KeyzError> {
+        self.check_ttl(ttl_ms.div_ceil(1000))?;
+
+        Ok(self.now_ms().saturating_add(ttl_ms))
+    }
+
+    // Second TTLs are checked before they're turned into milliseconds, which
+    // saturates, so an error names the TTL that was asked for.
+    fn check_ttl(&self, ttl: u64) -> Result<(), KeyzError> {
+        let limit = self.limits.max_ttl_secs.load(Ordering::Relaxed);
+        if ttl > limit {
+            return Err(KeyzError::TtlTooLarge { ttl, limit });
         }
 
-        let expire_in = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            + seconds;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, KeyzError> {
+        debug!(key, "getting key");
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some((value, _)) = self.read_entry(&mut data, key, now) else {
+            return Ok(None);
+        };
+        let decompressed_data = string_value(value, self.max_payload_bytes())?;
+
+        self.metrics.hits.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(decompressed_data))
+    }
+
+    // Reads a key and changes its TTL under the same lock, so nothing can
+    // write the key in between.
+    pub fn get_ex(&self, key: &str, modifier: GetExModifier) -> Result<Option<Vec<u8>>, KeyzError> {
+        let deadline = match modifier {
+            GetExModifier::Ex(seconds) => self.ttl_deadline(seconds)?,
+            GetExModifier::Px(ttl_ms) => self.ttl_deadline_ms(ttl_ms)?,
+            GetExModifier::None | GetExModifier::Persist => 0,
+        };
+
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some((value, expires_at)) = self.read_entry(&mut data, key, now) else {
+            return Ok(None);
+        };
+        let value = string_value(value, self.max_payload_bytes())?;
+        self.metrics.hits.fetch_add(1, Ordering::Relaxed);
+
+        match modifier {
+            GetExModifier::None => return Ok(Some(value)),
+            GetExModifier::Persist if *expires_at == 0 => return Ok(Some(value)),
+            GetExModifier::Persist => self.replicate(|| format!("{} {}", PERSIST_FRAME, key)),
+            // The deadline was worked out before the lock, so it can already
+            // have passed; the replica still gets a TTL that expires it.
+            GetExModifier::Ex(_) | GetExModifier::Px(_) => {
+                let ttl_ms = deadline.saturating_sub(now).max(1);
+                self.replicate(|| format!("{} {} {}", PEXPIRE_FRAME, key, ttl_ms))
+            }
+        }
+        *expires_at = deadline;
+        self.signal_watchers(self.db, Some(key));
 
-        data.insert(key, (compressed_data, expire_in));
+        Ok(Some(value))
     }
 
-    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
-        println!("[STORE] Getting {} ", key);
-        let mut data = self.data.lock().unwrap();
+    pub fn delete(&self, key: &str) -> Option<String> {
+        debug!(key, "deleting key");
+        let mut data = self.lock_data();
 
-        let value = data.get(key).is_none();
+        // Remove first and inspect the removed entry afterwards so the expiry
+        // check and the removal are one operation on the map.
+        let (_, expires_at) = data.remove(key)?;
+        self.replicate(|| format!("DEL {}", key));
+        self.signal_watchers(self.db, Some(key));
 
-        if value {
+        if is_expired(expires_at, self.now_ms()) {
+            self.metrics.expirations.fetch_add(1, Ordering::Relaxed);
             return None;
         }
 
-        let value = data.get(key).unwrap();
+        self.metrics.deletes.fetch_add(1, Ordering::Relaxed);
+        Some(key.to_owned())
+    }
+
+    // Deletes up to `limit` live keys matching `pattern` from the selected
+    // database. The keys are copied out and matched with the lock released,
+    // so a broad pattern over a large database doesn't stall other commands
+    // while it runs. A key set after the copy is left alone; one that matched
+    // and is set again before the removal is deleted anyway.
+    pub fn delete_matching(&self, pattern: &str, limit: usize) -> BulkDelete {
+        let candidates: Vec<String> = {
+            let data = self.lock_data();
+            let now = self.now_ms();
 
-        let mut d = GzDecoder::new(&value.0[..]);
-        let mut decompressed_data = Vec::new();
-        d.read_to_end(&mut decompressed_data).unwrap();
+            data.iter()
+                .filter(|(_, (_, expires_at))| !is_expired(*expires_at, now))
+                .map(|(key, _)| key.clone())
+                .collect()
+        };
 
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let mut matches = candidates.into_iter().filter(|key| glob_match(pattern, key));
+        let batch: Vec<String> = matches.by_ref().take(limit).collect();
+        let more = matches.next().is_some();
+
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+        let mut deleted = 0;
+
+        for key in batch {
+            let Some((_, expires_at)) = data.remove(&key) else {
+                continue;
+            };
+            self.replicate(|| format!("DEL {}", key));
+            self.signal_watchers(self.db, Some(&key));
+
+            if is_expired(expires_at, now) {
+                self.metrics.expirations.fetch_add(1, Ordering::Relaxed);
+            } else {
+                deleted += 1;
+            }
+        }
+
+        self.metrics.deletes.fetch_add(deleted as u64, Ordering::Relaxed);
+        BulkDelete { deleted, more }
+    }
+
+    // Like delete, but only marks the key expired and leaves removing the
+    // entry, and freeing a possibly large value, to a background task. Runs
+    // the removal inline when there is no tokio runtime to hand it to.
+    pub fn unlink(&self, key: &str) -> bool {
+        debug!(key, "unlinking key");
+        {
+            let mut data = self.lock_data();
+
+            let Some((_, expires_at)) = data.get_mut(key) else {
+                return false;
+            };
+            if is_expired(*expires_at, self.now_ms()) {
+                return false;
+            }
+
+            *expires_at = UNLINKED_AT;
+            self.replicate(|| format!("DEL {}", key));
+            self.signal_watchers(self.db, Some(key));
+        }
+
+        self.metrics.deletes.fetch_add(1, Ordering::Relaxed);
+        self.metrics.pending_unlinks.fetch_add(1, Ordering::Relaxed);
+
+        let key = key.to_string();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let store = self.clone();
+                runtime.spawn(async move { store.remove_unlinked(&key) });
+            }
+            Err(_) => self.remove_unlinked(&key),
+        }
+
+        true
+    }
+
+    // The key may have been set again, or already dropped by a read, since
+    // it was unlinked; only the tombstone itself is removed.
+    fn remove_unlinked(&self, key: &str) {
+        let removed = {
+            let mut data = self.lock_data();
+
+            match data.get(key) {
+                Some((_, UNLINKED_AT)) => data.remove(key),
+                _ => None,
+            }
+        };
+
+        // Freed here, outside the lock.
+        drop(removed);
+        self.metrics.pending_unlinks.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // There is no LRU eviction to feed yet, so this only counts the keys
+    // that are live; a key named twice counts twice.
+    pub fn touch(&self, keys: &[&str]) -> usize {
+        let data = self.lock_data();
+        let now = self.now_ms();
+
+        keys.iter()
+            .filter(|key| data.get(**key).is_some_and(|(_, expires_at)| !is_expired(*expires_at, now)))
+            .count()
+    }
+
+    // Gives a live key a new TTL, leaving its value alone. A TTL of 0 means
+    // the deadline has already passed, so the key is deleted. Returns false
+    // if the key is missing or expired.
+    pub fn update_ttl(&self, key: &str, seconds: u64) -> Result<bool, KeyzError> {
+        self.check_ttl(seconds)?;
+        self.update_ttl_ms(key, seconds.saturating_mul(1000))
+    }
 
-        if value.1 == 0 {
-            return Some(decompressed_data);
+    pub fn update_ttl_ms(&self, key: &str, ttl_ms: u64) -> Result<bool, KeyzError> {
+        let deadline = match ttl_ms {
+            0 => 0,
+            ttl_ms => self.ttl_deadline_ms(ttl_ms)?,
+        };
+
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        let Some((_, expires_at)) = data.get_mut(key) else {
+            return Ok(false);
+        };
+        if is_expired(*expires_at, now) {
+            return Ok(false);
         }
 
-        if now > value.1 {
+        if deadline == 0 {
             data.remove(key);
+            self.metrics.expirations.fetch_add(1, Ordering::Relaxed);
+            self.replicate(|| format!("DEL {}", key));
+        } else {
+            *expires_at = deadline;
+            self.replicate(|| format!("{} {} {}", PEXPIRE_FRAME, key, ttl_ms));
+        }
+        self.signal_watchers(self.db, Some(key));
+
+        Ok(true)
+    }
+
+    // Like update_ttl with the deadline as a unix timestamp in seconds.
+    pub fn expire_at(&self, key: &str, timestamp: u64) -> Result<bool, KeyzError> {
+        self.expire_at_ms(key, timestamp.saturating_mul(1000))
+    }
+
+    pub fn expire_at_ms(&self, key: &str, timestamp_ms: u64) -> Result<bool, KeyzError> {
+        self.update_ttl_ms(key, timestamp_ms.saturating_sub(self.now_ms()))
+    }
+
+    // Drops a live key's TTL. Returns false if the key is missing, expired
+    // or had no TTL.
+    pub fn persist(&self, key: &str) -> bool {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+
+        match data.get_mut(key) {
+            Some((_, expires_at)) if *expires_at != 0 && !is_expired(*expires_at, now) => {
+                *expires_at = 0;
+                self.replicate(|| format!("{} {}", PERSIST_FRAME, key));
+                self.signal_watchers(self.db, Some(key));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn expires_in(&self, key: &str) -> Option<u64> {
+        self.expires_in_ms(key).map(|ttl_ms| ttl_ms / 1000)
+    }
+
+    pub fn expires_in_ms(&self, key: &str) -> Option<u64> {
+        debug!(key, "getting expires_in");
+
+        let data = self.lock_data();
+        let now = self.now_ms();
+
+        match data.get(key) {
+            Some((_, expires_at)) if *expires_at != 0 && !is_expired(*expires_at, now) => Some(expires_at - now),
+            _ => None,
+        }
+    }
+
+    pub fn keys_matching(&self, pattern: &str) -> Vec<String> {
+        let data = self.lock_data();
+        let now = self.now_ms();
+
+        // A literal prefix and a trailing *, as in user:*, needs no glob.
+        let prefix = pattern
+            .strip_suffix('*')
+            .filter(|prefix| !prefix.contains(['*', '?', '[', '\\']));
+        let matches = |key: &str| match prefix {
+            Some(prefix) => key.starts_with(prefix),
+            None => glob_match(pattern, key),
+        };
+
+        let mut keys: Vec<String> = data
+            .iter()
+            .filter(|(key, value)| !is_expired(value.1, now) && matches(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+
+        keys
+    }
+
+    // SCAN's page: up to `count` live keys matching `pattern` that sort after
+    // `after`, in order, and whether more are left. A key written mid-scan
+    // is returned only if it sorts after the cursor.
+    pub fn scan(&self, after: Option<&str>, pattern: &str, count: usize) -> (Vec<String>, bool) {
+        let data = self.lock_data();
+        let now = self.now_ms();
+
+        let mut keys: Vec<String> = data
+            .iter()
+            .filter(|(key, (_, expires_at))| {
+                !is_expired(*expires_at, now)
+                    && after.is_none_or(|after| key.as_str() > after)
+                    && glob_match(pattern, key)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let more = keys.len() > count;
+        if more {
+            keys.select_nth_unstable(count);
+            keys.truncate(count);
+        }
+        keys.sort_unstable();
+
+        (keys, more)
+    }
+
+    // Live keys of the selected database per namespace, the part of the key
+    // before the first `separator`. Keys without one are in no namespace.
+    pub fn namespaces(&self, separator: char) -> BTreeMap<String, usize> {
+        let data = self.lock_data();
+        let now = self.now_ms();
+        let mut namespaces = BTreeMap::new();
+
+        for (key, (_, expires_at)) in data.iter() {
+            if is_expired(*expires_at, now) {
+                continue;
+            }
+            if let Some((namespace, _)) = key.split_once(separator) {
+                *namespaces.entry(namespace.to_string()).or_insert(0) += 1;
+            }
+        }
+
+        namespaces
+    }
+
+    // Across every database, largest first.
+    pub fn top_namespaces(&self, separator: char) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+
+        for db in 0..self.databases.len() {
+            let mut store = self.clone();
+            store.db = db;
+            for (namespace, keys) in store.namespaces(separator) {
+                *counts.entry(namespace).or_insert(0) += keys;
+            }
+        }
+
+        let mut top: Vec<(String, usize)> = counts.into_iter().collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(TOP_NAMESPACES);
+        top
+    }
+
+    pub fn namespace_stats(&self, namespace: &str, separator: char) -> NamespaceStats {
+        let data = self.lock_data();
+        let now = self.now_ms();
+        let prefix = format!("{}{}", namespace, separator);
+
+        let mut stats = NamespaceStats { keys: 0, bytes: 0, ttl_histogram: TtlHistogram::default() };
+        for (key, (value, expires_at)) in data.iter() {
+            if is_expired(*expires_at, now) || !key.starts_with(&prefix) {
+                continue;
+            }
+
+            stats.keys += 1;
+            stats.bytes += entry_usage(key, value);
+            stats.ttl_histogram.add(match expires_at {
+                0 => None,
+                expires_at => Some(secs_left(*expires_at, now)),
+            });
+        }
+
+        stats
+    }
+
+    // Deletes every key of a namespace in the selected database under one
+    // lock hold and returns how many were live.
+    pub fn flush_namespace(&self, namespace: &str, separator: char) -> usize {
+        let mut data = self.lock_data();
+        let now = self.now_ms();
+        let prefix = format!("{}{}", namespace, separator);
+
+        let keys: Vec<String> = data.keys().filter(|key| key.starts_with(&prefix)).cloned().collect();
+        let mut deleted = 0;
+
+        for key in keys {
+            let Some((_, expires_at)) = data.remove(&key) else {
+                continue;
+            };
+            self.replicate(|| format!("DEL {}", key));
+            self.signal_watchers(self.db, Some(&key));
+
+            if is_expired(expires_at, now) {
+                self.metrics.expirations.fetch_add(1, Ordering::Relaxed);
+            } else {
+                deleted += 1;
+            }
+        }
+
+        self.metrics.deletes.fetch_add(deleted as u64, Ordering::Relaxed);
+        deleted
+    }
+
+    // The stored payload goes into the blob as is, so a restored key is
+    // byte-for-byte the one that was dumped. Integers are dumped as the
+    // string they read as; other types can't be dumped.
+    pub fn dump(&self, key: &str) -> Result<Option<String>, KeyzError> {
+        let data = self.lock_data();
+
+        let Some((value, expires_at)) = data.get(key) else {
+            return Ok(None);
+        };
+        if is_expired(*expires_at, self.now_ms()) {
+            return Ok(None);
+        }
+
+        let payload = match value {
+            ValueData::String(compressed) => compressed.clone(),
+            ValueData::Integer(n) => compress(n.to_string().as_bytes()),
+            value => return Err(KeyzError::WrongType { actual: value.type_name() }),
+        };
+
+        Ok(Some(dump::encode(&Dump { expires_at_ms: *expires_at, compression: dump::GZIP, payload })))
+    }
+
+    // Replaces any existing value. The TTL comes from `ttl_ms` (0 for none),
+    // not from the blob.
+    pub fn restore(&self, key: String, blob: &str, ttl_ms: u64) -> Result<(), KeyzError> {
+        let dump = dump::decode(blob)?;
+        // Decompressed up front so a bad payload is refused here rather
+        // than failing every later read of the key.
+        let value = decompress(&dump.payload, self.max_payload_bytes())?;
+        self.check_limits(&key, value.len())?;
+
+        let expire_in = match ttl_ms {
+            0 => 0,
+            ttl_ms => self.ttl_deadline_ms(ttl_ms)?,
+        };
+
+        let mut data = self.lock_data();
+        debug!(key = %key, ttl_ms, "restoring key");
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| restore_frame(&key, &dump.payload, ttl_ms));
+        self.signal_watchers(self.db, Some(&key));
+        data.insert(key, (ValueData::String(dump.payload), expire_in));
+
+        Ok(())
+    }
+
+    // What a replica does with SETINT: replaces any existing value with
+    // `value` stored as an integer, as INCR leaves it, with the TTL from
+    // `ttl_ms` (0 for none).
+    pub fn insert_integer(&self, key: String, value: i64, ttl_ms: u64) -> Result<(), KeyzError> {
+        self.check_limits(&key, 0)?;
+
+        let expire_in = match ttl_ms {
+            0 => 0,
+            ttl_ms => self.ttl_deadline_ms(ttl_ms)?,
+        };
+
+        let mut data = self.lock_data();
+        debug!(key = %key, ttl_ms, "inserting integer");
+
+        self.metrics.sets.fetch_add(1, Ordering::Relaxed);
+        self.replicate(|| setint_frame(&key, value, ttl_ms));
+        self.signal_watchers(self.db, Some(&key));
+        data.insert(key, (ValueData::Integer(value), expire_in));
+
+        Ok(())
+    }
+
+    pub fn debug_entry(&self, key: &str) -> Option<DebugEntry> {
+        let data = self.lock_data();
+        let now = self.now_ms();
+
+        let (value, expires_at) = data.get(key)?;
+        if is_expired(*expires_at, now) {
             return None;
         }
 
-        return Some(decompressed_data);
+        Some(DebugEntry {
+            stored_bytes: value.stored_bytes(),
+            original_bytes: raw_len(value),
+            compressed: value.is_compressed(),
+            encoding: value.encoding(),
+            ttl_remaining_secs: match expires_at {
+                0 => None,
+                expires_at => Some(secs_left(*expires_at, now)),
+            },
+        })
     }
 
-    pub fn delete(&self, key: &str) -> Option<String> {
-        println!("[STORE] Deleting {}", key);
-        let mut data = self.data.lock().unwrap();
-        if data.contains_key(key) {
+    // `raw_bytes` is only filled in when asked for, as it means decompressing
+    // the value.
+    pub fn memory_usage(&self, key: &str, with_raw: bool) -> Option<MemoryUsage> {
+        let data = self.lock_data();
+
+        let (value, expires_at) = data.get(key)?;
+        if is_expired(*expires_at, self.now_ms()) {
+            return None;
+        }
 
-            let value = data.get(key).unwrap();
+        Some(MemoryUsage {
+            usage_bytes: entry_usage(key, value),
+            stored_bytes: value.stored_bytes(),
+            raw_bytes: with_raw.then(|| raw_len(value)),
+        })
+    }
 
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
+    // There is no expiry index, so this samples the first keys of each
+    // database in map order until TTL_HISTOGRAM_SAMPLE have been seen.
+    fn ttl_histogram(&self) -> TtlHistogram {
+        let now = self.now_ms();
+        let mut histogram = TtlHistogram::default();
 
-            if value.1 == 0 {
-                data.remove(key);
-                return Some(key.to_owned());
+        for data in self.databases.iter() {
+            let data = lock(data);
+            let remaining = TTL_HISTOGRAM_SAMPLE - histogram.sampled;
+
+            for (_, expires_at) in data.values().filter(|(_, e)| !is_expired(*e, now)).take(remaining) {
+                histogram.add(match expires_at {
+                    0 => None,
+                    expires_at => Some(secs_left(*expires_at, now)),
+                });
             }
 
-            if now > value.1 {
-                data.remove(key);
-                return None
+            if histogram.sampled == TTL_HISTOGRAM_SAMPLE {
+                break;
             }
         }
 
-        return None;
+        histogram
     }
 
-    pub fn expires_in(&self, key: &str) -> Option<u64> {
-        println!("[STORE] Getting expires_in {}", key);
+    // The `count` live keys of the selected database with the nearest
+    // deadlines, nearest first. Walks the whole database.
+    pub fn expiring(&self, count: usize) -> Vec<ExpiringKey> {
+        let data = self.lock_data();
+        let now = self.now_ms();
 
-        let data = self.data.lock().unwrap();
+        let mut expiring: Vec<(u64, &String)> = data
+            .iter()
+            .filter(|(_, (_, expires_at))| *expires_at != 0 && !is_expired(*expires_at, now))
+            .map(|(key, (_, expires_at))| (*expires_at, key))
+            .collect();
+        expiring.sort_unstable();
+        expiring.truncate(count);
 
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        expiring
+            .into_iter()
+            .map(|(expires_at, key)| ExpiringKey {
+                key: key.clone(),
+                ttl_secs: secs_left(expires_at, now),
+            })
+            .collect()
+    }
 
-        match data.get(key) {
-            Some(value) => {
-                if value.1 == 0 {
-                    return None;
+    // Decompresses every live value, so it is meant for occasional
+    // diagnostics rather than monitoring.
+    pub fn memory_report(&self, biggest: usize) -> MemoryReport {
+        let data = self.lock_data();
+        let now = self.now_ms();
+
+        let mut report = MemoryReport::default();
+        let mut usages = Vec::new();
+
+        for (key, (value, expires_at)) in data.iter() {
+            if is_expired(*expires_at, now) {
+                continue;
+            }
+
+            let usage = entry_usage(key, value);
+            report.keys += 1;
+            report.usage_bytes += usage;
+            report.stored_bytes += value.stored_bytes();
+            report.raw_bytes += raw_len(value);
+            usages.push((key.clone(), usage));
+        }
+
+        usages.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        usages.truncate(biggest);
+        report.biggest = usages;
+
+        report
+    }
+
+    // Expired keys are otherwise only removed when something reads them.
+    // Covers every database, not just the selected one.
+    pub fn purge_expired(&self) -> usize {
+        let now = self.now_ms();
+        let mut purged = 0;
+
+        for data in self.databases.iter() {
+            let mut data = lock(data);
+            let before = data.len();
+            data.retain(|_, (_, expires_at)| !is_expired(*expires_at, now));
+            purged += before - data.len();
+        }
+
+        self.metrics.expirations.fetch_add(purged as u64, Ordering::Relaxed);
+        purged
+    }
+
+    // Counts live keys only, not expired ones nothing has removed yet or the
+    // tombstones of unlinked ones.
+    pub fn stats(&self) -> StoreStats {
+        let now = self.now_ms();
+        let databases: Vec<DatabaseStats> = self
+            .databases
+            .iter()
+            .enumerate()
+            .map(|(db, data)| {
+                let data = lock(data);
+                let live = data.values().filter(|(_, expires_at)| !is_expired(*expires_at, now));
+                let (keys, expires) = live.fold((0, 0), |(keys, expires), (_, expires_at)| {
+                    (keys + 1, expires + usize::from(*expires_at != 0))
+                });
+                DatabaseStats { db, keys, expires }
+            })
+            .filter(|stats| stats.keys > 0)
+            .collect();
+
+        StoreStats {
+            keys: databases.iter().map(|stats| stats.keys).sum(),
+            databases,
+            ttl_histogram: self.ttl_histogram(),
+            slow_query_count: self.slow_log.len(),
+            commands_per_second: self.metrics.commands.rate_per_second(self.now_ms()),
+            latency: self.metrics.histograms.stats().into_iter().collect(),
+            role: if self.is_replica() { "replica" } else { "primary" },
+            read_only: self.is_read_only(),
+            command_timeouts: self.metrics.command_timeouts.load(Ordering::Relaxed),
+            panics: self.metrics.panics.load(Ordering::Relaxed),
+            pending_unlinks: self.metrics.pending_unlinks.load(Ordering::Relaxed),
+            access_log_dropped: self.access_log().map(AccessLog::dropped),
+            inflight_bytes: self.inflight.get().map(|budget| budget.in_use()),
+            config_reloads: self.metrics.config_reloads.load(Ordering::Relaxed),
+            last_reload_epoch: self.metrics.last_reload_epoch.load(Ordering::Relaxed),
+            listeners: lock(&self.listeners).clone(),
+            metrics: self.metrics.snapshot(),
+            top_namespaces: Vec::new(),
+            trace_id: None,
+        }
+    }
+}
+
+// Under cfg(keyz_loom) a store only works inside a model, so these run without it.
+#[cfg(all(test, not(keyz_loom)))]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Moved by hand. Clones share the time, so a test keeps one to move the
+    // clock of the store it gave the other to.
+    #[derive(Clone, Default)]
+    struct MockClock(std::sync::Arc<std::sync::atomic::AtomicU64>);
+
+    impl MockClock {
+        fn at(now: u64) -> Self {
+            Self(std::sync::Arc::new(std::sync::atomic::AtomicU64::new(now)))
+        }
+
+        fn set(&self, now: u64) {
+            self.0.store(now, Ordering::SeqCst);
+        }
+
+        fn advance(&self, ms: u64) {
+            self.0.fetch_add(ms, Ordering::SeqCst);
+        }
+    }
+
+    impl ClockSource for MockClock {
+        fn now_millis(&self) -> Result<u64, SystemTimeError> {
+            Ok(self.0.load(Ordering::SeqCst))
+        }
+    }
+
+    fn store() -> Store {
+        Store::new(&StoreConfig::default())
+    }
+
+    const PAYLOAD_LIMIT: usize = 64;
+
+    // Empty and one-byte values, either side of the payload limit, and
+    // anything in between.
+    fn payload_lengths() -> impl Strategy<Value = usize> {
+        prop_oneof![
+            Just(0),
+            Just(1),
+            (PAYLOAD_LIMIT - 1)..=(PAYLOAD_LIMIT + 1),
+            0..2 * PAYLOAD_LIMIT,
+        ]
+    }
+
+    // Moves on by itself every time it's read, so the time a deadline is
+    // worked out from is already past when the lock is taken.
+    #[derive(Clone, Default)]
+    struct TickingClock(std::sync::Arc<std::sync::atomic::AtomicU64>);
+
+    impl ClockSource for TickingClock {
+        fn now_millis(&self) -> Result<u64, SystemTimeError> {
+            Ok(1_000_000_000_000 + self.0.fetch_add(5, Ordering::SeqCst))
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn get_returns_inserted_value(key in "\\PC*", value in any::<Vec<u8>>()) {
+            let store = store();
+            store.insert(key.clone(), value.clone(), 0).unwrap();
+
+            prop_assert_eq!(store.get(&key).unwrap(), Some(value));
+        }
+
+        #[test]
+        fn delete_after_insert_returns_key(key in "\\PC*", value in any::<Vec<u8>>()) {
+            let store = store();
+            store.insert(key.clone(), value, 0).unwrap();
+
+            prop_assert_eq!(store.delete(&key), Some(key.clone()));
+            prop_assert_eq!(store.get(&key).unwrap(), None);
+        }
+
+        #[test]
+        fn get_of_expired_key_returns_none(
+            key in "\\PC*",
+            value in any::<Vec<u8>>(),
+            ttl in 1u64..1_000_000,
+            overshoot in 1u64..1_000,
+        ) {
+            let clock = MockClock::at(1_000_000_000_000);
+            let store = Store::with_clock(&StoreConfig::default(), clock.clone());
+            store.insert(key.clone(), value, ttl).unwrap();
+
+            clock.advance((ttl + overshoot) * 1000);
+
+            prop_assert_eq!(store.get(&key).unwrap(), None);
+            prop_assert_eq!(store.expires_in(&key), None);
+        }
+
+        #[test]
+        fn compression_round_trip_preserves_bytes(value in any::<Vec<u8>>()) {
+            prop_assert_eq!(decompress(&compress(&value), usize::MAX).unwrap(), value);
+        }
+
+        // Through the store, which compresses on insert and decompresses up
+        // to the payload limit on GET.
+        #[test]
+        fn stored_values_round_trip_up_to_the_payload_limit(
+            value in payload_lengths().prop_flat_map(|len| proptest::collection::vec(any::<u8>(), len)),
+        ) {
+            let store = store();
+            store.set_max_payload_bytes(PAYLOAD_LIMIT);
+            store.insert("k".to_string(), value.clone(), 0).unwrap();
+
+            match store.get("k") {
+                Ok(read) => prop_assert_eq!(read, Some(value)),
+                Err(e) => {
+                    prop_assert!(value.len() > PAYLOAD_LIMIT, "{}", e);
+                    let too_large = matches!(e, KeyzError::PayloadTooLarge { limit: PAYLOAD_LIMIT });
+                    prop_assert!(too_large, "{}", e);
                 }
+            }
+        }
 
-                if now > value.1 {
-                    return None;
+        #[test]
+        fn stats_keys_are_the_live_keys(
+            entries in proptest::collection::vec(("[a-z]{1,8}", any::<Vec<u8>>(), 0u64..4, any::<bool>()), 0..32),
+            elapsed_ms in 0u64..5_000,
+        ) {
+            // Entered but never driven, so unlinked keys stay as tombstones.
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            let _runtime = runtime.enter();
+
+            let clock = MockClock::at(1_000_000_000_000);
+            let store = Store::with_clock(&StoreConfig::default(), clock.clone());
+            for (key, value, ttl, unlink) in entries {
+                store.insert(key.clone(), value, ttl).unwrap();
+                if unlink {
+                    store.unlink(&key);
                 }
+            }
+            clock.advance(elapsed_ms);
+
+            prop_assert_eq!(store.stats().keys, store.keys_matching("*").len());
+        }
+    }
+
+    #[test]
+    fn ttls_hold_still_while_the_clock_is_stepped_back() {
+        let clock = MockClock::at(1_000_000_000_000);
+        let store = Store::with_clock(&StoreConfig::default(), clock.clone());
+        store.insert("session".into(), b"v".to_vec(), 60).unwrap();
+        clock.advance(10_000);
+        assert_eq!(store.expires_in_ms("session"), Some(50_000));
+
+        // An hour back, as an NTP step might do: the TTL keeps what it had
+        // instead of growing by the hour.
+        clock.set(1_000_000_000_000 - 3_600_000);
+        assert_eq!(store.expires_in_ms("session"), Some(50_000));
+        assert_eq!(store.expires_in("session"), Some(50));
+
+        // Time starts counting again once the clock passes where it was.
+        clock.set(1_000_000_020_000);
+        assert_eq!(store.expires_in_ms("session"), Some(40_000));
+    }
+
+    #[test]
+    fn a_step_back_is_warned_about_once() {
+        let source = MockClock::at(1_000_000_000_000);
+        let clock = MonotonicClock::new(source.clone());
+        assert_eq!(clock.now_millis(), 1_000_000_000_000);
 
-                return Some((Wrapping(value.1) - Wrapping(now)).0);
+        source.set(999_999_000_000);
+        for _ in 0..100 {
+            assert_eq!(clock.now_millis(), 1_000_000_000_000);
+        }
+        assert_eq!(clock.last_warning.load(Ordering::Relaxed), 1_000_000_000_000);
+
+        // Caught up and stepped back again within the minute: not warned.
+        source.set(1_000_000_001_000);
+        clock.now_millis();
+        source.set(999_999_000_000);
+        clock.now_millis();
+        assert_eq!(clock.last_warning.load(Ordering::Relaxed), 1_000_000_000_000);
+
+        // A minute later it is.
+        source.set(1_000_000_061_000);
+        clock.now_millis();
+        source.set(999_999_000_000);
+        assert_eq!(clock.now_millis(), 1_000_000_061_000);
+        assert_eq!(clock.last_warning.load(Ordering::Relaxed), 1_000_000_061_000);
+    }
+
+    #[test]
+    fn each_store_keeps_its_own_clock_floor() {
+        let ahead = MockClock::at(4_000_000_000_000);
+        let ahead_store = Store::with_clock(&StoreConfig::default(), ahead.clone());
+        ahead_store.insert("k".into(), b"v".to_vec(), 10).unwrap();
+
+        // A store on a clock far behind isn't held at the other's time.
+        let behind = MockClock::at(1_000_000_000_000);
+        let behind_store = Store::with_clock(&StoreConfig::default(), behind.clone());
+        behind_store.insert("k".into(), b"v".to_vec(), 10).unwrap();
+        assert_eq!(behind_store.expires_in_ms("k"), Some(10_000));
+        behind.advance(11_000);
+        assert_eq!(behind_store.get("k").unwrap(), None);
+        assert_eq!(ahead_store.expires_in_ms("k"), Some(10_000));
+    }
+
+    #[test]
+    fn command_rate_is_within_ten_percent_of_the_load() {
+        let counter = CommandRateCounter::default();
+        let start = 1_000_000_000_000;
+        // 5000 commands a second for three seconds, one every 200us.
+        for n in 0..15_000u64 {
+            counter.record(start + n / 5);
+        }
+
+        let rate = counter.rate_per_second(start + 3_000);
+        assert!((4_500.0..=5_500.0).contains(&rate), "{}", rate);
+
+        // Half the load for another two seconds.
+        for n in 0..5_000u64 {
+            counter.record(start + 3_000 + n * 2 / 5);
+        }
+        let rate = counter.rate_per_second(start + 5_000);
+        assert!((2_250.0..=2_750.0).contains(&rate), "{}", rate);
+
+        // Idle for longer than the window.
+        assert_eq!(counter.rate_per_second(start + 7_000), 0.0);
+    }
+
+    #[test]
+    fn command_rate_counts_from_many_threads() {
+        let counter = CommandRateCounter::default();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for n in 0..1_000u64 {
+                        counter.record(1_000_000_000_000 + n);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(counter.rate_per_second(1_000_000_001_000), 4_000.0);
+    }
+
+    #[test]
+    fn getex_replicates_a_deadline_already_passed_as_one_millisecond() {
+        let store = Store::with_clock(&StoreConfig::default(), TickingClock::default());
+        store.insert("k".to_string(), b"v".to_vec(), 0).unwrap();
+        let (mut updates, _) = store.subscribe_with_snapshot();
+
+        assert_eq!(store.get_ex("k", GetExModifier::Px(1)).unwrap(), Some(b"v".to_vec()));
+        assert_eq!(updates.try_recv().unwrap(), format!("{} k 1", PEXPIRE_FRAME));
+        assert_eq!(store.get("k").unwrap(), None);
+    }
+
+    // The loom model in tests/loom_store.rs is capped at four threads.
+    #[test]
+    fn increments_from_ten_threads_are_never_lost() {
+        let store = store();
+        std::thread::scope(|scope| {
+            for _ in 0..10 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        store.increment("counter", 1).unwrap();
+                    }
+                });
             }
-            None => None,
+        });
+
+        assert_eq!(store.increment("counter", 0).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn store_commands_are_counted_on_the_store_clock() {
+        let clock = MockClock::at(1_000_000_000_000);
+        let store = Store::with_clock(&StoreConfig::default(), clock.clone());
+        for _ in 0..300 {
+            store.record_command();
+            clock.advance(2);
+        }
+
+        clock.set(1_000_000_000_700);
+        assert_eq!(store.stats().commands_per_second, 300.0);
+        clock.advance(2_000);
+        assert_eq!(store.stats().commands_per_second, 0.0);
+    }
+
+    #[test]
+    fn compression_round_trip_of_tiny_values() {
+        for value in [vec![], vec![0u8], vec![0xff], vec![b'a'; 2]] {
+            assert_eq!(decompress(&compress(&value), usize::MAX).unwrap(), value);
         }
     }
+
+    #[test]
+    fn decompression_stops_at_the_payload_limit() {
+        let bomb = compress(&vec![0; 1024 * 1024]);
+        assert!(bomb.len() < 4096);
+
+        assert!(matches!(decompress(&bomb, 1024), Err(KeyzError::PayloadTooLarge { limit: 1024 })));
+        assert_eq!(decompress(&bomb, 1024 * 1024).unwrap().len(), 1024 * 1024);
+        assert_eq!(decompressed_len(&bomb), 1024 * 1024);
+    }
 }
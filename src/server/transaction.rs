@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use crate::config::ProtocolConfig;
+use crate::server::dispatcher::{exec, DISCARD, EXEC, MULTI, RESET, UNWATCH, WATCH};
+use crate::server::error::KeyzError;
+use crate::server::registry;
+use crate::server::response::Response;
+use crate::server::store::Store;
+
+// The MULTI and WATCH state of one connection.
+pub struct Transaction {
+    id: u64,
+    in_transaction: bool,
+    queued_commands: Vec<String>,
+    // Set when a command was refused while queueing; EXEC then runs nothing.
+    tx_error: bool,
+    // (database, key) pairs, registered with the store under `id`.
+    watched_keys: HashSet<(usize, String)>,
+    // Latched from `watch_receiver` once a watched key is written; EXEC then
+    // returns null.
+    watch_dirty: bool,
+    watch_signal: Sender<()>,
+    watch_receiver: Receiver<()>,
+}
+
+impl Transaction {
+    // `id` tells this connection's watches apart from others on the same key.
+    pub fn new(id: u64) -> Self {
+        // One pending signal is enough to know a watched key was written.
+        let (watch_signal, watch_receiver) = mpsc::channel(1);
+
+        Self {
+            id,
+            in_transaction: false,
+            queued_commands: Vec::new(),
+            tx_error: false,
+            watched_keys: HashSet::new(),
+            watch_dirty: false,
+            watch_signal,
+            watch_receiver,
+        }
+    }
+
+    // Inside a transaction every command except EXEC, DISCARD and RESET is
+    // queued, or refused, here instead of running. Returns None when the
+    // command should go on as usual.
+    pub fn queue(&mut self, command: &str, protocol: &ProtocolConfig) -> Option<String> {
+        if !self.in_transaction || is_control(command) {
+            return None;
+        }
+
+        match check(command) {
+            // Watching has to happen before MULTI to mean anything; refused
+            // without spoiling the transaction, like a nested MULTI.
+            Err(e) if is_watch(command) => Some(e.to_response(protocol.error_format())),
+            Ok(()) => {
+                self.queued_commands.push(command.to_string());
+                Some("QUEUED".to_string())
+            }
+            // A nested MULTI is refused without spoiling the transaction.
+            Err(e) if command.eq_ignore_ascii_case(MULTI) => Some(e.to_response(protocol.error_format())),
+            Err(e) => {
+                self.tx_error = true;
+                Some(e.to_response(protocol.error_format()))
+            }
+        }
+    }
+
+    // Answers MULTI, EXEC, DISCARD, RESET, WATCH and UNWATCH, or returns None
+    // for anything else.
+    pub fn control(
+        &mut self,
+        command: &str,
+        store: &mut Store,
+        protocol: &ProtocolConfig,
+    ) -> Option<Result<Response, Box<dyn Error>>> {
+        let mut words = command.split(' ');
+        let first = words.next().unwrap_or_default();
+        let name = [MULTI, EXEC, DISCARD, RESET, WATCH, UNWATCH]
+            .into_iter()
+            .find(|name| first.eq_ignore_ascii_case(name))?;
+        let keys: Vec<&str> = words.collect();
+
+        let reply = match name {
+            WATCH if keys.is_empty() => Err(KeyzError::invalid_command().into()),
+            WATCH => {
+                for key in keys {
+                    if self.watched_keys.insert((store.selected_db(), key.to_string())) {
+                        store.watch(self.id, key, self.watch_signal.clone());
+                    }
+                }
+                Ok(Response::Ok)
+            }
+            _ if !keys.is_empty() => Err(KeyzError::invalid_command().into()),
+            UNWATCH => {
+                self.unwatch(store);
+                Ok(Response::Ok)
+            }
+            MULTI => {
+                self.in_transaction = true;
+                Ok(Response::Ok)
+            }
+            EXEC if !self.in_transaction => Err(KeyzError::InvalidCommand("EXEC without MULTI".into()).into()),
+            EXEC => {
+                let aborted = self.tx_error;
+                let commands = self.end();
+
+                let reply = match aborted {
+                    true => Err(KeyzError::InvalidCommand("transaction aborted due to queued error".into()).into()),
+                    false => exec(&commands, store, protocol, || self.watch_dirty()),
+                };
+                self.unwatch(store);
+                reply
+            }
+            DISCARD if !self.in_transaction => {
+                Err(KeyzError::InvalidCommand("DISCARD without MULTI".into()).into())
+            }
+            DISCARD => {
+                self.end();
+                self.unwatch(store);
+                Ok(Response::Ok)
+            }
+            RESET => {
+                self.end();
+                self.unwatch(store);
+                store.select(0).map(|()| Response::Ok).map_err(Into::into)
+            }
+            _ => unreachable!(),
+        };
+
+        // Errors are answered like any dispatched command's.
+        Some(match reply {
+            Err(e) => match e.downcast::<KeyzError>() {
+                Ok(e) => Ok(Response::from(&*e)),
+                Err(e) => Err(e),
+            },
+            reply => reply,
+        })
+    }
+
+    // Leaves the transaction and hands back what was queued.
+    fn end(&mut self) -> Vec<String> {
+        self.in_transaction = false;
+        self.tx_error = false;
+        std::mem::take(&mut self.queued_commands)
+    }
+
+    fn watch_dirty(&mut self) -> bool {
+        if self.watch_receiver.try_recv().is_ok() {
+            self.watch_dirty = true;
+        }
+        self.watch_dirty
+    }
+
+    // Also drops any signal still pending, including ones EXEC's own writes
+    // sent.
+    pub fn unwatch(&mut self, store: &Store) {
+        store.unwatch(self.id, &self.watched_keys);
+        self.watched_keys.clear();
+        self.watch_dirty = false;
+        while self.watch_receiver.try_recv().is_ok() {}
+    }
+}
+
+fn is_watch(command: &str) -> bool {
+    let name = command.split(' ').next().unwrap_or_default();
+    name.eq_ignore_ascii_case(WATCH) || name.eq_ignore_ascii_case(UNWATCH)
+}
+
+fn is_control(command: &str) -> bool {
+    [EXEC, DISCARD, RESET].iter().any(|name| command.eq_ignore_ascii_case(name))
+}
+
+// Catches what can be known before running: unknown names, a wrong number of
+// words, and commands that only make sense outside a transaction.
+fn check(command: &str) -> Result<(), KeyzError> {
+    let mut words = command.split(' ');
+    let name = words.next().unwrap_or_default();
+
+    let doc = registry::find(name).ok_or_else(KeyzError::invalid_command)?;
+    if doc.name == WATCH || doc.name == UNWATCH {
+        return Err(KeyzError::InvalidCommand(format!("{} inside MULTI is not allowed", doc.name)));
+    }
+    if doc.flags.iter().any(|flag| matches!(*flag, "pubsub" | "transaction")) {
+        return Err(KeyzError::InvalidCommand(format!("{} is not allowed in a transaction", doc.name)));
+    }
+
+    let count = 1 + words.count() as i32;
+    let arity_ok = match doc.arity {
+        arity if arity > 0 => count == arity,
+        arity => count >= -arity,
+    };
+    match arity_ok {
+        true => Ok(()),
+        false => Err(KeyzError::invalid_command()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_names_and_arity_against_the_registry() {
+        assert!(check("SET k v").is_ok());
+        assert!(check("set k v EX 10").is_ok());
+        assert!(check("GET k").is_ok());
+        assert!(check("INFO").is_ok());
+
+        assert!(check("NOPE k").is_err());
+        assert!(check("GET").is_err());
+        assert!(check("GET a b").is_err());
+        assert!(check("SET k").is_err());
+        assert!(check("SUBSCRIBE news").is_err());
+        assert!(check("MULTI").is_err());
+        assert!(check("WATCH k").is_err());
+    }
+}
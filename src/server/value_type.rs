@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+// What a key holds. String payloads are kept gzipped as they always were;
+// the other types are kept as they are, since their commands work on parts
+// of them. Sorted set scores are never NaN, so the ordering by score is
+// total.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueData {
+    String(Vec<u8>),
+    Integer(i64),
+    List(VecDeque<Vec<u8>>),
+    Hash(HashMap<String, Vec<u8>>),
+    Set(HashSet<String>),
+    SortedSet(BTreeMap<String, f64>),
+    // Packed, see bits.rs.
+    Bits(Vec<u8>),
+}
+
+impl ValueData {
+    // What TYPE answers. Integers read back as strings, so they are one.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ValueData::String(_) | ValueData::Integer(_) => "string",
+            ValueData::List(_) => "list",
+            ValueData::Hash(_) => "hash",
+            ValueData::Set(_) => "set",
+            ValueData::SortedSet(_) => "zset",
+            ValueData::Bits(_) => "bits",
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            ValueData::String(_) => "gzip",
+            ValueData::Integer(_) => "int",
+            ValueData::List(_) => "list",
+            ValueData::Hash(_) => "hash",
+            ValueData::Set(_) => "set",
+            ValueData::SortedSet(_) => "zset",
+            ValueData::Bits(_) => "bits",
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, ValueData::String(_))
+    }
+
+    // The bytes the value takes up as stored, compressed for strings. Only
+    // the elements are counted, not the collection around them.
+    pub fn stored_bytes(&self) -> usize {
+        match self {
+            ValueData::String(compressed) => compressed.len(),
+            ValueData::Integer(_) => std::mem::size_of::<i64>(),
+            ValueData::List(items) => items.iter().map(Vec::len).sum(),
+            ValueData::Hash(fields) => fields.iter().map(|(field, value)| field.len() + value.len()).sum(),
+            ValueData::Set(members) => members.iter().map(String::len).sum(),
+            ValueData::SortedSet(members) => members.keys().map(|member| member.len() + std::mem::size_of::<f64>()).sum(),
+            ValueData::Bits(bits) => bits.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_are_reported_as_strings() {
+        assert_eq!(ValueData::String(Vec::new()).type_name(), "string");
+        assert_eq!(ValueData::Integer(7).type_name(), "string");
+        assert_eq!(ValueData::Integer(7).encoding(), "int");
+        assert_eq!(ValueData::List(VecDeque::new()).type_name(), "list");
+        assert_eq!(ValueData::Hash(HashMap::new()).type_name(), "hash");
+        assert_eq!(ValueData::Set(HashSet::new()).type_name(), "set");
+        assert_eq!(ValueData::SortedSet(BTreeMap::new()).type_name(), "zset");
+        assert_eq!(ValueData::Bits(Vec::new()).type_name(), "bits");
+    }
+}
@@ -0,0 +1,127 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use keyz::config::{AccessLogConfig, AccessLogScope};
+use keyz::server::access_log::AccessLog;
+use tokio::time;
+
+use crate::helpers::TestServer;
+
+fn log_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("keyz-access-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir.join("access.log")
+}
+
+fn config(path: &Path, scope: AccessLogScope, max_bytes: Option<u64>) -> AccessLogConfig {
+    AccessLogConfig {
+        path: path.to_path_buf(),
+        max_bytes,
+        keep: 2,
+        scope,
+    }
+}
+
+fn lines(path: &Path) -> Vec<serde_json::Value> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+// The writer runs on its own thread, so wait for it to catch up.
+async fn wait_for_lines(path: &Path, count: usize) -> Vec<serde_json::Value> {
+    for _ in 0..100 {
+        let lines = lines(path);
+        if lines.len() >= count {
+            return lines;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("access log never reached {} lines", count);
+}
+
+#[tokio::test]
+async fn writes_are_logged_without_values() {
+    let path = log_path("writes");
+    let server = TestServer::start().await;
+    server
+        .store()
+        .set_access_log(AccessLog::open(&config(&path, AccessLogScope::Writes, None)).unwrap());
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET user:1 secret-value").await.unwrap(), "ok");
+    assert_eq!(client.send("GET user:1").await.unwrap(), "secret-value");
+    assert_eq!(client.send("SELECT 2").await.unwrap(), "ok");
+    assert_eq!(client.send("DEL user:1").await.unwrap(), "null");
+    assert_eq!(client.send("SET k").await.unwrap(), "error:INVALID_CMD:set command invalid");
+
+    let lines = wait_for_lines(&path, 3).await;
+    assert_eq!(lines.len(), 3);
+
+    assert_eq!(lines[0]["command"], "SET");
+    assert_eq!(lines[0]["key"], "user:1");
+    assert_eq!(lines[0]["db"], 0);
+    assert_eq!(lines[0]["status"], "ok");
+    assert!(lines[0]["ts_ms"].as_u64().unwrap() > 0);
+    assert!(lines[0]["duration_us"].is_u64());
+    assert!(lines[0]["peer"].as_str().unwrap().starts_with("127.0.0.1:"));
+
+    assert_eq!(lines[1]["command"], "DEL");
+    assert_eq!(lines[1]["db"], 2);
+    assert_eq!(lines[2]["status"], "error");
+
+    assert!(!fs::read_to_string(&path).unwrap().contains("secret-value"));
+}
+
+#[tokio::test]
+async fn scope_all_logs_reads_too() {
+    let path = log_path("all");
+    let server = TestServer::start().await;
+    server
+        .store()
+        .set_access_log(AccessLog::open(&config(&path, AccessLogScope::All, None)).unwrap());
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("GET missing").await.unwrap(), "null");
+    assert_eq!(client.send("PING").await.unwrap(), "pong");
+
+    let lines = wait_for_lines(&path, 2).await;
+    assert_eq!(lines[0]["command"], "GET");
+    assert_eq!(lines[1]["command"], "PING");
+}
+
+#[tokio::test]
+async fn rotates_once_the_file_is_full() {
+    let path = log_path("rotate");
+    let server = TestServer::start().await;
+    server
+        .store()
+        .set_access_log(AccessLog::open(&config(&path, AccessLogScope::Writes, Some(300))).unwrap());
+    let mut client = server.connect().await;
+
+    for i in 0..20 {
+        assert_eq!(client.send(&format!("SET key:{} v", i)).await.unwrap(), "ok");
+    }
+
+    let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    for _ in 0..100 {
+        if lines(&path).last().is_some_and(|line| line["key"] == "key:19") {
+            break;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(lines(&path).last().unwrap()["key"], "key:19");
+    assert!(fs::metadata(&path).unwrap().len() <= 300);
+    assert!(fs::metadata(rotated(1)).unwrap().len() <= 300);
+    assert!(rotated(2).exists());
+    assert!(!rotated(3).exists());
+
+    // Only keep = 2 rotated files survive, so the oldest lines are gone.
+    let kept = lines(&path).len() + lines(&rotated(1)).len() + lines(&rotated(2)).len();
+    assert!(kept < 20);
+}
@@ -0,0 +1,94 @@
+use keyz::config::{ProtocolConfig, StoreConfig};
+use keyz::server::batch::{decode, encode};
+
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn a_batch_is_answered_with_one_response_per_command_in_order() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let commands = ["SET greeting hello world", "GET greeting", "INCR visits", "INCR visits", "GET missing", "PING"];
+    let response = client.send(&encode(&commands)).await.unwrap();
+
+    assert_eq!(decode(&response, 10).unwrap(), ["ok", "hello world", "1", "2", "null", "pong"]);
+    assert_eq!(client.send("GET visits").await.unwrap(), "2");
+    assert_eq!(client.send(&encode::<&str>(&[])).await.unwrap(), "*0\n");
+}
+
+#[tokio::test]
+async fn a_failing_command_does_not_stop_the_batch() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let commands = [
+        "SET name value",
+        "LPUSH name x",
+        "NOSUCH thing",
+        "SET after yes",
+        "SUBSCRIBE news",
+        "CLOSE",
+        "sync",
+    ];
+    let response = client.send(&encode(&commands)).await.unwrap();
+
+    assert_eq!(
+        decode(&response, 10).unwrap(),
+        [
+            "ok",
+            "error:WRONGTYPE:wrong type",
+            "error:INVALID_CMD:invalid command",
+            "ok",
+            "error:INVALID_CMD:SUBSCRIBE is not allowed in a batch",
+            "error:INVALID_CMD:CLOSE is not allowed in a batch",
+            "error:INVALID_CMD:sync is not allowed in a batch",
+        ]
+    );
+    // Still connected, and the writes after the failures landed.
+    assert_eq!(client.send("GET after").await.unwrap(), "yes");
+}
+
+#[tokio::test]
+async fn malformed_batches_are_refused_and_the_connection_goes_on() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    for frame in ["*", "*two\n", "*2\n3\nGET", "*1\n8\nGET a", "*1\n3\nGET a", "*1\nx\nPING"] {
+        assert_eq!(client.send(frame).await.unwrap(), "error:INVALID_CMD:batch frame invalid", "{:?}", frame);
+    }
+    let nested = client.send(&encode(&[encode(&["PING"])])).await.unwrap();
+    assert_eq!(decode(&nested, 1).unwrap(), ["error:INVALID_CMD:batches can't be nested"]);
+    assert_eq!(client.send("PING").await.unwrap(), "pong");
+}
+
+#[tokio::test]
+async fn batches_are_held_to_the_command_and_size_limits() {
+    let protocol = ProtocolConfig { max_batch_commands: 2, max_message_bytes: 64, ..ProtocolConfig::default() };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(
+        client.send(&encode(&["PING", "PING", "PING"])).await.unwrap(),
+        "error:INVALID_CMD:batch has 3 commands, limit is 2"
+    );
+
+    // Each response fits on its own, both together don't.
+    let value = "x".repeat(30);
+    assert_eq!(client.send(&format!("SET big {}", value)).await.unwrap(), "ok");
+    assert_eq!(
+        client.send(&encode(&["GET big", "GET big"])).await.unwrap(),
+        "error:TOOLARGE:batch response exceeds 64 bytes; send fewer commands"
+    );
+}
+
+#[tokio::test]
+async fn batched_commands_inside_multi_are_queued() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    let response = client.send(&encode(&["SET a 1", "INCR a"])).await.unwrap();
+    assert_eq!(decode(&response, 2).unwrap(), ["QUEUED", "QUEUED"]);
+    assert_eq!(client.send("GET a").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("EXEC").await.unwrap(), r#"["ok",2,"2"]"#);
+}
@@ -0,0 +1,143 @@
+use std::thread;
+
+use keyz::config::StoreConfig;
+use keyz::server::store::Store;
+
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn setbit_grows_the_array_and_returns_the_old_bit() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SETBIT flags 100000 1").await.unwrap(), "0");
+    assert_eq!(client.send("SETBIT flags 100000 1").await.unwrap(), "1");
+    assert_eq!(client.send("TYPE flags").await.unwrap(), "bits");
+    assert_eq!(client.send("GETBIT flags 100000").await.unwrap(), "1");
+    assert_eq!(client.send("GETBIT flags 99999").await.unwrap(), "0");
+    assert_eq!(client.send("GETBIT flags 4294967295").await.unwrap(), "0");
+    assert_eq!(client.send("GETBIT missing 3").await.unwrap(), "0");
+    assert_eq!(client.send("BITCOUNT flags").await.unwrap(), "1");
+
+    assert_eq!(client.send("SETBIT flags 100000 0").await.unwrap(), "1");
+    assert_eq!(client.send("BITCOUNT flags").await.unwrap(), "0");
+
+    assert_eq!(
+        client.send("SETBIT flags 4294967296 1").await.unwrap(),
+        "error:INVALID_CMD:bit offset is not an integer or out of range"
+    );
+    assert_eq!(
+        client.send("GETBIT flags -1").await.unwrap(),
+        "error:INVALID_CMD:bit offset is not an integer or out of range"
+    );
+    assert_eq!(client.send("SETBIT flags 3 2").await.unwrap(), "error:INVALID_CMD:bit is not 0 or 1");
+}
+
+#[tokio::test]
+async fn bitcount_and_bitpos_work_on_byte_ranges() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    // Offset 0 is the top bit of the first byte.
+    for offset in [0, 9, 17, 23] {
+        client.send(&format!("SETBIT counted {} 1", offset)).await.unwrap();
+    }
+
+    assert_eq!(client.send("BITCOUNT counted").await.unwrap(), "4");
+    assert_eq!(client.send("BITCOUNT counted 1 1").await.unwrap(), "1");
+    assert_eq!(client.send("BITCOUNT counted -1 -1").await.unwrap(), "2");
+    assert_eq!(client.send("BITCOUNT counted 2 0").await.unwrap(), "0");
+    assert_eq!(client.send("BITCOUNT missing").await.unwrap(), "0");
+    assert_eq!(client.send("BITCOUNT counted 1").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("BITCOUNT counted 0 x").await.unwrap(), "error:INVALID_CMD:bit index invalid");
+
+    assert_eq!(client.send("BITPOS counted 1").await.unwrap(), "0");
+    assert_eq!(client.send("BITPOS counted 0").await.unwrap(), "1");
+    assert_eq!(client.send("BITPOS counted 1 1").await.unwrap(), "9");
+    assert_eq!(client.send("BITPOS counted 1 -1").await.unwrap(), "17");
+
+    for offset in 0..8 {
+        client.send(&format!("SETBIT full {} 1", offset)).await.unwrap();
+    }
+    client.send("SETBIT sparse 20 1").await.unwrap();
+
+    // A full array answers with the first offset past it, unless an end is given.
+    assert_eq!(client.send("BITPOS full 0").await.unwrap(), "8");
+    assert_eq!(client.send("BITPOS full 0 0 0").await.unwrap(), "-1");
+    assert_eq!(client.send("BITPOS sparse 1 0 1").await.unwrap(), "-1");
+    assert_eq!(client.send("BITPOS sparse 1 2").await.unwrap(), "20");
+    assert_eq!(client.send("BITPOS missing 0").await.unwrap(), "0");
+    assert_eq!(client.send("BITPOS missing 1").await.unwrap(), "-1");
+}
+
+#[tokio::test]
+async fn bitop_combines_keys_into_the_destination() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    // a is 1100 0000, b is 0100 0000 1000 0000.
+    for (key, offset) in [("a", 0), ("a", 1), ("b", 1), ("b", 8)] {
+        client.send(&format!("SETBIT {} {} 1", key, offset)).await.unwrap();
+    }
+
+    assert_eq!(client.send("BITOP AND both a b").await.unwrap(), "2");
+    assert_eq!(client.send("BITCOUNT both").await.unwrap(), "1");
+    assert_eq!(client.send("GETBIT both 1").await.unwrap(), "1");
+
+    assert_eq!(client.send("BITOP or either a b").await.unwrap(), "2");
+    assert_eq!(client.send("BITCOUNT either").await.unwrap(), "3");
+
+    assert_eq!(client.send("BITOP XOR differ a b").await.unwrap(), "2");
+    assert_eq!(client.send("BITCOUNT differ").await.unwrap(), "2");
+    assert_eq!(client.send("GETBIT differ 1").await.unwrap(), "0");
+
+    assert_eq!(client.send("BITOP NOT inverted a").await.unwrap(), "1");
+    assert_eq!(client.send("BITCOUNT inverted").await.unwrap(), "6");
+    assert_eq!(client.send("BITPOS inverted 1").await.unwrap(), "2");
+
+    // The result replaces whatever was there, and an empty one deletes it.
+    client.send("EXPIRE both 100").await.unwrap();
+    assert_eq!(client.send("BITOP AND both missing other").await.unwrap(), "0");
+    assert_eq!(client.send("TYPE both").await.unwrap(), "null");
+
+    assert_eq!(
+        client.send("BITOP NOT inverted a b").await.unwrap(),
+        "error:INVALID_CMD:BITOP NOT takes one source key"
+    );
+    assert_eq!(client.send("BITOP NAND out a b").await.unwrap(), "error:INVALID_CMD:bitop operation invalid");
+    assert_eq!(client.send("BITOP AND out").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn bit_commands_refuse_other_types() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    client.send("SET name ada").await.unwrap();
+    client.send("SADD tags a").await.unwrap();
+    client.send("SETBIT flags 0 1").await.unwrap();
+
+    assert_eq!(client.send("SETBIT name 0 1").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("GETBIT tags 0").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("BITCOUNT name").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("BITPOS name 1").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("BITOP OR out flags name").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("SADD flags a").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("TYPE out").await.unwrap(), "null");
+}
+
+#[test]
+fn concurrent_setbits_lose_no_bits() {
+    let store: Store = Store::new(&StoreConfig::default());
+
+    thread::scope(|scope| {
+        for writer in 0..4 {
+            let store = store.clone();
+            scope.spawn(move || {
+                for offset in (writer..4000).step_by(4) {
+                    assert!(!store.set_bit("flags", offset, true).unwrap());
+                }
+            });
+        }
+    });
+
+    assert_eq!(store.bit_count("flags", None).unwrap(), 4000);
+    assert_eq!(store.bit_position("flags", false, 0, None).unwrap(), 4000);
+}
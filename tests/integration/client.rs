@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use keyz::client::{ClientError, KeyzConnection};
+use keyz::{Config, Server};
+
+async fn start(config: Config) -> Arc<Server> {
+    let server = Arc::new(Server::bind(&config).await.unwrap());
+
+    let running = server.clone();
+    tokio::spawn(async move { running.run().await });
+
+    server
+}
+
+fn config() -> Config {
+    let mut config = Config::default();
+    config.server.port = Some(0);
+    config
+}
+
+async fn connect(server: &Server) -> KeyzConnection {
+    KeyzConnection::connect(server.local_addr().unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn typed_calls_round_trip() {
+    let server = start(config()).await;
+    let mut client = connect(&server).await;
+
+    client.set("greeting", "hello world", None).await.unwrap();
+    assert_eq!(
+        client.get("greeting").await.unwrap().as_deref(),
+        Some(&b"hello world"[..])
+    );
+    assert_eq!(client.get("missing").await.unwrap(), None);
+
+    client
+        .set("session", "abc", Some(Duration::from_secs(60)))
+        .await
+        .unwrap();
+    let ttl = client.expires_in("session").await.unwrap().unwrap();
+    assert!(ttl <= Duration::from_secs(60) && ttl >= Duration::from_secs(58));
+    assert_eq!(client.expires_in("greeting").await.unwrap(), None);
+
+    assert!(client.delete("greeting").await.unwrap());
+    assert!(!client.delete("greeting").await.unwrap());
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn batches_go_out_in_one_frame() {
+    let server = start(config()).await;
+    let mut client = connect(&server).await;
+
+    let responses = client.batch(&["SET a 1", "INCR a", "LPUSH a x", "GET a"]).await.unwrap();
+    assert_eq!(responses, ["ok", "2", "error:WRONGTYPE:wrong type", "2"]);
+
+    let mut config = config();
+    config.protocol.max_batch_commands = 1;
+    let limited = start(config).await;
+    let mut client = connect(&limited).await;
+    assert!(matches!(client.batch(&["PING", "PING"]).await, Err(ClientError::InvalidCommand)));
+
+    server.shutdown();
+    limited.shutdown();
+}
+
+#[tokio::test]
+async fn info_is_deserialized() {
+    let server = start(config()).await;
+    let mut client = connect(&server).await;
+
+    client.set("a", "1", None).await.unwrap();
+    client.get("a").await.unwrap();
+    client.get("b").await.unwrap();
+
+    let info = client.info().await.unwrap();
+    assert_eq!(info.keys, 1);
+    assert_eq!(info.role, "primary");
+    assert_eq!(info.metrics.hits, 1);
+    assert_eq!(info.metrics.misses, 1);
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn server_errors_are_typed() {
+    let mut config = config();
+    config.store.max_value_bytes = Some(4);
+    let server = start(config).await;
+    let mut client = connect(&server).await;
+
+    assert!(matches!(
+        client.set("k", "too long", None).await,
+        Err(ClientError::ValueTooLarge)
+    ));
+    assert_eq!(
+        client.command("NOPE").await.unwrap(),
+        "error:INVALID_CMD:invalid command"
+    );
+
+    client.command("CONFIG SET server.read_only true").await.unwrap();
+    assert!(matches!(
+        client.set("k", "v", None).await,
+        Err(ClientError::ReadOnly)
+    ));
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn oversized_messages_are_rejected() {
+    let server = start(config()).await;
+    let mut client = connect(&server).await;
+
+    client.set("big", &"x".repeat(64), None).await.unwrap();
+
+    client.set_max_message_bytes(32);
+    assert!(matches!(
+        client.set("k", &"y".repeat(64), None).await,
+        Err(ClientError::MessageTooLarge { limit: 32, .. })
+    ));
+
+    // The request fits but the response does not.
+    assert!(matches!(
+        client.get("big").await,
+        Err(ClientError::MessageTooLarge { size: 64, limit: 32 })
+    ));
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn calls_time_out() {
+    let mut config = config();
+    config.protocol.allow_debug = true;
+    // Runs DEBUG SLEEP off the runtime thread the test shares with the server.
+    config.protocol.command_timeout_secs = Some(5);
+    let server = start(config).await;
+    let mut client = connect(&server).await;
+
+    client.set_timeout(Some(Duration::from_millis(100)));
+    assert!(matches!(
+        client.command("DEBUG SLEEP 1").await,
+        Err(ClientError::Timeout(_))
+    ));
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn connect_says_hello() {
+    let server = start(config()).await;
+    let client = connect(&server).await;
+
+    let hello = client.server_hello().unwrap();
+    assert_eq!(hello.version, env!("CARGO_PKG_VERSION"));
+    assert!(hello.protocols.contains(&1));
+    assert_eq!(client.protocol(), 1);
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn servers_without_hello_stay_on_protocol_1() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Answers like a server from before HELLO: unknown commands are invalid.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        loop {
+            let Ok(len) = stream.read_u32().await else {
+                return;
+            };
+            let mut command = vec![0; len as usize];
+            stream.read_exact(&mut command).await.unwrap();
+
+            let reply: &[u8] = if command == b"PING" { b"pong" } else { b"error:INVALID_CMD:invalid command" };
+            stream.write_u32(reply.len() as u32).await.unwrap();
+            stream.write_all(reply).await.unwrap();
+        }
+    });
+
+    let mut client = KeyzConnection::connect(addr).await.unwrap();
+    assert!(client.server_hello().is_none());
+    assert_eq!(client.protocol(), 1);
+    client.ping().await.unwrap();
+}
@@ -0,0 +1,1050 @@
+use std::thread;
+use std::time::Duration;
+
+use keyz::config::{ProtocolConfig, StoreConfig};
+use keyz::server::store::Store;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::helpers::{KeyzClient, TestServer};
+
+#[tokio::test]
+async fn set_then_get_round_trips() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET greeting hello world").await.unwrap(), "ok");
+    assert_eq!(client.send("GET greeting").await.unwrap(), "hello world");
+    assert_eq!(client.send("GET missing").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn incr_keeps_an_integer_that_reads_back_as_a_string() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("INCR counter").await.unwrap(), "1");
+    assert_eq!(client.send("SET hits 41 EX 100").await.unwrap(), "ok");
+    assert_eq!(client.send("INCR hits").await.unwrap(), "42");
+    assert_eq!(client.send("GET hits").await.unwrap(), "42");
+    let ttl = client.send("TTL hits").await.unwrap();
+    assert!(ttl == "100" || ttl == "99", "{}", ttl);
+    assert_eq!(client.send("TYPE hits").await.unwrap(), "string");
+    assert_eq!(client.send("OBJECT ENCODING hits").await.unwrap(), "int");
+    assert_eq!(client.send("TYPE missing").await.unwrap(), "null");
+
+    let blob = client.send("DUMP hits").await.unwrap();
+    assert_eq!(client.send(&format!("RESTORE copy 0 {}", blob)).await.unwrap(), "ok");
+    assert_eq!(client.send("INCR copy").await.unwrap(), "43");
+
+    assert_eq!(client.send("SET word hello").await.unwrap(), "ok");
+    assert_eq!(client.send("INCR word").await.unwrap(), "error:INVALID_CMD:value is not an integer");
+    assert_eq!(client.send(&format!("SET max {}", i64::MAX)).await.unwrap(), "ok");
+    assert_eq!(client.send("INCR max").await.unwrap(), "error:INVALID_CMD:increment would overflow");
+    assert_eq!(client.send("GET max").await.unwrap(), i64::MAX.to_string());
+}
+
+#[tokio::test]
+async fn keys_expire_after_their_ttl() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET session abc EX 1").await.unwrap(), "ok");
+    assert_eq!(client.send("GET session").await.unwrap(), "abc");
+
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+
+    assert_eq!(client.send("GET session").await.unwrap(), "null");
+    assert_eq!(client.send("EXIN session").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn hello_negotiates_the_protocol_per_connection() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    let mut other = server.connect().await;
+
+    let hello: serde_json::Value = serde_json::from_str(&client.send("HELLO").await.unwrap()).unwrap();
+    assert_eq!(hello["server"], "keyz");
+    assert_eq!(hello["proto"], 1);
+    assert_eq!(hello["protocols"], serde_json::json!([1, 2]));
+    assert_eq!(hello["features"]["pubsub"], true);
+
+    let hello: serde_json::Value = serde_json::from_str(&client.send("HELLO 2").await.unwrap()).unwrap();
+    assert_eq!(hello["proto"], 2);
+    let error: serde_json::Value = serde_json::from_str(&client.send("GET").await.unwrap()).unwrap();
+    assert_eq!(error["error"], "invalid_command");
+    assert_eq!(other.send("GET").await.unwrap(), "error:INVALID_CMD:invalid command");
+
+    // An unknown version is refused and the connection stays on 2.
+    let error: serde_json::Value = serde_json::from_str(&client.send("HELLO 9").await.unwrap()).unwrap();
+    assert_eq!(error["message"], "protocol version unsupported");
+    assert!(client.send("GET").await.unwrap().starts_with('{'));
+
+    client.send("HELLO 1").await.unwrap();
+    assert_eq!(client.send("GET").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("HELLO x").await.unwrap(), "error:INVALID_CMD:protocol version unsupported");
+}
+
+#[tokio::test]
+async fn close_answers_then_closes_the_connection() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("CLOSE").await.unwrap(), "Closing connection");
+    assert!(client.send("GET anything").await.is_err());
+}
+
+#[tokio::test]
+async fn sync_starts_replication_in_any_case() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    assert_eq!(client.send("SET greeting hello").await.unwrap(), "ok");
+
+    for sync in ["SYNC", "sync", "Sync"] {
+        let mut replica = server.connect().await;
+        let frame = replica.send(sync).await.unwrap();
+        assert!(frame.contains("greeting"), "{}: {}", sync, frame);
+        assert!(!frame.starts_with("error:"), "{}: {}", sync, frame);
+    }
+}
+
+#[tokio::test]
+async fn info_returns_valid_json() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    client.send("SET a 1").await.unwrap();
+    client.send("GET a").await.unwrap();
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(info["keys"], 1);
+    assert_eq!(info["metrics"]["sets"], 1);
+    assert_eq!(info["metrics"]["hits"], 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_clients_share_the_store() {
+    let server = TestServer::start().await;
+
+    let mut tasks = Vec::new();
+    for id in 0..10 {
+        let mut client = server.connect().await;
+        tasks.push(tokio::spawn(async move {
+            for n in 0..20 {
+                let key = format!("client:{}:{}", id, n);
+                assert_eq!(client.send(&format!("SET {} {}", key, n)).await.unwrap(), "ok");
+                assert_eq!(client.send(&format!("GET {}", key)).await.unwrap(), n.to_string());
+            }
+        }));
+    }
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let mut client = server.connect().await;
+    let keys: Vec<String> = serde_json::from_str(&client.send("KEYS client:*").await.unwrap()).unwrap();
+    assert_eq!(keys.len(), 200);
+}
+
+#[tokio::test]
+async fn large_values_round_trip() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let value = "v".repeat(1024 * 1024);
+    assert_eq!(client.send(&format!("SET big {}", value)).await.unwrap(), "ok");
+    assert_eq!(client.send("GET big").await.unwrap(), value);
+}
+
+#[tokio::test]
+async fn config_set_applies_to_the_next_command() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("CONFIG GET store.max_value_bytes").await.unwrap(), "null");
+    assert_eq!(client.send("CONFIG SET store.max_value_bytes 4").await.unwrap(), "ok");
+    assert_eq!(client.send("CONFIG GET store.max_value_bytes").await.unwrap(), "4");
+    assert_eq!(client.send("SET k12345 abcde").await.unwrap(), "error:TOOLARGE:value too large");
+    assert_eq!(client.send("SET k12345 abcd").await.unwrap(), "ok");
+
+    assert_eq!(client.send("CONFIG SET store.max_key_bytes 2").await.unwrap(), "ok");
+    assert_eq!(client.send("SET k12345 abcd").await.unwrap(), "error:KEYTOOLONG:key too long");
+
+    assert_eq!(client.send("CONFIG SET store.max_ttl_secs 10").await.unwrap(), "ok");
+    assert_eq!(client.send("SET k v EX 11").await.unwrap(), "error:TTLTOOLARGE:ttl too large");
+
+    assert_eq!(client.send("CONFIG SET store.max_value_bytes null").await.unwrap(), "ok");
+    assert_eq!(client.send("CONFIG GET store.max_value_bytes").await.unwrap(), "null");
+    assert_eq!(client.send("SET k abcdefgh").await.unwrap(), "ok");
+
+    assert_eq!(client.send("CONFIG SET server.read_only true").await.unwrap(), "ok");
+    assert_eq!(client.send("CONFIG GET server.read_only").await.unwrap(), "true");
+    assert_eq!(client.send("DEL k").await.unwrap(), "error:READONLY:read only");
+}
+
+#[tokio::test]
+async fn config_rejects_bad_fields_and_values() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("CONFIG GET server.port").await.unwrap(), server.addr().port().to_string());
+    assert_eq!(client.send("CONFIG SET server.port 1").await.unwrap(), "error:INVALID_CMD:field is not writable");
+    assert_eq!(client.send("CONFIG SET server.host 0.0.0.0").await.unwrap(), "error:INVALID_CMD:field is not writable");
+    assert_eq!(client.send("CONFIG SET store.max_key_bytes lots").await.unwrap(), "error:INVALID_CMD:config value invalid");
+    assert_eq!(client.send("CONFIG GET nope").await.unwrap(), "error:INVALID_CMD:unknown config parameter");
+    assert_eq!(client.send("CONFIG REWRITE").await.unwrap(), "error:INVALID_CMD:no config file");
+}
+
+#[tokio::test]
+async fn config_rewrite_saves_runtime_changes() {
+    let path = std::env::temp_dir().join(format!("keyz-rewrite-{}.toml", std::process::id()));
+    std::fs::write(&path, "[logging]\nlevel = \"debug\"\n\n[store]\nmax_ttl = \"1h\"\n").unwrap();
+
+    let server = TestServer::start().await;
+    server.store().set_config_path(path.clone());
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("CONFIG SET store.max_key_bytes 64").await.unwrap(), "ok");
+    assert_eq!(client.send("CONFIG SET server.read_only true").await.unwrap(), "ok");
+    assert_eq!(client.send("CONFIG REWRITE").await.unwrap(), "ok");
+
+    let config = keyz::Config::from_toml_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.store.max_key_bytes, Some(64));
+    assert!(config.server.read_only);
+    assert_eq!(config.logging.level, "debug");
+}
+
+#[tokio::test]
+async fn command_count_and_info_describe_the_registry() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let count: usize = client.send("COMMAND COUNT").await.unwrap().parse().unwrap();
+
+    let all: serde_json::Value = serde_json::from_str(&client.send("COMMAND INFO").await.unwrap()).unwrap();
+    assert_eq!(all.as_array().unwrap().len(), count);
+
+    let docs: serde_json::Value =
+        serde_json::from_str(&client.send("COMMAND INFO SET nope get").await.unwrap()).unwrap();
+    assert_eq!(docs[0]["name"], "SET");
+    assert_eq!(docs[0]["arity"], -3);
+    assert_eq!(docs[0]["flags"], serde_json::json!(["write"]));
+    assert_eq!(docs[0]["syntax"], "SET key value [EX secs]");
+    assert!(docs[1].is_null());
+    assert_eq!(docs[2]["name"], "GET");
+
+    assert_eq!(client.send("COMMAND LIST").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn delpattern_stops_at_the_cap_and_reports_more() {
+    let protocol = ProtocolConfig {
+        bulk_delete_max: 3,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    for i in 0..5 {
+        assert_eq!(client.send(&format!("SET test:{} v", i)).await.unwrap(), "ok");
+    }
+    assert_eq!(client.send("SET keep v").await.unwrap(), "ok");
+
+    assert_eq!(client.send("DELPATTERN test:*").await.unwrap(), r#"{"deleted":3,"more":true}"#);
+    assert_eq!(client.send("DELPATTERN test:*").await.unwrap(), r#"{"deleted":2,"more":false}"#);
+    assert_eq!(client.send("DELPATTERN test:*").await.unwrap(), r#"{"deleted":0,"more":false}"#);
+    assert_eq!(client.send("KEYS *").await.unwrap(), r#"["keep"]"#);
+}
+
+#[tokio::test]
+async fn delpattern_follows_glob_rules() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    for key in ["a*b", "axb", "a1", "a2", "ab", "[x]", "x"] {
+        assert_eq!(client.send(&format!("SET {} v", key)).await.unwrap(), "ok");
+    }
+
+    assert_eq!(client.send(r"DELPATTERN a\*b").await.unwrap(), r#"{"deleted":1,"more":false}"#);
+    assert_eq!(client.send("DELPATTERN a[0-9]").await.unwrap(), r#"{"deleted":2,"more":false}"#);
+    assert_eq!(client.send("DELPATTERN a?").await.unwrap(), r#"{"deleted":1,"more":false}"#);
+    assert_eq!(client.send(r"DELPATTERN \[x\]").await.unwrap(), r#"{"deleted":1,"more":false}"#);
+    assert_eq!(client.send("DELPATTERN nothing*").await.unwrap(), r#"{"deleted":0,"more":false}"#);
+    assert_eq!(client.send("KEYS *").await.unwrap(), r#"["axb","x"]"#);
+}
+
+#[tokio::test]
+async fn delpattern_can_be_disabled() {
+    let protocol = ProtocolConfig {
+        allow_bulk_delete: false,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET k v").await.unwrap(), "ok");
+    assert_eq!(client.send("DELPATTERN *").await.unwrap(), "error:INVALID_CMD:bulk delete disabled");
+    assert_eq!(client.send("GET k").await.unwrap(), "v");
+}
+
+#[tokio::test]
+async fn delpattern_runs_alongside_concurrent_sets() {
+    let protocol = ProtocolConfig {
+        bulk_delete_max: 100,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let store = server.store().clone();
+
+    for i in 0..2_000 {
+        store.insert(format!("bulk:{}", i), b"v".to_vec(), 0).unwrap();
+    }
+
+    let mut writer = server.connect().await;
+    let writer = tokio::spawn(async move {
+        for i in 0..200 {
+            assert_eq!(writer.send(&format!("SET other:{} v", i)).await.unwrap(), "ok");
+        }
+    });
+
+    let mut client = server.connect().await;
+    let mut deleted = 0;
+    loop {
+        let response: serde_json::Value =
+            serde_json::from_str(&client.send("DELPATTERN bulk:*").await.unwrap()).unwrap();
+        deleted += response["deleted"].as_u64().unwrap();
+        if response["more"] == false {
+            break;
+        }
+    }
+    writer.await.unwrap();
+
+    assert_eq!(deleted, 2_000);
+    assert_eq!(store.keys_matching("bulk:*").len(), 0);
+    assert_eq!(store.keys_matching("other:*").len(), 200);
+}
+
+#[tokio::test]
+async fn unlink_hides_the_key_at_once_and_frees_it_later() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let value = "x".repeat(64 * 1024);
+    assert_eq!(client.send(&format!("SET big {}", value)).await.unwrap(), "ok");
+
+    assert_eq!(client.send("UNLINK big").await.unwrap(), "ok");
+    assert_eq!(client.send("KEYS *").await.unwrap(), "[]");
+    assert_eq!(client.send("EXIN big").await.unwrap(), "null");
+    assert_eq!(client.send("UNLINK big").await.unwrap(), "null");
+    assert_eq!(client.send("UNLINK missing").await.unwrap(), "null");
+
+    for _ in 0..100 {
+        let stats = server.store().stats();
+        if stats.pending_unlinks == 0 && stats.keys == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let stats = server.store().stats();
+    assert_eq!(stats.pending_unlinks, 0);
+    assert_eq!(stats.keys, 0);
+
+    // A key set again before the background removal ran is kept.
+    assert_eq!(client.send("SET again 1").await.unwrap(), "ok");
+    assert_eq!(client.send("UNLINK again").await.unwrap(), "ok");
+    assert_eq!(client.send("SET again 2").await.unwrap(), "ok");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(client.send("GET again").await.unwrap(), "2");
+}
+
+#[tokio::test]
+async fn touch_counts_live_keys() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET a 1").await.unwrap(), "ok");
+    assert_eq!(client.send("SET b 2").await.unwrap(), "ok");
+    assert_eq!(client.send("SET gone 3 EX 1").await.unwrap(), "ok");
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+
+    assert_eq!(client.send("TOUCH a b gone missing a").await.unwrap(), "3");
+    assert_eq!(client.send("TOUCH missing").await.unwrap(), "0");
+    assert_eq!(client.send("TOUCH").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn expire_changes_the_ttl_but_not_the_value() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET session abc").await.unwrap(), "ok");
+    assert_eq!(client.send("EXPIRE session 100").await.unwrap(), "1");
+    let ttl: u64 = client.send("EXIN session").await.unwrap().parse().unwrap();
+    assert!((99..=100).contains(&ttl), "{}", ttl);
+    assert_eq!(client.send("GET session").await.unwrap(), "abc");
+
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(client.send(&format!("EXPIREAT session {}", now + 500)).await.unwrap(), "1");
+    let ttl: u64 = client.send("EXIN session").await.unwrap().parse().unwrap();
+    assert!((499..=500).contains(&ttl), "{}", ttl);
+    assert_eq!(client.send("GET session").await.unwrap(), "abc");
+
+    assert_eq!(client.send("EXPIRE missing 10").await.unwrap(), "0");
+    assert_eq!(client.send("SET gone 1 EX 1").await.unwrap(), "ok");
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    assert_eq!(client.send("EXPIRE gone 10").await.unwrap(), "0");
+    assert_eq!(client.send(&format!("EXPIREAT gone {}", now + 100)).await.unwrap(), "0");
+
+    // A deadline in the past deletes the key.
+    assert_eq!(client.send("EXPIREAT session 1").await.unwrap(), "1");
+    assert_eq!(client.send("GET session").await.unwrap(), "null");
+
+    assert_eq!(client.send("EXPIRE session soon").await.unwrap(), "error:INVALID_CMD:expire seconds invalid");
+    assert_eq!(client.send("EXPIRE session").await.unwrap(), "error:INVALID_CMD:expire seconds invalid");
+}
+
+#[tokio::test]
+async fn getex_resets_the_ttl_on_every_read() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET session abc EX 2").await.unwrap(), "ok");
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(client.send("GETEX session EX 2").await.unwrap(), "abc");
+        let ttl_ms: u64 = client.send("PTTL session").await.unwrap().parse().unwrap();
+        assert!((1900..=2000).contains(&ttl_ms), "{}", ttl_ms);
+    }
+
+    assert_eq!(client.send("GETEX session PX 60000").await.unwrap(), "abc");
+    assert_eq!(client.send("GETEX session").await.unwrap(), "abc");
+    let ttl: u64 = client.send("EXIN session").await.unwrap().parse().unwrap();
+    assert!((59..=60).contains(&ttl), "{}", ttl);
+    assert_eq!(client.send("GETEX session persist").await.unwrap(), "abc");
+    assert_eq!(client.send("EXIN session").await.unwrap(), "null");
+
+    assert_eq!(client.send("GETEX missing EX 10").await.unwrap(), "null");
+    assert_eq!(client.send("GETEX session EX 0").await.unwrap(), "error:INVALID_CMD:getex command invalid");
+    assert_eq!(client.send("GETEX session EX").await.unwrap(), "error:INVALID_CMD:getex command invalid");
+    assert_eq!(client.send("GETEX session KEEPTTL").await.unwrap(), "error:INVALID_CMD:getex command invalid");
+}
+
+#[tokio::test]
+async fn setex_stores_the_same_as_set_with_ex() {
+    let protocol = ProtocolConfig {
+        allow_debug: true,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SETEX a 60 hello world").await.unwrap(), "ok");
+    assert_eq!(client.send("SET b hello world EX 60").await.unwrap(), "ok");
+    assert_eq!(client.send("setex c 60 hello world").await.unwrap(), "ok");
+
+    for key in ["a", "c"] {
+        assert_eq!(client.send(&format!("GET {}", key)).await.unwrap(), "hello world");
+        // The TTLs are compared in milliseconds below, since whole seconds
+        // can differ across a second boundary.
+        let mut entry: serde_json::Value =
+            serde_json::from_str(&client.send(&format!("DEBUG OBJECT {}", key)).await.unwrap()).unwrap();
+        let mut set_entry: serde_json::Value = serde_json::from_str(&client.send("DEBUG OBJECT b").await.unwrap()).unwrap();
+        entry.as_object_mut().unwrap().remove("ttl_remaining_secs");
+        set_entry.as_object_mut().unwrap().remove("ttl_remaining_secs");
+        assert_eq!(entry, set_entry);
+        let ttl_ms = server.store().expires_in_ms(key).unwrap();
+        let set_ttl_ms = server.store().expires_in_ms("b").unwrap();
+        assert!(ttl_ms.abs_diff(set_ttl_ms) < 100, "{} {}", ttl_ms, set_ttl_ms);
+    }
+
+    assert_eq!(client.send("PSETEX p 1500 v").await.unwrap(), "ok");
+    let ttl_ms: u64 = client.send("PTTL p").await.unwrap().parse().unwrap();
+    assert!((1400..=1500).contains(&ttl_ms), "{}", ttl_ms);
+
+    for command in ["SETEX a 0 v", "SETEX a -1 v", "SETEX a 1.5 v", "SETEX a soon v"] {
+        assert_eq!(client.send(command).await.unwrap(), "error:INVALID_CMD:invalid expire time in setex");
+    }
+    assert_eq!(client.send("PSETEX a 0 v").await.unwrap(), "error:INVALID_CMD:invalid expire time in psetex");
+    assert_eq!(client.send("SETEX a 60").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("GET a").await.unwrap(), "hello world");
+}
+
+#[tokio::test]
+async fn pexpire_expires_keys_to_the_millisecond() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET short v").await.unwrap(), "ok");
+    assert_eq!(client.send("PEXPIRE short 500").await.unwrap(), "1");
+    let ttl_ms: u64 = client.send("PTTL short").await.unwrap().parse().unwrap();
+    assert!((400..=500).contains(&ttl_ms), "{}", ttl_ms);
+    assert_eq!(client.send("TTL short").await.unwrap(), "0");
+    assert_eq!(client.send("GET short").await.unwrap(), "v");
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(client.send("GET short").await.unwrap(), "null");
+    assert_eq!(client.send("PTTL short").await.unwrap(), "null");
+
+    let now_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+    assert_eq!(client.send("SET later v").await.unwrap(), "ok");
+    assert_eq!(client.send(&format!("PEXPIREAT later {}", now_ms + 90_500)).await.unwrap(), "1");
+    assert_eq!(client.send("TTL later").await.unwrap(), client.send("EXIN later").await.unwrap());
+    let ttl: u64 = client.send("TTL later").await.unwrap().parse().unwrap();
+    assert!((89..=90).contains(&ttl), "{}", ttl);
+
+    assert_eq!(client.send("SET forever v").await.unwrap(), "ok");
+    assert_eq!(client.send("PTTL forever").await.unwrap(), "null");
+    assert_eq!(client.send("PEXPIRE missing 500").await.unwrap(), "0");
+}
+
+#[tokio::test]
+async fn ttl_histogram_and_expiring_show_upcoming_deadlines() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    for (key, ttl) in [("soon", 5), ("minute", 30), ("tenmin", 300), ("hour", 1800), ("day", 7200), ("later", 200_000)] {
+        assert_eq!(client.send(&format!("SET {} v EX {}", key, ttl)).await.unwrap(), "ok");
+    }
+    assert_eq!(client.send("SET forever v").await.unwrap(), "ok");
+    assert_eq!(client.send("SET soon2 v EX 6").await.unwrap(), "ok");
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(
+        info["ttl_histogram"],
+        serde_json::json!({
+            "under_10s": 2, "under_60s": 1, "under_10m": 1, "under_1h": 1,
+            "under_1d": 1, "over_1d": 1, "none": 1, "sampled": 8,
+        })
+    );
+
+    let expiring: serde_json::Value = serde_json::from_str(&client.send("EXPIRING 3").await.unwrap()).unwrap();
+    let keys: Vec<&str> = expiring.as_array().unwrap().iter().map(|e| e["key"].as_str().unwrap()).collect();
+    assert_eq!(keys, ["soon", "soon2", "minute"]);
+    assert!(expiring[0]["ttl_secs"].as_u64().unwrap() <= 5);
+
+    assert_eq!(client.send("EXPIRING 0").await.unwrap(), "[]");
+    assert_eq!(client.send("EXPIRING x").await.unwrap(), "error:INVALID_CMD:expiring count invalid");
+}
+
+#[tokio::test]
+async fn object_encoding_reports_the_compression_ratio() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send(&format!("SET big {}", "a".repeat(4096))).await.unwrap(), "ok");
+    assert_eq!(client.send("SET tiny x").await.unwrap(), "ok");
+
+    let big = client.send("OBJECT ENCODING big").await.unwrap();
+    let ratio: f64 = big.strip_prefix("compressed:gzip:ratio=").unwrap().parse().unwrap();
+    assert!(ratio > 10.0, "{}", big);
+
+    let tiny = client.send("OBJECT ENCODING tiny").await.unwrap();
+    let ratio: f64 = tiny.strip_prefix("compressed:gzip:ratio=").unwrap().parse().unwrap();
+    assert!(ratio < 1.0, "{}", tiny);
+
+    assert_eq!(client.send("OBJECT ENCODING missing").await.unwrap(), "null");
+    assert_eq!(client.send("OBJECT IDLETIME big").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn dump_then_restore_round_trips_a_key() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let value = "hello world ".repeat(50);
+    assert_eq!(client.send(&format!("SET source {}", value)).await.unwrap(), "ok");
+
+    let blob = client.send("DUMP source").await.unwrap();
+    assert_eq!(client.send(&format!("RESTORE copy 0 {}", blob)).await.unwrap(), "ok");
+    assert_eq!(client.send("GET copy").await.unwrap(), value);
+    assert_eq!(client.send("EXIN copy").await.unwrap(), "null");
+    assert_eq!(
+        client.send("OBJECT ENCODING copy").await.unwrap(),
+        client.send("OBJECT ENCODING source").await.unwrap()
+    );
+
+    assert_eq!(client.send(&format!("RESTORE timed 1500 {}", blob)).await.unwrap(), "ok");
+    let ttl: u64 = client.send("EXIN timed").await.unwrap().parse().unwrap();
+    assert!((1..=2).contains(&ttl), "{}", ttl);
+
+    assert_eq!(client.send("DUMP missing").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn restore_rejects_corrupt_blobs() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET source value").await.unwrap(), "ok");
+    let blob = client.send("DUMP source").await.unwrap();
+
+    // Flip a character in the payload part of the base64 text.
+    let mut corrupt = blob.clone().into_bytes();
+    let at = corrupt.len() - 8;
+    corrupt[at] = if corrupt[at] == b'A' { b'B' } else { b'A' };
+    let corrupt = String::from_utf8(corrupt).unwrap();
+
+    assert_eq!(
+        client.send(&format!("RESTORE copy 0 {}", corrupt)).await.unwrap(),
+        "error:CHECKSUM:checksum mismatch"
+    );
+    assert_eq!(client.send("RESTORE copy 0 garbage").await.unwrap(), "error:INVALID_CMD:dump payload invalid");
+    assert_eq!(client.send(&format!("RESTORE copy x {}", blob)).await.unwrap(), "error:INVALID_CMD:restore ttl invalid");
+    assert_eq!(client.send("GET copy").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn values_inflating_past_the_message_limit_are_refused() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    // A megabyte of zeros compresses to about a kilobyte.
+    server.store().insert("bomb".to_string(), vec![b'0'; 1024 * 1024], 0).unwrap();
+    let blob = client.send("DUMP bomb").await.unwrap();
+    assert!(blob.len() < 8 * 1024, "{}", blob.len());
+
+    server.store().set_max_payload_bytes(64 * 1024);
+    assert_eq!(client.send("GET bomb").await.unwrap(), "error:PAYLOADTOOLARGE:value too large");
+    assert_eq!(client.send("JGET bomb $").await.unwrap(), "error:PAYLOADTOOLARGE:value too large");
+    assert_eq!(client.send(&format!("RESTORE copy 0 {}", blob)).await.unwrap(), "error:PAYLOADTOOLARGE:value too large");
+    assert_eq!(client.send("TOUCH copy").await.unwrap(), "0");
+    assert_eq!(client.send("PING").await.unwrap(), "pong");
+
+    server.store().set_max_payload_bytes(1024 * 1024);
+    assert_eq!(client.send("GET bomb").await.unwrap().len(), 1024 * 1024);
+}
+
+#[tokio::test]
+async fn oversized_frames_are_refused_and_the_connection_closed() {
+    let protocol = ProtocolConfig { max_message_bytes: 1024, ..ProtocolConfig::default() };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send(&format!("SET key {}", "a".repeat(1000))).await.unwrap(), "ok");
+    assert_eq!(
+        client.send(&format!("SET key {}", "a".repeat(1024))).await.unwrap(),
+        "error:MSGTOOLARGE:message too large"
+    );
+    assert!(client.read().await.is_err());
+}
+
+#[tokio::test]
+async fn frames_announced_but_never_sent_hold_little_memory_and_get_dropped() {
+    let protocol = ProtocolConfig {
+        max_inflight_bytes: Some(64 * 1024 * 1024),
+        frame_timeout_secs: 1,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+
+    // Each announces a 60 MiB frame, sends 100 KiB of it and goes quiet.
+    let mut attackers = Vec::new();
+    for _ in 0..8 {
+        let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+        stream.write_all(&(60u32 * 1024 * 1024).to_be_bytes()).await.unwrap();
+        stream.write_all(format!("SET key {}", "a".repeat(100 * 1024 - 8)).as_bytes()).await.unwrap();
+        attackers.push(stream);
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut client = server.connect().await;
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    let inflight = info["inflight_bytes"].as_u64().unwrap();
+    // What was sent plus at most one chunk each, nowhere near 8 * 60 MiB.
+    assert!((8 * 96 * 1024..=8 * 164 * 1024).contains(&inflight), "{} bytes in flight", inflight);
+    assert_eq!(client.send("SET key value").await.unwrap(), "ok");
+    assert_eq!(client.send("GET key").await.unwrap(), "value");
+
+    for mut stream in attackers {
+        let mut buf = [0; 16];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await.unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+    }
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(info["inflight_bytes"], 0);
+}
+
+#[tokio::test]
+async fn info_reports_latency_percentiles_per_command() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET key value").await.unwrap(), "ok");
+    for _ in 0..20 {
+        assert_eq!(client.send("GET key").await.unwrap(), "value");
+    }
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    let get = &info["latency"]["GET"];
+    assert_eq!(get["count"], 20);
+    assert!(get["p50_us"].as_u64().unwrap() <= get["p95_us"].as_u64().unwrap());
+    assert!(get["p99_us"].as_u64().unwrap() <= get["max_us"].as_u64().unwrap());
+    assert_eq!(info["latency"]["SET"]["count"], 1);
+    assert!(info["latency"].get("DEL").is_none());
+
+    assert_eq!(client.send("RESETSTATS").await.unwrap(), "ok");
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    // RESETSTATS itself is timed after it has cleared the histograms.
+    let names: Vec<&String> = info["latency"].as_object().unwrap().keys().collect();
+    assert_eq!(names, ["RESETSTATS"]);
+}
+
+#[tokio::test]
+async fn command_keywords_match_in_any_case() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("set Greeting Hello").await.unwrap(), "ok");
+    assert_eq!(client.send("Get Greeting").await.unwrap(), "Hello");
+    assert_eq!(client.send("GET greeting").await.unwrap(), "null");
+
+    assert_eq!(client.send("sEt session abc ex 60").await.unwrap(), "ok");
+    assert_eq!(client.send("get session").await.unwrap(), "abc");
+    // Whole seconds are truncated, so a slow millisecond can already show 59.
+    let ttl = client.send("exin session").await.unwrap();
+    assert!(ttl == "60" || ttl == "59", "{}", ttl);
+
+    // A value that merely starts with "ex " is still just a value.
+    assert_eq!(client.send("set note ex marks the spot").await.unwrap(), "ok");
+    assert_eq!(client.send("get note").await.unwrap(), "ex marks the spot");
+    assert_eq!(client.send("exin note").await.unwrap(), "null");
+
+    assert_eq!(client.send("slowlog get").await.unwrap(), "[]");
+    assert_eq!(client.send("Config Get server.read_only").await.unwrap(), "false");
+    assert!(client.send("object Encoding note").await.unwrap().starts_with("compressed:"));
+    assert!(!client.send("latency Reset").await.unwrap().starts_with("error:"));
+    assert_eq!(client.send("memory usage Greeting decomp").await.unwrap().split(' ').count(), 3);
+    assert!(client.send("info").await.unwrap().starts_with('{'));
+    assert_eq!(client.send("del Greeting").await.unwrap(), "Greeting");
+}
+
+#[tokio::test]
+async fn close_is_case_sensitive_unless_configured() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    assert_eq!(client.send("close").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("PING").await.unwrap(), "pong");
+
+    let protocol = ProtocolConfig {
+        close_command_case_insensitive: true,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+    assert_eq!(client.send("close").await.unwrap(), "Closing connection");
+    assert!(client.send("PING").await.is_err());
+}
+
+#[tokio::test]
+async fn limits_allow_exactly_the_maximum_size() {
+    let store = StoreConfig {
+        max_key_bytes: Some(8),
+        max_value_bytes: Some(16),
+        ..StoreConfig::default()
+    };
+    let server = TestServer::start_with(store, ProtocolConfig::default()).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET kkkkkkkk v").await.unwrap(), "ok");
+    assert_eq!(client.send("SET kkkkkkkkk v").await.unwrap(), "error:KEYTOOLONG:key too long");
+    assert_eq!(client.send(&format!("SET k {}", "v".repeat(16))).await.unwrap(), "ok");
+    assert_eq!(client.send(&format!("SET k {}", "v".repeat(17))).await.unwrap(), "error:TOOLARGE:value too large");
+    assert_eq!(client.send("GET k").await.unwrap(), "v".repeat(16));
+
+    // RESTORE is held to the same limits as SET.
+    let blob = client.send("DUMP k").await.unwrap();
+    assert_eq!(client.send(&format!("RESTORE kkkkkkkkk 0 {}", blob)).await.unwrap(), "error:KEYTOOLONG:key too long");
+    assert_eq!(client.send("CONFIG SET store.max_value_bytes 15").await.unwrap(), "ok");
+    assert_eq!(client.send(&format!("RESTORE copy 0 {}", blob)).await.unwrap(), "error:TOOLARGE:value too large");
+}
+
+#[tokio::test]
+async fn keys_are_capped_at_1024_bytes_by_default() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send(&format!("SET {} v", "k".repeat(1024))).await.unwrap(), "ok");
+    assert_eq!(client.send(&format!("SET {} v", "k".repeat(1025))).await.unwrap(), "error:KEYTOOLONG:key too long");
+}
+
+#[tokio::test]
+async fn namespace_list_follows_inserts_and_deletes() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("NAMESPACE LIST").await.unwrap(), "{}");
+    for key in ["user:1", "user:2", "session:a", "plain"] {
+        assert_eq!(client.send(&format!("SET {} v", key)).await.unwrap(), "ok");
+    }
+    assert_eq!(client.send("namespace list").await.unwrap(), r#"{"session":1,"user":2}"#);
+
+    assert_eq!(client.send("DEL session:a").await.unwrap(), "session:a");
+    assert_eq!(client.send("SET user:3 v EX 100").await.unwrap(), "ok");
+    assert_eq!(client.send("NAMESPACE LIST").await.unwrap(), r#"{"user":3}"#);
+    assert_eq!(client.send("KEYS user:*").await.unwrap(), r#"["user:1","user:2","user:3"]"#);
+
+    let stats: serde_json::Value = serde_json::from_str(&client.send("NAMESPACE STATS user").await.unwrap()).unwrap();
+    assert_eq!(stats["keys"], 3);
+    assert!(stats["bytes"].as_u64().unwrap() > 0);
+    assert_eq!(stats["ttl_histogram"]["none"], 2);
+    assert_eq!(stats["ttl_histogram"]["under_10m"], 1);
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(info["top_namespaces"], serde_json::json!([["user", 3]]));
+
+    assert_eq!(client.send("NAMESPACE FLUSH user").await.unwrap(), "3");
+    assert_eq!(client.send("NAMESPACE LIST").await.unwrap(), "{}");
+    assert_eq!(client.send("GET plain").await.unwrap(), "v");
+    assert_eq!(client.send("NAMESPACE STATS").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn namespace_commands_follow_their_settings() {
+    let protocol = ProtocolConfig { namespace_separator: Some('/'), allow_flush: false, ..ProtocolConfig::default() };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET a/b:c v").await.unwrap(), "ok");
+    assert_eq!(client.send("NAMESPACE LIST").await.unwrap(), r#"{"a":1}"#);
+    assert_eq!(client.send("NAMESPACE FLUSH a").await.unwrap(), "error:INVALID_CMD:flush disabled");
+
+    let protocol = ProtocolConfig { namespace_separator: None, ..ProtocolConfig::default() };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+    assert_eq!(client.send("NAMESPACE LIST").await.unwrap(), "error:INVALID_CMD:namespaces disabled");
+}
+
+#[tokio::test]
+async fn metrics_returns_prometheus_text() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET a 1").await.unwrap(), "ok");
+    assert_eq!(client.send("SET b 2").await.unwrap(), "ok");
+    assert_eq!(client.send("GET nope").await.unwrap(), "null");
+
+    let text = client.send("METRICS").await.unwrap();
+    let lines = text.lines().map(|line| Ok(line.to_string()));
+    let scrape = prometheus_parse::Scrape::parse(lines).unwrap();
+
+    let sample = |name: &str| scrape.samples.iter().find(|sample| sample.metric == name);
+    assert!(matches!(sample("keyz_keys_count").unwrap().value, prometheus_parse::Value::Gauge(keys) if keys == 2.0));
+
+    let sets = scrape
+        .samples
+        .iter()
+        .find(|sample| sample.metric == "keyz_commands_total" && sample.labels.get("command") == Some("SET"))
+        .unwrap();
+    assert!(matches!(sets.value, prometheus_parse::Value::Counter(count) if count == 2.0));
+    assert!(scrape.docs.contains_key("keyz_hit_ratio"));
+
+    assert_eq!(client.send("METRICS now").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+// hits, misses, sets, deletes, expirations and error responses, in that
+// order, as INFO and METRICS report them.
+async fn counters(client: &mut KeyzClient) -> [u64; 6] {
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    let metric = |name: &str| info["metrics"][name].as_u64().unwrap();
+
+    let text = client.send("METRICS").await.unwrap();
+    let scrape = prometheus_parse::Scrape::parse(text.lines().map(|line| Ok(line.to_string()))).unwrap();
+    let errors = scrape
+        .samples
+        .iter()
+        .filter(|sample| sample.metric == "keyz_errors_total")
+        .map(|sample| match sample.value {
+            prometheus_parse::Value::Counter(count) => count as u64,
+            _ => 0,
+        })
+        .sum();
+
+    [metric("hits"), metric("misses"), metric("sets"), metric("deletes"), metric("expirations"), errors]
+}
+
+#[tokio::test]
+async fn each_operation_counts_only_its_own_metric() {
+    let protocol = ProtocolConfig { allow_debug: true, ..ProtocolConfig::default() };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    let steps = [
+        ("GET name", [0, 1, 0, 0, 0, 0]),
+        ("SET name value", [0, 0, 1, 0, 0, 0]),
+        ("GET name", [1, 0, 0, 0, 0, 0]),
+        ("DEL name", [0, 0, 0, 1, 0, 0]),
+        ("NOSUCH thing", [0, 0, 0, 0, 0, 1]),
+        ("DEBUG PURGE", [0, 0, 0, 0, 1, 0]),
+    ];
+
+    // Left to expire for DEBUG PURGE.
+    assert_eq!(client.send("PSETEX short 20 value").await.unwrap(), "ok");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    for (command, expected) in steps {
+        let before = counters(&mut client).await;
+        client.send(command).await.unwrap();
+        let after = counters(&mut client).await;
+
+        let delta: Vec<u64> = after.iter().zip(before).map(|(after, before)| after - before).collect();
+        assert_eq!(delta, expected, "{}", command);
+    }
+}
+
+#[tokio::test]
+async fn a_panicking_command_closes_only_its_own_connection() {
+    for command_timeout_secs in [None, Some(5)] {
+        let protocol = ProtocolConfig {
+            allow_debug: true,
+            command_timeout_secs,
+            ..ProtocolConfig::default()
+        };
+        let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+        let mut bystander = server.connect().await;
+        assert_eq!(bystander.send("SET k v").await.unwrap(), "ok");
+
+        let mut client = server.connect().await;
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.send("DEBUG PANIC")).await;
+        assert!(closed.expect("connection was left hanging").is_err());
+
+        assert_eq!(bystander.send("GET k").await.unwrap(), "v");
+        let mut client = server.connect().await;
+        let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+        assert_eq!(info["panics"], 1);
+    }
+}
+
+#[tokio::test]
+async fn a_panic_holding_the_database_lock_leaves_it_usable() {
+    let protocol = ProtocolConfig { allow_debug: true, ..ProtocolConfig::default() };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut bystander = server.connect().await;
+    assert_eq!(bystander.send("SET k v").await.unwrap(), "ok");
+
+    let mut client = server.connect().await;
+    let closed = tokio::time::timeout(Duration::from_secs(5), client.send("DEBUG PANIC LOCKED")).await;
+    assert!(closed.expect("connection was left hanging").is_err());
+
+    assert_eq!(bystander.send("GET k").await.unwrap(), "v");
+    let mut client = server.connect().await;
+    assert_eq!(client.send("SET k w").await.unwrap(), "ok");
+    assert_eq!(client.send("GET k").await.unwrap(), "w");
+    assert_eq!(bystander.send("DEL k").await.unwrap(), "k");
+}
+
+#[tokio::test]
+async fn msetnx_sets_all_pairs_or_none() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("MSETNX a 1 b 2").await.unwrap(), "1");
+    assert_eq!(client.send("GET a").await.unwrap(), "1");
+    assert_eq!(client.send("GET b").await.unwrap(), "2");
+
+    assert_eq!(client.send("MSETNX c 3 b 20").await.unwrap(), "0");
+    assert_eq!(client.send("GET c").await.unwrap(), "null");
+    assert_eq!(client.send("GET b").await.unwrap(), "2");
+
+    // An expired key counts as absent.
+    assert_eq!(client.send("PSETEX gone 1 v").await.unwrap(), "ok");
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(client.send("MSETNX gone back d 4").await.unwrap(), "1");
+    assert_eq!(client.send("GET gone").await.unwrap(), "back");
+
+    assert_eq!(client.send("MSETNX e").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("MSETNX e 5 f").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[test]
+fn concurrent_msetnx_calls_never_partly_succeed() {
+    let store: Store = Store::new(&StoreConfig::default());
+
+    for round in 0..50 {
+        // Every caller wants the shared key plus one of its own, so exactly
+        // one of them may win, and only the winner's own key may exist.
+        let winners: Vec<usize> = thread::scope(|scope| {
+            let calls: Vec<_> = (0..8)
+                .map(|caller| {
+                    let store = store.clone();
+                    scope.spawn(move || {
+                        let entries = vec![
+                            (format!("own:{}:{}", round, caller), b"x".to_vec()),
+                            (format!("shared:{}", round), caller.to_string().into_bytes()),
+                        ];
+                        store.insert_all_if_absent(entries).unwrap().then_some(caller)
+                    })
+                })
+                .collect();
+            calls.into_iter().filter_map(|call| call.join().unwrap()).collect()
+        });
+
+        assert_eq!(winners.len(), 1, "round {}: {:?}", round, winners);
+        let winner = winners[0];
+        assert_eq!(store.get(&format!("shared:{}", round)).unwrap(), Some(winner.to_string().into_bytes()));
+        for caller in 0..8 {
+            let own = store.get(&format!("own:{}:{}", round, caller)).unwrap();
+            assert_eq!(own.is_some(), caller == winner, "round {} caller {}", round, caller);
+        }
+    }
+}
+
+#[test]
+fn racing_gets_and_deletes_lose_and_resurrect_nothing() {
+    let store: Store = Store::new(&StoreConfig::default());
+    let done = std::sync::atomic::AtomicBool::new(false);
+    const ROUNDS: usize = 500;
+
+    let deleted: usize = thread::scope(|scope| {
+        // Each value is written once the last one is gone, so every write
+        // must be deleted exactly once.
+        let writer = scope.spawn(|| {
+            for n in 0..ROUNDS {
+                store.insert("key".to_string(), n.to_string().into_bytes(), 0).unwrap();
+                while store.get("key").unwrap().is_some() {
+                    thread::yield_now();
+                }
+            }
+            done.store(true, std::sync::atomic::Ordering::SeqCst);
+        });
+
+        let deleters: Vec<_> = (0..3)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut deleted = 0;
+                    while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                        deleted += store.delete("key").is_some() as usize;
+                        thread::yield_now();
+                    }
+                    deleted
+                })
+            })
+            .collect();
+
+        // A reader never sees a value older than one it has already seen.
+        for _ in 0..2 {
+            scope.spawn(|| {
+                let mut latest = 0;
+                while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                    if let Some(value) = store.get("key").unwrap() {
+                        let n: usize = String::from_utf8(value).unwrap().parse().unwrap();
+                        assert!(n >= latest, "{} after {}", n, latest);
+                        latest = n;
+                    }
+                    thread::yield_now();
+                }
+            });
+        }
+
+        writer.join().unwrap();
+        deleters.into_iter().map(|deleter| deleter.join().unwrap()).sum()
+    });
+
+    assert_eq!(deleted, ROUNDS);
+    assert_eq!(store.get("key").unwrap(), None);
+    assert_eq!(store.delete("key"), None);
+}
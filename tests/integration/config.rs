@@ -0,0 +1,291 @@
+use std::fs;
+use std::path::PathBuf;
+
+use keyz::config::{ConfigLookup, ConfigSource, StoreConfig};
+use keyz::server::helpers;
+use keyz::server::store::Store;
+use keyz::{Config, Server};
+use tokio::net::TcpStream;
+
+// A fresh directory per test, so includes resolve against it.
+fn config_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("keyz-config-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("conf.d")).unwrap();
+    dir
+}
+
+#[test]
+fn later_files_override_earlier_ones_field_by_field() {
+    let dir = config_dir("precedence");
+    fs::write(
+        dir.join("conf.d/base.toml"),
+        "[server]\nport = 7000\nread_only = true\n\n[store]\ndatabases = 4\nmax_key_bytes = 64\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("keyz.toml"),
+        "include = [\"conf.d/base.toml\"]\n\n[server]\nport = 7001\n\n[store]\nmax_key_bytes = 128\n",
+    )
+    .unwrap();
+    fs::write(dir.join("local.toml"), "[store]\ndatabases = 8\n").unwrap();
+
+    let (config, source) =
+        Config::load_from_paths(&[dir.join("keyz.toml"), dir.join("local.toml")]).unwrap();
+
+    assert_eq!(config.server.port, Some(7001));
+    assert!(config.server.read_only);
+    assert_eq!(config.store.max_key_bytes, Some(128));
+    assert_eq!(config.store.databases, 8);
+    assert_eq!(
+        source.chain(),
+        [dir.join("conf.d/base.toml"), dir.join("keyz.toml"), dir.join("local.toml")]
+    );
+}
+
+#[test]
+fn a_missing_include_is_an_error() {
+    let dir = config_dir("missing");
+    fs::write(dir.join("keyz.toml"), "include = [\"conf.d/nope.toml\"]\n").unwrap();
+
+    let err = Config::load_from(&dir.join("keyz.toml")).err().unwrap().to_string();
+    assert!(err.contains("failed to read") && err.contains("nope.toml"), "{}", err);
+
+    // A missing top-level file still means defaults.
+    let (_, source) = Config::load_from(&dir.join("absent.toml")).unwrap();
+    assert!(matches!(source, ConfigSource::Defaults));
+}
+
+#[test]
+fn include_cycles_are_rejected() {
+    let dir = config_dir("cycle");
+    fs::write(dir.join("keyz.toml"), "include = \"conf.d/a.toml\"\n").unwrap();
+    fs::write(dir.join("conf.d/a.toml"), "include = [\"b.toml\"]\n").unwrap();
+    fs::write(dir.join("conf.d/b.toml"), "include = [\"../keyz.toml\"]\n").unwrap();
+
+    let err = Config::load_from(&dir.join("keyz.toml")).err().unwrap().to_string();
+    assert!(err.contains("config include cycle"), "{}", err);
+
+    // Including the same file from two places is not a cycle.
+    fs::write(dir.join("conf.d/b.toml"), "[store]\ndatabases = 2\n").unwrap();
+    fs::write(dir.join("keyz.toml"), "include = [\"conf.d/a.toml\", \"conf.d/b.toml\"]\n").unwrap();
+    let (config, _) = Config::load_from(&dir.join("keyz.toml")).unwrap();
+    assert_eq!(config.store.databases, 2);
+}
+
+#[test]
+fn unknown_keys_in_included_files_are_still_rejected() {
+    let dir = config_dir("unknown");
+    fs::write(dir.join("conf.d/base.toml"), "[store]\nmax_ttl_sec = 10\n").unwrap();
+    fs::write(dir.join("keyz.toml"), "include = [\"conf.d/base.toml\"]\n").unwrap();
+
+    let err = Config::load_from(&dir.join("keyz.toml")).err().unwrap().to_string();
+    assert!(err.contains("did you mean `max_ttl_secs`"), "{}", err);
+}
+
+#[test]
+fn misspelled_keys_suggest_the_closest_key_in_their_section() {
+    let cases = [
+        ("server", "prot = 7000", "prot", "port"),
+        ("server", "read_onyl = true", "read_onyl", "read_only"),
+        ("server", "tcp_keepalive_sec = 60", "tcp_keepalive_sec", "tcp_keepalive_secs"),
+        ("protocol", "structured_error = true", "structured_error", "structured_errors"),
+        ("protocol", "max_mesage_bytes = 10", "max_mesage_bytes", "max_message_bytes"),
+        ("protocol", "frame_timeout_sec = 5", "frame_timeout_sec", "frame_timeout_secs"),
+        ("store", "max_ttl_sec = 10", "max_ttl_sec", "max_ttl_secs"),
+        ("store", "databses = 2", "databses", "databases"),
+        ("store", "max_value_byte = 10", "max_value_byte", "max_value_bytes"),
+        ("logging", "levle = \"info\"", "levle", "level"),
+        ("logging", "access_log_keeps = 3", "access_log_keeps", "access_log_keep"),
+        ("replication", "replicate_form = \"a:1\"", "replicate_form", "replicate_from"),
+        ("prometheus", "enable = true", "enable", "enabled"),
+        ("otel", "endpont = \"x\"", "endpont", "endpoint"),
+    ];
+
+    for (section, line, typo, expected) in cases {
+        let toml = format!("[{}]\n{}\n", section, line);
+        let err = Config::from_toml_str(&toml).err().unwrap().to_string();
+        let wanted = format!("unknown key `{}` in [{}]; did you mean `{}`?", typo, section, expected);
+        assert_eq!(err, wanted, "{}", toml);
+    }
+
+    let err = Config::from_toml_str("[sever]\nport = 1\n").err().unwrap().to_string();
+    assert_eq!(err, "unknown section [sever]; did you mean [server]?");
+    let err = Config::from_toml_str("[store]\nwhatever = 1\n").err().unwrap().to_string();
+    assert_eq!(err, "unknown key `whatever` in [store]");
+}
+
+#[test]
+fn lenient_loading_drops_unknown_keys_with_a_warning() {
+    let toml = "[store]\nmax_ttl_sec = 10\ndatabases = 2\n";
+    assert!(Config::from_toml_str(toml).is_err());
+
+    let config = Config::from_toml_str_lenient(toml).unwrap();
+    assert_eq!(config.store.databases, 2);
+    assert_eq!(config.store.max_ttl_secs, Config::default().store.max_ttl_secs);
+    assert_eq!(
+        config.warnings,
+        vec!["ignoring unknown key `max_ttl_sec` in [store]; did you mean `max_ttl_secs`?"]
+    );
+}
+
+#[test]
+fn serialized_configs_parse_back_to_the_same_config() {
+    let mut config = Config::default();
+    config.server.listeners = vec!["127.0.0.1:7001".into(), "127.0.0.1:7002".into()];
+    config.server.read_only = true;
+    config.protocol.command_timeout_secs = Some(3);
+    config.protocol.command_timeout_response = "error:slow\n\n[store]\n".into();
+    config.protocol.namespace_separator = None;
+    config.store.max_value_bytes = Some(4096);
+    config.store.max_ttl_secs = Some(60);
+    config.store.seed_path = Some("seed.txt".into());
+    config.logging.access_log_path = Some("access.log".into());
+    config.replication.replicate_from = Some("127.0.0.1:7667".into());
+
+    for config in [Config::default(), config] {
+        let toml = config.to_toml_string().unwrap();
+        assert_eq!(Config::from_toml_str(&toml).unwrap(), config, "{}", toml);
+    }
+}
+
+#[test]
+fn unset_optional_fields_are_written_as_comments() {
+    let toml = Config::default().to_toml_string().unwrap();
+
+    assert!(toml.contains("[store]\nslow_log_max_len = 128\n"), "{}", toml);
+    assert!(toml.contains("# max_value_bytes = 1048576\n"), "{}", toml);
+    assert!(toml.contains("[replication]\n# replicate_from = "), "{}", toml);
+    assert!(!toml.contains("# max_key_bytes"), "{}", toml);
+    assert!(toml.contains("namespace_separator = \":\"\n"), "{}", toml);
+
+    // Uncommenting an example still gives a valid config.
+    let uncommented = toml.replace("# max_value_bytes", "max_value_bytes");
+    assert_eq!(Config::from_toml_str(&uncommented).unwrap().store.max_value_bytes, Some(1048576));
+
+    let disabled = toml.replace("namespace_separator = \":\"", "namespace_separator = \"\"");
+    assert_eq!(Config::from_toml_str(&disabled).unwrap().protocol.namespace_separator, None);
+    let invalid = toml.replace("namespace_separator = \":\"", "namespace_separator = \"::\"");
+    assert!(Config::from_toml_str(&invalid).is_err());
+}
+
+#[test]
+fn ttl_jitter_is_limited_to_half_the_ttl() {
+    let config = Config::from_toml_str("[store]\nttl_jitter_percent = 10.0\n").unwrap();
+    assert_eq!(config.store.ttl_jitter_percent, 10.0);
+    assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+
+    let config = Config::from_toml_str("[store]\nttl_jitter_percent = 30.0\n").unwrap();
+    assert_eq!(config.warnings.len(), 1, "{:?}", config.warnings);
+    assert!(config.warnings[0].contains("ttl_jitter_percent"), "{:?}", config.warnings);
+
+    for invalid in ["-1.0", "50.5", "nan"] {
+        let toml = format!("[store]\nttl_jitter_percent = {}\n", invalid);
+        let err = Config::from_toml_str(&toml).unwrap_err().to_string();
+        assert!(err.contains("between 0 and 50"), "{}: {}", invalid, err);
+    }
+}
+
+#[test]
+fn jittered_ttls_spread_evenly_over_the_range() {
+    let store: Store = Store::new(&StoreConfig {
+        ttl_jitter_percent: 10.0,
+        ..StoreConfig::default()
+    });
+
+    // Ten 1.2s-wide buckets over 54s..66s.
+    let mut buckets = [0; 10];
+    for i in 0..1000 {
+        let key = format!("key{}", i);
+        store.insert(key.clone(), b"v".to_vec(), 60).unwrap();
+
+        let ttl_ms = store.expires_in_ms(&key).unwrap();
+        assert!((53_900..=66_000).contains(&ttl_ms), "{}", ttl_ms);
+        buckets[((ttl_ms.max(54_000) - 54_000) / 1_200).min(9) as usize] += 1;
+    }
+
+    // 100 expected in each; a fair spread stays far inside these bounds.
+    for count in buckets {
+        assert!((50..=150).contains(&count), "{:?}", buckets);
+    }
+}
+
+// The port a loaded file sets, or part of the error it fails with.
+type Expected = Result<Option<u16>, &'static str>;
+
+#[test]
+fn malformed_config_files_load_or_fail_clearly() {
+    let cases: &[(&str, &[u8], Expected)] = &[
+        ("bom", b"\xef\xbb\xbf[server]\nport = 7001\n", Ok(Some(7001))),
+        ("crlf", b"[server]\r\nport = 7002\r\n\r\n[store]\r\ndatabases = 2\r\n", Ok(Some(7002))),
+        ("blank", b"  \n\t\r\n", Ok(None)),
+        ("comments", b"# nothing set yet\n# [server]\n", Ok(None)),
+        ("bom-only", b"\xef\xbb\xbf", Ok(None)),
+        ("latin1", b"# caf\xe9\n[server]\nport = 7003\n", Err("is not UTF-8 text")),
+        ("bom-invalid", b"\xef\xbb\xbf[server]\nport = \"x\"\n", Err("invalid config")),
+        ("invalid-blank", b"\n\n[store]\ndatabases = 0\n", Err("store.databases")),
+    ];
+
+    for (name, contents, expected) in cases {
+        let dir = config_dir(&format!("malformed-{}", name));
+        fs::write(dir.join("keyz.toml"), contents).unwrap();
+
+        match (Config::load_from(&dir.join("keyz.toml")), expected) {
+            (Ok((config, _)), Ok(port)) => assert_eq!(config.server.port, *port, "{}", name),
+            (Err(e), Err(text)) => assert!(e.to_string().contains(text), "{}: {}", name, e),
+            (result, _) => panic!("{}: unexpected {:?}", name, result.map(|(config, _)| config.server.port)),
+        }
+    }
+
+    let dir = config_dir("malformed-directory");
+    fs::create_dir(dir.join("keyz.toml")).unwrap();
+    let err = Config::load_via(&[dir.join("keyz.toml")], ConfigLookup::Env).err().unwrap().to_string();
+    let expected = format!(
+        "failed to read {}: it is a directory, not a file (path from KEYZ_CONFIG)",
+        dir.join("keyz.toml").display()
+    );
+    assert_eq!(err, expected);
+
+    let err = Config::load_via(&[dir.join("keyz.toml")], ConfigLookup::Default).err().unwrap().to_string();
+    assert!(err.ends_with("(path from the default keyz.toml)"), "{}", err);
+}
+
+#[test]
+fn blank_input_is_validated_defaults() {
+    assert_eq!(Config::from_toml_str(" \n# only a comment\n").unwrap(), Config::default());
+    assert_eq!(Config::from_toml_str("\u{feff}[server]\nport = 7004\n").unwrap().server.port, Some(7004));
+}
+
+#[tokio::test]
+async fn every_listener_serves_the_same_store() {
+    let mut config = Config::default();
+    config.server.listeners = vec!["127.0.0.1:0".into(), "127.0.0.1:0".into()];
+    let server = std::sync::Arc::new(Server::bind(&config).await.unwrap());
+    let addrs = server.local_addrs();
+    assert_eq!(addrs.len(), 2);
+    assert_ne!(addrs[0], addrs[1]);
+
+    let running = server.clone();
+    tokio::spawn(async move { running.run().await });
+
+    let mut first = TcpStream::connect(addrs[0]).await.unwrap();
+    let mut second = TcpStream::connect(addrs[1]).await.unwrap();
+    helpers::write_message(&mut first, "SET shared hello").await.unwrap();
+    assert_eq!(helpers::read_message(&mut first).await.unwrap(), "ok");
+    helpers::write_message(&mut second, "GET shared").await.unwrap();
+    assert_eq!(helpers::read_message(&mut second).await.unwrap(), "hello");
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn a_listener_that_cannot_bind_is_named() {
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let taken_addr = taken.local_addr().unwrap();
+
+    let mut config = Config::default();
+    config.server.listeners = vec!["127.0.0.1:0".into(), taken_addr.to_string()];
+    let err = Server::bind(&config).await.err().unwrap().to_string();
+
+    assert!(err.starts_with(&format!("failed to bind {}:", taken_addr)), "{}", err);
+}
@@ -0,0 +1,309 @@
+use std::time::Duration;
+
+use keyz::config::{ProtocolConfig, StoreConfig};
+use keyz::server::replication::replicate_from;
+use keyz::server::store::{ListEnd, Store};
+use tokio::time;
+
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn databases_are_isolated() {
+    let server = TestServer::start().await;
+    let mut first = server.connect().await;
+    let mut second = server.connect().await;
+
+    assert_eq!(first.send("SET shared zero").await.unwrap(), "ok");
+    assert_eq!(first.send("SELECT 3").await.unwrap(), "ok");
+    assert_eq!(first.send("GET shared").await.unwrap(), "null");
+    assert_eq!(first.send("SET shared three").await.unwrap(), "ok");
+    assert_eq!(first.send("KEYS *").await.unwrap(), r#"["shared"]"#);
+
+    // Each connection keeps its own selection.
+    assert_eq!(second.send("GET shared").await.unwrap(), "zero");
+    assert_eq!(second.send("SELECT 3").await.unwrap(), "ok");
+    assert_eq!(second.send("DEL shared").await.unwrap(), "shared");
+
+    assert_eq!(first.send("GET shared").await.unwrap(), "null");
+    assert_eq!(first.send("SELECT 0").await.unwrap(), "ok");
+    assert_eq!(first.send("GET shared").await.unwrap(), "zero");
+}
+
+#[tokio::test]
+async fn select_rejects_unknown_databases() {
+    let store = StoreConfig {
+        databases: 2,
+        ..StoreConfig::default()
+    };
+    let server = TestServer::start_with(store, ProtocolConfig::default()).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SELECT 1").await.unwrap(), "ok");
+    assert_eq!(client.send("SELECT 2").await.unwrap(), "error:INVALID_CMD:db index out of range");
+    assert_eq!(client.send("SELECT x").await.unwrap(), "error:INVALID_CMD:db index invalid");
+    assert_eq!(client.send("SELECT").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("CONFIG GET store.databases").await.unwrap(), "2");
+}
+
+#[tokio::test]
+async fn flush_clears_the_selected_database_and_flushall_every_one() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    for db in 0..3 {
+        assert_eq!(client.send(&format!("SELECT {}", db)).await.unwrap(), "ok");
+        assert_eq!(client.send("SET a 1").await.unwrap(), "ok");
+        assert_eq!(client.send("SET b 2").await.unwrap(), "ok");
+    }
+
+    assert_eq!(client.send("SELECT 1").await.unwrap(), "ok");
+    assert_eq!(client.send("FLUSH").await.unwrap(), "ok");
+    assert_eq!(client.send("KEYS *").await.unwrap(), "[]");
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(info["keys"], 4);
+    assert_eq!(
+        info["databases"],
+        serde_json::json!([{"db": 0, "keys": 2, "expires": 0}, {"db": 2, "keys": 2, "expires": 0}])
+    );
+
+    assert_eq!(client.send("FLUSHALL").await.unwrap(), "ok");
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(info["keys"], 0);
+    assert_eq!(info["databases"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn swapdb_exchanges_two_databases() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    let mut other = server.connect().await;
+
+    assert_eq!(client.send("SET a zero").await.unwrap(), "ok");
+    assert_eq!(client.send("SELECT 1").await.unwrap(), "ok");
+    assert_eq!(client.send("SET a one EX 100").await.unwrap(), "ok");
+    assert_eq!(client.send("SET b one").await.unwrap(), "ok");
+
+    assert_eq!(other.send("swapdb 0 1").await.unwrap(), "ok");
+    assert_eq!(other.send("GET a").await.unwrap(), "one");
+    assert_eq!(other.send("KEYS *").await.unwrap(), "[\"a\",\"b\"]");
+    assert_eq!(client.send("GET a").await.unwrap(), "zero");
+    assert_eq!(client.send("GET b").await.unwrap(), "null");
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(
+        info["databases"],
+        serde_json::json!([{"db": 0, "keys": 2, "expires": 1}, {"db": 1, "keys": 1, "expires": 0}])
+    );
+
+    assert_eq!(client.send("SWAPDB 1 1").await.unwrap(), "ok");
+    assert_eq!(client.send("SWAPDB 0 16").await.unwrap(), "error:INVALID_CMD:db index out of range");
+    assert_eq!(client.send("SWAPDB 0 x").await.unwrap(), "error:INVALID_CMD:db index invalid");
+    assert_eq!(client.send("SWAPDB 0").await.unwrap(), "error:INVALID_CMD:db index invalid");
+
+    assert_eq!(client.send("FLUSHDB").await.unwrap(), "ok");
+    assert_eq!(other.send("GET a").await.unwrap(), "one");
+    assert_eq!(client.send("KEYS *").await.unwrap(), "[]");
+}
+
+#[tokio::test]
+async fn select_survives_the_command_timeout_path() {
+    let protocol = ProtocolConfig {
+        command_timeout_secs: Some(5),
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SELECT 5").await.unwrap(), "ok");
+    assert_eq!(client.send("SET k v").await.unwrap(), "ok");
+    assert_eq!(client.send("SELECT 0").await.unwrap(), "ok");
+    assert_eq!(client.send("GET k").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn replicas_follow_every_database() {
+    let primary = TestServer::start().await;
+    let mut client = primary.connect().await;
+    assert_eq!(client.send("SELECT 2").await.unwrap(), "ok");
+    assert_eq!(client.send("SET before sync").await.unwrap(), "ok");
+
+    let replica = Store::new(&StoreConfig::default());
+    let task = tokio::spawn(replicate_from(primary.addr(), replica.clone()));
+
+    let mut db2 = replica.clone();
+    db2.select(2).unwrap();
+
+    wait_for(|| db2.get("before").unwrap() == Some(b"sync".to_vec())).await;
+
+    assert_eq!(client.send("SET after stream").await.unwrap(), "ok");
+    wait_for(|| db2.get("after").unwrap() == Some(b"stream".to_vec())).await;
+    assert_eq!(replica.get("after").unwrap(), None);
+
+    let mut db3 = replica.clone();
+    db3.select(3).unwrap();
+    assert_eq!(client.send("SWAPDB 2 3").await.unwrap(), "ok");
+    wait_for(|| db3.get("after").unwrap() == Some(b"stream".to_vec())).await;
+    assert_eq!(db2.get("after").unwrap(), None);
+
+    assert_eq!(client.send("SELECT 3").await.unwrap(), "ok");
+    assert_eq!(client.send("EXPIRE after 100").await.unwrap(), "1");
+    wait_for(|| db3.expires_in("after").is_some()).await;
+    assert_eq!(client.send("PSETEX exact 90000 v").await.unwrap(), "ok");
+    wait_for(|| db3.expires_in_ms("exact").is_some_and(|ttl_ms| ttl_ms <= 90_000)).await;
+
+    assert_eq!(client.send("FLUSH").await.unwrap(), "ok");
+    wait_for(|| db2.stats().keys == 0).await;
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn replicas_rebuild_lists_in_order() {
+    let primary = TestServer::start().await;
+    let mut client = primary.connect().await;
+    assert_eq!(client.send("RPUSH queue b").await.unwrap(), "1");
+    assert_eq!(client.send("RPUSH queue c").await.unwrap(), "2");
+    assert_eq!(client.send("RPUSH queue d").await.unwrap(), "3");
+    assert_eq!(client.send("LPUSH queue a").await.unwrap(), "4");
+    assert_eq!(client.send("EXPIRE queue 100").await.unwrap(), "1");
+
+    let replica = Store::new(&StoreConfig::default());
+    let task = tokio::spawn(replicate_from(primary.addr(), replica.clone()));
+
+    wait_for(|| replica.list_len("queue").unwrap() == 4).await;
+    assert_eq!(replica.list_range("queue", 0, -1).unwrap(), [b"a", b"b", b"c", b"d"]);
+    assert!(replica.expires_in("queue").is_some());
+
+    assert_eq!(client.send("LPOP queue").await.unwrap(), "a");
+    assert_eq!(client.send("RPOP queue").await.unwrap(), "d");
+    assert_eq!(client.send("RPUSH queue with spaces").await.unwrap(), "3");
+    wait_for(|| replica.list_range("queue", 0, -1).unwrap() == [&b"b"[..], b"c", b"with spaces"]).await;
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn replicas_rebuild_hashes() {
+    let primary = TestServer::start().await;
+    let mut client = primary.connect().await;
+    assert_eq!(client.send("HSET user name ada").await.unwrap(), "1");
+    assert_eq!(client.send("HSET user age 36").await.unwrap(), "1");
+    assert_eq!(client.send("EXPIRE user 100").await.unwrap(), "1");
+
+    let replica = Store::new(&StoreConfig::default());
+    let task = tokio::spawn(replicate_from(primary.addr(), replica.clone()));
+
+    wait_for(|| replica.hash_get_all("user").unwrap().len() == 2).await;
+    assert_eq!(replica.hash_get("user", "name").unwrap(), Some(b"ada".to_vec()));
+    assert!(replica.expires_in("user").is_some());
+
+    assert_eq!(client.send("HDEL user age").await.unwrap(), "1");
+    assert_eq!(client.send("HSET user bio likes engines").await.unwrap(), "1");
+    wait_for(|| replica.hash_get("user", "age").unwrap().is_none()).await;
+    wait_for(|| replica.hash_get("user", "bio").unwrap() == Some(b"likes engines".to_vec())).await;
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn replicas_keep_binary_values_and_integers() {
+    let primary = TestServer::start().await;
+    let binary = vec![0, 0xff, b' ', 0xfe, b'\n'];
+    primary.store().insert("blob".into(), binary.clone(), 0).unwrap();
+    let mut client = primary.connect().await;
+    assert_eq!(client.send("SET hits 40").await.unwrap(), "ok");
+    assert_eq!(client.send("INCR hits").await.unwrap(), "41");
+
+    let replica = Store::new(&StoreConfig::default());
+    let task = tokio::spawn(replicate_from(primary.addr(), replica.clone()));
+
+    wait_for(|| replica.get("hits").unwrap() == Some(b"41".to_vec())).await;
+    assert_eq!(replica.debug_entry("hits").unwrap().encoding, "int");
+    assert_eq!(replica.get("blob").unwrap(), Some(binary.clone()));
+
+    primary.store().push("queue", binary.clone(), ListEnd::Back).unwrap();
+    primary.store().hash_set("user", "avatar".into(), binary.clone()).unwrap();
+    assert_eq!(client.send("INCR hits").await.unwrap(), "42");
+    wait_for(|| replica.get("hits").unwrap() == Some(b"42".to_vec())).await;
+    assert_eq!(replica.debug_entry("hits").unwrap().encoding, "int");
+    assert_eq!(replica.hash_get("user", "avatar").unwrap(), Some(binary.clone()));
+    assert_eq!(replica.list_range("queue", 0, -1).unwrap(), [binary]);
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn replicas_rebuild_sets_and_get_stored_intersections() {
+    let primary = TestServer::start().await;
+    let mut client = primary.connect().await;
+    assert_eq!(client.send("SADD left a").await.unwrap(), "1");
+    assert_eq!(client.send("SADD left b").await.unwrap(), "1");
+    assert_eq!(client.send("SADD right b").await.unwrap(), "1");
+
+    let replica = Store::new(&StoreConfig::default());
+    let task = tokio::spawn(replicate_from(primary.addr(), replica.clone()));
+
+    wait_for(|| replica.set_len("left").unwrap() == 2 && replica.set_len("right").unwrap() == 1).await;
+
+    assert_eq!(client.send("SINTERSTORE both left right").await.unwrap(), "1");
+    assert_eq!(client.send("SREM left a").await.unwrap(), "1");
+    wait_for(|| replica.set_len("left").unwrap() == 1).await;
+    assert_eq!(replica.set_members("both").unwrap().into_iter().collect::<Vec<_>>(), ["b"]);
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn replicas_rebuild_sorted_sets_with_exact_scores() {
+    let primary = TestServer::start().await;
+    let mut client = primary.connect().await;
+    assert_eq!(client.send("ZADD board 0.1 ada").await.unwrap(), "1");
+    assert_eq!(client.send("ZADD board -inf grace").await.unwrap(), "1");
+
+    let replica = Store::new(&StoreConfig::default());
+    let task = tokio::spawn(replicate_from(primary.addr(), replica.clone()));
+
+    wait_for(|| replica.zset_range("board", 0, -1).unwrap().len() == 2).await;
+    assert_eq!(replica.zset_score("board", "ada").unwrap(), Some(0.1));
+    assert_eq!(replica.zset_score("board", "grace").unwrap(), Some(f64::NEG_INFINITY));
+
+    assert_eq!(client.send("ZREM board grace").await.unwrap(), "1");
+    assert_eq!(client.send("ZADD board 1e300 ada").await.unwrap(), "0");
+    wait_for(|| replica.zset_score("board", "ada").unwrap() == Some(1e300)).await;
+    assert_eq!(replica.zset_rank("board", "grace").unwrap(), None);
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn replicas_rebuild_bit_arrays() {
+    let primary = TestServer::start().await;
+    let mut client = primary.connect().await;
+    assert_eq!(client.send("SETBIT flags 3 1").await.unwrap(), "0");
+    assert_eq!(client.send("SETBIT flags 10 1").await.unwrap(), "0");
+
+    let replica = Store::new(&StoreConfig::default());
+    let task = tokio::spawn(replicate_from(primary.addr(), replica.clone()));
+
+    wait_for(|| replica.bit_count("flags", None).unwrap() == 2).await;
+    assert!(replica.get_bit("flags", 10).unwrap());
+
+    assert_eq!(client.send("BITOP NOT inverted flags").await.unwrap(), "2");
+    assert_eq!(client.send("SETBIT flags 10 0").await.unwrap(), "1");
+    wait_for(|| replica.bit_count("flags", None).unwrap() == 1).await;
+    assert_eq!(replica.bit_count("inverted", None).unwrap(), 14);
+    assert!(!replica.get_bit("inverted", 3).unwrap());
+
+    task.abort();
+}
+
+async fn wait_for(condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("condition not met in time");
+}
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::thread;
+use std::time::Duration;
+
+use keyz::config::StoreConfig;
+use keyz::server::store::{ListEnd, Store};
+use keyz::server::value_type::ValueData;
+
+fn sample_store() -> Store {
+    let store = Store::new(&StoreConfig { databases: 2, ..StoreConfig::default() });
+    store.insert("greeting".into(), b"hello world".to_vec(), 0).unwrap();
+    store.insert("session".into(), b"abc".to_vec(), 60).unwrap();
+    store.increment("visits", 7).unwrap();
+    store.push("queue", b"a".to_vec(), ListEnd::Back).unwrap();
+    store.push("queue", b"b c".to_vec(), ListEnd::Back).unwrap();
+    store.hash_set("user", "name".into(), b"ada".to_vec()).unwrap();
+    store.set_add("tags", "rust".into()).unwrap();
+    store.update_ttl("tags", 100).unwrap();
+
+    let mut other = store.clone();
+    other.select(1).unwrap();
+    other.insert("other".into(), b"db one".to_vec(), 0).unwrap();
+    store
+}
+
+#[test]
+fn iter_yields_every_live_key_of_the_selected_database() {
+    let store = sample_store();
+    store.insert_ms("short".into(), b"gone".to_vec(), 1).unwrap();
+    thread::sleep(Duration::from_millis(10));
+
+    let views: BTreeMap<_, _> = store.iter().collect();
+    assert_eq!(views.keys().collect::<Vec<_>>(), ["greeting", "queue", "session", "tags", "user", "visits"]);
+
+    assert_eq!(views["greeting"].bytes().unwrap(), b"hello world");
+    assert_eq!(views["greeting"].ttl(), None);
+    assert!(views["greeting"].stored_bytes() > 0);
+    assert_eq!(views["visits"].bytes().unwrap(), b"7");
+    assert!(views["session"].ttl().unwrap() > Duration::from_secs(59));
+
+    assert_eq!(views["queue"].type_name(), "list");
+    assert!(views["queue"].bytes().is_err());
+    assert!(matches!(views["queue"].value(), ValueData::List(items) if items.len() == 2));
+}
+
+#[test]
+fn iterating_alongside_writers_never_deadlocks() {
+    let store: Store = Store::new(&StoreConfig::default());
+    for n in 0..200 {
+        store.insert(format!("key:{}", n), b"value".to_vec(), 0).unwrap();
+    }
+
+    thread::scope(|scope| {
+        for writer in 0..4 {
+            let store = store.clone();
+            scope.spawn(move || {
+                for n in 0..500 {
+                    let key = format!("key:{}", (writer * 500 + n) % 300);
+                    match n % 3 {
+                        0 => drop(store.delete(&key)),
+                        1 => store.insert(key, b"again".to_vec(), 0).unwrap(),
+                        _ => drop(store.push(&format!("list:{}", writer), b"item".to_vec(), ListEnd::Back).unwrap()),
+                    }
+                }
+            });
+        }
+
+        for _ in 0..2 {
+            let store = store.clone();
+            scope.spawn(move || {
+                for _ in 0..20 {
+                    for (key, view) in store.iter() {
+                        // Writing from inside the loop is fine too: no lock
+                        // is held while the caller has a value.
+                        if key.starts_with("key:") {
+                            assert!(view.bytes().is_ok());
+                            store.insert(key, b"seen".to_vec(), 0).unwrap();
+                        }
+                    }
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn export_and_import_round_trip_every_type_and_database() {
+    let store = sample_store();
+    let mut file = Vec::new();
+    let frames = store.export(&mut file).unwrap();
+
+    let copy: Store = Store::new(&StoreConfig { databases: 2, ..StoreConfig::default() });
+    assert_eq!(copy.import(&mut Cursor::new(&file)).unwrap(), frames);
+
+    assert_eq!(copy.get("greeting").unwrap(), Some(b"hello world".to_vec()));
+    assert_eq!(copy.get("visits").unwrap(), Some(b"7".to_vec()));
+    assert_eq!(copy.debug_entry("visits").unwrap().encoding, "int");
+    assert_eq!(copy.list_range("queue", 0, -1).unwrap(), [&b"a"[..], b"b c"]);
+    assert_eq!(copy.hash_get("user", "name").unwrap(), Some(b"ada".to_vec()));
+    assert!(copy.set_contains("tags", "rust").unwrap());
+    assert!(copy.expires_in("session").unwrap() > 50);
+    assert!(copy.expires_in("tags").unwrap() > 90);
+    assert_eq!(copy.expires_in("greeting"), None);
+
+    let mut other = copy.clone();
+    other.select(1).unwrap();
+    assert_eq!(other.get("other").unwrap(), Some(b"db one".to_vec()));
+}
+
+#[test]
+fn import_refuses_a_file_cut_short() {
+    let mut file = Vec::new();
+    sample_store().export(&mut file).unwrap();
+    file.truncate(file.len() - 2);
+
+    let copy: Store = Store::new(&StoreConfig::default());
+    let e = copy.import(&mut Cursor::new(&file)).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert!(copy.import(&mut Cursor::new(b"\0\0\0\x05SET")).is_err());
+    assert_eq!(copy.import(&mut Cursor::new(b"")).unwrap(), 0);
+}
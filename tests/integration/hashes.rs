@@ -0,0 +1,99 @@
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn fields_are_set_read_and_deleted() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("HSET user name ada").await.unwrap(), "1");
+    assert_eq!(client.send("HSET user bio likes engines").await.unwrap(), "1");
+    assert_eq!(client.send("TYPE user").await.unwrap(), "hash");
+    assert_eq!(client.send("HGET user name").await.unwrap(), "ada");
+    assert_eq!(client.send("HGET user bio").await.unwrap(), "likes engines");
+    assert_eq!(client.send("HGET user age").await.unwrap(), "null");
+    assert_eq!(client.send("HGET nobody name").await.unwrap(), "null");
+    assert_eq!(client.send("HEXISTS user name").await.unwrap(), "1");
+    assert_eq!(client.send("HEXISTS user age").await.unwrap(), "0");
+
+    assert_eq!(client.send("HDEL user age").await.unwrap(), "0");
+    assert_eq!(client.send("HDEL user bio").await.unwrap(), "1");
+    assert_eq!(client.send("HEXISTS user bio").await.unwrap(), "0");
+
+    // The last field takes the key with it.
+    assert_eq!(client.send("HDEL user name").await.unwrap(), "1");
+    assert_eq!(client.send("TYPE user").await.unwrap(), "null");
+    assert_eq!(client.send("KEYS *").await.unwrap(), "[]");
+
+    assert_eq!(client.send("HSET user").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("HSET user name").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn hset_overwrites_an_existing_field() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("HSET user name ada").await.unwrap(), "1");
+    assert_eq!(client.send("HSET user name grace").await.unwrap(), "0");
+    assert_eq!(client.send("HGET user name").await.unwrap(), "grace");
+    assert_eq!(client.send("HKEYS user").await.unwrap(), r#"["name"]"#);
+}
+
+#[tokio::test]
+async fn hgetall_is_a_json_object_sorted_by_field() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("HGETALL user").await.unwrap(), "{}");
+    assert_eq!(client.send("HKEYS user").await.unwrap(), "[]");
+    assert_eq!(client.send("HVALS user").await.unwrap(), "[]");
+
+    for (field, value) in [("name", "ada"), ("age", "36"), ("quote", r#"say "hi""#)] {
+        client.send(&format!("HSET user {} {}", field, value)).await.unwrap();
+    }
+
+    let all = client.send("HGETALL user").await.unwrap();
+    assert_eq!(all, r#"{"age":"36","name":"ada","quote":"say \"hi\""}"#);
+    let parsed: serde_json::Value = serde_json::from_str(&all).unwrap();
+    assert_eq!(parsed["quote"], r#"say "hi""#);
+
+    assert_eq!(client.send("HKEYS user").await.unwrap(), r#"["age","name","quote"]"#);
+    assert_eq!(client.send("HVALS user").await.unwrap(), r#"["36","ada","say \"hi\""]"#);
+}
+
+#[tokio::test]
+async fn hash_and_other_commands_refuse_each_others_keys() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET name value").await.unwrap(), "ok");
+    assert_eq!(client.send("RPUSH queue x").await.unwrap(), "1");
+    for key in ["name", "queue"] {
+        assert_eq!(client.send(&format!("HSET {} f v", key)).await.unwrap(), "error:WRONGTYPE:wrong type");
+        assert_eq!(client.send(&format!("HGET {} f", key)).await.unwrap(), "error:WRONGTYPE:wrong type");
+        assert_eq!(client.send(&format!("HDEL {} f", key)).await.unwrap(), "error:WRONGTYPE:wrong type");
+        assert_eq!(client.send(&format!("HGETALL {}", key)).await.unwrap(), "error:WRONGTYPE:wrong type");
+    }
+
+    assert_eq!(client.send("HSET user name ada").await.unwrap(), "1");
+    assert_eq!(client.send("GET user").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("LLEN user").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("INCR user").await.unwrap(), "error:WRONGTYPE:wrong type");
+}
+
+#[tokio::test]
+async fn a_ttl_covers_the_whole_hash() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("HSET user name ada").await.unwrap(), "1");
+    assert_eq!(client.send("PEXPIRE user 50").await.unwrap(), "1");
+    assert_eq!(client.send("HSET user age 36").await.unwrap(), "1");
+    assert!(client.send("PTTL user").await.unwrap().parse::<u64>().unwrap() <= 50);
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(client.send("HGET user name").await.unwrap(), "null");
+    assert_eq!(client.send("HGETALL user").await.unwrap(), "{}");
+    assert_eq!(client.send("HSET user name grace").await.unwrap(), "1");
+    assert_eq!(client.send("PTTL user").await.unwrap(), "null");
+}
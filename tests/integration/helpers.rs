@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use keyz::config::{ProtocolConfig, StoreConfig};
+use keyz::server::{helpers, init, pubsub::PubSubHub, socket::SocketOptions, store::Store};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+pub struct TestServer {
+    addr: SocketAddr,
+    store: Store,
+    handle: JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start() -> TestServer {
+        TestServer::start_with(StoreConfig::default(), ProtocolConfig::default()).await
+    }
+
+    pub async fn start_with(store: StoreConfig, protocol: ProtocolConfig) -> TestServer {
+        let addr = helpers::socket_address_from_string_ip("127.0.0.1:0".to_string()).unwrap();
+        let listener = helpers::create_listener(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let store = Store::new(&store);
+        store.set_listeners([addr]);
+        store.set_max_payload_bytes(protocol.max_message_bytes);
+        if let Some(limit) = protocol.max_inflight_bytes {
+            store.set_max_inflight_bytes(limit);
+        }
+        let protocol = Arc::new(ArcSwap::from_pointee(protocol));
+
+        let handle = tokio::spawn({
+            let store = store.clone();
+            async move {
+                let pubsub = Arc::new(PubSubHub::default());
+                init::start(&listener, store, protocol, pubsub, &SocketOptions::default()).await
+            }
+        });
+
+        TestServer { addr, store, handle }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    pub async fn connect(&self) -> KeyzClient {
+        KeyzClient {
+            stream: TcpStream::connect(self.addr).await.unwrap(),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+pub struct KeyzClient {
+    stream: TcpStream,
+}
+
+impl KeyzClient {
+    pub async fn send(&mut self, command: &str) -> Result<String, Box<dyn Error>> {
+        helpers::write_message(&mut self.stream, command).await?;
+        helpers::read_message(&mut self.stream).await
+    }
+
+    // Waits for the next frame without sending anything, for pushed messages.
+    pub async fn read(&mut self) -> Result<String, Box<dyn Error>> {
+        helpers::read_message(&mut self.stream).await
+    }
+}
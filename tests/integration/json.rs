@@ -0,0 +1,75 @@
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn jset_updates_nested_fields_in_place() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(
+        client.send(r#"SET user:1 {"name": "ann", "tags": ["a"]}"#).await.unwrap(),
+        "ok"
+    );
+    assert_eq!(client.send(r#"JSET user:1 name "bob""#).await.unwrap(), "ok");
+    assert_eq!(client.send(r#"JSET user:1 address.city "Sofia""#).await.unwrap(), "ok");
+    assert_eq!(client.send("JSET user:1 tags[1] {\"b\": true}").await.unwrap(), "ok");
+
+    assert_eq!(client.send("JGET user:1 name").await.unwrap(), "\"bob\"");
+    assert_eq!(client.send("JGET user:1 address").await.unwrap(), r#"{"city":"Sofia"}"#);
+    assert_eq!(client.send("JGET user:1 tags[1].b").await.unwrap(), "true");
+    assert_eq!(
+        client.send("GET user:1").await.unwrap(),
+        r#"{"address":{"city":"Sofia"},"name":"bob","tags":["a",{"b":true}]}"#
+    );
+}
+
+#[tokio::test]
+async fn jset_creates_missing_documents() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("JSET doc a.b[0] 1").await.unwrap(), "ok");
+    assert_eq!(client.send("JGET doc $").await.unwrap(), r#"{"a":{"b":[1]}}"#);
+}
+
+#[tokio::test]
+async fn jset_keeps_the_ttl() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET doc {} EX 100").await.unwrap(), "ok");
+    assert_eq!(client.send("JSET doc a 1").await.unwrap(), "ok");
+    let ttl: u64 = client.send("EXIN doc").await.unwrap().parse().unwrap();
+    assert!(ttl > 90);
+}
+
+#[tokio::test]
+async fn jget_of_missing_paths_is_null() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("JGET missing a").await.unwrap(), "null");
+
+    assert_eq!(client.send(r#"SET doc {"list": [1, 2]}"#).await.unwrap(), "ok");
+    assert_eq!(client.send("JGET doc list[2]").await.unwrap(), "null");
+    assert_eq!(client.send("JGET doc other.deep").await.unwrap(), "null");
+    assert_eq!(client.send("JGET doc list[0].x").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn json_commands_report_bad_input() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET text plain words").await.unwrap(), "ok");
+    assert_eq!(client.send("JGET text a").await.unwrap(), "error:INVALID_CMD:not json");
+    assert_eq!(client.send("JSET text a 1").await.unwrap(), "error:INVALID_CMD:not json");
+    assert_eq!(client.send("GET text").await.unwrap(), "plain words");
+
+    assert_eq!(client.send(r#"SET doc {"n": 1, "list": []}"#).await.unwrap(), "ok");
+    assert_eq!(client.send("JSET doc a {oops").await.unwrap(), "error:INVALID_CMD:invalid json");
+    assert_eq!(client.send("JSET doc a..b 1").await.unwrap(), "error:INVALID_CMD:invalid path");
+    assert_eq!(client.send("JSET doc n.x 1").await.unwrap(), "error:INVALID_CMD:path type mismatch");
+    assert_eq!(client.send("JSET doc list[3] 1").await.unwrap(), "error:INVALID_CMD:index out of range");
+    assert_eq!(client.send("JSET doc a").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("JGET doc").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
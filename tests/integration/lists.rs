@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+use std::thread;
+
+use keyz::config::StoreConfig;
+use keyz::server::store::{ListEnd, Store};
+
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn pushes_and_pops_work_on_both_ends() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("RPUSH queue b").await.unwrap(), "1");
+    assert_eq!(client.send("RPUSH queue c d").await.unwrap(), "2");
+    assert_eq!(client.send("LPUSH queue a").await.unwrap(), "3");
+    assert_eq!(client.send("LLEN queue").await.unwrap(), "3");
+    assert_eq!(client.send("TYPE queue").await.unwrap(), "list");
+    assert_eq!(client.send("LRANGE queue 0 -1").await.unwrap(), r#"["a","b","c d"]"#);
+
+    assert_eq!(client.send("LPOP queue").await.unwrap(), "a");
+    assert_eq!(client.send("RPOP queue").await.unwrap(), "c d");
+    assert_eq!(client.send("RPOP queue").await.unwrap(), "b");
+
+    // The last pop takes the key with it.
+    assert_eq!(client.send("RPOP queue").await.unwrap(), "null");
+    assert_eq!(client.send("TYPE queue").await.unwrap(), "null");
+    assert_eq!(client.send("LLEN queue").await.unwrap(), "0");
+    assert_eq!(client.send("LRANGE queue 0 -1").await.unwrap(), "[]");
+    assert_eq!(client.send("KEYS *").await.unwrap(), "[]");
+}
+
+#[tokio::test]
+async fn lrange_indices_count_from_the_end_and_are_clamped() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    for value in ["a", "b", "c", "d", "e"] {
+        client.send(&format!("RPUSH letters {}", value)).await.unwrap();
+    }
+
+    assert_eq!(client.send("LRANGE letters 1 2").await.unwrap(), r#"["b","c"]"#);
+    assert_eq!(client.send("LRANGE letters -2 -1").await.unwrap(), r#"["d","e"]"#);
+    assert_eq!(client.send("LRANGE letters 0 -3").await.unwrap(), r#"["a","b","c"]"#);
+    assert_eq!(client.send("LRANGE letters -100 1").await.unwrap(), r#"["a","b"]"#);
+    assert_eq!(client.send("LRANGE letters 3 100").await.unwrap(), r#"["d","e"]"#);
+    assert_eq!(client.send("LRANGE letters 2 1").await.unwrap(), "[]");
+    assert_eq!(client.send("LRANGE letters 5 10").await.unwrap(), "[]");
+    assert_eq!(client.send("LRANGE letters 0 -6").await.unwrap(), "[]");
+    assert_eq!(client.send("LRANGE letters 0 x").await.unwrap(), "error:INVALID_CMD:lrange index invalid");
+}
+
+#[tokio::test]
+async fn list_and_string_commands_refuse_each_others_keys() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET name value").await.unwrap(), "ok");
+    assert_eq!(client.send("LPUSH name x").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("LLEN name").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("GET name").await.unwrap(), "value");
+
+    assert_eq!(client.send("RPUSH queue x").await.unwrap(), "1");
+    assert_eq!(client.send("GET queue").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("INCR queue").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("DUMP queue").await.unwrap(), "error:WRONGTYPE:wrong type");
+
+    // SET replaces whatever the key held.
+    assert_eq!(client.send("SET queue now a string").await.unwrap(), "ok");
+    assert_eq!(client.send("GET queue").await.unwrap(), "now a string");
+}
+
+#[tokio::test]
+async fn a_ttl_covers_the_whole_list() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("RPUSH queue a").await.unwrap(), "1");
+    assert_eq!(client.send("PEXPIRE queue 50").await.unwrap(), "1");
+    assert_eq!(client.send("RPUSH queue b").await.unwrap(), "2");
+    assert!(client.send("PTTL queue").await.unwrap().parse::<u64>().unwrap() <= 50);
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(client.send("LLEN queue").await.unwrap(), "0");
+    assert_eq!(client.send("RPUSH queue c").await.unwrap(), "1");
+    assert_eq!(client.send("PTTL queue").await.unwrap(), "null");
+}
+
+#[test]
+fn concurrent_pushes_and_pops_lose_and_repeat_nothing() {
+    let store: Store = Store::new(&StoreConfig::default());
+
+    let popped: Vec<Vec<u8>> = thread::scope(|scope| {
+        for pusher in 0..4 {
+            let store = store.clone();
+            scope.spawn(move || {
+                for n in 0..500 {
+                    store.push("queue", format!("{}:{}", pusher, n).into_bytes(), ListEnd::Front).unwrap();
+                }
+            });
+        }
+
+        let poppers: Vec<_> = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                scope.spawn(move || {
+                    let mut popped = Vec::new();
+                    for _ in 0..1000 {
+                        popped.extend(store.pop("queue", ListEnd::Back).unwrap());
+                    }
+                    popped
+                })
+            })
+            .collect();
+        poppers.into_iter().flat_map(|popper| popper.join().unwrap()).collect()
+    });
+
+    let left = store.list_range("queue", 0, -1).unwrap();
+    assert_eq!(popped.len() + left.len(), 2000);
+    let seen: HashSet<Vec<u8>> = popped.into_iter().chain(left).collect();
+    assert_eq!(seen.len(), 2000);
+}
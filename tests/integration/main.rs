@@ -0,0 +1,27 @@
+// Under cfg(keyz_loom) a store only works inside a model; see tests/loom_store.rs.
+#![cfg(not(keyz_loom))]
+
+mod access_log;
+mod batch;
+mod bits;
+#[cfg(feature = "client")]
+mod client;
+mod commands;
+mod config;
+mod databases;
+mod export;
+mod hashes;
+mod helpers;
+mod json;
+mod lists;
+mod otel;
+#[cfg(feature = "client")]
+mod pool;
+mod pubsub;
+mod reload;
+mod scan;
+mod seed;
+mod sets;
+mod transactions;
+mod wire;
+mod zsets;
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use opentelemetry::trace::{SpanId, TracerProvider as _};
+use opentelemetry::Value;
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+use opentelemetry_sdk::trace::TracerProvider;
+use tracing_subscriber::prelude::*;
+
+use crate::helpers::TestServer;
+
+fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| &kv.value)
+}
+
+async fn finished_connection(exporter: &InMemorySpanExporter) -> Vec<SpanData> {
+    for _ in 0..100 {
+        let spans = exporter.get_finished_spans().unwrap();
+        if spans.iter().any(|span| span.name == "connection") {
+            return spans;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    panic!("connection span was never closed");
+}
+
+// The test runtime is single-threaded, so the server tasks run on this thread
+// and see the scoped subscriber.
+#[tokio::test]
+async fn set_and_get_are_traced_under_the_connection() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET greeting hello").await.unwrap(), "ok");
+    assert_eq!(client.send("GET greeting").await.unwrap(), "hello");
+    let info: serde_json::Value =
+        serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    client.send("CLOSE").await.unwrap();
+
+    let spans = finished_connection(&exporter).await;
+    let connection = spans.iter().find(|span| span.name == "connection").unwrap();
+    assert_eq!(connection.parent_span_id, SpanId::INVALID);
+
+    let commands: Vec<&SpanData> = spans.iter().filter(|span| span.name == "command").collect();
+    assert_eq!(commands.len(), 3);
+
+    for (span, (name, key, size)) in commands
+        .iter()
+        .zip([("SET", "greeting", 2), ("GET", "greeting", 5)])
+    {
+        assert_eq!(span.parent_span_id, connection.span_context.span_id());
+        assert_eq!(attribute(span, "command.name"), Some(&Value::from(name)));
+        assert_eq!(attribute(span, "command.key"), Some(&Value::from(key)));
+        assert_eq!(attribute(span, "response.size_bytes"), Some(&Value::I64(size)));
+        assert!(span.end_time >= span.start_time);
+    }
+
+    assert_eq!(
+        info["trace_id"].as_str().unwrap(),
+        connection.span_context.trace_id().to_string()
+    );
+}
+
+#[tokio::test]
+async fn span_keys_are_truncated() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let key = "k".repeat(100);
+    client.send(&format!("GET {}", key)).await.unwrap();
+    client.send("CLOSE").await.unwrap();
+
+    let spans = finished_connection(&exporter).await;
+    let command = spans.iter().find(|span| span.name == "command").unwrap();
+    assert_eq!(
+        attribute(command, "command.key"),
+        Some(&Value::from("k".repeat(64)))
+    );
+}
+
+#[tokio::test]
+async fn info_has_no_trace_id_without_otel() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let info: serde_json::Value =
+        serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert!(info.get("trace_id").is_none());
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use keyz::client::pool::{KeyzPool, PoolConfig};
+use keyz::client::ClientError;
+use keyz::{Config, Server};
+use tokio::task::JoinSet;
+
+async fn start() -> Arc<Server> {
+    let mut config = Config::default();
+    config.server.port = Some(0);
+
+    let server = Arc::new(Server::bind(&config).await.unwrap());
+    let running = server.clone();
+    tokio::spawn(async move { running.run().await });
+
+    server
+}
+
+async fn pool(server: &Server, config: PoolConfig) -> KeyzPool {
+    KeyzPool::new(server.local_addr().unwrap().to_string(), config)
+        .await
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn many_tasks_share_a_small_pool() {
+    let server = start().await;
+    let pool = pool(
+        &server,
+        PoolConfig {
+            max_size: 4,
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    let mut tasks = JoinSet::new();
+    for i in 0..300 {
+        let pool = pool.clone();
+        tasks.spawn(async move {
+            let mut connection = pool.get().await.unwrap();
+            let key = format!("key:{}", i);
+            let value = format!("value {}", i);
+
+            connection.set(&key, &value, None).await.unwrap();
+            let stored = connection.get(&key).await.unwrap().unwrap();
+            assert_eq!(stored, value.as_bytes());
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        result.unwrap();
+    }
+
+    let stats = pool.stats();
+    assert_eq!(stats.in_use, 0);
+    assert!(stats.created <= 4);
+    assert_eq!(stats.idle as u64, stats.created);
+    assert_eq!(stats.errors, 0);
+
+    let mut connection = pool.get().await.unwrap();
+    assert_eq!(connection.info().await.unwrap().keys, 300);
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn exhausted_pool_times_out() {
+    let server = start().await;
+    let pool = pool(
+        &server,
+        PoolConfig {
+            max_size: 1,
+            checkout_timeout: Duration::from_millis(50),
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    let held = pool.get().await.unwrap();
+    assert!(matches!(
+        pool.get().await,
+        Err(ClientError::PoolExhausted(_))
+    ));
+    assert_eq!(pool.stats().in_use, 1);
+
+    drop(held);
+    assert!(pool.get().await.is_ok());
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn closed_connections_are_replaced() {
+    let server = start().await;
+    let pool = pool(&server, PoolConfig::default()).await;
+
+    {
+        let mut connection = pool.get().await.unwrap();
+        connection.command("CLOSE").await.unwrap();
+    }
+    assert_eq!(pool.stats().idle, 1);
+
+    let mut connection = pool.get().await.unwrap();
+    connection.ping().await.unwrap();
+
+    let stats = pool.stats();
+    assert_eq!(stats.created, 2);
+    assert_eq!(stats.errors, 1);
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn connections_past_their_lifetime_are_not_reused() {
+    let server = start().await;
+    let pool = pool(
+        &server,
+        PoolConfig {
+            max_lifetime: Some(Duration::from_millis(20)),
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    drop(pool.get().await.unwrap());
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    drop(pool.get().await.unwrap());
+
+    assert_eq!(pool.stats().created, 2);
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn min_idle_connections_are_opened_up_front() {
+    let server = start().await;
+    let pool = pool(
+        &server,
+        PoolConfig {
+            min_idle: 3,
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    let stats = pool.stats();
+    assert_eq!(stats.idle, 3);
+    assert_eq!(stats.created, 3);
+
+    server.shutdown();
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn publish_reaches_every_subscriber() {
+    let server = TestServer::start().await;
+    let mut first = server.connect().await;
+    let mut second = server.connect().await;
+    let mut publisher = server.connect().await;
+
+    assert_eq!(first.send("SUBSCRIBE news").await.unwrap(), "subscribe news 1");
+    assert_eq!(second.send("subscribe news").await.unwrap(), "subscribe news 1");
+    assert_eq!(second.send("SUBSCRIBE sports").await.unwrap(), "subscribe sports 2");
+
+    assert_eq!(publisher.send("PUBLISH news hello everyone").await.unwrap(), "2");
+    assert_eq!(first.read().await.unwrap(), "message news hello everyone");
+    assert_eq!(second.read().await.unwrap(), "message news hello everyone");
+
+    assert_eq!(publisher.send("PUBLISH sports goal").await.unwrap(), "1");
+    assert_eq!(second.read().await.unwrap(), "message sports goal");
+    assert_eq!(publisher.send("PUBLISH empty anyone").await.unwrap(), "0");
+    assert_eq!(publisher.send("PUBLISH news").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn subscribed_connections_only_take_pubsub_commands() {
+    let server = TestServer::start().await;
+    let mut subscriber = server.connect().await;
+    let mut publisher = server.connect().await;
+
+    assert_eq!(subscriber.send("SUBSCRIBE news").await.unwrap(), "subscribe news 1");
+    assert_eq!(
+        subscriber.send("GET key").await.unwrap(),
+        "error:INVALID_CMD:only SUBSCRIBE, UNSUBSCRIBE, PING and CLOSE are allowed while subscribed"
+    );
+    assert_eq!(subscriber.send("PING").await.unwrap(), "pong");
+
+    // The last UNSUBSCRIBE puts the connection back into normal mode.
+    assert_eq!(subscriber.send("UNSUBSCRIBE news").await.unwrap(), "unsubscribe news 0");
+    assert_eq!(subscriber.send("SET key value").await.unwrap(), "ok");
+    assert_eq!(publisher.send("PUBLISH news hello").await.unwrap(), "0");
+}
+
+#[tokio::test]
+async fn disconnected_subscribers_are_dropped() {
+    let server = TestServer::start().await;
+    let mut publisher = server.connect().await;
+
+    {
+        let mut subscriber = server.connect().await;
+        assert_eq!(subscriber.send("SUBSCRIBE news").await.unwrap(), "subscribe news 1");
+        assert_eq!(subscriber.send("CLOSE").await.unwrap(), "Closing connection");
+    }
+
+    let mut delivered = String::new();
+    for _ in 0..50 {
+        delivered = publisher.send("PUBLISH news hello").await.unwrap();
+        if delivered == "0" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(delivered, "0");
+}
@@ -0,0 +1,143 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use arc_swap::ArcSwap;
+use keyz::config::ProtocolConfig;
+use keyz::reload::Reloader;
+use keyz::Config;
+
+use crate::helpers::TestServer;
+
+const STARTING: &str = "[server]\nport = 7667\n\n[store]\nmax_key_bytes = 64\n";
+
+struct Reloading {
+    server: TestServer,
+    reloader: Reloader,
+    protocol: Arc<ArcSwap<ProtocolConfig>>,
+    path: PathBuf,
+}
+
+// A server whose store a Reloader reading `keyz.toml` in a fresh directory
+// applies to, as SIGHUP would.
+async fn reloading(name: &str) -> Reloading {
+    let dir = std::env::temp_dir().join(format!("keyz-reload-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("keyz.toml");
+    fs::write(&path, STARTING).unwrap();
+
+    let (config, _) = Config::load_from(&path).unwrap();
+    let (started, _) = Config::load_from(&path).unwrap();
+    let server = TestServer::start_with(started.store, started.protocol).await;
+    let protocol = Arc::new(ArcSwap::from_pointee(config.protocol.clone()));
+    let reloader = Reloader::new(vec![path.clone()], &config, server.store().clone(), protocol.clone(), None);
+
+    Reloading { server, reloader, protocol, path }
+}
+
+// Collects what tracing writes while a test runs.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Captured {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+#[tokio::test]
+async fn a_reload_applies_changed_hot_fields() {
+    let reloading = reloading("apply").await;
+    let mut client = reloading.server.connect().await;
+    assert_eq!(client.send(&format!("SET {} v", "k".repeat(100))).await.unwrap(), "error:KEYTOOLONG:key too long");
+
+    fs::write(
+        &reloading.path,
+        "[server]\nport = 7667\nread_only = true\n\n\
+         [store]\nmax_key_bytes = 128\n\n\
+         [protocol]\nstructured_errors = true\n",
+    )
+    .unwrap();
+    reloading.reloader.reload().unwrap();
+
+    assert_eq!(reloading.server.store().store_config().max_key_bytes, Some(128));
+    assert!(reloading.server.store().is_read_only());
+    assert!(reloading.protocol.load().structured_errors);
+    assert_eq!(client.send(&format!("GET {}", "k".repeat(100))).await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn a_broken_file_leaves_the_running_config_alone() {
+    let reloading = reloading("broken").await;
+
+    for contents in [
+        // Doesn't parse.
+        "[store\nmax_key_bytes = 128\n",
+        // Parses, but doesn't validate.
+        "[store]\nmax_key_bytes = 128\n\n[protocol]\nmax_batch_commands = 0\n",
+    ] {
+        fs::write(&reloading.path, contents).unwrap();
+        assert!(reloading.reloader.reload().is_err(), "{}", contents);
+
+        assert_eq!(reloading.server.store().store_config().max_key_bytes, Some(64));
+        assert_eq!(reloading.protocol.load().max_batch_commands, ProtocolConfig::default().max_batch_commands);
+        assert_eq!(reloading.server.store().stats().config_reloads, 0);
+    }
+}
+
+#[tokio::test]
+async fn a_reload_keeps_the_listening_address() {
+    let reloading = reloading("address").await;
+    let captured = Captured::default();
+    let subscriber = tracing_subscriber::fmt().with_ansi(false).with_writer({
+        let captured = captured.clone();
+        move || captured.clone()
+    });
+    let _guard = tracing::subscriber::set_default(subscriber.finish());
+
+    fs::write(&reloading.path, "[server]\nhost = \"0.0.0.0\"\nport = 7668\n\n[store]\nmax_key_bytes = 128\n").unwrap();
+    reloading.reloader.reload().unwrap();
+
+    // The rest of the file still applies.
+    assert_eq!(reloading.server.store().store_config().max_key_bytes, Some(128));
+    let logged = captured.text();
+    assert!(logged.contains("server listeners cannot change without a restart"), "{}", logged);
+    assert!(logged.contains("127.0.0.1:7667") && logged.contains("0.0.0.0:7668"), "{}", logged);
+
+    // The server still answers where it was.
+    let mut client = reloading.server.connect().await;
+    assert_eq!(client.send("PING").await.unwrap(), "pong");
+}
+
+#[tokio::test]
+async fn reloads_are_counted_in_info() {
+    let reloading = reloading("info").await;
+    let mut client = reloading.server.connect().await;
+
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(info["config_reloads"], 0);
+    assert_eq!(info["last_reload_epoch"], 0);
+
+    reloading.reloader.reload().unwrap();
+    reloading.reloader.reload().unwrap();
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let info: serde_json::Value = serde_json::from_str(&client.send("INFO").await.unwrap()).unwrap();
+    assert_eq!(info["config_reloads"], 2);
+    let last_reload = info["last_reload_epoch"].as_u64().unwrap();
+    assert!(now.abs_diff(last_reload) <= 2, "{} {}", now, last_reload);
+}
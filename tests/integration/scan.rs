@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+
+use keyz::config::{ProtocolConfig, StoreConfig};
+
+use crate::helpers::TestServer;
+
+async fn server_with_keys(max_message_bytes: usize, keys: usize) -> TestServer {
+    let protocol = ProtocolConfig { max_message_bytes, ..ProtocolConfig::default() };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    for n in 0..keys {
+        server.store().insert(format!("key:{:04}", n), b"v".to_vec(), 0).unwrap();
+    }
+    server
+}
+
+#[tokio::test]
+async fn responses_longer_than_a_frame_become_an_error() {
+    let server = server_with_keys(256, 100).await;
+    let mut client = server.connect().await;
+
+    assert_eq!(
+        client.send("KEYS *").await.unwrap(),
+        "error:TOOLARGE:response exceeds 256 bytes; use SCAN/COUNT"
+    );
+    assert!(client.send("KEYS key:000*").await.unwrap().starts_with(r#"["key:0000","key:0001""#));
+    // The connection is still usable.
+    assert_eq!(client.send("GET key:0001").await.unwrap(), "v");
+}
+
+#[tokio::test]
+async fn scan_pages_shrink_to_fit_and_cover_every_key() {
+    let server = server_with_keys(256, 100).await;
+    let mut client = server.connect().await;
+
+    let mut seen = BTreeSet::new();
+    let mut cursor = "0".to_string();
+    let mut pages = 0;
+    loop {
+        let reply = client.send(&format!("SCAN {} COUNT 1000", cursor)).await.unwrap();
+        assert!(reply.len() <= 256, "{}", reply);
+
+        let page: (String, Vec<String>) = serde_json::from_str(&reply).unwrap();
+        assert!(!page.1.is_empty());
+        for key in page.1 {
+            assert!(seen.insert(key));
+        }
+        pages += 1;
+
+        cursor = page.0;
+        if cursor == "0" {
+            break;
+        }
+    }
+
+    assert_eq!(seen.len(), 100);
+    assert!(pages > 1);
+}
+
+#[tokio::test]
+async fn scan_filters_and_counts() {
+    let server = server_with_keys(64 * 1024, 30).await;
+    let mut client = server.connect().await;
+    server.store().insert("other".to_string(), b"v".to_vec(), 0).unwrap();
+
+    let first: (String, Vec<String>) = serde_json::from_str(&client.send("SCAN 0").await.unwrap()).unwrap();
+    assert_eq!(first.1, (0..10).map(|n| format!("key:{:04}", n)).collect::<Vec<_>>());
+
+    // Keys deleted and added behind the cursor don't disturb the rest.
+    assert_eq!(client.send("DEL key:0003").await.unwrap(), "key:0003");
+    assert_eq!(client.send("SET key:0000a v").await.unwrap(), "ok");
+    let command = format!("SCAN {} MATCH key:* COUNT 100", first.0);
+    let rest: (String, Vec<String>) = serde_json::from_str(&client.send(&command).await.unwrap()).unwrap();
+    assert_eq!(rest.0, "0");
+    assert_eq!(rest.1, (10..30).map(|n| format!("key:{:04}", n)).collect::<Vec<_>>());
+
+    assert_eq!(client.send("SCAN 0 MATCH nothing*").await.unwrap(), r#"["0",[]]"#);
+    assert_eq!(client.send("SCAN zz").await.unwrap(), "error:INVALID_CMD:scan cursor invalid");
+    assert_eq!(client.send("SCAN 0 COUNT 0").await.unwrap(), "error:INVALID_CMD:scan count invalid");
+    assert_eq!(client.send("SCAN 0 COUNT").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
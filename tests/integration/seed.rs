@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::PathBuf;
+
+use keyz::config::{ProtocolConfig, StoreConfig};
+use keyz::server::seed::{seed, SeedSummary};
+use keyz::server::store::Store;
+use keyz::{Config, Server};
+
+const SEED: &str = "\
+# fixtures for the demo
+SET greeting hello world
+SET session abc EX 60
+
+GET
+SELECT 2
+SET other db two
+";
+
+fn seed_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("keyz-seed-{}-{}.txt", name, std::process::id()));
+    fs::write(&path, SEED).unwrap();
+    path
+}
+
+#[test]
+fn lenient_seeding_skips_failing_lines() {
+    let path = seed_file("lenient");
+    let store = Store::new(&StoreConfig::default());
+
+    let summary = seed(&store, &path, false, &ProtocolConfig::default()).unwrap();
+
+    assert_eq!(summary, SeedSummary { applied: 4, skipped: 2, failed: 1 });
+    assert_eq!(store.get("greeting").unwrap(), Some(b"hello world".to_vec()));
+    assert!(store.expires_in("session").is_some());
+    // The file's SELECT stayed with the seed.
+    assert_eq!(store.selected_db(), 0);
+    assert_eq!(store.get("other").unwrap(), None);
+
+    let mut db2 = store.clone();
+    db2.select(2).unwrap();
+    assert_eq!(db2.get("other").unwrap(), Some(b"db two".to_vec()));
+}
+
+#[test]
+fn strict_seeding_stops_at_the_first_failing_line() {
+    let path = seed_file("strict");
+    let store = Store::new(&StoreConfig::default());
+
+    let error = seed(&store, &path, true, &ProtocolConfig::default()).unwrap_err();
+
+    assert!(error.to_string().contains("line 5: error:INVALID_CMD:invalid command"), "{}", error);
+    assert_eq!(store.get("greeting").unwrap(), Some(b"hello world".to_vec()));
+    assert_eq!(store.stats().keys, 2);
+}
+
+#[tokio::test]
+async fn servers_refuse_to_start_on_a_strict_seed_failure() {
+    let mut config = Config::default();
+    config.server.port = Some(0);
+    config.server.read_only = true;
+    config.store.seed_path = Some(seed_file("server"));
+
+    let server = Server::bind(&config).await.unwrap();
+    assert_eq!(server.store().get("greeting").unwrap(), Some(b"hello world".to_vec()));
+    assert!(server.store().is_read_only());
+    drop(server);
+
+    config.store.seed_strict = true;
+    assert!(Server::bind(&config).await.is_err());
+}
@@ -0,0 +1,90 @@
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn sadd_is_idempotent_and_srem_of_an_absent_member_is_zero() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SADD tags rust").await.unwrap(), "1");
+    assert_eq!(client.send("SADD tags rust").await.unwrap(), "0");
+    assert_eq!(client.send("SADD tags key value").await.unwrap(), "1");
+    assert_eq!(client.send("SCARD tags").await.unwrap(), "2");
+    assert_eq!(client.send("TYPE tags").await.unwrap(), "set");
+    assert_eq!(client.send("SMEMBERS tags").await.unwrap(), r#"["key value","rust"]"#);
+    assert_eq!(client.send("SISMEMBER tags rust").await.unwrap(), "1");
+    assert_eq!(client.send("SISMEMBER tags go").await.unwrap(), "0");
+    assert_eq!(client.send("SISMEMBER nothing go").await.unwrap(), "0");
+
+    assert_eq!(client.send("SREM tags go").await.unwrap(), "0");
+    assert_eq!(client.send("SREM nothing go").await.unwrap(), "0");
+    assert_eq!(client.send("SREM tags rust").await.unwrap(), "1");
+    assert_eq!(client.send("SREM tags rust").await.unwrap(), "0");
+
+    // The last member takes the key with it.
+    assert_eq!(client.send("SREM tags key value").await.unwrap(), "1");
+    assert_eq!(client.send("TYPE tags").await.unwrap(), "null");
+    assert_eq!(client.send("SCARD tags").await.unwrap(), "0");
+    assert_eq!(client.send("SMEMBERS tags").await.unwrap(), "[]");
+}
+
+#[tokio::test]
+async fn sunion_has_the_members_of_either_set() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    for member in ["a", "b", "c"] {
+        client.send(&format!("SADD left {}", member)).await.unwrap();
+    }
+    for member in ["c", "d"] {
+        client.send(&format!("SADD right {}", member)).await.unwrap();
+    }
+
+    assert_eq!(client.send("SUNION left right").await.unwrap(), r#"["a","b","c","d"]"#);
+    assert_eq!(client.send("SUNION right left").await.unwrap(), r#"["a","b","c","d"]"#);
+    assert_eq!(client.send("SUNION left nothing").await.unwrap(), r#"["a","b","c"]"#);
+    assert_eq!(client.send("SUNION nothing none").await.unwrap(), "[]");
+    assert_eq!(client.send("SUNION left").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn sinterstore_replaces_the_destination_with_the_common_members() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    for member in ["a", "b", "c"] {
+        client.send(&format!("SADD left {}", member)).await.unwrap();
+    }
+    for member in ["b", "c", "d"] {
+        client.send(&format!("SADD right {}", member)).await.unwrap();
+    }
+    assert_eq!(client.send("SET both old").await.unwrap(), "ok");
+    assert_eq!(client.send("EXPIRE both 100").await.unwrap(), "1");
+
+    assert_eq!(client.send("SINTERSTORE both left right").await.unwrap(), "2");
+    assert_eq!(client.send("SMEMBERS both").await.unwrap(), r#"["b","c"]"#);
+    assert_eq!(client.send("TTL both").await.unwrap(), "null");
+
+    // The destination can be one of the sources.
+    assert_eq!(client.send("SINTERSTORE left left both").await.unwrap(), "2");
+    assert_eq!(client.send("SMEMBERS left").await.unwrap(), r#"["b","c"]"#);
+
+    assert_eq!(client.send("SINTERSTORE both left nothing").await.unwrap(), "0");
+    assert_eq!(client.send("TYPE both").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn set_and_other_commands_refuse_each_others_keys() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("SET name value").await.unwrap(), "ok");
+    assert_eq!(client.send("HSET user name ada").await.unwrap(), "1");
+    for key in ["name", "user"] {
+        assert_eq!(client.send(&format!("SADD {} x", key)).await.unwrap(), "error:WRONGTYPE:wrong type");
+        assert_eq!(client.send(&format!("SCARD {}", key)).await.unwrap(), "error:WRONGTYPE:wrong type");
+        assert_eq!(client.send(&format!("SUNION {} other", key)).await.unwrap(), "error:WRONGTYPE:wrong type");
+    }
+
+    assert_eq!(client.send("SADD tags x").await.unwrap(), "1");
+    assert_eq!(client.send("GET tags").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("HGET tags x").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("SINTERSTORE out tags name").await.unwrap(), "error:WRONGTYPE:wrong type");
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use keyz::config::{ProtocolConfig, StoreConfig};
+
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn exec_runs_the_queued_commands_in_order() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("SET a 1").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("get a").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("JGET a a]").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("DEL missing").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("MULTI").await.unwrap(), "error:INVALID_CMD:MULTI is not allowed in a transaction");
+
+    // Nothing ran while queueing.
+    let mut other = server.connect().await;
+    assert_eq!(other.send("GET a").await.unwrap(), "null");
+
+    let results: serde_json::Value = serde_json::from_str(&client.send("EXEC").await.unwrap()).unwrap();
+    assert_eq!(results, serde_json::json!(["ok", "1", "error:INVALID_CMD:invalid path", null]));
+    assert_eq!(client.send("GET a").await.unwrap(), "1");
+    assert_eq!(client.send("EXEC").await.unwrap(), "error:INVALID_CMD:EXEC without MULTI");
+}
+
+#[tokio::test]
+async fn queue_errors_abort_the_transaction() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("SET a 1").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("GET").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("NOPE x").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("SUBSCRIBE news").await.unwrap(), "error:INVALID_CMD:SUBSCRIBE is not allowed in a transaction");
+    assert_eq!(client.send("EXEC").await.unwrap(), "error:INVALID_CMD:transaction aborted due to queued error");
+    assert_eq!(client.send("GET a").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn discard_and_reset_drop_the_queue() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("DISCARD").await.unwrap(), "error:INVALID_CMD:DISCARD without MULTI");
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("SET a 1").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("DISCARD").await.unwrap(), "ok");
+    assert_eq!(client.send("GET a").await.unwrap(), "null");
+
+    assert_eq!(client.send("SELECT 3").await.unwrap(), "ok");
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("SET a 1").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("RESET").await.unwrap(), "ok");
+    assert_eq!(client.send("SET b 2").await.unwrap(), "ok");
+    assert_eq!(client.send("EXEC").await.unwrap(), "error:INVALID_CMD:EXEC without MULTI");
+
+    // RESET also went back to database 0.
+    let mut other = server.connect().await;
+    assert_eq!(other.send("GET b").await.unwrap(), "2");
+    assert_eq!(other.send("GET a").await.unwrap(), "null");
+}
+
+// A second connection's SET arrives while the transaction sleeps between its
+// SET and GET. It has to wait for EXEC to finish rather than land in between.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn exec_is_not_interleaved_with_other_connections() {
+    let protocol = ProtocolConfig {
+        allow_debug: true,
+        command_timeout_secs: Some(5),
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(StoreConfig::default(), protocol).await;
+    let mut client = server.connect().await;
+    let mut writer = server.connect().await;
+
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("SET shared mine").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("DEBUG SLEEP 0.5").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("GET shared").await.unwrap(), "QUEUED");
+
+    let interleaver = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        writer.send("SET shared theirs").await.unwrap()
+    });
+
+    let results: serde_json::Value = serde_json::from_str(&client.send("EXEC").await.unwrap()).unwrap();
+    assert_eq!(results, serde_json::json!(["ok", "ok", "mine"]));
+
+    assert_eq!(interleaver.await.unwrap(), "ok");
+    assert_eq!(client.send("GET shared").await.unwrap(), "theirs");
+}
+
+#[tokio::test]
+async fn a_write_to_a_watched_key_aborts_exec() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    let mut other = server.connect().await;
+
+    assert_eq!(client.send("SET balance 10").await.unwrap(), "ok");
+    assert_eq!(client.send("WATCH balance other").await.unwrap(), "ok");
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("WATCH late").await.unwrap(), "error:INVALID_CMD:WATCH inside MULTI is not allowed");
+    assert_eq!(client.send("SET balance 20").await.unwrap(), "QUEUED");
+
+    assert_eq!(other.send("SET balance 15").await.unwrap(), "ok");
+
+    assert_eq!(client.send("EXEC").await.unwrap(), "null");
+    assert_eq!(client.send("GET balance").await.unwrap(), "15");
+
+    // EXEC stopped the watch, so the next transaction goes through.
+    assert_eq!(other.send("DEL balance").await.unwrap(), "balance");
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("SET balance 30").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("EXEC").await.unwrap(), "[\"ok\"]");
+
+    // A same-named key in another database isn't the watched one.
+    assert_eq!(client.send("WATCH balance").await.unwrap(), "ok");
+    assert_eq!(other.send("SELECT 1").await.unwrap(), "ok");
+    assert_eq!(other.send("SET balance 1").await.unwrap(), "ok");
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("EXEC").await.unwrap(), "[]");
+}
+
+#[tokio::test]
+async fn unwatch_stops_later_writes_from_aborting() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    let mut other = server.connect().await;
+
+    assert_eq!(client.send("WATCH a").await.unwrap(), "ok");
+    assert_eq!(other.send("SET a 1").await.unwrap(), "ok");
+    assert_eq!(client.send("UNWATCH").await.unwrap(), "ok");
+    assert_eq!(other.send("SET a 2").await.unwrap(), "ok");
+
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("GET a").await.unwrap(), "QUEUED");
+    assert_eq!(client.send("EXEC").await.unwrap(), "[\"2\"]");
+
+    assert_eq!(client.send("WATCH a").await.unwrap(), "ok");
+    assert_eq!(other.send("FLUSH").await.unwrap(), "ok");
+    assert_eq!(client.send("MULTI").await.unwrap(), "ok");
+    assert_eq!(client.send("EXEC").await.unwrap(), "null");
+    assert_eq!(client.send("WATCH").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
@@ -0,0 +1,190 @@
+use keyz::config::{ProtocolConfig, StoreConfig};
+
+use crate::helpers::TestServer;
+
+// Exact response bytes of every command, so changes to how responses are
+// built can't change what existing clients receive.
+const LEGACY: &[(&str, &str)] = &[
+    ("PING", "pong"),
+    ("PING hello there", "hello there"),
+    ("ECHO  spaced  out ", " spaced  out "),
+    ("GET missing", "null"),
+    ("SET greeting hello world", "ok"),
+    ("GET greeting", "hello world"),
+    ("SET unicode héllo wörld ✓", "ok"),
+    ("GET unicode", "héllo wörld ✓"),
+    ("SET json {\"a\": [1, 2]}", "ok"),
+    ("GET json", "{\"a\": [1, 2]}"),
+    ("SET null null", "ok"),
+    ("GET null", "null"),
+    ("EXIN greeting", "null"),
+    ("EXIN missing", "null"),
+    ("KEYS *", "[\"greeting\",\"json\",\"null\",\"unicode\"]"),
+    ("KEYS g*", "[\"greeting\"]"),
+    ("KEYS nothing*", "[]"),
+    ("DEL greeting", "greeting"),
+    ("DEL greeting", "null"),
+    ("SLOWLOG GET", "[]"),
+    ("SLOWLOG GET 5", "[]"),
+    ("SLOWLOG RESET", "ok"),
+    ("DEBUG OBJECT missing", "null"),
+    ("DEBUG PURGE", "0"),
+    (
+        "DEBUG OBJECT json",
+        "{\"stored_bytes\":33,\"original_bytes\":13,\"compressed\":true,\"encoding\":\"gzip\",\"ttl_remaining_secs\":null}",
+    ),
+    ("MEMORY USAGE missing", "0"),
+    ("MEMORY USAGE json", "125"),
+    ("MEMORY USAGE json DECOMP", "raw:13 stored:33 saved:-153.8%"),
+    ("LATENCY GRAPH missing", "null"),
+    ("LATENCY HISTORY GET", "[]"),
+    ("NOPE", "error:invalid command"),
+    ("GET", "error:invalid command"),
+    ("SET k", "error:set command invalid"),
+    ("SLOWLOG GET x", "error:slowlog count invalid"),
+    ("SLOWLOG NOPE", "error:invalid command"),
+    ("LATENCY HISTORY GET x", "error:latency count invalid"),
+    ("MEMORY NOPE", "error:invalid command"),
+    ("DEBUG SLEEP x", "error:debug sleep seconds invalid"),
+    ("CONFIG SET nope 1", "error:unknown config parameter"),
+    ("CONFIG SET server.read_only maybe", "error:config value invalid"),
+    ("SET big 0123456789abcdefghijk", "error:value too large"),
+    ("SET 0123456789abcdefg v", "error:key too long"),
+    ("SET k v EX 1000", "error:ttl too large"),
+    ("SET k v EX 18446744073709551615", "error:ttl too large"),
+    ("EXPIRE unicode 18446744073709551615", "error:ttl too large"),
+    ("CONFIG SET server.read_only true", "ok"),
+    ("SET k v", "error:read only"),
+    ("DEL unicode", "error:read only"),
+    ("DEBUG PURGE", "error:read only"),
+    ("GET unicode", "héllo wörld ✓"),
+    ("CONFIG SET server.read_only false", "ok"),
+    ("DEL unicode", "unicode"),
+];
+
+const STRUCTURED: &[(&str, &str)] = &[
+    ("SET k v", "ok"),
+    ("GET missing", "null"),
+    (
+        "NOPE",
+        "{\"error\":\"invalid_command\",\"message\":\"invalid command\",\"code\":4001}",
+    ),
+    (
+        "SET big 0123456789abcdefghijk",
+        "{\"error\":\"value_too_large\",\"message\":\"value for key 'big' is 21 bytes, limit is 20\",\"code\":4131}",
+    ),
+    (
+        "SET 0123456789abcdefg v",
+        "{\"error\":\"key_too_large\",\"message\":\"key '0123456789abcdefg' is 17 bytes, limit is 16\",\"code\":4141}",
+    ),
+    (
+        "SET k v EX 1000",
+        "{\"error\":\"ttl_too_large\",\"message\":\"TTL of 1000s exceeds maximum of 999s\",\"code\":4002}",
+    ),
+    (
+        "SET k v EX 18446744073709551615",
+        "{\"error\":\"ttl_too_large\",\"message\":\"TTL of 18446744073709551615s exceeds maximum of 999s\",\"code\":4002}",
+    ),
+    (
+        "EXPIRE k 18446744073709551615",
+        "{\"error\":\"ttl_too_large\",\"message\":\"TTL of 18446744073709551615s exceeds maximum of 999s\",\"code\":4002}",
+    ),
+    (
+        "DEBUG PURGE",
+        "{\"error\":\"invalid_command\",\"message\":\"debug disabled\",\"code\":4001}",
+    ),
+    ("CONFIG SET server.read_only true", "ok"),
+    (
+        "DEL k",
+        "{\"error\":\"read_only\",\"message\":\"server is in read-only mode\",\"code\":4030}",
+    ),
+];
+
+// The same failures with the default, coded errors.
+const CODED: &[(&str, &str)] = &[
+    ("NOPE", "error:INVALID_CMD:invalid command"),
+    ("SET k", "error:INVALID_CMD:set command invalid"),
+    ("SLOWLOG GET x", "error:INVALID_CMD:slowlog count invalid"),
+    ("SET big 0123456789abcdefghijk", "error:TOOLARGE:value too large"),
+    ("SET 0123456789abcdefg v", "error:KEYTOOLONG:key too long"),
+    ("SET k v EX 18446744073709551615", "error:TTLTOOLARGE:ttl too large"),
+    ("SET k v EX 998", "ok"),
+    ("SET k v EX 999", "ok"),
+    ("SET k v EX 1000", "error:TTLTOOLARGE:ttl too large"),
+    ("EXPIRE k 18446744073709551615", "error:TTLTOOLARGE:ttl too large"),
+    ("EXPIRE k 998", "1"),
+    ("EXPIRE k 999", "1"),
+    ("EXPIRE k 1000", "error:TTLTOOLARGE:ttl too large"),
+    ("DEBUG SLEEP 1.5", "error:TIMEOUT:command timed out"),
+    ("CONFIG SET server.read_only true", "ok"),
+    ("SET k v", "error:READONLY:read only"),
+];
+
+fn limits() -> StoreConfig {
+    StoreConfig {
+        max_key_bytes: Some(16),
+        max_value_bytes: Some(20),
+        max_ttl_secs: Some(999),
+        ..StoreConfig::default()
+    }
+}
+
+async fn assert_responses(server: &TestServer, cases: &[(&str, &str)]) {
+    let mut client = server.connect().await;
+
+    for (command, expected) in cases {
+        let response = client.send(command).await.unwrap();
+        assert_eq!(&response, expected, "response to {:?}", command);
+    }
+}
+
+#[tokio::test]
+async fn legacy_responses_are_unchanged() {
+    let protocol = ProtocolConfig {
+        legacy_errors: true,
+        allow_debug: true,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(limits(), protocol).await;
+
+    assert_responses(&server, LEGACY).await;
+}
+
+#[tokio::test]
+async fn structured_responses_are_unchanged() {
+    let protocol = ProtocolConfig {
+        structured_errors: true,
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(limits(), protocol).await;
+
+    assert_responses(&server, STRUCTURED).await;
+}
+
+#[tokio::test]
+async fn errors_carry_a_code_by_default() {
+    let protocol = ProtocolConfig {
+        allow_debug: true,
+        command_timeout_secs: Some(1),
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(limits(), protocol).await;
+
+    assert_responses(&server, CODED).await;
+}
+
+#[tokio::test]
+async fn old_timeout_responses_keep_their_message() {
+    let protocol = ProtocolConfig {
+        allow_debug: true,
+        command_timeout_secs: Some(1),
+        command_timeout_response: "error:too slow".into(),
+        ..ProtocolConfig::default()
+    };
+    let server = TestServer::start_with(limits(), protocol.clone()).await;
+    assert_responses(&server, &[("DEBUG SLEEP 1.5", "error:TIMEOUT:too slow")]).await;
+
+    let protocol = ProtocolConfig { legacy_errors: true, ..protocol };
+    let server = TestServer::start_with(limits(), protocol).await;
+    assert_responses(&server, &[("DEBUG SLEEP 1.5", "error:too slow")]).await;
+}
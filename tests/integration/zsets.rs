@@ -0,0 +1,92 @@
+use crate::helpers::TestServer;
+
+#[tokio::test]
+async fn zadd_updates_the_score_of_an_existing_member() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("ZADD board 10 ada").await.unwrap(), "1");
+    assert_eq!(client.send("ZADD board 20 grace").await.unwrap(), "1");
+    assert_eq!(client.send("TYPE board").await.unwrap(), "zset");
+    assert_eq!(client.send("ZRANK board ada").await.unwrap(), "0");
+
+    assert_eq!(client.send("ZADD board 30.5 ada").await.unwrap(), "0");
+    assert_eq!(client.send("ZSCORE board ada").await.unwrap(), "30.5");
+    assert_eq!(client.send("ZRANK board ada").await.unwrap(), "1");
+    assert_eq!(client.send("ZRANK board grace").await.unwrap(), "0");
+    assert_eq!(client.send("ZRANGE board 0 -1").await.unwrap(), r#"["grace","ada"]"#);
+
+    assert_eq!(client.send("ZSCORE board nobody").await.unwrap(), "null");
+    assert_eq!(client.send("ZRANK board nobody").await.unwrap(), "null");
+    assert_eq!(client.send("ZSCORE missing ada").await.unwrap(), "null");
+
+    assert_eq!(client.send("ZREM board nobody").await.unwrap(), "0");
+    assert_eq!(client.send("ZREM board ada").await.unwrap(), "1");
+    // The last member takes the key with it.
+    assert_eq!(client.send("ZREM board grace").await.unwrap(), "1");
+    assert_eq!(client.send("TYPE board").await.unwrap(), "null");
+}
+
+#[tokio::test]
+async fn zrange_orders_by_score_then_member_and_can_add_scores() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    for (score, member) in [("2", "c"), ("1", "b"), ("1", "a"), ("-inf", "low"), ("inf", "high"), ("0.25", "two words")] {
+        client.send(&format!("ZADD board {} {}", score, member)).await.unwrap();
+    }
+
+    assert_eq!(client.send("ZRANGE board 0 -1").await.unwrap(), r#"["low","two words","a","b","c","high"]"#);
+    assert_eq!(
+        client.send("ZRANGE board 1 3 WITHSCORES").await.unwrap(),
+        r#"["two words","0.25","a","1","b","1"]"#
+    );
+    assert_eq!(client.send("ZRANGE board -1 -1 withscores").await.unwrap(), r#"["high","inf"]"#);
+    assert_eq!(client.send("ZRANGE board 0 0 WITHSCORES").await.unwrap(), r#"["low","-inf"]"#);
+    assert_eq!(client.send("ZRANGE board 4 100").await.unwrap(), r#"["c","high"]"#);
+    assert_eq!(client.send("ZRANGE board 3 1").await.unwrap(), "[]");
+    assert_eq!(client.send("ZRANGE missing 0 -1").await.unwrap(), "[]");
+    assert_eq!(client.send("ZRANK board two words").await.unwrap(), "1");
+
+    assert_eq!(client.send("ZRANGE board 0 x").await.unwrap(), "error:INVALID_CMD:zrange index invalid");
+    assert_eq!(client.send("ZRANGE board 0 1 SCORES").await.unwrap(), "error:INVALID_CMD:invalid command");
+}
+
+#[tokio::test]
+async fn zrangebyscore_includes_its_bounds_unless_told_not_to() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    for (score, member) in [("1", "a"), ("2", "b"), ("2", "c"), ("3", "d")] {
+        client.send(&format!("ZADD board {} {}", score, member)).await.unwrap();
+    }
+
+    assert_eq!(client.send("ZRANGEBYSCORE board 2 3").await.unwrap(), r#"["b","c","d"]"#);
+    assert_eq!(client.send("ZRANGEBYSCORE board 2 2").await.unwrap(), r#"["b","c"]"#);
+    assert_eq!(client.send("ZRANGEBYSCORE board (2 3").await.unwrap(), r#"["d"]"#);
+    assert_eq!(client.send("ZRANGEBYSCORE board 1 (2").await.unwrap(), r#"["a"]"#);
+    assert_eq!(client.send("ZRANGEBYSCORE board (2 (2").await.unwrap(), "[]");
+    assert_eq!(client.send("ZRANGEBYSCORE board 3 1").await.unwrap(), "[]");
+    assert_eq!(client.send("ZRANGEBYSCORE board -inf +inf").await.unwrap(), r#"["a","b","c","d"]"#);
+    assert_eq!(client.send("ZRANGEBYSCORE board 1.5 2.5 WITHSCORES").await.unwrap(), r#"["b","2","c","2"]"#);
+    assert_eq!(client.send("ZRANGEBYSCORE missing -inf +inf").await.unwrap(), "[]");
+
+    assert_eq!(client.send("ZRANGEBYSCORE board x 3").await.unwrap(), "error:INVALID_CMD:score is not a number");
+    assert_eq!(client.send("ZRANGEBYSCORE board 1 nan").await.unwrap(), "error:INVALID_CMD:score is not a number");
+}
+
+#[tokio::test]
+async fn scores_must_be_numbers_and_keys_the_right_type() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    assert_eq!(client.send("ZADD board nan a").await.unwrap(), "error:INVALID_CMD:score is not a number");
+    assert_eq!(client.send("ZADD board ten a").await.unwrap(), "error:INVALID_CMD:score is not a number");
+    assert_eq!(client.send("ZADD board 1").await.unwrap(), "error:INVALID_CMD:invalid command");
+    assert_eq!(client.send("TYPE board").await.unwrap(), "null");
+
+    assert_eq!(client.send("SADD tags a").await.unwrap(), "1");
+    assert_eq!(client.send("ZADD tags 1 a").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("ZRANGE tags 0 -1").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("ZADD board 1 a").await.unwrap(), "1");
+    assert_eq!(client.send("SMEMBERS board").await.unwrap(), "error:WRONGTYPE:wrong type");
+    assert_eq!(client.send("GET board").await.unwrap(), "error:WRONGTYPE:wrong type");
+}
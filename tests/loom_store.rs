@@ -0,0 +1,182 @@
+#![cfg(keyz_loom)]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTimeError;
+
+use keyz::config::StoreConfig;
+use keyz::server::store::{ClockSource, Store};
+use loom::thread::{self, JoinHandle};
+
+fn store() -> Store {
+    Store::new(&StoreConfig::default())
+}
+
+// Only moved before any thread is spawned, so loom doesn't need to see it.
+#[derive(Clone, Default)]
+struct ManualClock(std::sync::Arc<AtomicU64>);
+
+impl ManualClock {
+    fn advance(&self, ms: u64) {
+        self.0.fetch_add(ms, Ordering::SeqCst);
+    }
+}
+
+impl ClockSource for ManualClock {
+    fn now_millis(&self) -> Result<u64, SystemTimeError> {
+        Ok(self.0.load(Ordering::SeqCst))
+    }
+}
+
+// Every insert gzips its value, which needs more stack than loom's default
+// coroutine stack provides, so all store calls run on threads spawned here.
+// That root thread counts towards loom's limit of five threads per model,
+// leaving room for three more.
+const STACK_SIZE: usize = 1024 * 1024;
+
+fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::Builder::new().stack_size(STACK_SIZE).spawn(f).unwrap()
+}
+
+fn model<F>(f: F)
+where
+    F: Fn() + Sync + Send + 'static,
+{
+    let f = std::sync::Arc::new(f);
+
+    // A preemption bound of 3 keeps the run within the CI budget while still
+    // covering the interleavings that matter for a single lock.
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(3);
+    builder.check(move || {
+        let f = f.clone();
+        spawn(move || f()).join().unwrap();
+    });
+}
+
+#[test]
+fn insert_and_delete_of_the_same_key_stay_consistent() {
+    model(|| {
+        let store = store();
+
+        let writer = {
+            let store = store.clone();
+            spawn(move || store.insert("key".to_string(), b"value".to_vec(), 0).unwrap())
+        };
+        let deleter = {
+            let store = store.clone();
+            spawn(move || store.delete("key"))
+        };
+
+        writer.join().unwrap();
+        let deleted = deleter.join().unwrap();
+
+        match deleted {
+            Some(key) => {
+                assert_eq!(key, "key");
+                assert_eq!(store.get("key").unwrap(), None);
+            }
+            None => assert_eq!(store.get("key").unwrap(), Some(b"value".to_vec())),
+        }
+    });
+}
+
+#[test]
+fn gets_racing_a_delete_never_see_a_torn_value() {
+    model(|| {
+        let store = store();
+        store.insert("key".to_string(), b"value".to_vec(), 0).unwrap();
+
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let store = store.clone();
+                spawn(move || store.get("key").unwrap())
+            })
+            .collect();
+        let deleter = {
+            let store = store.clone();
+            spawn(move || store.delete("key"))
+        };
+
+        for reader in readers {
+            let value = reader.join().unwrap();
+            assert!(value.is_none() || value == Some(b"value".to_vec()));
+        }
+        assert_eq!(deleter.join().unwrap(), Some("key".to_string()));
+        assert_eq!(store.get("key").unwrap(), None);
+    });
+}
+
+#[test]
+fn concurrent_inserts_are_all_counted() {
+    model(|| {
+        let store = store();
+
+        let writers: Vec<_> = (0..3)
+            .map(|n| {
+                let store = store.clone();
+                spawn(move || store.insert(format!("key:{}", n), vec![n], 0).unwrap())
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let stats = store.stats();
+        assert_eq!(stats.keys, 3);
+        assert_eq!(stats.metrics.sets, 3);
+    });
+}
+
+#[test]
+fn gets_racing_a_purge_count_each_expiry_once() {
+    model(|| {
+        let clock = ManualClock::default();
+        clock.advance(1_000_000);
+        let store = Store::with_clock(&StoreConfig::default(), clock.clone());
+        store.insert("old".to_string(), b"stale".to_vec(), 1).unwrap();
+        store.insert("live".to_string(), b"fresh".to_vec(), 0).unwrap();
+        clock.advance(2_000);
+
+        let readers: Vec<_> = ["old", "old", "live"]
+            .into_iter()
+            .map(|key| {
+                let store = store.clone();
+                spawn(move || store.get(key).unwrap())
+            })
+            .collect();
+        let purged = store.purge_expired();
+
+        let values: Vec<_> = readers.into_iter().map(|reader| reader.join().unwrap()).collect();
+        assert_eq!(values, [None, None, Some(b"fresh".to_vec())]);
+        assert!(purged <= 1);
+
+        let stats = store.stats();
+        assert_eq!(stats.keys, 1);
+        assert_eq!(stats.metrics.expirations, 1);
+    });
+}
+
+#[test]
+fn concurrent_increments_are_never_lost() {
+    model(|| {
+        let store = store();
+
+        let writers: Vec<_> = (0..3)
+            .map(|_| {
+                let store = store.clone();
+                spawn(move || store.increment("counter", 1).unwrap())
+            })
+            .collect();
+        let mut seen = vec![store.increment("counter", 1).unwrap()];
+        seen.extend(writers.into_iter().map(|writer| writer.join().unwrap()));
+
+        seen.sort();
+        assert_eq!(seen, [1, 2, 3, 4]);
+        assert_eq!(store.increment("counter", 0).unwrap(), 4);
+    });
+}